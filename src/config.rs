@@ -0,0 +1,441 @@
+use crate::config_result::{ConfigError, ConfigResult};
+use crate::glob::glob_match;
+use crate::resp::split_inline_arguments;
+use std::collections::HashSet;
+use std::fs;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+/// A directive and its arguments, as written on one line of a config file.
+type Directive = (String, Vec<String>);
+
+/// A directive exposed through `CONFIG GET`, `CONFIG SET` and `CONFIG REWRITE`.
+struct Parameter {
+    name: &'static str,
+    get: fn(&Config) -> String,
+    /// Whether `CONFIG SET` may change it while the server is running.
+    mutable: bool,
+}
+
+const PARAMETERS: &[Parameter] = &[
+    Parameter {
+        name: "bind",
+        get: |config| config.bind.join(" "),
+        mutable: false,
+    },
+    Parameter {
+        name: "port",
+        get: |config| config.port.to_string(),
+        mutable: false,
+    },
+    Parameter {
+        name: "hz",
+        get: |config| config.hz.to_string(),
+        mutable: true,
+    },
+    Parameter {
+        name: "maxclients",
+        get: |config| config.maxclients.to_string(),
+        mutable: true,
+    },
+    Parameter {
+        name: "timeout",
+        get: |config| config.timeout.to_string(),
+        mutable: true,
+    },
+    Parameter {
+        name: "active-expire",
+        get: |config| String::from(if config.active_expire { "yes" } else { "no" }),
+        mutable: true,
+    },
+];
+
+/// Server settings, loaded from a `redis.conf`-style file and `--directive value`
+/// command line options using the same directive names.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Config {
+    pub bind: Vec<String>,
+    pub port: u16,
+    pub hz: u32,
+    pub maxclients: usize,
+    pub timeout: u64,
+    pub active_expire: bool,
+    pub config_file: Option<PathBuf>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            bind: vec![String::from("127.0.0.1")],
+            port: 6379,
+            hz: 100,
+            maxclients: 10000,
+            timeout: 0,
+            active_expire: true,
+            config_file: None,
+        }
+    }
+}
+
+impl Config {
+    /// Builds the configuration from the command line, mirroring `redis-server`: an
+    /// optional config file path followed by `--directive value ...` options, which
+    /// override the directives found in the file.
+    pub fn from_args(args: &[String]) -> ConfigResult<Self> {
+        let mut config = Config::default();
+        let mut options = args;
+
+        if let Some(path) = args.first().filter(|arg| !arg.starts_with("--")) {
+            let text = fs::read_to_string(path)
+                .map_err(|e| ConfigError::ReadFile(path.clone(), e.to_string()))?;
+            for (name, arguments) in parse_config_text(&text)? {
+                config.set(&name, &arguments)?;
+            }
+            config.config_file = Some(PathBuf::from(path));
+            options = &args[1..];
+        }
+
+        for (name, arguments) in parse_command_line(options)? {
+            config.set(&name, &arguments)?;
+        }
+        Ok(config)
+    }
+
+    /// Applies a single directive, validating its arguments.
+    pub fn set(&mut self, name: &str, arguments: &[String]) -> ConfigResult<()> {
+        match name.to_lowercase().as_str() {
+            "bind" => {
+                if arguments.is_empty() {
+                    return Err(ConfigError::WrongArgumentCount(String::from(name)));
+                }
+                self.bind = arguments.to_vec();
+            }
+            "port" => self.port = parse_value(name, arguments)?,
+            // Like Redis, out of range frequencies are clamped rather than rejected.
+            "hz" => self.hz = parse_value::<u32>(name, arguments)?.clamp(1, 500),
+            "maxclients" => {
+                let maxclients: usize = parse_value(name, arguments)?;
+                if maxclients == 0 {
+                    return Err(ConfigError::InvalidValue(
+                        String::from(name),
+                        arguments[0].clone(),
+                    ));
+                }
+                self.maxclients = maxclients;
+            }
+            "timeout" => self.timeout = parse_value(name, arguments)?,
+            "active-expire" => self.active_expire = parse_yes_no(name, arguments)?,
+            _ => return Err(ConfigError::UnknownDirective(String::from(name))),
+        }
+        Ok(())
+    }
+}
+
+impl Config {
+    /// Returns the name and current value of every parameter matching one of the glob
+    /// patterns, in registry order.
+    pub fn get(&self, patterns: &[Vec<u8>]) -> Vec<(String, String)> {
+        PARAMETERS
+            .iter()
+            .filter(|parameter| {
+                patterns
+                    .iter()
+                    .any(|pattern| glob_match(pattern, parameter.name.as_bytes(), true))
+            })
+            .map(|parameter| (String::from(parameter.name), (parameter.get)(self)))
+            .collect()
+    }
+
+    /// Applies `name value` pairs at runtime. Either every pair is applied or, if any of
+    /// them is unknown, immutable or invalid, none is.
+    pub fn set_at_runtime(&mut self, pairs: &[(String, String)]) -> ConfigResult<()> {
+        let mut updated = self.clone();
+        for (name, value) in pairs.iter() {
+            let name = name.to_lowercase();
+            match PARAMETERS.iter().find(|parameter| parameter.name == name) {
+                Some(parameter) if parameter.mutable => {
+                    let arguments: Vec<String> =
+                        value.split_whitespace().map(String::from).collect();
+                    updated.set(&name, &arguments)?;
+                }
+                Some(_) => return Err(ConfigError::Immutable(name)),
+                None => return Err(ConfigError::UnknownDirective(name)),
+            }
+        }
+        *self = updated;
+        Ok(())
+    }
+
+    /// Writes the current configuration back to the file it was loaded from.
+    ///
+    /// Comments and unknown lines are kept, the first occurrence of each directive is
+    /// updated in place and later duplicates dropped, and parameters missing from the file
+    /// are appended when they differ from their default value.
+    pub fn rewrite(&self) -> ConfigResult<()> {
+        let path = self.config_file.as_ref().ok_or(ConfigError::NoConfigFile)?;
+        let display_path = path.to_string_lossy().into_owned();
+        let text = fs::read_to_string(path)
+            .map_err(|e| ConfigError::ReadFile(display_path.clone(), e.to_string()))?;
+
+        let mut lines = Vec::new();
+        let mut written = HashSet::new();
+        for line in text.lines() {
+            let name = line
+                .split_whitespace()
+                .next()
+                .filter(|name| !name.starts_with('#'))
+                .map(str::to_lowercase);
+            match name.and_then(|name| PARAMETERS.iter().find(|p| p.name == name)) {
+                Some(parameter) => {
+                    if written.insert(parameter.name) {
+                        lines.push(format!("{} {}", parameter.name, (parameter.get)(self)));
+                    }
+                }
+                None => lines.push(String::from(line)),
+            }
+        }
+
+        let defaults = Config::default();
+        for parameter in PARAMETERS.iter() {
+            if !written.contains(parameter.name)
+                && (parameter.get)(self) != (parameter.get)(&defaults)
+            {
+                lines.push(format!("{} {}", parameter.name, (parameter.get)(self)));
+            }
+        }
+
+        let mut content = lines.join("\n");
+        content.push('\n');
+        // Write next to the original and rename over it, so a crash never leaves a
+        // truncated config file behind.
+        let temporary = path.with_extension("rewrite.tmp");
+        fs::write(&temporary, content)
+            .and_then(|_| fs::rename(&temporary, path))
+            .map_err(|e| ConfigError::WriteFile(display_path, e.to_string()))
+    }
+}
+
+fn single_argument<'a>(name: &str, arguments: &'a [String]) -> ConfigResult<&'a str> {
+    match arguments {
+        [value] => Ok(value),
+        _ => Err(ConfigError::WrongArgumentCount(String::from(name))),
+    }
+}
+
+fn parse_value<T: FromStr>(name: &str, arguments: &[String]) -> ConfigResult<T> {
+    let value = single_argument(name, arguments)?;
+    value
+        .parse()
+        .map_err(|_| ConfigError::InvalidValue(String::from(name), String::from(value)))
+}
+
+fn parse_yes_no(name: &str, arguments: &[String]) -> ConfigResult<bool> {
+    match single_argument(name, arguments)?.to_lowercase().as_str() {
+        "yes" => Ok(true),
+        "no" => Ok(false),
+        value => Err(ConfigError::InvalidValue(
+            String::from(name),
+            String::from(value),
+        )),
+    }
+}
+
+/// Splits a config file into directives, skipping blank lines and `#` comments.
+/// Arguments can be quoted the same way as inline commands.
+fn parse_config_text(text: &str) -> ConfigResult<Vec<Directive>> {
+    let mut directives = Vec::new();
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let mut words = split_inline_arguments(line.as_bytes())
+            .map_err(|_| ConfigError::InvalidLine(number + 1))?
+            .into_iter()
+            .map(|word| String::from_utf8_lossy(&word).into_owned());
+        if let Some(name) = words.next() {
+            directives.push((name, words.collect()));
+        }
+    }
+    Ok(directives)
+}
+
+/// Groups `--directive value ...` options into directives.
+fn parse_command_line(options: &[String]) -> ConfigResult<Vec<Directive>> {
+    let mut directives: Vec<Directive> = Vec::new();
+    for option in options {
+        match (option.strip_prefix("--"), directives.last_mut()) {
+            (Some(name), _) => directives.push((String::from(name), Vec::new())),
+            (None, Some((_, arguments))) => arguments.push(option.clone()),
+            (None, None) => return Err(ConfigError::UnknownDirective(option.clone())),
+        }
+    }
+    Ok(directives)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(values: &[&str]) -> Vec<String> {
+        values.iter().map(|value| String::from(*value)).collect()
+    }
+
+    #[test]
+    fn test_from_args_defaults() {
+        let config = Config::from_args(&[]).unwrap();
+        assert_eq!(config, Config::default());
+    }
+
+    #[test]
+    fn test_from_args_options() {
+        let config = Config::from_args(&args(&[
+            "--port",
+            "7000",
+            "--bind",
+            "0.0.0.0",
+            "::1",
+            "--hz",
+            "1000",
+            "--active-expire",
+            "no",
+        ]))
+        .unwrap();
+        assert_eq!(config.port, 7000);
+        assert_eq!(config.bind, args(&["0.0.0.0", "::1"]));
+        assert_eq!(config.hz, 500);
+        assert!(!config.active_expire);
+    }
+
+    #[test]
+    fn test_from_args_invalid_value() {
+        let error = Config::from_args(&args(&["--port", "seventy"])).unwrap_err();
+        assert_eq!(
+            error,
+            ConfigError::InvalidValue(String::from("port"), String::from("seventy"))
+        );
+    }
+
+    #[test]
+    fn test_from_args_unknown_directive() {
+        let error = Config::from_args(&args(&["--save", "60"])).unwrap_err();
+        assert_eq!(error, ConfigError::UnknownDirective(String::from("save")));
+    }
+
+    #[test]
+    fn test_from_args_missing_file() {
+        let error = Config::from_args(&args(&["/does/not/exist.conf"])).unwrap_err();
+        assert!(matches!(error, ConfigError::ReadFile(_, _)));
+    }
+
+    #[test]
+    fn test_from_args_file_and_overrides() {
+        let path = std::env::temp_dir().join(format!("new-redis-{}.conf", std::process::id()));
+        fs::write(&path, "# test\n\nport 7001\nHZ 20\ntimeout \"30\"\n").unwrap();
+        let path_arg = path.to_string_lossy().into_owned();
+
+        let config = Config::from_args(&[path_arg, String::from("--port"), String::from("7002")]);
+        fs::remove_file(&path).unwrap();
+        let config = config.unwrap();
+
+        assert_eq!(config.port, 7002);
+        assert_eq!(config.hz, 20);
+        assert_eq!(config.timeout, 30);
+        assert_eq!(config.config_file, Some(path));
+    }
+
+    #[test]
+    fn test_parse_config_text_unbalanced_quotes() {
+        let error = parse_config_text("port 1\nbind \"127.0.0.1\n").unwrap_err();
+        assert_eq!(error, ConfigError::InvalidLine(2));
+    }
+
+    #[test]
+    fn test_set_wrong_argument_count() {
+        let mut config = Config::default();
+        let error = config.set("port", &args(&["1", "2"])).unwrap_err();
+        assert_eq!(error, ConfigError::WrongArgumentCount(String::from("port")));
+        let error = config.set("maxclients", &args(&["0"])).unwrap_err();
+        assert!(matches!(error, ConfigError::InvalidValue(_, _)));
+    }
+
+    #[test]
+    fn test_get_patterns() {
+        let config = Config::default();
+        let output = config.get(&[b"MAX*".to_vec(), b"hz".to_vec()]);
+        assert_eq!(
+            output,
+            vec![
+                (String::from("hz"), String::from("100")),
+                (String::from("maxclients"), String::from("10000")),
+            ]
+        );
+        assert!(config.get(&[b"nothing".to_vec()]).is_empty());
+    }
+
+    #[test]
+    fn test_set_at_runtime() {
+        let mut config = Config::default();
+        config
+            .set_at_runtime(&[
+                (String::from("hz"), String::from("10")),
+                (String::from("ACTIVE-EXPIRE"), String::from("no")),
+            ])
+            .unwrap();
+        assert_eq!(config.hz, 10);
+        assert!(!config.active_expire);
+    }
+
+    #[test]
+    fn test_set_at_runtime_is_atomic() {
+        let mut config = Config::default();
+        let error = config
+            .set_at_runtime(&[
+                (String::from("hz"), String::from("10")),
+                (String::from("timeout"), String::from("never")),
+            ])
+            .unwrap_err();
+        assert_eq!(
+            error,
+            ConfigError::InvalidValue(String::from("timeout"), String::from("never"))
+        );
+        assert_eq!(config, Config::default());
+    }
+
+    #[test]
+    fn test_set_at_runtime_immutable() {
+        let mut config = Config::default();
+        let error = config
+            .set_at_runtime(&[(String::from("port"), String::from("1"))])
+            .unwrap_err();
+        assert_eq!(error, ConfigError::Immutable(String::from("port")));
+        let error = config
+            .set_at_runtime(&[(String::from("save"), String::from("1"))])
+            .unwrap_err();
+        assert_eq!(error, ConfigError::UnknownDirective(String::from("save")));
+    }
+
+    #[test]
+    fn test_rewrite_without_file() {
+        let error = Config::default().rewrite().unwrap_err();
+        assert_eq!(error, ConfigError::NoConfigFile);
+    }
+
+    #[test]
+    fn test_rewrite() {
+        let path =
+            std::env::temp_dir().join(format!("new-redis-rewrite-{}.conf", std::process::id()));
+        fs::write(&path, "# keep me\nhz 20\nunknown-line stays\nHZ 30\n").unwrap();
+        let config = Config {
+            hz: 50,
+            timeout: 5,
+            config_file: Some(path.clone()),
+            ..Config::default()
+        };
+        config.rewrite().unwrap();
+        let content = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(content, "# keep me\nhz 50\nunknown-line stays\ntimeout 5\n");
+    }
+}
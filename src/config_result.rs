@@ -0,0 +1,40 @@
+use std::fmt;
+
+#[derive(Debug, PartialEq)]
+pub enum ConfigError {
+    ReadFile(String, String),
+    InvalidLine(usize),
+    UnknownDirective(String),
+    WrongArgumentCount(String),
+    InvalidValue(String, String),
+    Immutable(String),
+    NoConfigFile,
+    WriteFile(String, String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::ReadFile(path, reason) => {
+                write!(f, "Cannot read config file {}: {}", path, reason)
+            }
+            ConfigError::InvalidLine(number) => {
+                write!(f, "Unbalanced quotes in configuration line {}", number)
+            }
+            ConfigError::UnknownDirective(name) => write!(f, "Unknown directive '{}'", name),
+            ConfigError::WrongArgumentCount(name) => {
+                write!(f, "Wrong number of arguments for '{}'", name)
+            }
+            ConfigError::InvalidValue(name, value) => {
+                write!(f, "Invalid value '{}' for '{}'", value, name)
+            }
+            ConfigError::Immutable(name) => write!(f, "Cannot set immutable config '{}'", name),
+            ConfigError::NoConfigFile => write!(f, "The server is running without a config file"),
+            ConfigError::WriteFile(path, reason) => {
+                write!(f, "Cannot write config file {}: {}", path, reason)
+            }
+        }
+    }
+}
+
+pub type ConfigResult<T> = Result<T, ConfigError>;
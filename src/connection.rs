@@ -0,0 +1,224 @@
+use crate::connection_result::{ConnectionError, ConnectionResult};
+use crate::resp::{decode_frame, RESPVersion, RESP};
+use crate::resp_encoder::encode;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+};
+
+/// Amount of spare capacity reserved in the read buffer before each read.
+const READ_CHUNK_SIZE: usize = 16 * 1024;
+
+/// Output buffers grown past this size by a large reply are shrunk back after flushing,
+/// so one big `GET` does not pin memory for the lifetime of the connection.
+const MAX_IDLE_OUTPUT_CAPACITY: usize = 1024 * 1024;
+
+/// A client connection with a growable read buffer, so frames larger than a single read
+/// or split across TCP segments are reassembled before being decoded, and an output
+/// buffer collecting the replies to a pipelined batch into a single write.
+pub struct Connection {
+    stream: TcpStream,
+    buffer: Vec<u8>,
+    output: Vec<u8>,
+}
+
+impl Connection {
+    pub fn new(stream: TcpStream) -> Self {
+        Self {
+            stream,
+            buffer: Vec::with_capacity(READ_CHUNK_SIZE),
+            output: Vec::new(),
+        }
+    }
+
+    pub fn stream(&self) -> &TcpStream {
+        &self.stream
+    }
+
+    /// Reads from the socket until at least one complete frame is buffered, then returns
+    /// every complete frame in the buffer, in order.
+    ///
+    /// Returns `None` when the peer closed the connection cleanly between frames. A
+    /// protocol error following valid frames is reported by the next call, once the
+    /// frames before it have been handled.
+    pub async fn read_frames(&mut self) -> ConnectionResult<Option<Vec<RESP>>> {
+        loop {
+            let mut frames = Vec::new();
+            let mut consumed: usize = 0;
+            loop {
+                match decode_frame(&self.buffer[consumed..]) {
+                    Ok(Some((frame, size))) => {
+                        frames.push(frame);
+                        consumed += size;
+                    }
+                    Ok(None) => break,
+                    Err(_) if !frames.is_empty() => break,
+                    Err(e) => return Err(e.into()),
+                }
+            }
+            if !frames.is_empty() {
+                self.buffer.drain(..consumed);
+                return Ok(Some(frames));
+            }
+
+            self.buffer.reserve(READ_CHUNK_SIZE);
+            if self.stream.read_buf(&mut self.buffer).await? == 0 {
+                if self.buffer.is_empty() {
+                    return Ok(None);
+                }
+                return Err(ConnectionError::ResetByPeer);
+            }
+        }
+    }
+
+    /// Reads whatever the client sends while its last request is blocked, keeping it
+    /// for the next `read_frames`. Returns `false` once the peer has closed the connection.
+    pub async fn read_pending(&mut self) -> ConnectionResult<bool> {
+        self.buffer.reserve(READ_CHUNK_SIZE);
+        Ok(self.stream.read_buf(&mut self.buffer).await? > 0)
+    }
+
+    /// Appends a reply to the output buffer; nothing is sent until `flush` is called.
+    pub fn queue_frame(&mut self, frame: &RESP, version: RESPVersion) {
+        encode(frame, &mut self.output, version);
+    }
+
+    /// Writes every queued reply to the socket.
+    pub async fn flush(&mut self) -> ConnectionResult<()> {
+        self.stream.write_all(&self.output).await?;
+        self.output.clear();
+        if self.output.capacity() > MAX_IDLE_OUTPUT_CAPACITY {
+            self.output.shrink_to(READ_CHUNK_SIZE);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpListener;
+
+    async fn connection_pair() -> (Connection, TcpStream) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let client = TcpStream::connect(listener.local_addr().unwrap())
+            .await
+            .unwrap();
+        let (server, _) = listener.accept().await.unwrap();
+        (Connection::new(server), client)
+    }
+
+    #[tokio::test]
+    async fn test_read_frame_split_across_writes() {
+        let (mut connection, mut client) = connection_pair().await;
+        let reader = tokio::spawn(async move { connection.read_frames().await.unwrap() });
+
+        client.write_all(b"*2\r\n$4\r\nEC").await.unwrap();
+        client.flush().await.unwrap();
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        client.write_all(b"HO\r\n$2\r\nhi\r\n").await.unwrap();
+
+        let frames = reader.await.unwrap();
+        assert_eq!(
+            frames,
+            Some(vec![RESP::Array(vec![
+                RESP::BulkString(b"ECHO".to_vec()),
+                RESP::BulkString(b"hi".to_vec()),
+            ])])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_read_frame_large_value() {
+        let (mut connection, mut client) = connection_pair().await;
+        let value = vec![b'x'; 3 * 1024 * 1024];
+        let frame = RESP::Array(vec![
+            RESP::BulkString(b"SET".to_vec()),
+            RESP::BulkString(b"key".to_vec()),
+            RESP::BulkString(value.clone()),
+        ]);
+        let bytes = frame.to_bytes(RESPVersion::RESP2);
+        tokio::spawn(async move { client.write_all(&bytes).await.unwrap() });
+
+        let output = connection.read_frames().await.unwrap();
+        assert_eq!(output, Some(vec![frame]));
+    }
+
+    #[tokio::test]
+    async fn test_read_frame_closed() {
+        let (mut connection, client) = connection_pair().await;
+        drop(client);
+        assert!(connection.read_frames().await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_read_frame_closed_mid_frame() {
+        let (mut connection, mut client) = connection_pair().await;
+        client.write_all(b"*1\r\n$4\r\nPI").await.unwrap();
+        drop(client);
+        let error = connection.read_frames().await.unwrap_err();
+        assert!(matches!(error, ConnectionError::ResetByPeer));
+    }
+
+    #[tokio::test]
+    async fn test_read_frame_protocol_error() {
+        let (mut connection, mut client) = connection_pair().await;
+        client.write_all(b"*1\r\n$x\r\n").await.unwrap();
+        let error = connection.read_frames().await.unwrap_err();
+        assert!(matches!(error, ConnectionError::Protocol(_)));
+    }
+
+    #[tokio::test]
+    async fn test_read_frames_pipelined() {
+        let (mut connection, mut client) = connection_pair().await;
+        client
+            .write_all(b"*1\r\n$4\r\nPING\r\n*2\r\n$4\r\nECHO\r\n$1\r\na\r\n*1\r\n$3\r\nGE")
+            .await
+            .unwrap();
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+
+        let frames = connection.read_frames().await.unwrap().unwrap();
+        assert_eq!(
+            frames,
+            vec![
+                RESP::Array(vec![RESP::BulkString(b"PING".to_vec())]),
+                RESP::Array(vec![
+                    RESP::BulkString(b"ECHO".to_vec()),
+                    RESP::BulkString(b"a".to_vec()),
+                ]),
+            ]
+        );
+        assert_eq!(connection.buffer, b"*1\r\n$3\r\nGE".to_vec());
+    }
+
+    #[tokio::test]
+    async fn test_read_frames_defers_protocol_error() {
+        let (mut connection, mut client) = connection_pair().await;
+        client
+            .write_all(b"*1\r\n$4\r\nPING\r\n*1\r\n$x\r\n")
+            .await
+            .unwrap();
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+
+        let frames = connection.read_frames().await.unwrap().unwrap();
+        assert_eq!(frames.len(), 1);
+        let error = connection.read_frames().await.unwrap_err();
+        assert!(matches!(error, ConnectionError::Protocol(_)));
+    }
+
+    #[tokio::test]
+    async fn test_flush_coalesces_replies() {
+        let (mut connection, mut client) = connection_pair().await;
+        connection.queue_frame(
+            &RESP::SimpleString(String::from("PONG")),
+            RESPVersion::RESP2,
+        );
+        connection.queue_frame(&RESP::Integer(3), RESPVersion::RESP2);
+        connection.flush().await.unwrap();
+        assert!(connection.output.is_empty());
+
+        let mut reply = vec![0; 11];
+        client.read_exact(&mut reply).await.unwrap();
+        assert_eq!(reply, b"+PONG\r\n:3\r\n".to_vec());
+    }
+}
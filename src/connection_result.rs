@@ -0,0 +1,34 @@
+use crate::resp_result::RESPError;
+use std::fmt;
+use std::io;
+
+#[derive(Debug)]
+pub enum ConnectionError {
+    Io(io::Error),
+    Protocol(RESPError),
+    ResetByPeer,
+}
+
+impl fmt::Display for ConnectionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConnectionError::Io(err) => write!(f, "I/O error: {}", err),
+            ConnectionError::Protocol(err) => write!(f, "Protocol error: {}", err),
+            ConnectionError::ResetByPeer => write!(f, "Connection reset by peer mid-frame"),
+        }
+    }
+}
+
+impl From<io::Error> for ConnectionError {
+    fn from(err: io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+
+impl From<RESPError> for ConnectionError {
+    fn from(err: RESPError) -> Self {
+        Self::Protocol(err)
+    }
+}
+
+pub type ConnectionResult<T> = Result<T, ConnectionError>;
@@ -0,0 +1,156 @@
+/// Matches `string` against a Redis glob `pattern`.
+///
+/// Supports `*`, `?`, character classes such as `[abc]`, `[a-z]` and `[^a]`, and `\`
+/// escapes, following the semantics of Redis' `stringmatchlen`. Stars are matched by
+/// backtracking to the most recent one only, so the cost stays linear in the common case
+/// instead of exploding on patterns like `*a*a*a*b`.
+pub fn glob_match(pattern: &[u8], string: &[u8], nocase: bool) -> bool {
+    let mut p: usize = 0;
+    let mut s: usize = 0;
+    // Pattern position after the last star seen, and the string position it was tried at.
+    let mut star: Option<(usize, usize)> = None;
+
+    while s < string.len() {
+        if p < pattern.len() {
+            if pattern[p] == b'*' {
+                while p < pattern.len() && pattern[p] == b'*' {
+                    p += 1;
+                }
+                star = Some((p, s));
+                continue;
+            }
+            if let Some(next) = match_token(pattern, p, string[s], nocase) {
+                p = next;
+                s += 1;
+                continue;
+            }
+        }
+        match star {
+            Some((star_p, star_s)) => {
+                p = star_p;
+                s = star_s + 1;
+                star = Some((star_p, s));
+            }
+            None => return false,
+        }
+    }
+
+    while p < pattern.len() && pattern[p] == b'*' {
+        p += 1;
+    }
+    p == pattern.len()
+}
+
+/// Matches the single-character token starting at `pattern[p]` against `elem`, returning
+/// the position of the following token on success.
+fn match_token(pattern: &[u8], p: usize, elem: u8, nocase: bool) -> Option<usize> {
+    match pattern[p] {
+        b'?' => Some(p + 1),
+        b'\\' if p + 1 < pattern.len() => equal(pattern[p + 1], elem, nocase).then_some(p + 2),
+        b'[' => {
+            let mut idx = p + 1;
+            let negate = idx < pattern.len() && pattern[idx] == b'^';
+            if negate {
+                idx += 1;
+            }
+            let mut matched = false;
+            while idx < pattern.len() && pattern[idx] != b']' {
+                if pattern[idx] == b'\\' && idx + 1 < pattern.len() {
+                    matched |= equal(pattern[idx + 1], elem, nocase);
+                    idx += 2;
+                } else if idx + 2 < pattern.len() && pattern[idx + 1] == b'-' {
+                    let (mut start, mut end) = (pattern[idx], pattern[idx + 2]);
+                    let mut elem = elem;
+                    if nocase {
+                        start = start.to_ascii_lowercase();
+                        end = end.to_ascii_lowercase();
+                        elem = elem.to_ascii_lowercase();
+                    }
+                    if start > end {
+                        std::mem::swap(&mut start, &mut end);
+                    }
+                    matched |= (start..=end).contains(&elem);
+                    idx += 3;
+                } else {
+                    matched |= equal(pattern[idx], elem, nocase);
+                    idx += 1;
+                }
+            }
+            // An unterminated class extends to the end of the pattern, like in Redis.
+            let next = (idx + 1).min(pattern.len());
+            (matched != negate).then_some(next)
+        }
+        literal => equal(literal, elem, nocase).then_some(p + 1),
+    }
+}
+
+fn equal(a: u8, b: u8, nocase: bool) -> bool {
+    if nocase {
+        a.eq_ignore_ascii_case(&b)
+    } else {
+        a == b
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::glob_match;
+
+    fn matches(pattern: &str, string: &str) -> bool {
+        glob_match(pattern.as_bytes(), string.as_bytes(), false)
+    }
+
+    #[test]
+    fn test_glob_match_literal() {
+        assert!(matches("hello", "hello"));
+        assert!(!matches("hello", "hell"));
+        assert!(!matches("hell", "hello"));
+        assert!(matches("", ""));
+    }
+
+    #[test]
+    fn test_glob_match_star() {
+        assert!(matches("*", ""));
+        assert!(matches("*", "anything"));
+        assert!(matches("h*llo", "hllo"));
+        assert!(matches("h*llo", "heeeello"));
+        assert!(matches("user:*:name", "user:42:name"));
+        assert!(!matches("user:*:name", "user:42:age"));
+        assert!(matches("*a*a*a*b", "aaaaaaaaaaaaaaaaaaaaaaaab"));
+        assert!(!matches("*a*a*a*b", "aaaaaaaaaaaaaaaaaaaaaaaaa"));
+    }
+
+    #[test]
+    fn test_glob_match_question_mark() {
+        assert!(matches("h?llo", "hello"));
+        assert!(matches("h?llo", "hallo"));
+        assert!(!matches("h?llo", "hllo"));
+    }
+
+    #[test]
+    fn test_glob_match_classes() {
+        assert!(matches("h[ae]llo", "hello"));
+        assert!(matches("h[ae]llo", "hallo"));
+        assert!(!matches("h[ae]llo", "hillo"));
+        assert!(matches("h[^e]llo", "hallo"));
+        assert!(!matches("h[^e]llo", "hello"));
+        assert!(matches("h[a-b]llo", "hbllo"));
+        assert!(matches("h[b-a]llo", "hallo"));
+        assert!(!matches("h[a-b]llo", "hcllo"));
+        assert!(matches("[\\]]", "]"));
+    }
+
+    #[test]
+    fn test_glob_match_escapes() {
+        assert!(matches("h\\*llo", "h*llo"));
+        assert!(!matches("h\\*llo", "hello"));
+        assert!(matches("h\\?", "h?"));
+    }
+
+    #[test]
+    fn test_glob_match_nocase() {
+        assert!(glob_match(b"MAX*", b"maxclients", true));
+        assert!(glob_match(b"[A-Z]z", b"aZ", true));
+        assert!(!glob_match(b"MAX*", b"maxclients", false));
+    }
+}
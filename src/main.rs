@@ -1,75 +1,169 @@
-use crate::resp::{bytes_to_resp, RESP};
-use crate::server::process_request;
-use crate::storage::Storage;
+use crate::config::Config;
+use crate::connection::Connection;
+use crate::connection_result::ConnectionError;
+use crate::resp::{RESPVersion, RESP};
+use crate::server::{process_request, Session};
+use crate::storage::{BlockedClient, Response, Storage};
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::{Arc, Mutex};
 use std::time::Duration;
-use tokio::{
-    io::{AsyncReadExt, AsyncWriteExt},
-    net::{TcpListener, TcpStream},
-};
-mod server_result;
+use tokio::net::{TcpListener, TcpStream};
+mod config;
+mod config_result;
+mod connection;
+mod connection_result;
+mod glob;
+mod random;
 mod resp;
+mod resp_encoder;
 mod resp_result;
+mod scan;
 mod server;
 mod set;
+mod skiplist;
 mod storage;
 mod storage_result;
 
+const USAGE: &str = "Usage: new-redis [/path/to/redis.conf] [--directive value ...]
+
+Examples:
+    new-redis --port 7777
+    new-redis /etc/redis/6379.conf --hz 50 --bind 0.0.0.0";
+
 #[tokio::main]
 async fn main() -> std::io::Result<()> {
-    let listener = TcpListener::bind("127.0.0.1:6379").await?;
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match args.first().map(String::as_str) {
+        Some("-h") | Some("--help") => {
+            println!("{}", USAGE);
+            return Ok(());
+        }
+        Some("-v") | Some("--version") => {
+            println!("new-redis v{}", env!("CARGO_PKG_VERSION"));
+            return Ok(());
+        }
+        _ => {}
+    }
+    let config = match Config::from_args(&args) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            eprintln!("{}", USAGE);
+            std::process::exit(1);
+        }
+    };
 
-    let storage = Arc::new(Mutex::new(Storage::new()));
+    let storage = Arc::new(Mutex::new(Storage::from_config(config.clone())));
+    let clients = Arc::new(AtomicUsize::new(0));
 
-    let mut interval_timer = tokio::time::interval(Duration::from_millis(10));
+    for address in config.bind.iter() {
+        // Like Redis, a `-` prefix marks an address that is skipped if it is unavailable.
+        let (address, optional) = match address.strip_prefix('-') {
+            Some(address) => (address, true),
+            None => (address.as_str(), false),
+        };
+        let listener = match TcpListener::bind((address, config.port)).await {
+            Ok(listener) => listener,
+            Err(e) if optional => {
+                eprintln!("Skipping {}:{}: {}", address, config.port, e);
+                continue;
+            }
+            Err(e) => return Err(e),
+        };
+        println!("Listening on {}:{}", address, config.port);
+        tokio::spawn(accept_connections(
+            listener,
+            storage.clone(),
+            clients.clone(),
+        ));
+    }
+
+    let mut hz = config.hz;
+    let mut interval_timer = tokio::time::interval(Duration::from_millis(1000 / hz as u64));
     loop {
-        tokio::select! {
-                connection = listener.accept() => {
-                    match connection {
-                        Ok((stream, _)) => {
-                            tokio::spawn(handle_connection(stream, storage.clone()));
-                        }
-                        Err(e) => {
-                            println!("Error: {}", e);
-                            continue;
-                        }
-                    }
-                }
+        interval_timer.tick().await;
+        tokio::spawn(expire_keys(storage.clone()));
+        // `hz` can be changed with CONFIG SET, so pick the new frequency up on the next tick.
+        let current = read_config(&storage, |config| config.hz).unwrap_or(hz);
+        if current != hz {
+            hz = current;
+            interval_timer = tokio::time::interval(Duration::from_millis(1000 / hz as u64));
+            interval_timer.tick().await;
+        }
+    }
+}
+
+/// Reads a setting from the live configuration, which CONFIG SET may change at any time.
+fn read_config<T>(storage: &Arc<Mutex<Storage>>, read: impl Fn(&Config) -> T) -> Option<T> {
+    match storage.lock() {
+        Ok(guard) => Some(read(guard.config())),
+        Err(e) => {
+            eprintln!("Error locking storage: {}", e);
+            None
+        }
+    }
+}
 
-                _ = interval_timer.tick() => {
-                    tokio::spawn(expire_keys(storage.clone()));
+async fn accept_connections(
+    listener: TcpListener,
+    storage: Arc<Mutex<Storage>>,
+    clients: Arc<AtomicUsize>,
+) {
+    loop {
+        match listener.accept().await {
+            Ok((stream, _)) => {
+                let maxclients = read_config(&storage, |config| config.maxclients).unwrap_or(0);
+                if clients.fetch_add(1, Ordering::SeqCst) >= maxclients {
+                    clients.fetch_sub(1, Ordering::SeqCst);
+                    tokio::spawn(reject_connection(stream));
+                    continue;
                 }
+                let clients = clients.clone();
+                let storage = storage.clone();
+                tokio::spawn(async move {
+                    handle_connection(stream, storage).await;
+                    clients.fetch_sub(1, Ordering::SeqCst);
+                });
+            }
+            Err(e) => {
+                println!("Error: {}", e);
+                continue;
+            }
         }
     }
 }
 
-async fn handle_connection(mut stream: TcpStream, storage: Arc<Mutex<Storage>>) {
-    let mut buff = [0; 512];
+async fn reject_connection(stream: TcpStream) {
+    let mut connection = Connection::new(stream);
+    let error = RESP::Error(String::from("ERR max number of clients reached"));
+    connection.queue_frame(&error, RESPVersion::RESP2);
+    let _ = connection.flush().await;
+}
+
+/// Serves a client until it disconnects or stays idle for longer than the configured
+/// `timeout` (0 keeps idle clients forever).
+async fn handle_connection(stream: TcpStream, storage: Arc<Mutex<Storage>>) {
+    let mut connection = Connection::new(stream);
+    let mut session = Session::new();
 
     loop {
-        match stream.read(&mut buff).await {
-            Ok(size) if size != 0 => {
-                let mut index: usize = 0;
-                let request = match bytes_to_resp(&buff[..size], &mut index) {
-                    Ok(v) => v,
-                    Err(e) => {
-                        eprintln!("Error: {}", e);
-                        return;
-                    }
-                };
-                let response = match process_request(request, storage.clone()) {
-                    Ok(v) => v,
-                    Err(_) => {
-                        eprintln!("Error processing request");
-                        return;
-                    }
-                };
-                if let Err(e) = stream.write_all(response.to_string().as_bytes()).await {
-                    eprintln!("Error writing to socket: {}", e);
-                }
+        let timeout = match read_config(&storage, |config| config.timeout) {
+            Some(timeout) => timeout,
+            None => return,
+        };
+        let read = connection.read_frames();
+        let result = if timeout == 0 {
+            read.await
+        } else {
+            match tokio::time::timeout(Duration::from_secs(timeout), read).await {
+                Ok(result) => result,
+                Err(_) => return,
             }
-            Ok(_) | Err(_) => {
-                match stream.peer_addr() {
+        };
+        let requests = match result {
+            Ok(Some(v)) => v,
+            Ok(None) => {
+                match connection.stream().peer_addr() {
                     Ok(addr) => {
                         println!("{} Connection closed", addr);
                     }
@@ -79,11 +173,89 @@ async fn handle_connection(mut stream: TcpStream, storage: Arc<Mutex<Storage>>)
                 }
                 return;
             }
+            Err(ConnectionError::Protocol(e)) => {
+                // The stream cannot be resynchronised after a protocol error, so reply
+                // and close the connection like Redis does.
+                eprintln!("Error: {}", e);
+                connection.queue_frame(&RESP::from(e), session.version);
+                let _ = connection.flush().await;
+                return;
+            }
             Err(e) => {
                 println!("Error: {}", e);
                 return;
             }
+        };
+        for request in requests {
+            let response = match process_request(request, storage.clone(), &mut session) {
+                Ok(Response::Reply(v)) => v,
+                Ok(Response::Blocked(client)) => {
+                    // The replies queued so far are final, so send them before waiting.
+                    if let Err(e) = connection.flush().await {
+                        eprintln!("Error writing to socket: {}", e);
+                        unblock_disconnected(&storage, client);
+                        return;
+                    }
+                    match wait_until_served(&mut connection, &storage, client).await {
+                        Some(v) => v,
+                        None => return,
+                    }
+                }
+                Err(e) => RESP::from(e),
+            };
+            connection.queue_frame(&response, session.version);
         }
+        if let Err(e) = connection.flush().await {
+            eprintln!("Error writing to socket: {}", e);
+            return;
+        }
+    }
+}
+
+/// Parks a blocked client until a push serves it or its timeout elapses, in which case
+/// it gets a null. Returns `None` if the client disconnects while waiting.
+async fn wait_until_served(
+    connection: &mut Connection,
+    storage: &Arc<Mutex<Storage>>,
+    mut client: BlockedClient,
+) -> Option<RESP> {
+    let timeout = client.timeout;
+    let deadline = async {
+        match timeout {
+            Some(timeout) => tokio::time::sleep(timeout).await,
+            None => std::future::pending().await,
+        }
+    };
+    // Anything the client sends meanwhile is kept for once it is unblocked; reading it
+    // is how a disconnect is noticed.
+    let disconnected = async { while let Ok(true) = connection.read_pending().await {} };
+    tokio::select! {
+        Ok(response) = &mut client.reply => return Some(response),
+        _ = deadline => {}
+        _ = disconnected => {
+            unblock_disconnected(storage, client);
+            return None;
+        }
+    }
+    // A push may have served the client between the timeout and taking the lock.
+    Some(unblock(storage, &mut client).unwrap_or(RESP::Null))
+}
+
+fn unblock(storage: &Arc<Mutex<Storage>>, client: &mut BlockedClient) -> Option<RESP> {
+    match storage.lock() {
+        Ok(mut guard) => guard.unblock(client),
+        Err(e) => {
+            eprintln!("Error locking storage: {}", e);
+            None
+        }
+    }
+}
+
+/// Unblocks a client that went away, putting back anything it was served meanwhile.
+fn unblock_disconnected(storage: &Arc<Mutex<Storage>>, client: BlockedClient) {
+    match storage.lock() {
+        Ok(mut guard) => guard.unblock_disconnected(client),
+        Err(e) => eprintln!("Error locking storage: {}", e),
     }
 }
 
@@ -0,0 +1,51 @@
+use std::cell::Cell;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+thread_local! {
+    static STATE: Cell<u64> = Cell::new(seed());
+}
+
+/// Seeds the generator from the randomly keyed hasher of the standard library, so that
+/// every thread and every run picks differently.
+fn seed() -> u64 {
+    let mut hasher = RandomState::new().build_hasher();
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_nanos() as u64);
+    hasher.write_u64(nanos);
+    // xorshift never leaves an all-zero state.
+    hasher.finish() | 1
+}
+
+/// Returns a pseudo-random 64 bit integer. This is an xorshift64* generator: fast and
+/// good enough for picking elements, but not for anything where the choice must be
+/// unpredictable.
+pub fn random_u64() -> u64 {
+    STATE.with(|state| {
+        let mut x = state.get();
+        x ^= x >> 12;
+        x ^= x << 25;
+        x ^= x >> 27;
+        state.set(x);
+        x.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    })
+}
+
+/// Returns a pseudo-random integer in `0..bound`, which must not be 0.
+pub fn random_below(bound: usize) -> usize {
+    (random_u64() % bound as u64) as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_random_below_stays_in_range() {
+        let picks: HashSet<usize> = (0..1000).map(|_| random_below(5)).collect();
+        assert_eq!(picks, (0..5).collect());
+    }
+}
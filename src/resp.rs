@@ -1,30 +1,81 @@
+use crate::resp_encoder::encode;
 use crate::resp_result::{RESPError, RESPLength, RESPResult};
 use std::fmt;
 
+/// Largest bulk string accepted from a client, mirroring Redis' `proto-max-bulk-len`.
+pub const MAX_BULK_LENGTH: RESPLength = 512 * 1024 * 1024;
+
+/// Largest inline command accepted from a client, mirroring Redis' inline request limit.
+const MAX_INLINE_LENGTH: usize = 64 * 1024;
+
+/// Protocol version negotiated with `HELLO`; every connection starts on RESP2.
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RESPVersion {
+    #[default]
+    RESP2,
+    RESP3,
+}
+
+#[allow(clippy::upper_case_acronyms)]
 #[derive(Debug, PartialEq)]
 pub enum RESP {
     Null,
     SimpleString(String),
-    BulkString(String),
+    Error(String),
+    Integer(i64),
+    BulkString(Vec<u8>),
     Array(Vec<RESP>),
+    Map(Vec<(RESP, RESP)>),
+    Set(Vec<RESP>),
+    Double(f64),
+    Boolean(bool),
+    BigNumber(String),
+    Verbatim(String, Vec<u8>),
+    Push(Vec<RESP>),
+}
+
+/// Displays a double the way Redis replies with it, using `inf`, `-inf` and `nan` for the
+/// special values. Otherwise this follows `%.17g`, switching to exponent notation with a
+/// signed exponent of at least two digits (`1e+20`, `1e-05`) for very large or very
+/// small magnitudes, but with the shortest digits that read back as the same value.
+pub struct FormattedDouble(pub f64);
+
+impl fmt::Display for FormattedDouble {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let value = self.0;
+        if value.is_nan() {
+            write!(f, "nan")
+        } else if value.is_infinite() {
+            write!(f, "{}", if value > 0.0 { "inf" } else { "-inf" })
+        } else if value != 0.0 && !(1e-4..1e17).contains(&value.abs()) {
+            let formatted = format!("{:e}", value);
+            let (mantissa, exponent) = formatted.split_once('e').unwrap_or((&formatted, "0"));
+            let exponent: i32 = exponent.parse().unwrap_or(0);
+            let sign = if exponent < 0 { '-' } else { '+' };
+            write!(f, "{}e{}{:02}", mantissa, sign, exponent.unsigned_abs())
+        } else {
+            write!(f, "{}", value)
+        }
+    }
+}
+
+impl RESP {
+    /// Encodes the value into a freshly allocated buffer, see `resp_encoder::encode`.
+    pub fn to_bytes(&self, version: RESPVersion) -> Vec<u8> {
+        let mut output = Vec::new();
+        encode(self, &mut output, version);
+        output
+    }
 }
 
 impl fmt::Display for RESP {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let data = match self {
-            Self::Null => String::from("$-1\r\n"),
-            Self::SimpleString(data) => format!("+{}\r\n", data),
-            Self::BulkString(data) => format!("${}\r\n{}\r\n", data.len(), data),
-            Self::Array(data) => {
-                let mut output = String::from("*");
-                output.push_str(format!("{}", data.len()).as_str());
-                for elem in data.iter() {
-                    output.push_str(elem.to_string().as_str());
-                }
-                output
-            }
-        };
-        write!(f, "{}", data)
+        write!(
+            f,
+            "{}",
+            String::from_utf8_lossy(&self.to_bytes(RESPVersion::default()))
+        )
     }
 }
 
@@ -35,7 +86,7 @@ fn binary_extract_line(buffer: &[u8], index: &mut usize) -> RESPResult<Vec<u8>>
         return Err(RESPError::OutOfBounds(*index));
     }
 
-    if buffer.len() - *index - 1 < 2 {
+    if buffer.len() - *index < 2 {
         *index = buffer.len();
         return Err(RESPError::OutOfBounds(*index));
     }
@@ -64,7 +115,6 @@ fn binary_extract_line(buffer: &[u8], index: &mut usize) -> RESPResult<Vec<u8>>
 }
 
 fn binary_extract_bytes(buffer: &[u8], index: &mut usize, length: usize) -> RESPResult<Vec<u8>> {
-    println!("extracting bytes from {:?}", buffer);
     let mut output = Vec::new();
     if *index + length > buffer.len() {
         return Err(RESPError::OutOfBounds(buffer.len()));
@@ -86,6 +136,9 @@ pub fn binary_extract_line_as_string(buffer: &[u8], index: &mut usize) -> RESPRe
 }
 
 pub fn resp_remove_type(value: char, buffer: &[u8], index: &mut usize) -> RESPResult<()> {
+    if *index >= buffer.len() {
+        return Err(RESPError::OutOfBounds(*index));
+    }
     if buffer[*index] != value as u8 {
         return Err(RESPError::WrongType);
     }
@@ -99,50 +152,285 @@ fn parse_simple_string(buffer: &[u8], index: &mut usize) -> RESPResult<RESP> {
     Ok(RESP::SimpleString(line))
 }
 
+fn parse_error(buffer: &[u8], index: &mut usize) -> RESPResult<RESP> {
+    resp_remove_type('-', buffer, index)?;
+    let line = binary_extract_line_as_string(buffer, index)?;
+    Ok(RESP::Error(line))
+}
+
+fn parse_integer(buffer: &[u8], index: &mut usize) -> RESPResult<RESP> {
+    resp_remove_type(':', buffer, index)?;
+    let line = binary_extract_line_as_string(buffer, index)?;
+    Ok(RESP::Integer(line.parse()?))
+}
+
 fn parse_bulk_string(buffer: &[u8], index: &mut usize) -> RESPResult<RESP> {
     resp_remove_type('$', buffer, index)?;
     let length = resp_extract_length(buffer, index)?;
-    println!("Parsed Length: {}", length);
     if length == -1 {
         return Ok(RESP::Null);
     }
-    if length < -1 {
+    if !(0..=MAX_BULK_LENGTH).contains(&length) {
         return Err(RESPError::IncorrectLength(length));
     }
-    let bytes = binary_extract_bytes(buffer, index, length as usize)?;
-    let data = String::from_utf8(bytes)?;
+    // Wait for the payload and its trailing separator before copying anything, so that
+    // re-parsing a large partially received value stays cheap.
+    if *index + length as usize + 2 > buffer.len() {
+        return Err(RESPError::OutOfBounds(buffer.len()));
+    }
+    let data = binary_extract_bytes(buffer, index, length as usize)?;
+    if &buffer[*index..*index + 2] != b"\r\n" {
+        return Err(RESPError::MissingSeparator(*index));
+    }
     *index += 2;
     Ok(RESP::BulkString(data))
 }
 
-fn parse_array(buffer: &[u8], index: &mut usize) -> RESPResult<RESP> {
-    resp_remove_type('*', buffer, index)?;
+fn parse_aggregate_length(prefix: char, buffer: &[u8], index: &mut usize) -> RESPResult<usize> {
+    resp_remove_type(prefix, buffer, index)?;
     let length = resp_extract_length(buffer, index)?;
     if length < 0 {
         return Err(RESPError::IncorrectLength(length));
     }
+    Ok(length as usize)
+}
+
+/// How deeply aggregates may nest. Requests are flat arrays, so this only has to allow
+/// for replies, and stops a peer from overflowing the stack with `*1\r\n*1\r\n…`.
+const MAX_NESTING_DEPTH: usize = 128;
+
+/// Parses an array, set, push or map, whose prefix is `prefix`, found `depth` aggregates
+/// deep.
+fn parse_aggregate(
+    prefix: char,
+    buffer: &[u8],
+    index: &mut usize,
+    depth: usize,
+) -> RESPResult<RESP> {
+    if depth >= MAX_NESTING_DEPTH {
+        return Err(RESPError::TooDeep);
+    }
+    let length = parse_aggregate_length(prefix, buffer, index)?;
+    let length = if prefix == '%' { length * 2 } else { length };
     let mut data = Vec::new();
 
     for _ in 0..length {
-        match parser_router(buffer, index) {
-            Some(parse_func) => {
-                let array_element = parse_func(buffer, index)?;
-                data.push(array_element);
+        let element = match buffer.get(*index) {
+            None => return Err(RESPError::OutOfBounds(*index)),
+            Some(&nested @ (b'*' | b'~' | b'>' | b'%')) => {
+                parse_aggregate(nested as char, buffer, index, depth + 1)?
             }
-            None => return Err(RESPError::Unknown),
+            Some(_) => match parser_router(buffer, index) {
+                Some(parse_func) => parse_func(buffer, index)?,
+                None => return Err(RESPError::Unknown),
+            },
+        };
+        data.push(element);
+    }
+    Ok(match prefix {
+        '*' => RESP::Array(data),
+        '~' => RESP::Set(data),
+        '>' => RESP::Push(data),
+        _ => {
+            let mut elements = data.into_iter();
+            let mut pairs = Vec::new();
+            while let (Some(key), Some(value)) = (elements.next(), elements.next()) {
+                pairs.push((key, value));
+            }
+            RESP::Map(pairs)
         }
+    })
+}
+
+fn parse_array(buffer: &[u8], index: &mut usize) -> RESPResult<RESP> {
+    parse_aggregate('*', buffer, index, 0)
+}
+
+fn parse_set(buffer: &[u8], index: &mut usize) -> RESPResult<RESP> {
+    parse_aggregate('~', buffer, index, 0)
+}
+
+fn parse_push(buffer: &[u8], index: &mut usize) -> RESPResult<RESP> {
+    parse_aggregate('>', buffer, index, 0)
+}
+
+fn parse_map(buffer: &[u8], index: &mut usize) -> RESPResult<RESP> {
+    parse_aggregate('%', buffer, index, 0)
+}
+
+fn parse_null(buffer: &[u8], index: &mut usize) -> RESPResult<RESP> {
+    resp_remove_type('_', buffer, index)?;
+    let line = binary_extract_line(buffer, index)?;
+    if !line.is_empty() {
+        return Err(RESPError::WrongType);
     }
-    Ok(RESP::Array(data))
+    Ok(RESP::Null)
 }
 
-fn parser_router(
-    buffer: &[u8],
-    index: &mut usize,
-) -> Option<fn(&[u8], &mut usize) -> RESPResult<RESP>> {
+fn parse_double(buffer: &[u8], index: &mut usize) -> RESPResult<RESP> {
+    resp_remove_type(',', buffer, index)?;
+    let line = binary_extract_line_as_string(buffer, index)?;
+    Ok(RESP::Double(line.parse()?))
+}
+
+fn parse_boolean(buffer: &[u8], index: &mut usize) -> RESPResult<RESP> {
+    resp_remove_type('#', buffer, index)?;
+    match binary_extract_line(buffer, index)?.as_slice() {
+        b"t" => Ok(RESP::Boolean(true)),
+        b"f" => Ok(RESP::Boolean(false)),
+        _ => Err(RESPError::ParseBool),
+    }
+}
+
+fn parse_big_number(buffer: &[u8], index: &mut usize) -> RESPResult<RESP> {
+    resp_remove_type('(', buffer, index)?;
+    let line = binary_extract_line_as_string(buffer, index)?;
+    let digits = line.strip_prefix('-').unwrap_or(&line);
+    if digits.is_empty() || !digits.bytes().all(|elem| elem.is_ascii_digit()) {
+        return Err(RESPError::ParseInt);
+    }
+    Ok(RESP::BigNumber(line))
+}
+
+fn parse_verbatim(buffer: &[u8], index: &mut usize) -> RESPResult<RESP> {
+    resp_remove_type('=', buffer, index)?;
+    let length = resp_extract_length(buffer, index)?;
+    if !(4..=MAX_BULK_LENGTH).contains(&length) {
+        return Err(RESPError::IncorrectLength(length));
+    }
+    if *index + length as usize + 2 > buffer.len() {
+        return Err(RESPError::OutOfBounds(buffer.len()));
+    }
+    let mut data = binary_extract_bytes(buffer, index, length as usize)?;
+    if &buffer[*index..*index + 2] != b"\r\n" {
+        return Err(RESPError::MissingSeparator(*index));
+    }
+    *index += 2;
+    if data[3] != b':' {
+        return Err(RESPError::IncorrectLength(length));
+    }
+    let format = String::from_utf8(data.drain(..4).take(3).collect())?;
+    Ok(RESP::Verbatim(format, data))
+}
+
+/// Parses an inline command: space separated arguments terminated by a newline, as typed
+/// into `telnet` or `nc`. Arguments can be quoted following the rules of `redis-cli`.
+fn parse_inline(buffer: &[u8], index: &mut usize) -> RESPResult<RESP> {
+    let end = match buffer[*index..].iter().position(|&elem| elem == b'\n') {
+        Some(position) => *index + position,
+        None if buffer.len() - *index > MAX_INLINE_LENGTH => {
+            return Err(RESPError::InlineTooLong);
+        }
+        None => return Err(RESPError::OutOfBounds(buffer.len())),
+    };
+    let line = &buffer[*index..end];
+    let line = line.strip_suffix(b"\r").unwrap_or(line);
+    let arguments = split_inline_arguments(line)?;
+    *index = end + 1;
+    Ok(RESP::Array(
+        arguments.into_iter().map(RESP::BulkString).collect(),
+    ))
+}
+
+pub fn split_inline_arguments(line: &[u8]) -> RESPResult<Vec<Vec<u8>>> {
+    let mut arguments = Vec::new();
+    let mut idx: usize = 0;
+
+    loop {
+        while idx < line.len() && line[idx].is_ascii_whitespace() {
+            idx += 1;
+        }
+        if idx >= line.len() {
+            return Ok(arguments);
+        }
+
+        let mut current = Vec::new();
+        let mut in_double_quotes = false;
+        let mut in_single_quotes = false;
+        loop {
+            let elem = line.get(idx).copied();
+            let next = line.get(idx + 1).copied();
+            if in_double_quotes {
+                match (elem, next) {
+                    (None, _) => return Err(RESPError::UnbalancedQuotes),
+                    (Some(b'\\'), Some(b'x'))
+                        if idx + 3 < line.len()
+                            && line[idx + 2].is_ascii_hexdigit()
+                            && line[idx + 3].is_ascii_hexdigit() =>
+                    {
+                        let hex = std::str::from_utf8(&line[idx + 2..idx + 4]).unwrap_or("0");
+                        current.push(u8::from_str_radix(hex, 16).unwrap_or(0));
+                        idx += 3;
+                    }
+                    (Some(b'\\'), Some(escaped)) => {
+                        current.push(match escaped {
+                            b'n' => b'\n',
+                            b'r' => b'\r',
+                            b't' => b'\t',
+                            b'b' => 0x08,
+                            b'a' => 0x07,
+                            other => other,
+                        });
+                        idx += 1;
+                    }
+                    (Some(b'"'), next) => {
+                        // The closing quote must be followed by a space or end the line.
+                        if next.is_some_and(|elem| !elem.is_ascii_whitespace()) {
+                            return Err(RESPError::UnbalancedQuotes);
+                        }
+                        idx += 1;
+                        break;
+                    }
+                    (Some(elem), _) => current.push(elem),
+                }
+            } else if in_single_quotes {
+                match (elem, next) {
+                    (None, _) => return Err(RESPError::UnbalancedQuotes),
+                    (Some(b'\\'), Some(b'\'')) => {
+                        current.push(b'\'');
+                        idx += 1;
+                    }
+                    (Some(b'\''), next) => {
+                        if next.is_some_and(|elem| !elem.is_ascii_whitespace()) {
+                            return Err(RESPError::UnbalancedQuotes);
+                        }
+                        idx += 1;
+                        break;
+                    }
+                    (Some(elem), _) => current.push(elem),
+                }
+            } else {
+                match elem {
+                    None => break,
+                    Some(elem) if elem.is_ascii_whitespace() => break,
+                    Some(b'"') => in_double_quotes = true,
+                    Some(b'\'') => in_single_quotes = true,
+                    Some(elem) => current.push(elem),
+                }
+            }
+            idx += 1;
+        }
+        arguments.push(current);
+    }
+}
+
+type ParserFn = fn(&[u8], &mut usize) -> RESPResult<RESP>;
+
+fn parser_router(buffer: &[u8], index: &mut usize) -> Option<ParserFn> {
     match buffer[*index] {
         b'+' => Some(parse_simple_string),
+        b'-' => Some(parse_error),
+        b':' => Some(parse_integer),
         b'$' => Some(parse_bulk_string),
         b'*' => Some(parse_array),
+        b'%' => Some(parse_map),
+        b'~' => Some(parse_set),
+        b'>' => Some(parse_push),
+        b'_' => Some(parse_null),
+        b',' => Some(parse_double),
+        b'#' => Some(parse_boolean),
+        b'(' => Some(parse_big_number),
+        b'=' => Some(parse_verbatim),
         _ => None,
     }
 }
@@ -157,12 +445,41 @@ pub fn bytes_to_resp(buffer: &[u8], index: &mut usize) -> RESPResult<RESP> {
     }
 }
 
+/// Decodes a single request frame from the start of `buffer`.
+///
+/// Requests starting with `*` are parsed as RESP arrays, anything else as an inline
+/// command; blank inline lines are skipped. Returns the frame together with the number of
+/// bytes it occupied, or `None` if the buffer only holds part of a frame and more bytes
+/// must be read before retrying.
+pub fn decode_frame(buffer: &[u8]) -> RESPResult<Option<(RESP, usize)>> {
+    let mut index: usize = 0;
+    loop {
+        if index >= buffer.len() {
+            return Ok(None);
+        }
+        let result = match buffer[index] {
+            b'*' => bytes_to_resp(buffer, &mut index),
+            _ => match parse_inline(buffer, &mut index) {
+                Ok(RESP::Array(arguments)) if arguments.is_empty() => continue,
+                other => other,
+            },
+        };
+        return match result {
+            Ok(frame) => Ok(Some((frame, index))),
+            Err(RESPError::OutOfBounds(_)) => Ok(None),
+            Err(e) => Err(e),
+        };
+    }
+}
+
 #[cfg(test)]
 mod tests {
 
     use super::{
         binary_extract_bytes, binary_extract_line, binary_extract_line_as_string, bytes_to_resp,
-        parse_array, parse_bulk_string, parse_simple_string, resp_remove_type, RESPError, RESP,
+        decode_frame, parse_array, parse_bulk_string, parse_error, parse_inline, parse_integer,
+        parse_simple_string, resp_remove_type, split_inline_arguments, FormattedDouble, RESPError,
+        RESPVersion, MAX_NESTING_DEPTH, RESP,
     };
 
     #[test]
@@ -296,6 +613,15 @@ mod tests {
         assert_eq!(index, 5);
     }
 
+    #[test]
+    fn test_parse_error() {
+        let buffer = "-ERR unknown command\r\n".as_bytes();
+        let mut index: usize = 0;
+        let output = parse_error(buffer, &mut index).unwrap();
+        assert_eq!(output, RESP::Error(String::from("ERR unknown command")));
+        assert_eq!(index, 22);
+    }
+
     #[test]
     fn test_bytes_to_resp_simple_string() {
         let buffer = "+OK\r\n".as_bytes();
@@ -305,6 +631,32 @@ mod tests {
         assert_eq!(index, 5);
     }
 
+    #[test]
+    fn test_parse_integer() {
+        let buffer = ":-42\r\n".as_bytes();
+        let mut index: usize = 0;
+        let output = parse_integer(buffer, &mut index).unwrap();
+        assert_eq!(output, RESP::Integer(-42));
+        assert_eq!(index, 6);
+    }
+
+    #[test]
+    fn test_parse_integer_unparsable() {
+        let buffer = ":4x\r\n".as_bytes();
+        let mut index: usize = 0;
+        let error = parse_integer(buffer, &mut index).unwrap_err();
+        assert_eq!(error, RESPError::ParseInt);
+    }
+
+    #[test]
+    fn test_bytes_to_resp_integer() {
+        let buffer = ":1000\r\n".as_bytes();
+        let mut index: usize = 0;
+        let output = bytes_to_resp(buffer, &mut index).unwrap();
+        assert_eq!(output, RESP::Integer(1000));
+        assert_eq!(index, 7);
+    }
+
     #[test]
     fn test_bytes_to_resp_unknown() {
         let buffer = "?OK\r\n".as_bytes();
@@ -337,10 +689,19 @@ mod tests {
         let buffer = "$2\r\nOK\r\n".as_bytes();
         let mut index: usize = 0;
         let output = parse_bulk_string(buffer, &mut index).unwrap();
-        assert_eq!(output, RESP::BulkString(String::from("OK")));
+        assert_eq!(output, RESP::BulkString(b"OK".to_vec()));
         assert_eq!(index, 8);
     }
 
+    #[test]
+    fn test_parse_bulk_string_binary() {
+        let buffer = b"$4\r\n\xff\x00\r\n\r\n";
+        let mut index: usize = 0;
+        let output = parse_bulk_string(buffer, &mut index).unwrap();
+        assert_eq!(output, RESP::BulkString(vec![0xff, 0x00, b'\r', b'\n']));
+        assert_eq!(index, 10);
+    }
+
     #[test]
     fn test_parse_bulk_string_empty() {
         let buffer = "$-1\r\n".as_bytes();
@@ -393,7 +754,7 @@ mod tests {
         let buffer = "$2\r\nOK\r\n".as_bytes();
         let mut index: usize = 0;
         let output = bytes_to_resp(buffer, &mut index).unwrap();
-        assert_eq!(output, RESP::BulkString(String::from("OK")));
+        assert_eq!(output, RESP::BulkString(b"OK".to_vec()));
         assert_eq!(index, 8);
     }
 
@@ -406,7 +767,7 @@ mod tests {
             output,
             RESP::Array(vec![
                 RESP::SimpleString(String::from("OK")),
-                RESP::BulkString(String::from("VALUE"))
+                RESP::BulkString(b"VALUE".to_vec())
             ])
         );
         assert_eq!(index, 20);
@@ -430,9 +791,342 @@ mod tests {
             output,
             RESP::Array(vec![
                 RESP::SimpleString(String::from("OK")),
-                RESP::BulkString(String::from("VALUE"))
+                RESP::BulkString(b"VALUE".to_vec())
             ])
         );
         assert_eq!(index, 20);
     }
+
+    #[test]
+    fn test_to_bytes_bulk_string_binary() {
+        let output = RESP::BulkString(vec![0xff, 0x00]);
+        assert_eq!(
+            output.to_bytes(RESPVersion::RESP2),
+            b"$2\r\n\xff\x00\r\n".to_vec()
+        );
+    }
+
+    #[test]
+    fn test_to_bytes_array() {
+        let output = RESP::Array(vec![RESP::Integer(1), RESP::BulkString(b"OK".to_vec())]);
+        assert_eq!(
+            output.to_bytes(RESPVersion::RESP2),
+            b"*2\r\n:1\r\n$2\r\nOK\r\n".to_vec()
+        );
+    }
+
+    #[test]
+    fn test_display_integer() {
+        assert_eq!(RESP::Integer(1).to_string(), ":1\r\n");
+        assert_eq!(RESP::Integer(-2).to_string(), ":-2\r\n");
+    }
+
+    #[test]
+    fn test_display_error() {
+        let output = RESP::Error(String::from("ERR unknown command 'foo'"));
+        assert_eq!(output.to_string(), "-ERR unknown command 'foo'\r\n");
+    }
+
+    #[test]
+    fn test_binary_extract_line_empty_line() {
+        let buffer = "\r\n".as_bytes();
+        let mut index: usize = 0;
+        let output = binary_extract_line(buffer, &mut index).unwrap();
+        assert_eq!(output, Vec::<u8>::new());
+        assert_eq!(index, 2);
+    }
+
+    #[test]
+    fn test_parse_bulk_string_missing_separator() {
+        let buffer = "$2\r\nOKxx".as_bytes();
+        let mut index: usize = 0;
+        let error = parse_bulk_string(buffer, &mut index).unwrap_err();
+        assert_eq!(error, RESPError::MissingSeparator(6));
+    }
+
+    #[test]
+    fn test_parse_bulk_string_too_long() {
+        let buffer = "$536870913\r\n".as_bytes();
+        let mut index: usize = 0;
+        let error = parse_bulk_string(buffer, &mut index).unwrap_err();
+        assert_eq!(error, RESPError::IncorrectLength(536870913));
+    }
+
+    #[test]
+    fn test_decode_frame() {
+        let buffer = "*1\r\n$4\r\nPING\r\n+extra".as_bytes();
+        let (frame, size) = decode_frame(buffer).unwrap().unwrap();
+        assert_eq!(frame, RESP::Array(vec![RESP::BulkString(b"PING".to_vec())]));
+        assert_eq!(size, 14);
+    }
+
+    #[test]
+    fn test_decode_frame_incomplete() {
+        let buffer = "*2\r\n$3\r\nGET\r\n$3\r\nke".as_bytes();
+        for end in 0..buffer.len() {
+            assert_eq!(decode_frame(&buffer[..end]).unwrap(), None);
+        }
+    }
+
+    #[test]
+    fn test_decode_frame_protocol_error() {
+        let buffer = "*1\r\n$x\r\n".as_bytes();
+        let error = decode_frame(buffer).unwrap_err();
+        assert_eq!(error, RESPError::ParseInt);
+    }
+
+    #[test]
+    fn test_decode_frame_too_deep() {
+        let buffer = "*1\r\n".repeat(1_000_000);
+        let error = decode_frame(buffer.as_bytes()).unwrap_err();
+        assert_eq!(error, RESPError::TooDeep);
+        let nested = format!("{}:1\r\n", "*1\r\n".repeat(MAX_NESTING_DEPTH));
+        assert!(decode_frame(nested.as_bytes()).unwrap().is_some());
+    }
+
+    fn inline_arguments(line: &str) -> Vec<Vec<u8>> {
+        split_inline_arguments(line.as_bytes()).unwrap()
+    }
+
+    #[test]
+    fn test_parse_inline() {
+        let buffer = "SET foo  bar\r\n".as_bytes();
+        let mut index: usize = 0;
+        let output = parse_inline(buffer, &mut index).unwrap();
+        assert_eq!(
+            output,
+            RESP::Array(vec![
+                RESP::BulkString(b"SET".to_vec()),
+                RESP::BulkString(b"foo".to_vec()),
+                RESP::BulkString(b"bar".to_vec()),
+            ])
+        );
+        assert_eq!(index, 14);
+    }
+
+    #[test]
+    fn test_parse_inline_bare_newline() {
+        let buffer = "PING\n".as_bytes();
+        let mut index: usize = 0;
+        let output = parse_inline(buffer, &mut index).unwrap();
+        assert_eq!(
+            output,
+            RESP::Array(vec![RESP::BulkString(b"PING".to_vec())])
+        );
+        assert_eq!(index, 5);
+    }
+
+    #[test]
+    fn test_parse_inline_incomplete() {
+        let buffer = "SET foo".as_bytes();
+        let mut index: usize = 0;
+        let error = parse_inline(buffer, &mut index).unwrap_err();
+        assert_eq!(error, RESPError::OutOfBounds(7));
+        assert_eq!(index, 0);
+    }
+
+    #[test]
+    fn test_parse_inline_too_long() {
+        let buffer = vec![b'a'; 64 * 1024 + 1];
+        let mut index: usize = 0;
+        let error = parse_inline(&buffer, &mut index).unwrap_err();
+        assert_eq!(error, RESPError::InlineTooLong);
+    }
+
+    #[test]
+    fn test_split_inline_arguments_double_quotes() {
+        assert_eq!(
+            inline_arguments(r#"SET "hello world" "a\"b\n" "\x41\x7a""#),
+            vec![
+                b"SET".to_vec(),
+                b"hello world".to_vec(),
+                b"a\"b\n".to_vec(),
+                b"Az".to_vec()
+            ]
+        );
+    }
+
+    #[test]
+    fn test_split_inline_arguments_single_quotes() {
+        assert_eq!(
+            inline_arguments(r"SET 'it\'s' 'a\nb'"),
+            vec![b"SET".to_vec(), b"it's".to_vec(), b"a\\nb".to_vec()]
+        );
+    }
+
+    #[test]
+    fn test_split_inline_arguments_empty_quotes() {
+        assert_eq!(
+            inline_arguments(r#"SET key """#),
+            vec![b"SET".to_vec(), b"key".to_vec(), b"".to_vec()]
+        );
+    }
+
+    #[test]
+    fn test_split_inline_arguments_unbalanced_quotes() {
+        let error = split_inline_arguments(br#"SET "foo"#).unwrap_err();
+        assert_eq!(error, RESPError::UnbalancedQuotes);
+        let error = split_inline_arguments(br#"SET "foo"bar"#).unwrap_err();
+        assert_eq!(error, RESPError::UnbalancedQuotes);
+        let error = split_inline_arguments(b"SET 'foo").unwrap_err();
+        assert_eq!(error, RESPError::UnbalancedQuotes);
+    }
+
+    #[test]
+    fn test_decode_frame_inline() {
+        let buffer = "\r\n\r\nPING\r\n*1\r\n$4\r\nPING\r\n".as_bytes();
+        let (frame, size) = decode_frame(buffer).unwrap().unwrap();
+        assert_eq!(frame, RESP::Array(vec![RESP::BulkString(b"PING".to_vec())]));
+        assert_eq!(size, 10);
+    }
+
+    #[test]
+    fn test_decode_frame_blank_lines() {
+        let buffer = "\r\n  \r\n".as_bytes();
+        assert_eq!(decode_frame(buffer).unwrap(), None);
+    }
+
+    fn parse(buffer: &str) -> RESP {
+        let mut index: usize = 0;
+        let output = bytes_to_resp(buffer.as_bytes(), &mut index).unwrap();
+        assert_eq!(index, buffer.len());
+        output
+    }
+
+    #[test]
+    fn test_bytes_to_resp_resp3_scalars() {
+        assert_eq!(parse("_\r\n"), RESP::Null);
+        assert_eq!(parse(",1.5\r\n"), RESP::Double(1.5));
+        assert_eq!(parse(",-inf\r\n"), RESP::Double(f64::NEG_INFINITY));
+        assert_eq!(parse("#t\r\n"), RESP::Boolean(true));
+        assert_eq!(parse("#f\r\n"), RESP::Boolean(false));
+        assert_eq!(
+            parse("(3492890328409238509324850943850943825024385\r\n"),
+            RESP::BigNumber(String::from("3492890328409238509324850943850943825024385"))
+        );
+        assert_eq!(
+            parse("=15\r\ntxt:Some string\r\n"),
+            RESP::Verbatim(String::from("txt"), b"Some string".to_vec())
+        );
+    }
+
+    #[test]
+    fn test_bytes_to_resp_resp3_aggregates() {
+        assert_eq!(
+            parse("%2\r\n+first\r\n:1\r\n+second\r\n#f\r\n"),
+            RESP::Map(vec![
+                (RESP::SimpleString(String::from("first")), RESP::Integer(1)),
+                (
+                    RESP::SimpleString(String::from("second")),
+                    RESP::Boolean(false)
+                ),
+            ])
+        );
+        assert_eq!(
+            parse("~2\r\n:1\r\n:2\r\n"),
+            RESP::Set(vec![RESP::Integer(1), RESP::Integer(2)])
+        );
+        assert_eq!(
+            parse(">2\r\n+message\r\n$2\r\nhi\r\n"),
+            RESP::Push(vec![
+                RESP::SimpleString(String::from("message")),
+                RESP::BulkString(b"hi".to_vec())
+            ])
+        );
+    }
+
+    #[test]
+    fn test_bytes_to_resp_resp3_invalid() {
+        let mut index: usize = 0;
+        let error = bytes_to_resp(b"#x\r\n", &mut index).unwrap_err();
+        assert_eq!(error, RESPError::ParseBool);
+        let mut index: usize = 0;
+        let error = bytes_to_resp(b",abc\r\n", &mut index).unwrap_err();
+        assert_eq!(error, RESPError::ParseFloat);
+        let mut index: usize = 0;
+        let error = bytes_to_resp(b"(12a\r\n", &mut index).unwrap_err();
+        assert_eq!(error, RESPError::ParseInt);
+        let mut index: usize = 0;
+        let error = bytes_to_resp(b"=5\r\ntxt-a\r\n", &mut index).unwrap_err();
+        assert_eq!(error, RESPError::IncorrectLength(5));
+    }
+
+    #[test]
+    fn test_formatted_double() {
+        assert_eq!(FormattedDouble(1.0).to_string(), "1");
+        assert_eq!(FormattedDouble(-2.5).to_string(), "-2.5");
+        assert_eq!(FormattedDouble(0.0).to_string(), "0");
+        assert_eq!(FormattedDouble(1e20).to_string(), "1e+20");
+        assert_eq!(FormattedDouble(-1.5e300).to_string(), "-1.5e+300");
+        assert_eq!(FormattedDouble(0.0001).to_string(), "0.0001");
+        assert_eq!(FormattedDouble(0.00001).to_string(), "1e-05");
+        assert_eq!(FormattedDouble(f64::INFINITY).to_string(), "inf");
+        assert_eq!(FormattedDouble(f64::NAN).to_string(), "nan");
+    }
+
+    #[test]
+    fn test_to_bytes_resp3() {
+        let map = RESP::Map(vec![(
+            RESP::BulkString(b"proto".to_vec()),
+            RESP::Integer(3),
+        )]);
+        assert_eq!(
+            map.to_bytes(RESPVersion::RESP3),
+            b"%1\r\n$5\r\nproto\r\n:3\r\n".to_vec()
+        );
+        assert_eq!(RESP::Null.to_bytes(RESPVersion::RESP3), b"_\r\n".to_vec());
+        assert_eq!(
+            RESP::Double(1.5).to_bytes(RESPVersion::RESP3),
+            b",1.5\r\n".to_vec()
+        );
+        assert_eq!(
+            RESP::Boolean(true).to_bytes(RESPVersion::RESP3),
+            b"#t\r\n".to_vec()
+        );
+        assert_eq!(
+            RESP::Verbatim(String::from("txt"), b"hi".to_vec()).to_bytes(RESPVersion::RESP3),
+            b"=6\r\ntxt:hi\r\n".to_vec()
+        );
+        assert_eq!(
+            RESP::Set(vec![RESP::Integer(1)]).to_bytes(RESPVersion::RESP3),
+            b"~1\r\n:1\r\n".to_vec()
+        );
+        assert_eq!(
+            RESP::Push(vec![RESP::Integer(1)]).to_bytes(RESPVersion::RESP3),
+            b">1\r\n:1\r\n".to_vec()
+        );
+    }
+
+    #[test]
+    fn test_to_bytes_resp3_downgraded_to_resp2() {
+        let map = RESP::Map(vec![(
+            RESP::BulkString(b"proto".to_vec()),
+            RESP::Integer(2),
+        )]);
+        assert_eq!(
+            map.to_bytes(RESPVersion::RESP2),
+            b"*2\r\n$5\r\nproto\r\n:2\r\n".to_vec()
+        );
+        assert_eq!(RESP::Null.to_bytes(RESPVersion::RESP2), b"$-1\r\n".to_vec());
+        assert_eq!(
+            RESP::Double(1.5).to_bytes(RESPVersion::RESP2),
+            b"$3\r\n1.5\r\n".to_vec()
+        );
+        assert_eq!(
+            RESP::Boolean(true).to_bytes(RESPVersion::RESP2),
+            b":1\r\n".to_vec()
+        );
+        assert_eq!(
+            RESP::BigNumber(String::from("12")).to_bytes(RESPVersion::RESP2),
+            b"$2\r\n12\r\n".to_vec()
+        );
+        assert_eq!(
+            RESP::Verbatim(String::from("txt"), b"hi".to_vec()).to_bytes(RESPVersion::RESP2),
+            b"$2\r\nhi\r\n".to_vec()
+        );
+        assert_eq!(
+            RESP::Set(vec![RESP::Integer(1)]).to_bytes(RESPVersion::RESP2),
+            b"*1\r\n:1\r\n".to_vec()
+        );
+    }
 }
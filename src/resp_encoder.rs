@@ -0,0 +1,178 @@
+use crate::resp::{FormattedDouble, RESPVersion, RESP};
+use std::fmt;
+use std::io::{self, Write};
+
+/// Appends the wire representation of `frame` to `output` for the given protocol version.
+///
+/// Everything is written straight into `output`, so a connection can keep reusing the same
+/// buffer for all of its replies. Under RESP2 the RESP3-only types are downgraded the way
+/// Redis does it: maps are flattened into arrays, sets and pushes become arrays, doubles,
+/// big numbers and verbatim strings become bulk strings and booleans become integers.
+pub fn encode(frame: &RESP, output: &mut Vec<u8>, version: RESPVersion) {
+    let resp3 = version == RESPVersion::RESP3;
+    match frame {
+        RESP::Null if resp3 => output.extend_from_slice(b"_\r\n"),
+        RESP::Null => output.extend_from_slice(b"$-1\r\n"),
+        RESP::SimpleString(data) => encode_line(output, b'+', data),
+        RESP::Error(data) => encode_line(output, b'-', data),
+        RESP::Integer(data) => encode_line(output, b':', data),
+        RESP::BulkString(data) => encode_bulk(output, b'$', data),
+        RESP::Array(data) => encode_aggregate(output, b'*', data, version),
+        RESP::Set(data) => encode_aggregate(output, if resp3 { b'~' } else { b'*' }, data, version),
+        RESP::Push(data) => {
+            encode_aggregate(output, if resp3 { b'>' } else { b'*' }, data, version)
+        }
+        RESP::Map(data) => {
+            if resp3 {
+                encode_line(output, b'%', data.len());
+            } else {
+                encode_line(output, b'*', data.len() * 2);
+            }
+            for (key, value) in data.iter() {
+                encode(key, output, version);
+                encode(value, output, version);
+            }
+        }
+        RESP::Double(data) if resp3 => encode_line(output, b',', FormattedDouble(*data)),
+        RESP::Double(data) => {
+            // The bulk length is only known once the digits are formatted, which happens on
+            // the stack; formatted doubles are far shorter than this buffer.
+            let mut digits = [0u8; 64];
+            let mut cursor = io::Cursor::new(&mut digits[..]);
+            let _ = write!(cursor, "{}", FormattedDouble(*data));
+            let length = cursor.position() as usize;
+            encode_bulk(output, b'$', &digits[..length]);
+        }
+        RESP::Boolean(data) if resp3 => encode_line(output, b'#', if *data { 't' } else { 'f' }),
+        RESP::Boolean(data) => encode_line(output, b':', *data as i64),
+        RESP::BigNumber(data) if resp3 => encode_line(output, b'(', data),
+        RESP::BigNumber(data) => encode_bulk(output, b'$', data.as_bytes()),
+        RESP::Verbatim(format, data) if resp3 => {
+            encode_line(output, b'=', data.len() + 4);
+            output.extend_from_slice(format.as_bytes());
+            output.push(b':');
+            output.extend_from_slice(data);
+            output.extend_from_slice(b"\r\n");
+        }
+        RESP::Verbatim(_, data) => encode_bulk(output, b'$', data),
+    }
+}
+
+fn encode_line(output: &mut Vec<u8>, prefix: u8, line: impl fmt::Display) {
+    output.push(prefix);
+    // Writing into a `Vec` cannot fail.
+    let _ = write!(output, "{}", line);
+    output.extend_from_slice(b"\r\n");
+}
+
+fn encode_bulk(output: &mut Vec<u8>, prefix: u8, data: &[u8]) {
+    encode_line(output, prefix, data.len());
+    output.extend_from_slice(data);
+    output.extend_from_slice(b"\r\n");
+}
+
+fn encode_aggregate(output: &mut Vec<u8>, prefix: u8, data: &[RESP], version: RESPVersion) {
+    encode_line(output, prefix, data.len());
+    for elem in data.iter() {
+        encode(elem, output, version);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resp::bytes_to_resp;
+
+    fn roundtrip(frame: RESP, version: RESPVersion) {
+        let mut output = Vec::new();
+        encode(&frame, &mut output, version);
+        let mut index: usize = 0;
+        let parsed = bytes_to_resp(&output, &mut index).unwrap();
+        assert_eq!(parsed, frame);
+        assert_eq!(index, output.len());
+    }
+
+    #[test]
+    fn test_encode_nested_array() {
+        let frame = RESP::Array(vec![
+            RESP::Array(vec![RESP::Integer(1), RESP::BulkString(b"a".to_vec())]),
+            RESP::Array(vec![]),
+            RESP::Null,
+        ]);
+        let mut output = Vec::new();
+        encode(&frame, &mut output, RESPVersion::RESP2);
+        assert_eq!(
+            output,
+            b"*3\r\n*2\r\n:1\r\n$1\r\na\r\n*0\r\n$-1\r\n".to_vec()
+        );
+    }
+
+    #[test]
+    fn test_encode_appends_to_buffer() {
+        let mut output = b"+OK\r\n".to_vec();
+        encode(&RESP::Integer(7), &mut output, RESPVersion::RESP2);
+        assert_eq!(output, b"+OK\r\n:7\r\n".to_vec());
+    }
+
+    #[test]
+    fn test_encode_double_resp2() {
+        let mut output = b":1\r\n".to_vec();
+        encode(&RESP::Double(-10.25), &mut output, RESPVersion::RESP2);
+        assert_eq!(output, b":1\r\n$6\r\n-10.25\r\n".to_vec());
+    }
+
+    #[test]
+    fn test_roundtrip_resp2() {
+        roundtrip(RESP::Null, RESPVersion::RESP2);
+        roundtrip(RESP::SimpleString(String::from("OK")), RESPVersion::RESP2);
+        roundtrip(RESP::Error(String::from("ERR oops")), RESPVersion::RESP2);
+        roundtrip(RESP::Integer(i64::MIN), RESPVersion::RESP2);
+        roundtrip(RESP::BulkString(vec![0, 0xff, b'\r']), RESPVersion::RESP2);
+        roundtrip(RESP::BulkString(vec![]), RESPVersion::RESP2);
+        roundtrip(
+            RESP::Array(vec![
+                RESP::Array(vec![RESP::Array(vec![RESP::Integer(3)])]),
+                RESP::SimpleString(String::from("x")),
+                RESP::Error(String::from("ERR y")),
+            ]),
+            RESPVersion::RESP2,
+        );
+    }
+
+    #[test]
+    fn test_roundtrip_resp3() {
+        roundtrip(RESP::Null, RESPVersion::RESP3);
+        roundtrip(RESP::Double(1.5), RESPVersion::RESP3);
+        roundtrip(RESP::Double(1e300), RESPVersion::RESP3);
+        roundtrip(RESP::Double(f64::NEG_INFINITY), RESPVersion::RESP3);
+        roundtrip(RESP::Boolean(false), RESPVersion::RESP3);
+        roundtrip(RESP::BigNumber(String::from("-123")), RESPVersion::RESP3);
+        roundtrip(
+            RESP::Verbatim(String::from("txt"), b"hello".to_vec()),
+            RESPVersion::RESP3,
+        );
+        roundtrip(
+            RESP::Map(vec![(
+                RESP::BulkString(b"k".to_vec()),
+                RESP::Set(vec![RESP::Push(vec![RESP::Integer(1)])]),
+            )]),
+            RESPVersion::RESP3,
+        );
+    }
+
+    #[test]
+    fn test_roundtrip_downgraded_map() {
+        let frame = RESP::Map(vec![(RESP::BulkString(b"k".to_vec()), RESP::Double(2.0))]);
+        let mut output = Vec::new();
+        encode(&frame, &mut output, RESPVersion::RESP2);
+        let mut index: usize = 0;
+        let parsed = bytes_to_resp(&output, &mut index).unwrap();
+        assert_eq!(
+            parsed,
+            RESP::Array(vec![
+                RESP::BulkString(b"k".to_vec()),
+                RESP::BulkString(b"2".to_vec()),
+            ])
+        );
+    }
+}
@@ -1,3 +1,4 @@
+use crate::resp::RESP;
 use std::fmt;
 use std::num;
 use std::string::FromUtf8Error;
@@ -6,8 +7,14 @@ use std::string::FromUtf8Error;
 pub enum RESPError {
     FromUtf8,
     OutOfBounds(usize),
+    MissingSeparator(usize),
+    UnbalancedQuotes,
+    InlineTooLong,
+    TooDeep,
     IncorrectLength(RESPLength),
     ParseInt,
+    ParseFloat,
+    ParseBool,
     WrongType,
     Unknown,
 }
@@ -18,7 +25,13 @@ impl fmt::Display for RESPError {
             RESPError::FromUtf8 => write!(f, "Cannot convert from UTF-8"),
             RESPError::IncorrectLength(length) => write!(f, "Incorrect Length: {}", length),
             RESPError::OutOfBounds(index) => write!(f, "Index out of bounds at index {}", index),
+            RESPError::MissingSeparator(index) => write!(f, "Missing separator at index {}", index),
+            RESPError::UnbalancedQuotes => write!(f, "Unbalanced quotes in inline command"),
+            RESPError::InlineTooLong => write!(f, "Inline command exceeds the maximum length"),
+            RESPError::TooDeep => write!(f, "Aggregates nested too deeply"),
             RESPError::ParseInt => write!(f, "Cannot parse string into integer"),
+            RESPError::ParseFloat => write!(f, "Cannot parse string into double"),
+            RESPError::ParseBool => write!(f, "Cannot parse string into boolean"),
             RESPError::WrongType => write!(f, "Wrong Prefix for RESP type"),
             RESPError::Unknown => write!(f, "Unknown Format for RESP String"),
         }
@@ -37,6 +50,32 @@ impl From<num::ParseIntError> for RESPError {
     }
 }
 
+impl From<num::ParseFloatError> for RESPError {
+    fn from(_err: num::ParseFloatError) -> Self {
+        Self::ParseFloat
+    }
+}
+
+impl From<RESPError> for RESP {
+    fn from(err: RESPError) -> Self {
+        let reason = match err {
+            RESPError::FromUtf8 => String::from("invalid UTF-8 in request"),
+            RESPError::OutOfBounds(_) => String::from("unexpected end of request"),
+            RESPError::MissingSeparator(_) => String::from("expected CRLF after bulk data"),
+            RESPError::UnbalancedQuotes => String::from("unbalanced quotes in request"),
+            RESPError::InlineTooLong => String::from("too big inline request"),
+            RESPError::TooDeep => String::from("too deeply nested request"),
+            RESPError::IncorrectLength(length) => format!("invalid length {}", length),
+            RESPError::ParseInt => String::from("invalid length"),
+            RESPError::ParseFloat => String::from("invalid double"),
+            RESPError::ParseBool => String::from("invalid boolean"),
+            RESPError::WrongType => String::from("unexpected type prefix"),
+            RESPError::Unknown => String::from("unknown type prefix"),
+        };
+        RESP::Error(format!("ERR Protocol error: {}", reason))
+    }
+}
+
 pub type RESPResult<T> = Result<T, RESPError>;
 
 pub type RESPLength = i32;
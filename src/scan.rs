@@ -0,0 +1,450 @@
+use crate::random::{random_below, random_u64};
+use crate::storage_result::{command_to_string, StorageError, StorageResult};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::hash::{DefaultHasher, Hasher};
+use std::ops::Deref;
+use std::sync::Arc;
+
+#[derive(Debug, PartialEq)]
+pub struct ScanArgs {
+    pub pattern: Option<Vec<u8>>,
+    pub count: usize,
+    pub type_name: Option<Vec<u8>>,
+}
+
+impl ScanArgs {
+    pub fn new() -> Self {
+        ScanArgs {
+            pattern: None,
+            count: 10,
+            type_name: None,
+        }
+    }
+}
+
+/// Parses a SCAN cursor, which is an unsigned 64 bit integer.
+pub fn parse_cursor(argument: &[u8]) -> StorageResult<u64> {
+    std::str::from_utf8(argument)
+        .ok()
+        .and_then(|argument| argument.parse().ok())
+        .ok_or_else(|| StorageError::InvalidArgument(String::from("invalid cursor")))
+}
+
+/// Parses the options following the cursor. `TYPE` is only accepted when `allow_type`
+/// is set, as the per-type variants (HSCAN, SSCAN, ZSCAN) do not support it.
+pub fn parse_scan_arguments(arguments: &[Vec<u8>], allow_type: bool) -> StorageResult<ScanArgs> {
+    let mut args = ScanArgs::new();
+    let mut idx: usize = 0;
+
+    while idx < arguments.len() {
+        if idx + 1 >= arguments.len() {
+            return Err(StorageError::CommandSyntaxError(command_to_string(
+                arguments,
+            )));
+        }
+        let value = &arguments[idx + 1];
+        match arguments[idx].to_ascii_lowercase().as_slice() {
+            b"match" => args.pattern = Some(value.clone()),
+            b"count" => {
+                let count: i64 = std::str::from_utf8(value)
+                    .ok()
+                    .and_then(|value| value.parse().ok())
+                    .ok_or(StorageError::NotAnInteger)?;
+                if count < 1 {
+                    return Err(StorageError::CommandSyntaxError(command_to_string(
+                        arguments,
+                    )));
+                }
+                args.count = count as usize;
+            }
+            b"type" if allow_type => args.type_name = Some(value.to_ascii_lowercase()),
+            _ => {
+                return Err(StorageError::CommandSyntaxError(command_to_string(
+                    arguments,
+                )));
+            }
+        }
+        idx += 2;
+    }
+    Ok(args)
+}
+
+/// Position of `item` in the scan order, never 0 so that cursor 0 can mean "start" and
+/// "done". The hasher has fixed keys, so an item keeps its position for the lifetime of
+/// the process no matter how the map holding it grows or rehashes.
+pub fn scan_hash(item: &[u8]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    hasher.write(item);
+    (hasher.finish() >> 1) + 1
+}
+
+/// Most picks a negative count makes the random element commands return. Replies are
+/// built in memory, so a count like -9223372036854775807 must not be taken literally.
+const MAX_REPEATED_PICKS: usize = 1024 * 1024;
+
+/// The items of a collection ordered by `scan_hash`, kept next to it so that a scan
+/// resumes from its cursor, and a random item is found, in O(log n) instead of walking
+/// the whole collection. Items are shared with the collection rather than copied, and
+/// sorting those with equal hashes by their bytes keeps them apart.
+#[derive(Debug, Clone, Default)]
+struct ScanOrder(BTreeSet<(u64, Arc<[u8]>)>);
+
+impl ScanOrder {
+    fn insert(&mut self, item: Arc<[u8]>) {
+        self.0.insert((scan_hash(&item), item));
+    }
+
+    fn remove(&mut self, item: Arc<[u8]>) {
+        self.0.remove(&(scan_hash(&item), item));
+    }
+
+    /// The items from position `cursor` onwards.
+    fn starting_at(&self, cursor: u64) -> impl Iterator<Item = &(u64, Arc<[u8]>)> {
+        self.0.range((cursor, Arc::from([].as_slice()))..)
+    }
+
+    /// Picks the next batch of a scan: at least `count` items, in hash order from
+    /// `cursor`, never splitting items with equal hashes between two calls. Every item
+    /// present for the whole scan is therefore returned, however the collection changes
+    /// in between. Returns the next cursor, 0 once nothing is left.
+    fn batch(&self, cursor: u64, count: usize) -> (u64, Vec<&Arc<[u8]>>) {
+        let mut batch = Vec::new();
+        let mut items = self.starting_at(cursor).peekable();
+        while let Some((hash, item)) = items.next() {
+            batch.push(item);
+            if batch.len() >= count && items.peek().is_none_or(|(next, _)| next != hash) {
+                break;
+            }
+        }
+        let next = items.next().map_or(0, |&(hash, _)| hash);
+        (next, batch)
+    }
+
+    /// Picks the first item at or after a random position, wrapping around. Like the
+    /// random picks of Redis this is not exactly uniform: items following a wide gap in
+    /// the order come up more often.
+    fn random(&self) -> Option<&Arc<[u8]>> {
+        let position = (random_u64() >> 1) + 1;
+        let (_, item) = self
+            .starting_at(position)
+            .next()
+            .or_else(|| self.0.first())?;
+        Some(item)
+    }
+
+    /// Picks items out of the `length` held, for the commands returning random elements:
+    /// a positive `count` picks up to that many distinct ones, a negative one picks
+    /// `-count` that may repeat, at most `MAX_REPEATED_PICKS`.
+    fn random_picks(&self, length: usize, count: i64) -> Vec<&Arc<[u8]>> {
+        if count < 0 {
+            let count = count.unsigned_abs().min(MAX_REPEATED_PICKS as u64);
+            return (0..count).filter_map(|_| self.random()).collect();
+        }
+        let count = count as usize;
+        let items = self.0.iter().map(|(_, item)| item);
+        if count >= length {
+            return items.collect();
+        }
+        if count * 3 > length {
+            // Most of the items are wanted, so make a single pass keeping each with the
+            // odds that leave exactly `count` picked at the end.
+            let mut picked = Vec::with_capacity(count);
+            for (seen, item) in items.enumerate() {
+                if random_below(length - seen) < count - picked.len() {
+                    picked.push(item);
+                }
+            }
+            return picked;
+        }
+        let mut picked = HashSet::with_capacity(count);
+        while picked.len() < count {
+            picked.extend(self.random());
+        }
+        picked.into_iter().collect()
+    }
+}
+
+/// A `HashMap` keyed by bytes that SCAN-style commands can iterate with a cursor. Reads
+/// go through `Deref`; writes go through the methods here, which keep the scan order in
+/// sync with the map. Keys are shared with the scan order, hence `Arc<[u8]>`.
+#[derive(Debug, Clone)]
+pub struct ScanMap<V> {
+    map: HashMap<Arc<[u8]>, V>,
+    order: ScanOrder,
+}
+
+impl<V> Default for ScanMap<V> {
+    fn default() -> Self {
+        ScanMap {
+            map: HashMap::new(),
+            order: ScanOrder::default(),
+        }
+    }
+}
+
+impl<V: PartialEq> PartialEq for ScanMap<V> {
+    fn eq(&self, other: &Self) -> bool {
+        self.map == other.map
+    }
+}
+
+impl<V> Deref for ScanMap<V> {
+    type Target = HashMap<Arc<[u8]>, V>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.map
+    }
+}
+
+impl<V> ScanMap<V> {
+    pub fn get(&self, key: &[u8]) -> Option<&V> {
+        self.map.get(key)
+    }
+
+    pub fn contains_key(&self, key: &[u8]) -> bool {
+        self.map.contains_key(key)
+    }
+
+    /// Sets the value of `key`, which may already be shared, returning the previous one.
+    pub fn insert<K>(&mut self, key: K, value: V) -> Option<V>
+    where
+        K: AsRef<[u8]> + Into<Arc<[u8]>>,
+    {
+        if let Some(current) = self.map.get_mut(key.as_ref()) {
+            return Some(std::mem::replace(current, value));
+        }
+        let key: Arc<[u8]> = key.into();
+        self.order.insert(key.clone());
+        self.map.insert(key, value)
+    }
+
+    pub fn remove(&mut self, key: &[u8]) -> Option<V> {
+        let (key, value) = self.map.remove_entry(key)?;
+        self.order.remove(key);
+        Some(value)
+    }
+
+    pub fn get_mut(&mut self, key: &[u8]) -> Option<&mut V> {
+        self.map.get_mut(key)
+    }
+
+    /// Returns a random entry, or `None` if the map is empty.
+    pub fn random(&self) -> Option<(&Arc<[u8]>, &V)> {
+        self.order
+            .random()
+            .and_then(|key| self.map.get_key_value(key))
+    }
+
+    /// Returns random entries, picked as described at `ScanOrder::random_picks`.
+    pub fn random_picks(&self, count: i64) -> Vec<(&Arc<[u8]>, &V)> {
+        self.order
+            .random_picks(self.map.len(), count)
+            .into_iter()
+            .filter_map(|key| self.map.get_key_value(key))
+            .collect()
+    }
+
+    /// Returns the next cursor and the entries of the batch starting at `cursor`.
+    pub fn scan(&self, cursor: u64, count: usize) -> (u64, Vec<(&[u8], &V)>) {
+        let (next, batch) = self.order.batch(cursor, count);
+        let entries = batch
+            .into_iter()
+            .filter_map(|key| Some((&key[..], self.map.get(key)?)))
+            .collect();
+        (next, entries)
+    }
+}
+
+/// The `HashSet` counterpart of `ScanMap`.
+#[derive(Debug, Clone, Default)]
+pub struct ScanSet {
+    set: HashSet<Arc<[u8]>>,
+    order: ScanOrder,
+}
+
+impl PartialEq for ScanSet {
+    fn eq(&self, other: &Self) -> bool {
+        self.set == other.set
+    }
+}
+
+impl Deref for ScanSet {
+    type Target = HashSet<Arc<[u8]>>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.set
+    }
+}
+
+impl FromIterator<Vec<u8>> for ScanSet {
+    fn from_iter<I: IntoIterator<Item = Vec<u8>>>(items: I) -> Self {
+        let mut set = ScanSet::default();
+        for item in items {
+            set.insert(item);
+        }
+        set
+    }
+}
+
+impl ScanSet {
+    pub fn contains(&self, item: &[u8]) -> bool {
+        self.set.contains(item)
+    }
+
+    pub fn insert(&mut self, item: Vec<u8>) -> bool {
+        if self.contains(&item) {
+            return false;
+        }
+        let item: Arc<[u8]> = item.into();
+        self.order.insert(item.clone());
+        self.set.insert(item)
+    }
+
+    pub fn remove(&mut self, item: &[u8]) -> bool {
+        match self.set.take(item) {
+            Some(item) => {
+                self.order.remove(item);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Returns a random member, or `None` if the set is empty.
+    pub fn random(&self) -> Option<&Arc<[u8]>> {
+        self.order.random()
+    }
+
+    /// Returns random members, picked as described at `ScanOrder::random_picks`.
+    pub fn random_picks(&self, count: i64) -> Vec<&Arc<[u8]>> {
+        self.order.random_picks(self.set.len(), count)
+    }
+
+    /// Returns the next cursor and the members of the batch starting at `cursor`.
+    pub fn scan(&self, cursor: u64, count: usize) -> (u64, Vec<&Arc<[u8]>>) {
+        self.order.batch(cursor, count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_scan_arguments() {
+        let arguments = vec![
+            b"MATCH".to_vec(),
+            b"user:*".to_vec(),
+            b"count".to_vec(),
+            b"100".to_vec(),
+            b"TYPE".to_vec(),
+            b"String".to_vec(),
+        ];
+        assert_eq!(
+            parse_scan_arguments(&arguments, true).unwrap(),
+            ScanArgs {
+                pattern: Some(b"user:*".to_vec()),
+                count: 100,
+                type_name: Some(b"string".to_vec()),
+            }
+        );
+        assert!(parse_scan_arguments(&arguments, false).is_err());
+    }
+
+    #[test]
+    fn test_parse_scan_arguments_invalid() {
+        let arguments = vec![b"COUNT".to_vec(), b"0".to_vec()];
+        assert!(parse_scan_arguments(&arguments, true).is_err());
+        let arguments = vec![b"COUNT".to_vec(), b"ten".to_vec()];
+        assert_eq!(
+            parse_scan_arguments(&arguments, true),
+            Err(StorageError::NotAnInteger)
+        );
+        let arguments = vec![b"MATCH".to_vec()];
+        assert!(parse_scan_arguments(&arguments, true).is_err());
+    }
+
+    #[test]
+    fn test_parse_cursor() {
+        assert_eq!(parse_cursor(b"18446744073709551615"), Ok(u64::MAX));
+        assert!(parse_cursor(b"-1").is_err());
+    }
+
+    #[test]
+    fn test_scan_covers_everything() {
+        let set: ScanSet = (0..100).map(|i: u32| i.to_be_bytes().to_vec()).collect();
+        let mut seen = Vec::new();
+        let mut cursor = 0;
+        loop {
+            let (next, batch) = set.scan(cursor, 7);
+            assert!(batch.len() >= 7 || next == 0);
+            seen.extend(batch.into_iter().map(|member| member.to_vec()));
+            if next == 0 {
+                break;
+            }
+            cursor = next;
+        }
+        seen.sort();
+        let mut expected: Vec<Vec<u8>> = set.iter().map(|member| member.to_vec()).collect();
+        expected.sort();
+        assert_eq!(seen, expected);
+    }
+
+    #[test]
+    fn test_scan_order_keeps_equal_hashes_together() {
+        let mut order = ScanOrder::default();
+        for (hash, item) in [(1, "a"), (2, "b"), (2, "c"), (2, "d"), (3, "e")] {
+            order.0.insert((hash, Arc::from(item.as_bytes())));
+        }
+        let (next, batch) = order.batch(0, 2);
+        assert_eq!(next, 3);
+        assert_eq!(batch.len(), 4);
+        let (next, batch) = order.batch(next, 2);
+        assert_eq!(next, 0);
+        assert_eq!(batch, vec![&Arc::from(b"e".as_slice())]);
+    }
+
+    #[test]
+    fn test_scan_survives_changes() {
+        let mut map: ScanMap<u32> = ScanMap::default();
+        for i in 0..50 {
+            map.insert(format!("key:{}", i).into_bytes(), i);
+        }
+        let original: HashSet<Vec<u8>> = map.keys().map(|key| key.to_vec()).collect();
+        let mut seen = HashSet::new();
+        let mut cursor = 0;
+        let mut round = 0;
+        loop {
+            let (next, batch) = map.scan(cursor, 5);
+            seen.extend(batch.into_iter().map(|(key, _)| key.to_vec()));
+            // Grow and shrink the map between calls so that it rehashes mid-scan.
+            for i in 0..3 {
+                map.insert(format!("new:{}:{}", round, i).into_bytes(), i);
+            }
+            map.remove(format!("new:{}:0", round).as_bytes());
+            round += 1;
+            if next == 0 {
+                break;
+            }
+            cursor = next;
+        }
+        assert!(original.is_subset(&seen));
+        assert_eq!(map.order.0.len(), map.len());
+    }
+
+    #[test]
+    fn test_random_picks() {
+        let set: ScanSet = (0..30).map(|i: u32| i.to_be_bytes().to_vec()).collect();
+        assert!(set.contains(set.random().unwrap()));
+        assert!(ScanSet::default().random().is_none());
+        // Few, most and all of the members are picked in different ways.
+        for count in [4, 25, 30, 100] {
+            let picks = set.random_picks(count);
+            let distinct: HashSet<&Arc<[u8]>> = picks.iter().copied().collect();
+            assert_eq!(picks.len(), count.min(30) as usize);
+            assert_eq!(distinct.len(), picks.len());
+        }
+        assert_eq!(set.random_picks(-100).len(), 100);
+        assert_eq!(set.random_picks(-i64::MAX).len(), MAX_REPEATED_PICKS);
+    }
+}
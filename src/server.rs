@@ -1,25 +1,33 @@
-use crate::storage::Storage;
-use crate::storage_result::{StorageError, StorageResult};
+use crate::resp::RESPVersion;
+use crate::storage::{Response, Storage};
+use crate::storage_result::{command_to_string, StorageError, StorageResult};
 use crate::RESP;
-use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::{Arc, Mutex};
 
-#[derive(Debug, PartialEq)]
-pub enum ServerError {
-    CommandError,
+static NEXT_CLIENT_ID: AtomicU64 = AtomicU64::new(1);
+
+/// Per-connection state that outlives a single request.
+#[derive(Debug)]
+pub struct Session {
+    pub id: u64,
+    pub version: RESPVersion,
 }
 
-impl fmt::Display for ServerError {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self {
-            ServerError::CommandError => write!(f, "Error while processing"),
+impl Session {
+    pub fn new() -> Self {
+        Self {
+            id: NEXT_CLIENT_ID.fetch_add(1, Ordering::Relaxed),
+            version: RESPVersion::default(),
         }
     }
 }
 
-pub type ServerResult<T> = Result<T, ServerError>;
-
-pub fn process_request(request: RESP, storage: Arc<Mutex<Storage>>) -> StorageResult<RESP> {
+pub fn process_request(
+    request: RESP,
+    storage: Arc<Mutex<Storage>>,
+    session: &mut Session,
+) -> StorageResult<Response> {
     let elements = match request {
         RESP::Array(v) => v,
         _ => return Err(StorageError::IncorrectRequest),
@@ -33,41 +41,154 @@ pub fn process_request(request: RESP, storage: Arc<Mutex<Storage>>) -> StorageRe
         }
     }
 
+    // Commands acting on the connection itself never reach the storage.
+    if command
+        .first()
+        .is_some_and(|name| name.eq_ignore_ascii_case(b"hello"))
+    {
+        return command_hello(&command, session).map(Response::Reply);
+    }
+
     let mut guard = match storage.lock() {
         Ok(guard) => guard,
         Err(_) => return Err(StorageError::StorageUnavailable),
     };
-    
-    guard.process_command(&command)
+
+    guard.process_client_command(&command)
+}
+
+fn command_hello(command: &[Vec<u8>], session: &mut Session) -> StorageResult<RESP> {
+    let mut version = session.version;
+
+    if command.len() > 1 {
+        version = match command[1].as_slice() {
+            b"2" => RESPVersion::RESP2,
+            b"3" => RESPVersion::RESP3,
+            _ => return Err(StorageError::UnsupportedProtocolVersion),
+        };
+
+        let mut idx: usize = 2;
+        while idx < command.len() {
+            match command[idx].to_ascii_lowercase().as_slice() {
+                // There are no users besides the password-less default one.
+                b"auth" if idx + 2 < command.len() => {
+                    if command[idx + 1] != b"default" {
+                        return Err(StorageError::InvalidCredentials);
+                    }
+                    idx += 3;
+                }
+                // Accepted for compatibility, but no command reports the name, so it is
+                // only checked and not kept.
+                b"setname" if idx + 1 < command.len() => {
+                    validate_client_name(&command[idx + 1])?;
+                    idx += 2;
+                }
+                _ => return Err(StorageError::CommandSyntaxError(command_to_string(command))),
+            }
+        }
+    }
+
+    session.version = version;
+
+    let protocol = match version {
+        RESPVersion::RESP2 => 2,
+        RESPVersion::RESP3 => 3,
+    };
+    Ok(RESP::Map(vec![
+        (
+            RESP::BulkString(b"server".to_vec()),
+            RESP::BulkString(b"redis".to_vec()),
+        ),
+        (
+            RESP::BulkString(b"version".to_vec()),
+            RESP::BulkString(env!("CARGO_PKG_VERSION").as_bytes().to_vec()),
+        ),
+        (RESP::BulkString(b"proto".to_vec()), RESP::Integer(protocol)),
+        (
+            RESP::BulkString(b"id".to_vec()),
+            RESP::Integer(session.id as i64),
+        ),
+        (
+            RESP::BulkString(b"mode".to_vec()),
+            RESP::BulkString(b"standalone".to_vec()),
+        ),
+        (
+            RESP::BulkString(b"role".to_vec()),
+            RESP::BulkString(b"master".to_vec()),
+        ),
+        (RESP::BulkString(b"modules".to_vec()), RESP::Array(vec![])),
+    ]))
+}
+
+/// Checks a client name the way Redis does, which allows an empty one.
+fn validate_client_name(name: &[u8]) -> StorageResult<()> {
+    if !name.iter().all(|&elem| (b'!'..=b'~').contains(&elem)) {
+        return Err(StorageError::InvalidArgument(String::from(
+            "Client names cannot contain spaces, newlines or special characters.",
+        )));
+    }
+    Ok(())
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    fn reply(response: Response) -> RESP {
+        match response {
+            Response::Reply(reply) => reply,
+            Response::Blocked(_) => panic!("the request blocked"),
+        }
+    }
+
     #[test]
     fn test_process_request_ping() {
-        let request = RESP::Array(vec![RESP::BulkString(String::from("PING"))]);
-        let output = process_request(request, Arc::new(Mutex::new(Storage::new()))).unwrap();
+        let request = RESP::Array(vec![RESP::BulkString(b"PING".to_vec())]);
+        let output = reply(
+            process_request(
+                request,
+                Arc::new(Mutex::new(Storage::new())),
+                &mut Session::new(),
+            )
+            .unwrap(),
+        );
         assert_eq!(output, RESP::SimpleString(String::from("PONG")));
     }
 
     #[test]
     fn test_process_request_echo() {
         let request = RESP::Array(vec![
-            RESP::BulkString(String::from("ECHO")),
-            RESP::BulkString(String::from("Hello World")),
+            RESP::BulkString(b"ECHO".to_vec()),
+            RESP::BulkString(b"Hello World".to_vec()),
         ]);
         let storage = Arc::new(Mutex::new(Storage::new()));
-        let output = process_request(request, storage).unwrap();
-        assert_eq!(output, RESP::BulkString(String::from("Hello World")));
+        let output = reply(process_request(request, storage, &mut Session::new()).unwrap());
+        assert_eq!(output, RESP::BulkString(b"Hello World".to_vec()));
+    }
+
+    #[test]
+    fn test_process_request_binary_roundtrip() {
+        let storage = Arc::new(Mutex::new(Storage::new()));
+        let value = vec![0x00, 0xff, b'\r', b'\n', 0xc3];
+        let request = RESP::Array(vec![
+            RESP::BulkString(b"SET".to_vec()),
+            RESP::BulkString(vec![0xfe, 0x01]),
+            RESP::BulkString(value.clone()),
+        ]);
+        process_request(request, storage.clone(), &mut Session::new()).unwrap();
+        let request = RESP::Array(vec![
+            RESP::BulkString(b"GET".to_vec()),
+            RESP::BulkString(vec![0xfe, 0x01]),
+        ]);
+        let output = reply(process_request(request, storage, &mut Session::new()).unwrap());
+        assert_eq!(output, RESP::BulkString(value));
     }
 
     #[test]
     fn test_process_request_not_array() {
-        let request = RESP::BulkString(String::from("PING"));
+        let request = RESP::BulkString(b"PING".to_vec());
         let storage = Arc::new(Mutex::new(Storage::new()));
-        let error = process_request(request, storage).unwrap_err();
+        let error = process_request(request, storage, &mut Session::new()).unwrap_err();
         assert_eq!(error, StorageError::IncorrectRequest);
     }
 
@@ -75,7 +196,70 @@ mod tests {
     fn test_process_request_not_bulkstrings() {
         let request = RESP::Array(vec![RESP::SimpleString(String::from("PING"))]);
         let storage = Arc::new(Mutex::new(Storage::new()));
-        let error = process_request(request, storage).unwrap_err();
+        let error = process_request(request, storage, &mut Session::new()).unwrap_err();
         assert_eq!(error, StorageError::IncorrectRequest);
     }
+
+    fn command(arguments: &[&str]) -> RESP {
+        RESP::Array(
+            arguments
+                .iter()
+                .map(|argument| RESP::BulkString(argument.as_bytes().to_vec()))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn test_process_request_hello_switches_protocol() {
+        let storage = Arc::new(Mutex::new(Storage::new()));
+        let mut session = Session::new();
+        let output =
+            reply(process_request(command(&["HELLO", "3"]), storage, &mut session).unwrap());
+        assert_eq!(session.version, RESPVersion::RESP3);
+        match output {
+            RESP::Map(fields) => {
+                assert!(fields.contains(&(RESP::BulkString(b"proto".to_vec()), RESP::Integer(3))));
+                assert!(fields.contains(&(
+                    RESP::BulkString(b"id".to_vec()),
+                    RESP::Integer(session.id as i64)
+                )));
+            }
+            _ => panic!("HELLO should reply with a map"),
+        }
+    }
+
+    #[test]
+    fn test_process_request_hello_without_version() {
+        let storage = Arc::new(Mutex::new(Storage::new()));
+        let mut session = Session::new();
+        let output = reply(process_request(command(&["HELLO"]), storage, &mut session).unwrap());
+        assert_eq!(session.version, RESPVersion::RESP2);
+        assert!(matches!(output, RESP::Map(_)));
+    }
+
+    #[test]
+    fn test_process_request_hello_unsupported_version() {
+        let storage = Arc::new(Mutex::new(Storage::new()));
+        let mut session = Session::new();
+        let error = process_request(command(&["HELLO", "4"]), storage, &mut session).unwrap_err();
+        assert_eq!(error, StorageError::UnsupportedProtocolVersion);
+        assert_eq!(session.version, RESPVersion::RESP2);
+    }
+
+    #[test]
+    fn test_process_request_hello_auth_and_setname() {
+        let storage = Arc::new(Mutex::new(Storage::new()));
+        let mut session = Session::new();
+        let request = command(&["HELLO", "3", "AUTH", "default", "pw", "SETNAME", "worker"]);
+        process_request(request, storage.clone(), &mut session).unwrap();
+        assert_eq!(session.version, RESPVersion::RESP3);
+
+        let request = command(&["HELLO", "3", "SETNAME", "two words"]);
+        assert!(process_request(request, storage.clone(), &mut session).is_err());
+
+        let request = command(&["HELLO", "2", "AUTH", "alice", "pw"]);
+        let error = process_request(request, storage, &mut session).unwrap_err();
+        assert_eq!(error, StorageError::InvalidCredentials);
+        assert_eq!(session.version, RESPVersion::RESP3);
+    }
 }
@@ -1,14 +1,18 @@
-use crate::storage_result::{StorageError, StorageResult};
+use crate::storage_result::{command_to_string, StorageError, StorageResult};
 #[derive(Debug, PartialEq)]
 pub enum KeyExistence {
     NX,
     XX,
 }
 
+#[allow(clippy::upper_case_acronyms)]
 #[derive(Debug, PartialEq)]
 pub enum KeyExpiry {
     EX(u64),
     PX(u64),
+    EXAT(u64),
+    PXAT(u64),
+    KEEPTTL,
 }
 
 #[derive(Debug, PartialEq)]
@@ -28,7 +32,11 @@ impl SetArgs {
     }
 }
 
-pub fn parse_set_arguments(arguments: &Vec<String>) -> StorageResult<SetArgs> {
+fn parse_i64(argument: &[u8]) -> Option<i64> {
+    std::str::from_utf8(argument).ok()?.parse().ok()
+}
+
+pub fn parse_set_arguments(arguments: &[Vec<u8>]) -> StorageResult<SetArgs> {
     let mut args = SetArgs::new();
     let mut idx: usize = 0;
 
@@ -37,59 +45,73 @@ pub fn parse_set_arguments(arguments: &Vec<String>) -> StorageResult<SetArgs> {
             break;
         }
 
-        match arguments[idx].to_lowercase().as_str() {
-            "nx" => {
+        match arguments[idx].to_ascii_lowercase().as_slice() {
+            b"nx" => {
                 if args.existence == Some(KeyExistence::XX) {
-                    return Err(StorageError::CommandSyntaxError(arguments.join(" ")));
+                    return Err(StorageError::CommandSyntaxError(command_to_string(
+                        arguments,
+                    )));
                 }
                 args.existence = Some(KeyExistence::NX);
 
                 idx += 1;
             }
-            "xx" => {
+            b"xx" => {
                 if args.existence == Some(KeyExistence::NX) {
-                    return Err(StorageError::CommandSyntaxError(arguments.join(" ")));
+                    return Err(StorageError::CommandSyntaxError(command_to_string(
+                        arguments,
+                    )));
                 }
                 args.existence = Some(KeyExistence::XX);
 
                 idx += 1;
             }
-            "get" => {
+            b"get" => {
                 args.get = true;
                 idx += 1;
             }
-            "ex" => {
-                if let Some(KeyExpiry::PX(_)) = args.expiry {
-                    return Err(StorageError::CommandSyntaxError(arguments.join(" ")));
-                }
-                if idx + 1 >= arguments.len() {
-                    return Err(StorageError::CommandSyntaxError(arguments.join(" ")));
+            option @ (b"ex" | b"px" | b"exat" | b"pxat") => {
+                if args.expiry.is_some() {
+                    return Err(StorageError::CommandSyntaxError(command_to_string(
+                        arguments,
+                    )));
                 }
-                let value: u64 = match arguments[idx + 1].parse() {
-                    Ok(v) => v,
-                    Err(_) => {
-                        return Err(StorageError::CommandSyntaxError(arguments.join(" ")));
-                    }
-                };
-                args.expiry = Some(KeyExpiry::EX(value));
+                let value = arguments
+                    .get(idx + 1)
+                    .and_then(|argument| parse_i64(argument))
+                    .ok_or_else(|| {
+                        StorageError::CommandSyntaxError(command_to_string(arguments))
+                    })?;
+                // Only positive times are valid, as in Redis.
+                let value = u64::try_from(value)
+                    .ok()
+                    .filter(|&value| value > 0)
+                    .ok_or_else(|| {
+                        StorageError::InvalidArgument(String::from(
+                            "invalid expire time in 'set' command",
+                        ))
+                    })?;
+                args.expiry = Some(match option {
+                    b"ex" => KeyExpiry::EX(value),
+                    b"px" => KeyExpiry::PX(value),
+                    b"exat" => KeyExpiry::EXAT(value),
+                    _ => KeyExpiry::PXAT(value),
+                });
                 idx += 2;
             }
-            "px" => {
-                if let Some(KeyExpiry::EX(_)) = args.expiry {
-                    return Err(StorageError::CommandSyntaxError(arguments.join(" ")));
+            b"keepttl" => {
+                if args.expiry.is_some() {
+                    return Err(StorageError::CommandSyntaxError(command_to_string(
+                        arguments,
+                    )));
                 }
-                if idx + 1 == arguments.len() {
-                    return Err(StorageError::CommandSyntaxError(arguments.join(" ")));
-                }
-                let value: u64 = arguments[idx + 1]
-                    .parse()
-                    .map_err(|_| StorageError::CommandSyntaxError(arguments.join(" ")))?;
-                args.expiry = Some(KeyExpiry::PX(value));
-                idx += 2;
+                args.expiry = Some(KeyExpiry::KEEPTTL);
+                idx += 1;
             }
-
             _ => {
-                return Err(StorageError::CommandSyntaxError(arguments.join(" ")));
+                return Err(StorageError::CommandSyntaxError(command_to_string(
+                    arguments,
+                )));
             }
         }
     }
@@ -104,27 +126,27 @@ mod tests {
 
     #[test]
     fn test_parse_nx() {
-        let commands: Vec<String> = vec![String::from("NX")];
+        let commands: Vec<Vec<u8>> = vec![b"NX".to_vec()];
         let args = parse_set_arguments(&commands).unwrap();
         assert_eq!(args.existence, Some(KeyExistence::NX));
     }
 
     #[test]
     fn test_parse_nx_lowercase() {
-        let commands: Vec<String> = vec![String::from("nx")];
+        let commands: Vec<Vec<u8>> = vec![b"nx".to_vec()];
         let args = parse_set_arguments(&commands).unwrap();
         assert_eq!(args.existence, Some(KeyExistence::NX));
     }
 
     #[test]
     fn test_parse_xx() {
-        let commands: Vec<String> = vec![String::from("XX")];
+        let commands: Vec<Vec<u8>> = vec![b"XX".to_vec()];
         let args = parse_set_arguments(&commands).unwrap();
         assert_eq!(args.existence, Some(KeyExistence::XX));
     }
     #[test]
     fn test_parse_xx_and_nx() {
-        let commands: Vec<String> = vec![String::from("XX"), String::from("NX")];
+        let commands: Vec<Vec<u8>> = vec![b"XX".to_vec(), b"NX".to_vec()];
         assert!(matches!(
             parse_set_arguments(&commands),
             Err(StorageError::CommandSyntaxError(_))
@@ -132,7 +154,7 @@ mod tests {
     }
     #[test]
     fn test_parse_nx_and_xx() {
-        let commands: Vec<String> = vec![String::from("NX"), String::from("XX")];
+        let commands: Vec<Vec<u8>> = vec![b"NX".to_vec(), b"XX".to_vec()];
         assert!(matches!(
             parse_set_arguments(&commands),
             Err(StorageError::CommandSyntaxError(_))
@@ -140,13 +162,13 @@ mod tests {
     }
     #[test]
     fn test_parse_get() {
-        let commands: Vec<String> = vec![String::from("GET")];
+        let commands: Vec<Vec<u8>> = vec![b"GET".to_vec()];
         let args = parse_set_arguments(&commands).unwrap();
         assert!(args.get);
     }
     #[test]
     fn parse_nx_and_get() {
-        let commands: Vec<String> = vec![String::from("NX"), String::from("GET")];
+        let commands: Vec<Vec<u8>> = vec![b"NX".to_vec(), b"GET".to_vec()];
         let args = parse_set_arguments(&commands).unwrap();
         assert_eq!(
             args,
@@ -159,7 +181,7 @@ mod tests {
     }
     #[test]
     fn test_parse_xx_and_get() {
-        let commands: Vec<String> = vec![String::from("XX"), String::from("GET")];
+        let commands: Vec<Vec<u8>> = vec![b"XX".to_vec(), b"GET".to_vec()];
         let args = parse_set_arguments(&commands).unwrap();
         assert_eq!(
             args,
@@ -173,13 +195,13 @@ mod tests {
 
     #[test]
     fn test_parse_ex() {
-        let commands: Vec<String> = vec![String::from("EX"), String::from("100")];
+        let commands: Vec<Vec<u8>> = vec![b"EX".to_vec(), b"100".to_vec()];
         let args = parse_set_arguments(&commands).unwrap();
         assert_eq!(args.expiry, Some(KeyExpiry::EX(100)));
     }
     #[test]
     fn test_parse_ex_wrong_value() {
-        let commands: Vec<String> = vec![String::from("EX"), String::from("value")];
+        let commands: Vec<Vec<u8>> = vec![b"EX".to_vec(), b"value".to_vec()];
         assert!(matches!(
             parse_set_arguments(&commands),
             Err(StorageError::CommandSyntaxError(_))
@@ -187,7 +209,7 @@ mod tests {
     }
     #[test]
     fn test_parse_ex_end_of_vector() {
-        let commands: Vec<String> = vec![String::from("EX")];
+        let commands: Vec<Vec<u8>> = vec![b"EX".to_vec()];
         assert!(matches!(
             parse_set_arguments(&commands),
             Err(StorageError::CommandSyntaxError(_))
@@ -195,13 +217,13 @@ mod tests {
     }
     #[test]
     fn test_parse_px() {
-        let commands: Vec<String> = vec![String::from("PX"), String::from("100")];
+        let commands: Vec<Vec<u8>> = vec![b"PX".to_vec(), b"100".to_vec()];
         let args = parse_set_arguments(&commands).unwrap();
         assert_eq!(args.expiry, Some(KeyExpiry::PX(100)));
     }
     #[test]
     fn test_parse_px_wrong_value() {
-        let commands: Vec<String> = vec![String::from("PX"), String::from("value")];
+        let commands: Vec<Vec<u8>> = vec![b"PX".to_vec(), b"value".to_vec()];
         assert!(matches!(
             parse_set_arguments(&commands),
             Err(StorageError::CommandSyntaxError(_))
@@ -209,7 +231,7 @@ mod tests {
     }
     #[test]
     fn test_parse_px_end_of_vector() {
-        let commands: Vec<String> = vec![String::from("PX")];
+        let commands: Vec<Vec<u8>> = vec![b"PX".to_vec()];
         assert!(matches!(
             parse_set_arguments(&commands),
             Err(StorageError::CommandSyntaxError(_))
@@ -217,15 +239,62 @@ mod tests {
     }
     #[test]
     fn test_parse_ex_and_px() {
-        let commands: Vec<String> = vec![
-            String::from("EX"),
-            String::from("100"),
-            String::from("PX"),
-            String::from("100"),
+        let commands: Vec<Vec<u8>> = vec![
+            b"EX".to_vec(),
+            b"100".to_vec(),
+            b"PX".to_vec(),
+            b"100".to_vec(),
         ];
         assert!(matches!(
             parse_set_arguments(&commands),
             Err(StorageError::CommandSyntaxError(_))
         ));
     }
+    #[test]
+    fn test_parse_exat_and_pxat() {
+        let commands: Vec<Vec<u8>> = vec![b"EXAT".to_vec(), b"1700000000".to_vec()];
+        let args = parse_set_arguments(&commands).unwrap();
+        assert_eq!(args.expiry, Some(KeyExpiry::EXAT(1700000000)));
+        let commands: Vec<Vec<u8>> = vec![b"pxat".to_vec(), b"1700000000000".to_vec()];
+        let args = parse_set_arguments(&commands).unwrap();
+        assert_eq!(args.expiry, Some(KeyExpiry::PXAT(1700000000000)));
+    }
+    #[test]
+    fn test_parse_keepttl() {
+        let commands: Vec<Vec<u8>> = vec![b"KEEPTTL".to_vec(), b"XX".to_vec()];
+        let args = parse_set_arguments(&commands).unwrap();
+        assert_eq!(args.expiry, Some(KeyExpiry::KEEPTTL));
+        assert_eq!(args.existence, Some(KeyExistence::XX));
+    }
+    #[test]
+    fn test_parse_keepttl_and_ex() {
+        let commands: Vec<Vec<u8>> = vec![b"KEEPTTL".to_vec(), b"EX".to_vec(), b"10".to_vec()];
+        assert!(matches!(
+            parse_set_arguments(&commands),
+            Err(StorageError::CommandSyntaxError(_))
+        ));
+        let commands: Vec<Vec<u8>> = vec![b"PXAT".to_vec(), b"10".to_vec(), b"KEEPTTL".to_vec()];
+        assert!(matches!(
+            parse_set_arguments(&commands),
+            Err(StorageError::CommandSyntaxError(_))
+        ));
+    }
+    #[test]
+    fn test_parse_ex_zero() {
+        let commands: Vec<Vec<u8>> = vec![b"EX".to_vec(), b"0".to_vec()];
+        assert!(matches!(
+            parse_set_arguments(&commands),
+            Err(StorageError::InvalidArgument(_))
+        ));
+    }
+    #[test]
+    fn test_parse_ex_negative() {
+        let commands: Vec<Vec<u8>> = vec![b"PX".to_vec(), b"-100".to_vec()];
+        assert_eq!(
+            parse_set_arguments(&commands),
+            Err(StorageError::InvalidArgument(String::from(
+                "invalid expire time in 'set' command"
+            )))
+        );
+    }
 }
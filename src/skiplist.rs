@@ -0,0 +1,357 @@
+use crate::random::random_below;
+use std::sync::Arc;
+
+/// Enough levels for 4^32 entries, matching Redis.
+const MAX_LEVEL: usize = 32;
+
+/// Index of the header node, which holds no entry.
+const HEAD: usize = 0;
+
+#[derive(Debug, Clone)]
+struct Level {
+    forward: Option<usize>,
+    /// Number of entries the forward link skips over, counting the one it points to. For
+    /// the last link of a level, the number of entries left up to the end of the list.
+    span: usize,
+}
+
+#[derive(Debug, Clone)]
+struct Node {
+    score: f64,
+    member: Arc<[u8]>,
+    backward: Option<usize>,
+    levels: Vec<Level>,
+}
+
+/// Whether `node` sorts before `(score, member)`: by score, then by member bytes.
+fn precedes(node: &Node, score: f64, member: &[u8]) -> bool {
+    node.score < score || (node.score == score && *node.member < *member)
+}
+
+/// The score-ordered index of a sorted set, a skiplist as in Redis whose links record
+/// how many entries they skip, so that finding the rank of an entry or the entry at a
+/// rank takes O(log n). Nodes live in an arena and link to each other by index.
+#[derive(Debug, Clone)]
+pub struct SkipList {
+    nodes: Vec<Node>,
+    free: Vec<usize>,
+    tail: Option<usize>,
+    length: usize,
+    level: usize,
+}
+
+impl Default for SkipList {
+    fn default() -> Self {
+        let head = Node {
+            score: 0.0,
+            member: Arc::default(),
+            backward: None,
+            levels: vec![
+                Level {
+                    forward: None,
+                    span: 0,
+                };
+                MAX_LEVEL
+            ],
+        };
+        SkipList {
+            nodes: vec![head],
+            free: Vec::new(),
+            tail: None,
+            length: 0,
+            level: 1,
+        }
+    }
+}
+
+impl SkipList {
+    pub fn len(&self) -> usize {
+        self.length
+    }
+
+    /// Adds an entry, which must not be in the list already. The member is shared with
+    /// the scores of the sorted set rather than copied.
+    pub fn insert(&mut self, score: f64, member: Arc<[u8]>) {
+        let (mut update, mut rank) = self.find(|node| precedes(node, score, &member));
+        let level = random_level();
+        if level > self.level {
+            for i in self.level..level {
+                rank[i] = 0;
+                update[i] = HEAD;
+                self.nodes[HEAD].levels[i].span = self.length;
+            }
+            self.level = level;
+        }
+
+        let node = Node {
+            score,
+            member,
+            backward: (update[0] != HEAD).then_some(update[0]),
+            levels: vec![
+                Level {
+                    forward: None,
+                    span: 0,
+                };
+                level
+            ],
+        };
+        let id = match self.free.pop() {
+            Some(id) => {
+                self.nodes[id] = node;
+                id
+            }
+            None => {
+                self.nodes.push(node);
+                self.nodes.len() - 1
+            }
+        };
+        for i in 0..level {
+            let previous = update[i];
+            let skipped = rank[0] - rank[i];
+            self.nodes[id].levels[i] = Level {
+                forward: self.nodes[previous].levels[i].forward,
+                span: self.nodes[previous].levels[i].span - skipped,
+            };
+            self.nodes[previous].levels[i] = Level {
+                forward: Some(id),
+                span: skipped + 1,
+            };
+        }
+        for (i, &previous) in update.iter().enumerate().take(self.level).skip(level) {
+            self.nodes[previous].levels[i].span += 1;
+        }
+        match self.nodes[id].levels[0].forward {
+            Some(next) => self.nodes[next].backward = Some(id),
+            None => self.tail = Some(id),
+        }
+        self.length += 1;
+    }
+
+    /// Removes an entry, returning whether it was there.
+    pub fn remove(&mut self, score: f64, member: &[u8]) -> bool {
+        let (update, _) = self.find(|node| precedes(node, score, member));
+        let id = match self.nodes[update[0]].levels[0].forward {
+            Some(id) if self.nodes[id].score == score && *self.nodes[id].member == *member => id,
+            _ => return false,
+        };
+        for (i, &previous) in update.iter().enumerate().take(self.level) {
+            if self.nodes[previous].levels[i].forward == Some(id) {
+                let removed = &self.nodes[id].levels[i];
+                let (forward, span) = (removed.forward, removed.span);
+                self.nodes[previous].levels[i] = Level {
+                    forward,
+                    span: self.nodes[previous].levels[i].span + span - 1,
+                };
+            } else {
+                self.nodes[previous].levels[i].span -= 1;
+            }
+        }
+        let backward = self.nodes[id].backward;
+        match self.nodes[id].levels[0].forward {
+            Some(next) => self.nodes[next].backward = backward,
+            None => self.tail = backward,
+        }
+        while self.level > 1 && self.nodes[HEAD].levels[self.level - 1].forward.is_none() {
+            self.level -= 1;
+        }
+        self.nodes[id].member = Arc::default();
+        self.nodes[id].levels = Vec::new();
+        self.free.push(id);
+        self.length -= 1;
+        true
+    }
+
+    /// Returns how many entries, from the start, `before` holds for. `before` must hold
+    /// for a leading run of the entries and for none after it, like the predicate of
+    /// `slice::partition_point`.
+    pub fn partition_point(&self, before: impl Fn(f64, &[u8]) -> bool) -> usize {
+        let (_, rank) = self.find(|node| before(node.score, &node.member));
+        rank[0]
+    }
+
+    /// Returns the 0-based rank of an entry, if it is in the list.
+    pub fn rank(&self, score: f64, member: &[u8]) -> Option<usize> {
+        let rank = self.partition_point(|s, m| s < score || (s == score && m < member));
+        match self.get(rank) {
+            Some((s, m)) if s == score && m == member => Some(rank),
+            _ => None,
+        }
+    }
+
+    /// Returns the entry at a 0-based rank.
+    pub fn get(&self, rank: usize) -> Option<(f64, &[u8])> {
+        self.node_at(rank).map(|id| {
+            let node = &self.nodes[id];
+            (node.score, &*node.member)
+        })
+    }
+
+    /// Iterates from the entry at `rank` towards the end of the list, or towards the start
+    /// if `reverse` is set.
+    pub fn iter_from(&self, rank: usize, reverse: bool) -> Iter<'_> {
+        Iter {
+            list: self,
+            node: self.node_at(rank),
+            reverse,
+        }
+    }
+
+    /// Walks down the levels to the last node for which `before` holds, returning for
+    /// each level the last node visited on it and the rank of that node, counting from 1
+    /// with the header at 0.
+    fn find(&self, before: impl Fn(&Node) -> bool) -> ([usize; MAX_LEVEL], [usize; MAX_LEVEL]) {
+        let mut update = [HEAD; MAX_LEVEL];
+        let mut rank = [0; MAX_LEVEL];
+        let mut id = HEAD;
+        let mut traversed = 0;
+        for i in (0..self.level).rev() {
+            while let Some(next) = self.nodes[id].levels[i].forward {
+                if !before(&self.nodes[next]) {
+                    break;
+                }
+                traversed += self.nodes[id].levels[i].span;
+                id = next;
+            }
+            update[i] = id;
+            rank[i] = traversed;
+        }
+        (update, rank)
+    }
+
+    fn node_at(&self, rank: usize) -> Option<usize> {
+        if rank >= self.length {
+            return None;
+        }
+        if rank == self.length - 1 {
+            return self.tail;
+        }
+        let target = rank + 1;
+        let mut id = HEAD;
+        let mut traversed = 0;
+        for i in (0..self.level).rev() {
+            while let Some(next) = self.nodes[id].levels[i].forward {
+                if traversed + self.nodes[id].levels[i].span > target {
+                    break;
+                }
+                traversed += self.nodes[id].levels[i].span;
+                id = next;
+            }
+            if traversed == target {
+                return Some(id);
+            }
+        }
+        None
+    }
+}
+
+/// Picks the level of a new node: each level above the first with probability 1/4.
+fn random_level() -> usize {
+    let mut level = 1;
+    while level < MAX_LEVEL && random_below(4) == 0 {
+        level += 1;
+    }
+    level
+}
+
+pub struct Iter<'a> {
+    list: &'a SkipList,
+    node: Option<usize>,
+    reverse: bool,
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = (f64, &'a [u8]);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = &self.list.nodes[self.node?];
+        self.node = match self.reverse {
+            true => node.backward,
+            false => node.levels[0].forward,
+        };
+        Some((node.score, &*node.member))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entries(list: &SkipList) -> Vec<(f64, Vec<u8>)> {
+        list.iter_from(0, false)
+            .map(|(score, member)| (score, member.to_vec()))
+            .collect()
+    }
+
+    #[test]
+    fn test_insert_keeps_order() {
+        let mut list = SkipList::default();
+        list.insert(2.0, Arc::from(b"b".as_slice()));
+        list.insert(1.0, Arc::from(b"z".as_slice()));
+        list.insert(2.0, Arc::from(b"a".as_slice()));
+        list.insert(-5.0, Arc::from(b"m".as_slice()));
+        assert_eq!(
+            entries(&list),
+            vec![
+                (-5.0, b"m".to_vec()),
+                (1.0, b"z".to_vec()),
+                (2.0, b"a".to_vec()),
+                (2.0, b"b".to_vec()),
+            ]
+        );
+        let reversed: Vec<&[u8]> = list.iter_from(3, true).map(|(_, member)| member).collect();
+        assert_eq!(reversed, vec![b"b".as_slice(), b"a", b"z", b"m"]);
+    }
+
+    #[test]
+    fn test_ranks_match_a_sorted_vec() {
+        let mut list = SkipList::default();
+        let mut expected: Vec<(f64, Vec<u8>)> = Vec::new();
+        for i in 0..500u32 {
+            let score = ((i * 7919) % 101) as f64;
+            let member = format!("m{}", i).into_bytes();
+            list.insert(score, Arc::from(member.as_slice()));
+            expected.push((score, member));
+        }
+        // Remove every third entry to exercise the span bookkeeping of removals.
+        for (score, member) in expected.iter().step_by(3) {
+            assert!(list.remove(*score, member));
+        }
+        assert!(!list.remove(1000.0, b"none"));
+        let mut expected: Vec<(f64, Vec<u8>)> = expected
+            .into_iter()
+            .enumerate()
+            .filter(|(i, _)| i % 3 != 0)
+            .map(|(_, entry)| entry)
+            .collect();
+        expected.sort_by(|a, b| a.0.total_cmp(&b.0).then_with(|| a.1.cmp(&b.1)));
+
+        assert_eq!(list.len(), expected.len());
+        assert_eq!(entries(&list), expected);
+        for (rank, (score, member)) in expected.iter().enumerate() {
+            assert_eq!(list.rank(*score, member), Some(rank));
+            assert_eq!(list.get(rank), Some((*score, member.as_slice())));
+        }
+        assert_eq!(list.get(expected.len()), None);
+        assert_eq!(
+            list.partition_point(|score, _| score < 50.0),
+            expected.iter().filter(|(score, _)| *score < 50.0).count()
+        );
+    }
+
+    #[test]
+    fn test_remove_everything_and_reuse() {
+        let mut list = SkipList::default();
+        for i in 0..50 {
+            list.insert(i as f64, Arc::from([i].as_slice()));
+        }
+        for i in 0..50 {
+            assert!(list.remove(i as f64, &[i]));
+        }
+        assert_eq!(list.len(), 0);
+        assert_eq!(list.get(0), None);
+        assert_eq!(list.iter_from(0, false).count(), 0);
+        list.insert(1.0, Arc::from(b"again".as_slice()));
+        assert_eq!(entries(&list), vec![(1.0, b"again".to_vec())]);
+        assert_eq!(list.nodes.len(), 51);
+    }
+}
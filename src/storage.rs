@@ -1,18 +1,147 @@
+use crate::config::Config;
+use crate::config_result::ConfigError;
+use crate::glob::glob_match;
 use crate::resp::RESP;
-use crate::set::{parse_set_arguments, KeyExpiry, SetArgs};
-use crate::storage_result::{StorageError, StorageResult};
-use std::collections::HashMap;
-use std::ops::Add;
-use std::time::{Duration, SystemTime};
+use crate::scan::{parse_cursor, parse_scan_arguments, ScanMap, ScanSet};
+use crate::set::{parse_set_arguments, KeyExistence, KeyExpiry, SetArgs};
+use crate::storage_result::{command_name, command_to_string, StorageError, StorageResult};
+use blocking::BlockedClients;
+use hash::Hash;
+use list::ListEnd;
+use set::SetOperation;
+use sorted_set::SortedSet;
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::mpsc::{self, Sender};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use stream::Stream;
 
-#[derive(Debug, PartialEq)]
+mod blocking;
+mod hash;
+mod list;
+mod set;
+mod sorted_set;
+mod stream;
+mod string;
+
+pub use blocking::{BlockedClient, Response};
+
+/// Longest string stored with the `embstr` encoding, as in Redis.
+const EMBSTR_MAX_LENGTH: usize = 44;
+
+/// Largest collections reported with the compact `listpack` encoding, matching the
+/// default `*-max-listpack-entries` and `*-max-listpack-value` settings of Redis.
+const LISTPACK_MAX_ENTRIES: usize = 128;
+const LISTPACK_MAX_VALUE: usize = 64;
+
+/// Largest sets of integers reported with the `intset` encoding, the default
+/// `set-max-intset-entries` of Redis.
+const INTSET_MAX_ENTRIES: usize = 512;
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum StorageValue {
-    String(String),
+    String(Vec<u8>),
+    /// A string that is the canonical decimal form of an i64, kept as the number itself
+    /// so that counters are not parsed and formatted on every INCR.
+    Integer(i64),
+    List(VecDeque<Vec<u8>>),
+    Hash(Hash),
+    Set(ScanSet),
+    SortedSet(SortedSet),
+    Stream(Stream),
+}
+
+impl StorageValue {
+    /// Builds a string value, picking the integer encoding whenever it round-trips.
+    pub fn from_bytes(value: Vec<u8>) -> StorageValue {
+        // Anything longer than "-9223372036854775808" cannot be an i64.
+        if value.len() <= 20 {
+            if let Ok(n) = parse_integer(&value) {
+                return StorageValue::Integer(n);
+            }
+        }
+        StorageValue::String(value)
+    }
+
+    /// The name reported by the TYPE command.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            StorageValue::String(_) | StorageValue::Integer(_) => "string",
+            StorageValue::List(_) => "list",
+            StorageValue::Hash(_) => "hash",
+            StorageValue::Set(_) => "set",
+            StorageValue::SortedSet(_) => "zset",
+            StorageValue::Stream(_) => "stream",
+        }
+    }
+
+    /// The name reported by OBJECT ENCODING.
+    pub fn encoding(&self) -> &'static str {
+        match self {
+            StorageValue::Integer(_) => "int",
+            StorageValue::String(v) if v.len() <= EMBSTR_MAX_LENGTH => "embstr",
+            StorageValue::String(_) => "raw",
+            StorageValue::List(list) if is_listpack(list.iter()) => "listpack",
+            StorageValue::List(_) => "quicklist",
+            StorageValue::Hash(hash) if is_listpack(hash.keys()) && is_listpack(hash.values()) => {
+                match hash.has_deadlines() {
+                    true => "listpackex",
+                    false => "listpack",
+                }
+            }
+            StorageValue::Hash(_) => "hashtable",
+            StorageValue::Set(set)
+                if set.len() <= INTSET_MAX_ENTRIES
+                    && set.iter().all(|member| parse_integer(member).is_ok()) =>
+            {
+                "intset"
+            }
+            StorageValue::Set(set) if is_listpack(set.iter()) => "listpack",
+            StorageValue::Set(_) => "hashtable",
+            StorageValue::SortedSet(zset) if is_listpack(zset.members()) => "listpack",
+            StorageValue::SortedSet(_) => "skiplist",
+            StorageValue::Stream(_) => "stream",
+        }
+    }
+
+    /// Whether this is a collection with nothing left in it, which Redis never keeps.
+    pub fn is_empty_collection(&self) -> bool {
+        match self {
+            StorageValue::List(list) => list.is_empty(),
+            StorageValue::Hash(hash) => hash.is_empty(),
+            StorageValue::Set(set) => set.is_empty(),
+            StorageValue::SortedSet(zset) => zset.is_empty(),
+            _ => false,
+        }
+    }
+
+    /// The contents of a string value, whatever its encoding.
+    pub fn string_bytes(&self) -> Option<Cow<'_, [u8]>> {
+        match self {
+            StorageValue::String(v) => Some(Cow::Borrowed(v)),
+            StorageValue::Integer(n) => Some(Cow::Owned(n.to_string().into_bytes())),
+            _ => None,
+        }
+    }
+
+    /// The contents of a string value for modifying in place, which first turns an
+    /// integer-encoded value into a plain string.
+    pub fn raw_string_mut(&mut self) -> Option<&mut Vec<u8>> {
+        if let StorageValue::Integer(n) = *self {
+            *self = StorageValue::String(n.to_string().into_bytes());
+        }
+        match self {
+            StorageValue::String(v) => Some(v),
+            _ => None,
+        }
+    }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct StorageData {
     pub value: StorageValue,
+    #[allow(dead_code)]
     pub created_at: SystemTime,
     pub expiry: Option<Duration>,
 }
@@ -23,16 +152,22 @@ impl StorageData {
     }
 }
 
-impl From<String> for StorageData {
-    fn from(s: String) -> StorageData {
+impl From<StorageValue> for StorageData {
+    fn from(value: StorageValue) -> StorageData {
         StorageData {
-            value: StorageValue::String(s),
+            value,
             created_at: SystemTime::now(),
             expiry: None,
         }
     }
 }
 
+impl From<Vec<u8>> for StorageData {
+    fn from(s: Vec<u8>) -> StorageData {
+        StorageData::from(StorageValue::from_bytes(s))
+    }
+}
+
 impl PartialEq for StorageData {
     fn eq(&self, other: &Self) -> bool {
         self.value == other.value && self.expiry == other.expiry
@@ -40,106 +175,686 @@ impl PartialEq for StorageData {
 }
 
 pub struct Storage {
-    store: HashMap<String, StorageData>,
-    expiry: HashMap<String, SystemTime>,
-    active_expiry: bool,
+    store: ScanMap<StorageData>,
+    expiry: HashMap<Vec<u8>, SystemTime>,
+    config: Config,
+    lazyfree: Sender<Vec<StorageData>>,
+    blocked: BlockedClients,
+    /// Keys that may hold a hash with field TTLs, for active expiry to visit. Entries
+    /// can outlive the hash or its TTLs and are dropped when visited.
+    field_expiry: HashSet<Vec<u8>>,
 }
 
 impl Storage {
+    #[cfg(test)]
     pub fn new() -> Self {
-        let store: HashMap<String, StorageData> = HashMap::new();
-        let expiry: HashMap<String, SystemTime> = HashMap::new();
-        let active_expiry: bool = true;
+        Self::from_config(Config::default())
+    }
+
+    pub fn from_config(config: Config) -> Self {
+        let store: ScanMap<StorageData> = ScanMap::default();
+        let expiry: HashMap<Vec<u8>, SystemTime> = HashMap::new();
+        // Values removed by UNLINK are dropped on this thread, so freeing a large value
+        // never holds up the clients waiting on the storage lock.
+        let (lazyfree, unlinked) = mpsc::channel::<Vec<StorageData>>();
+        thread::spawn(move || {
+            for values in unlinked.iter() {
+                drop(values);
+            }
+        });
         Self {
             store,
             expiry,
-            active_expiry,
+            config,
+            lazyfree,
+            blocked: BlockedClients::default(),
+            field_expiry: HashSet::new(),
         }
     }
 
-    pub fn process_command(&mut self, command: &Vec<String>) -> StorageResult<RESP> {
-        match command[0].to_lowercase().as_str() {
-            "ping" => self.command_ping(command),
-            "echo" => self.command_echo(command),
-            "get" => self.command_get(command),
-            "set" => self.command_set(command),
-            _ => Err(StorageError::CommandNotAvailable(command[0].clone())),
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    pub fn process_command(&mut self, command: &[Vec<u8>]) -> StorageResult<RESP> {
+        let result = self.execute_command(command);
+        self.serve_blocked_clients();
+        result
+    }
+
+    fn execute_command(&mut self, command: &[Vec<u8>]) -> StorageResult<RESP> {
+        if command.is_empty() {
+            return Err(StorageError::IncorrectRequest);
+        }
+        match command[0].to_ascii_lowercase().as_slice() {
+            b"ping" => self.command_ping(command),
+            b"echo" => self.command_echo(command),
+            b"get" => self.command_get(command),
+            b"set" => self.command_set(command),
+            b"dbsize" => self.command_dbsize(command),
+            b"expire" => self.command_expire(command, 1000, false),
+            b"pexpire" => self.command_expire(command, 1, false),
+            b"expireat" => self.command_expire(command, 1000, true),
+            b"pexpireat" => self.command_expire(command, 1, true),
+            b"ttl" => self.command_ttl(command, 1000, false),
+            b"pttl" => self.command_ttl(command, 1, false),
+            b"expiretime" => self.command_ttl(command, 1000, true),
+            b"pexpiretime" => self.command_ttl(command, 1, true),
+            b"persist" => self.command_persist(command),
+            b"del" => self.command_del(command, false),
+            b"unlink" => self.command_del(command, true),
+            b"exists" => self.command_exists(command),
+            b"type" => self.command_type(command),
+            b"rename" => self.command_rename(command, false),
+            b"renamenx" => self.command_rename(command, true),
+            b"copy" => self.command_copy(command),
+            b"keys" => self.command_keys(command),
+            b"scan" => self.command_scan(command),
+            b"object" => self.command_object(command),
+            b"incr" => self.command_incr(command, 1),
+            b"decr" => self.command_incr(command, -1),
+            b"incrby" => self.command_incrby(command, false),
+            b"decrby" => self.command_incrby(command, true),
+            b"incrbyfloat" => self.command_incrbyfloat(command),
+            b"append" => self.command_append(command),
+            b"strlen" => self.command_strlen(command),
+            b"getrange" => self.command_getrange(command),
+            b"setrange" => self.command_setrange(command),
+            b"getdel" => self.command_getdel(command),
+            b"getex" => self.command_getex(command),
+            b"setnx" => self.command_setnx(command),
+            b"setex" => self.command_setex(command, 1000),
+            b"psetex" => self.command_setex(command, 1),
+            b"mget" => self.command_mget(command),
+            b"mset" => self.command_mset(command, false),
+            b"msetnx" => self.command_mset(command, true),
+            b"lpush" => self.command_push(command, ListEnd::Left, false),
+            b"rpush" => self.command_push(command, ListEnd::Right, false),
+            b"lpushx" => self.command_push(command, ListEnd::Left, true),
+            b"rpushx" => self.command_push(command, ListEnd::Right, true),
+            b"lpop" => self.command_pop(command, ListEnd::Left),
+            b"rpop" => self.command_pop(command, ListEnd::Right),
+            b"llen" => self.command_llen(command),
+            b"lrange" => self.command_lrange(command),
+            b"lindex" => self.command_lindex(command),
+            b"lset" => self.command_lset(command),
+            b"linsert" => self.command_linsert(command),
+            b"lrem" => self.command_lrem(command),
+            b"ltrim" => self.command_ltrim(command),
+            b"lpos" => self.command_lpos(command),
+            b"lmove" => self.command_lmove(command),
+            b"rpoplpush" => self.command_rpoplpush(command),
+            b"blpop" | b"brpop" | b"blmove" | b"blmpop" => self.command_blocking(command),
+            b"bzpopmin" | b"bzpopmax" | b"bzmpop" => self.command_blocking(command),
+            b"hset" => self.command_hset(command),
+            b"hsetnx" => self.command_hsetnx(command),
+            b"hget" => self.command_hget(command),
+            b"hmget" => self.command_hmget(command),
+            b"hgetall" => self.command_hgetall(command),
+            b"hdel" => self.command_hdel(command),
+            b"hexists" => self.command_hexists(command),
+            b"hlen" => self.command_hlen(command),
+            b"hkeys" => self.command_hkeys(command, false),
+            b"hvals" => self.command_hkeys(command, true),
+            b"hstrlen" => self.command_hstrlen(command),
+            b"hincrby" => self.command_hincrby(command),
+            b"hincrbyfloat" => self.command_hincrbyfloat(command),
+            b"hrandfield" => self.command_hrandfield(command),
+            b"hscan" => self.command_hscan(command),
+            b"hexpire" => self.command_hexpire(command, 1000, false),
+            b"hpexpire" => self.command_hexpire(command, 1, false),
+            b"hexpireat" => self.command_hexpire(command, 1000, true),
+            b"httl" => self.command_httl(command, 1000),
+            b"hpttl" => self.command_httl(command, 1),
+            b"hpersist" => self.command_hpersist(command),
+            b"sadd" => self.command_sadd(command),
+            b"srem" => self.command_srem(command),
+            b"smembers" => self.command_smembers(command),
+            b"sismember" => self.command_sismember(command),
+            b"smismember" => self.command_smismember(command),
+            b"scard" => self.command_scard(command),
+            b"spop" => self.command_spop(command),
+            b"srandmember" => self.command_srandmember(command),
+            b"smove" => self.command_smove(command),
+            b"sinter" => self.command_setop(command, SetOperation::Intersection),
+            b"sunion" => self.command_setop(command, SetOperation::Union),
+            b"sdiff" => self.command_setop(command, SetOperation::Difference),
+            b"sinterstore" => self.command_setop_store(command, SetOperation::Intersection),
+            b"sunionstore" => self.command_setop_store(command, SetOperation::Union),
+            b"sdiffstore" => self.command_setop_store(command, SetOperation::Difference),
+            b"sintercard" => self.command_sintercard(command),
+            b"sscan" => self.command_sscan(command),
+            b"zadd" => self.command_zadd(command),
+            b"zincrby" => self.command_zincrby(command),
+            b"zrem" => self.command_zrem(command),
+            b"zscore" => self.command_zscore(command),
+            b"zcard" => self.command_zcard(command),
+            b"zcount" => self.command_zcount(command),
+            b"zrank" => self.command_zrank(command, false),
+            b"zrevrank" => self.command_zrank(command, true),
+            b"zrange" => self.command_zrange(command),
+            b"zrangestore" => self.command_zrangestore(command),
+            b"zpopmin" => self.command_zpop(command, false),
+            b"zpopmax" => self.command_zpop(command, true),
+            b"zunionstore" => self.command_zsetop_store(command, SetOperation::Union),
+            b"zinterstore" => self.command_zsetop_store(command, SetOperation::Intersection),
+            b"zscan" => self.command_zscan(command),
+            b"xadd" => self.command_xadd(command),
+            b"xlen" => self.command_xlen(command),
+            b"xrange" => self.command_xrange(command, false),
+            b"xrevrange" => self.command_xrange(command, true),
+            b"xdel" => self.command_xdel(command),
+            b"xtrim" => self.command_xtrim(command),
+            b"xread" | b"xreadgroup" => self.command_xread(command),
+            b"xgroup" => self.command_xgroup(command),
+            b"xack" => self.command_xack(command),
+            b"xpending" => self.command_xpending(command),
+            b"xclaim" => self.command_xclaim(command),
+            b"xautoclaim" => self.command_xautoclaim(command),
+            b"xinfo" => self.command_xinfo(command),
+            b"config" => self.command_config(command),
+            _ => Err(StorageError::CommandNotAvailable(
+                String::from_utf8_lossy(&command[0]).into_owned(),
+            )),
         }
     }
 
-    fn command_ping(&self, _command: &Vec<String>) -> StorageResult<RESP> {
+    fn command_ping(&self, _command: &[Vec<u8>]) -> StorageResult<RESP> {
         Ok(RESP::SimpleString("PONG".to_string()))
     }
 
-    fn command_echo(&self, command: &Vec<String>) -> StorageResult<RESP> {
+    fn command_echo(&self, command: &[Vec<u8>]) -> StorageResult<RESP> {
+        if command.len() != 2 {
+            return Err(StorageError::WrongArgumentCount(command_name(command)));
+        }
         Ok(RESP::BulkString(command[1].clone()))
     }
 
-    fn set(&mut self, key: String, value: String, args: SetArgs) -> StorageResult<String> {
+    fn set(&mut self, key: Vec<u8>, value: Vec<u8>, args: SetArgs) -> StorageResult<String> {
         let mut data = StorageData::from(value);
 
-        if let Some(value) = args.expiry {
-            let expiry = match value {
-                KeyExpiry::EX(v) => Duration::from_secs(v),
-                KeyExpiry::PX(v) => Duration::from_millis(v),
-            };
-            data.add_expiry(expiry);
-            self.expiry
-                .insert(key.clone(), SystemTime::now().add(expiry));
+        let now = SystemTime::now();
+        let deadline = match &args.expiry {
+            Some(KeyExpiry::KEEPTTL) => match self.lookup(&key) {
+                Some(_) => self.expiry.get(&key).copied(),
+                None => None,
+            },
+            Some(expiry) => expiry_deadline(expiry, now),
+            None => None,
+        };
+        if args.expiry.is_some() && args.expiry != Some(KeyExpiry::KEEPTTL) && deadline.is_none() {
+            return Err(StorageError::InvalidArgument(String::from(
+                "invalid expire time in 'set' command",
+            )));
+        }
+
+        // A SET without KEEPTTL replaces the old TTL along with the value.
+        match deadline {
+            Some(deadline) => {
+                data.add_expiry(deadline.duration_since(now).unwrap_or_default());
+                self.expiry.insert(key.clone(), deadline);
+            }
+            None => {
+                self.expiry.remove(&key);
+            }
         }
-        self.store.insert(key.clone(), data);
+        self.store.insert(key, data);
         Ok(String::from("OK"))
     }
 
-    fn get(&mut self, key: String) -> StorageResult<Option<String>> {
-        if let Some(&expiry) = self.expiry.get(&key) {
+    /// Tells whether the TTL of `key` has passed at `now`, for the read-only paths that
+    /// skip expired keys and leave them to be reclaimed later.
+    fn is_expired(&self, key: &[u8], now: SystemTime) -> bool {
+        self.expiry.get(key).is_some_and(|&expiry| now >= expiry)
+    }
+
+    /// Returns the entry stored at `key`, first removing it if its TTL has passed.
+    fn lookup(&mut self, key: &[u8]) -> Option<&mut StorageData> {
+        if let Some(&expiry) = self.expiry.get(key) {
             if SystemTime::now() >= expiry {
-                self.expiry.remove(&key);
-                self.store.remove(&key);
-                return Ok(None); // Key has expired
+                self.expiry.remove(key);
+                self.store.remove(key);
+                return None;
             }
         }
-        match self.store.get(&key) {
-            Some(StorageData {
-                value: StorageValue::String(v),
-                created_at: _,
-                expiry: _,
-            }) => Ok(Some(v.clone())),
-            None => Ok(None),
-        }
+        self.store.get_mut(key)
+    }
+
+    fn get(&mut self, key: Vec<u8>) -> StorageResult<Option<Vec<u8>>> {
+        Ok(self.string_at(&key)?.map(|v| v.into_owned()))
     }
 
-    fn command_set(&mut self, command: &Vec<String>) -> StorageResult<RESP> {
+    fn command_set(&mut self, command: &[Vec<u8>]) -> StorageResult<RESP> {
         if command.len() < 3 {
-            return Err(StorageError::CommandSyntaxError(command.join(" ")));
+            return Err(StorageError::WrongArgumentCount(command_name(command)));
         }
 
         let key = command[1].clone();
         let value = command[2].clone();
-        let args = parse_set_arguments(&command[3..].to_vec())?;
-        let _ = self.set(key, value, args);
+        let args = parse_set_arguments(&command[3..])?;
+
+        let get = args.get;
+        let exists = self.lookup(&key).is_some();
+        let old_value = match get {
+            true => self.string_at(&key)?.map(Cow::into_owned),
+            false => None,
+        };
+        let skip = match args.existence {
+            Some(KeyExistence::NX) => exists,
+            Some(KeyExistence::XX) => !exists,
+            None => false,
+        };
+        if !skip {
+            self.set(key, value, args)?;
+        }
+
+        // With GET the reply is always the previous value, whether or not the write happened.
+        if get {
+            return Ok(old_value.map_or(RESP::Null, RESP::BulkString));
+        }
+        if skip {
+            return Ok(RESP::Null);
+        }
         Ok(RESP::SimpleString(String::from("OK")))
     }
 
-    fn command_get(&mut self, command: &Vec<String>) -> StorageResult<RESP> {
+    fn command_get(&mut self, command: &[Vec<u8>]) -> StorageResult<RESP> {
         if command.len() != 2 {
-            return Err(StorageError::CommandSyntaxError(command.join(" ")));
+            return Err(StorageError::WrongArgumentCount(command_name(command)));
         }
         let key = command[1].clone();
-        let output = self.get(key);
-        match output {
-            Ok(Some(value)) => Ok(RESP::BulkString(value)),
-            Ok(None) => Ok(RESP::Null),
-            Err(_) => Err(StorageError::CommandInternalError(command.join(" "))),
+        match self.get(key)? {
+            Some(value) => Ok(RESP::BulkString(value)),
+            None => Ok(RESP::Null),
+        }
+    }
+
+    fn command_dbsize(&self, command: &[Vec<u8>]) -> StorageResult<RESP> {
+        if command.len() != 1 {
+            return Err(StorageError::WrongArgumentCount(command_name(command)));
+        }
+        Ok(RESP::Integer(self.store.len() as i64))
+    }
+
+    /// Handles DEL and UNLINK, which differ only in where the removed values are freed.
+    fn command_del(&mut self, command: &[Vec<u8>], lazy: bool) -> StorageResult<RESP> {
+        if command.len() < 2 {
+            return Err(StorageError::WrongArgumentCount(command_name(command)));
+        }
+        let mut removed = Vec::new();
+        for key in command[1..].iter() {
+            if self.lookup(key).is_some() {
+                removed.extend(self.remove(key));
+            }
+        }
+        let count = removed.len();
+        if lazy && count > 0 {
+            // The receiving thread only exits once the storage is gone, so this cannot fail.
+            let _ = self.lazyfree.send(removed);
+        }
+        Ok(RESP::Integer(count as i64))
+    }
+
+    fn command_exists(&mut self, command: &[Vec<u8>]) -> StorageResult<RESP> {
+        if command.len() < 2 {
+            return Err(StorageError::WrongArgumentCount(command_name(command)));
+        }
+        let mut count = 0;
+        for key in command[1..].iter() {
+            if self.lookup(key).is_some() {
+                count += 1;
+            }
+        }
+        Ok(RESP::Integer(count))
+    }
+
+    fn command_type(&mut self, command: &[Vec<u8>]) -> StorageResult<RESP> {
+        if command.len() != 2 {
+            return Err(StorageError::WrongArgumentCount(command_name(command)));
+        }
+        let name = match self.lookup(&command[1]) {
+            Some(data) => data.value.type_name(),
+            None => "none",
+        };
+        Ok(RESP::SimpleString(String::from(name)))
+    }
+
+    /// Handles RENAME and, with `nx`, RENAMENX. The TTL of the source moves with it and
+    /// any TTL the destination had is dropped along with its value.
+    fn command_rename(&mut self, command: &[Vec<u8>], nx: bool) -> StorageResult<RESP> {
+        if command.len() != 3 {
+            return Err(StorageError::WrongArgumentCount(command_name(command)));
+        }
+        let (source, destination) = (&command[1], &command[2]);
+        if self.lookup(source).is_none() {
+            return Err(StorageError::InvalidArgument(String::from("no such key")));
+        }
+        let reply = |renamed: bool| match nx {
+            true => RESP::Integer(renamed as i64),
+            false => RESP::SimpleString(String::from("OK")),
+        };
+        if source == destination {
+            return Ok(reply(false));
+        }
+        if nx && self.lookup(destination).is_some() {
+            return Ok(reply(false));
+        }
+
+        let deadline = self.expiry.get(source.as_slice()).copied();
+        if let Some(data) = self.remove(source) {
+            self.remove(destination);
+            self.store.insert(destination.clone(), data);
+            if let Some(deadline) = deadline {
+                self.expiry.insert(destination.clone(), deadline);
+            }
+            self.signal_key_ready(destination);
+            self.track_field_expiry(destination);
+        }
+        Ok(reply(true))
+    }
+
+    fn command_copy(&mut self, command: &[Vec<u8>]) -> StorageResult<RESP> {
+        if command.len() < 3 {
+            return Err(StorageError::WrongArgumentCount(command_name(command)));
+        }
+        let (source, destination) = (&command[1], &command[2]);
+        let mut replace = false;
+        let mut idx: usize = 3;
+        while idx < command.len() {
+            match command[idx].to_ascii_lowercase().as_slice() {
+                b"replace" => {
+                    replace = true;
+                    idx += 1;
+                }
+                // There is only one database, so DB 0 is the only valid target.
+                b"db" if idx + 1 < command.len() => {
+                    if parse_integer(&command[idx + 1])? != 0 {
+                        return Err(StorageError::InvalidArgument(String::from(
+                            "DB index is out of range",
+                        )));
+                    }
+                    idx += 2;
+                }
+                _ => return Err(StorageError::CommandSyntaxError(command_to_string(command))),
+            }
+        }
+        if source == destination {
+            return Err(StorageError::InvalidArgument(String::from(
+                "source and destination objects are the same",
+            )));
+        }
+
+        let data = match self.lookup(source) {
+            Some(data) => data.clone(),
+            None => return Ok(RESP::Integer(0)),
+        };
+        if self.lookup(destination).is_some() {
+            if !replace {
+                return Ok(RESP::Integer(0));
+            }
+            self.remove(destination);
+        }
+        let deadline = self.expiry.get(source.as_slice()).copied();
+        self.store.insert(destination.clone(), data);
+        if let Some(deadline) = deadline {
+            self.expiry.insert(destination.clone(), deadline);
+        }
+        self.signal_key_ready(destination);
+        self.track_field_expiry(destination);
+        Ok(RESP::Integer(1))
+    }
+
+    fn command_keys(&self, command: &[Vec<u8>]) -> StorageResult<RESP> {
+        if command.len() != 2 {
+            return Err(StorageError::WrongArgumentCount(command_name(command)));
+        }
+        let pattern = &command[1];
+        let now = SystemTime::now();
+        let keys = self
+            .store
+            .keys()
+            .filter(|key| !self.is_expired(key, now) && glob_match(pattern, key, false))
+            .map(|key| RESP::BulkString(key.to_vec()))
+            .collect();
+        Ok(RESP::Array(keys))
+    }
+
+    /// Iterates the keyspace in the order of `scan_hash`, which is what lets a cursor
+    /// survive the map growing or rehashing between calls.
+    fn command_scan(&self, command: &[Vec<u8>]) -> StorageResult<RESP> {
+        if command.len() < 2 {
+            return Err(StorageError::WrongArgumentCount(command_name(command)));
+        }
+        let cursor = parse_cursor(&command[1])?;
+        let args = parse_scan_arguments(&command[2..], true)?;
+        let now = SystemTime::now();
+        let (next, batch) = self.store.scan(cursor, args.count);
+        let keys = batch
+            .into_iter()
+            .filter(|(key, data)| {
+                !self.is_expired(key, now)
+                    && args
+                        .pattern
+                        .as_ref()
+                        .is_none_or(|pattern| glob_match(pattern, key, false))
+                    && args
+                        .type_name
+                        .as_ref()
+                        .is_none_or(|name| name == data.value.type_name().as_bytes())
+            })
+            .map(|(key, _)| RESP::BulkString(key.to_vec()))
+            .collect();
+        Ok(RESP::Array(vec![
+            RESP::BulkString(next.to_string().into_bytes()),
+            RESP::Array(keys),
+        ]))
+    }
+
+    fn command_object(&mut self, command: &[Vec<u8>]) -> StorageResult<RESP> {
+        if command.len() != 3 {
+            return Err(StorageError::WrongArgumentCount(command_name(command)));
+        }
+        if !command[1].eq_ignore_ascii_case(b"encoding") {
+            return Err(StorageError::CommandSyntaxError(command_to_string(command)));
+        }
+        match self.lookup(&command[2]) {
+            Some(data) => Ok(RESP::BulkString(data.value.encoding().as_bytes().to_vec())),
+            None => Ok(RESP::Null),
+        }
+    }
+
+    /// Handles the EXPIRE family; `unit` is the length of the argument's unit in
+    /// milliseconds and `absolute` selects a Unix timestamp instead of a relative TTL.
+    fn command_expire(
+        &mut self,
+        command: &[Vec<u8>],
+        unit: i64,
+        absolute: bool,
+    ) -> StorageResult<RESP> {
+        if command.len() < 3 {
+            return Err(StorageError::WrongArgumentCount(command_name(command)));
+        }
+        let key = &command[1];
+        let value = parse_integer(&command[2])?;
+
+        let mut conditions = ExpireConditions::default();
+        for option in command[3..].iter() {
+            if !conditions.set(option) {
+                return Err(StorageError::InvalidArgument(format!(
+                    "Unsupported option {}",
+                    String::from_utf8_lossy(option)
+                )));
+            }
+        }
+        conditions.validate()?;
+
+        let invalid = || {
+            StorageError::InvalidArgument(format!(
+                "invalid expire time in '{}' command",
+                String::from_utf8_lossy(&command[0]).to_lowercase()
+            ))
+        };
+        let mut when = value.checked_mul(unit).ok_or_else(invalid)?;
+        if !absolute {
+            when = when.checked_add(now_millis()).ok_or_else(invalid)?;
+        }
+
+        if self.lookup(key).is_none() {
+            return Ok(RESP::Integer(0));
+        }
+        let current = self.expiry.get(key.as_slice()).copied();
+        if !conditions.allow(current.map(millis_since_epoch), when) {
+            return Ok(RESP::Integer(0));
+        }
+
+        if when <= now_millis() {
+            self.remove(key);
+        } else {
+            self.set_deadline(key, UNIX_EPOCH + Duration::from_millis(when as u64));
+        }
+        Ok(RESP::Integer(1))
+    }
+
+    /// Handles TTL, PTTL, EXPIRETIME and PEXPIRETIME, replying -2 for a missing key and
+    /// -1 for a key without a TTL.
+    fn command_ttl(
+        &mut self,
+        command: &[Vec<u8>],
+        unit: i64,
+        absolute: bool,
+    ) -> StorageResult<RESP> {
+        if command.len() != 2 {
+            return Err(StorageError::WrongArgumentCount(command_name(command)));
+        }
+        let key = &command[1];
+        if self.lookup(key).is_none() {
+            return Ok(RESP::Integer(-2));
+        }
+        let deadline = match self.expiry.get(key.as_slice()) {
+            Some(&deadline) => millis_since_epoch(deadline),
+            None => return Ok(RESP::Integer(-1)),
+        };
+        // A remaining TTL is rounded to the nearest unit, a deadline is truncated.
+        if absolute {
+            return Ok(RESP::Integer(deadline / unit));
+        }
+        let millis = (deadline - now_millis()).max(0);
+        Ok(RESP::Integer((millis + unit / 2) / unit))
+    }
+
+    fn command_persist(&mut self, command: &[Vec<u8>]) -> StorageResult<RESP> {
+        if command.len() != 2 {
+            return Err(StorageError::WrongArgumentCount(command_name(command)));
+        }
+        let persisted = self.lookup(&command[1]).is_some() && self.persist(&command[1]);
+        Ok(RESP::Integer(persisted as i64))
+    }
+
+    /// Removes the TTL of `key`, returning whether it had one.
+    fn persist(&mut self, key: &[u8]) -> bool {
+        if self.expiry.remove(key).is_none() {
+            return false;
+        }
+        if let Some(data) = self.store.get_mut(key) {
+            data.expiry = None;
+        }
+        true
+    }
+
+    /// Sets the deadline of an existing key, keeping `Storage.expiry` and the entry's own
+    /// TTL in step.
+    fn set_deadline(&mut self, key: &[u8], deadline: SystemTime) {
+        if let Some(data) = self.store.get_mut(key) {
+            data.add_expiry(
+                deadline
+                    .duration_since(SystemTime::now())
+                    .unwrap_or_default(),
+            );
+            self.expiry.insert(key.to_vec(), deadline);
         }
     }
 
+    /// Removes `key` if it holds a collection that has just been emptied.
+    fn remove_if_empty(&mut self, key: &[u8]) {
+        if self
+            .store
+            .get(key)
+            .is_some_and(|data| data.value.is_empty_collection())
+        {
+            self.remove(key);
+        }
+    }
+
+    /// Removes `key` and its TTL, returning the entry it held.
+    fn remove(&mut self, key: &[u8]) -> Option<StorageData> {
+        self.expiry.remove(key);
+        self.store.remove(key)
+    }
+
+    fn command_config(&mut self, command: &[Vec<u8>]) -> StorageResult<RESP> {
+        if command.len() < 2 {
+            return Err(StorageError::WrongArgumentCount(command_name(command)));
+        }
+        match command[1].to_ascii_lowercase().as_slice() {
+            b"get" if command.len() > 2 => {
+                let parameters = self.config.get(&command[2..]);
+                Ok(RESP::Map(
+                    parameters
+                        .into_iter()
+                        .map(|(name, value)| {
+                            (
+                                RESP::BulkString(name.into_bytes()),
+                                RESP::BulkString(value.into_bytes()),
+                            )
+                        })
+                        .collect(),
+                ))
+            }
+            b"set" if command.len() > 2 && command.len().is_multiple_of(2) => {
+                let pairs: Vec<(String, String)> = command[2..]
+                    .chunks(2)
+                    .map(|pair| {
+                        (
+                            String::from_utf8_lossy(&pair[0]).into_owned(),
+                            String::from_utf8_lossy(&pair[1]).into_owned(),
+                        )
+                    })
+                    .collect();
+                self.config.set_at_runtime(&pairs).map_err(|e| {
+                    let name = match &e {
+                        ConfigError::InvalidValue(name, _)
+                        | ConfigError::WrongArgumentCount(name)
+                        | ConfigError::UnknownDirective(name)
+                        | ConfigError::Immutable(name) => name.clone(),
+                        _ => String::new(),
+                    };
+                    StorageError::InvalidArgument(format!(
+                        "CONFIG SET failed (possibly related to argument '{}') - {}",
+                        name, e
+                    ))
+                })?;
+                Ok(RESP::SimpleString(String::from("OK")))
+            }
+            // No statistics are kept, so there is nothing to reset.
+            b"resetstat" if command.len() == 2 => Ok(RESP::SimpleString(String::from("OK"))),
+            b"rewrite" if command.len() == 2 => {
+                self.config.rewrite().map_err(|e| match e {
+                    ConfigError::NoConfigFile => StorageError::InvalidArgument(e.to_string()),
+                    _ => StorageError::InvalidArgument(format!("Rewriting config file: {}", e)),
+                })?;
+                Ok(RESP::SimpleString(String::from("OK")))
+            }
+            _ => Err(StorageError::CommandSyntaxError(command_to_string(command))),
+        }
+    }
+
+    #[cfg(test)]
     pub fn set_active_expiry(&mut self, active: bool) {
-        self.active_expiry = active;
+        self.config.active_expire = active;
     }
 
     pub fn expire_keys(&mut self) {
-        if !self.active_expiry {
+        if !self.config.active_expire {
             return;
         }
         let now = SystemTime::now();
@@ -151,11 +866,191 @@ impl Storage {
                 true
             }
         });
+        self.field_expiry.retain(|key| {
+            let hash = match self.store.get_mut(key) {
+                Some(StorageData {
+                    value: StorageValue::Hash(hash),
+                    ..
+                }) => hash,
+                _ => return false,
+            };
+            hash.remove_expired(now);
+            if hash.is_empty() {
+                self.store.remove(key);
+                self.expiry.remove(key);
+                return false;
+            }
+            hash.has_deadlines()
+        });
+    }
+}
+
+/// The NX, XX, GT and LT options of the EXPIRE family.
+#[derive(Debug, Default, Clone, Copy)]
+struct ExpireConditions {
+    nx: bool,
+    xx: bool,
+    gt: bool,
+    lt: bool,
+}
+
+impl ExpireConditions {
+    /// Records `option` if it is one of the conditions, returning whether it was.
+    fn set(&mut self, option: &[u8]) -> bool {
+        match option.to_ascii_lowercase().as_slice() {
+            b"nx" => self.nx = true,
+            b"xx" => self.xx = true,
+            b"gt" => self.gt = true,
+            b"lt" => self.lt = true,
+            _ => return false,
+        }
+        true
+    }
+
+    fn validate(&self) -> StorageResult<()> {
+        if self.nx && (self.xx || self.gt || self.lt) {
+            return Err(StorageError::InvalidArgument(String::from(
+                "NX and XX, GT or LT options at the same time are not compatible",
+            )));
+        }
+        if self.gt && self.lt {
+            return Err(StorageError::InvalidArgument(String::from(
+                "GT and LT options at the same time are not compatible",
+            )));
+        }
+        Ok(())
+    }
+
+    /// Whether the deadline `current`, if any, may be replaced by `when`, both in Unix
+    /// milliseconds. Without a TTL a value lives forever, so GT never applies to it and
+    /// LT always does.
+    fn allow(&self, current: Option<i64>, when: i64) -> bool {
+        match current {
+            None => !self.xx && !self.gt,
+            Some(current) => {
+                !self.nx && (!self.gt || when > current) && (!self.lt || when < current)
+            }
+        }
+    }
+}
+
+/// Parses a command argument as a signed 64 bit integer the way Redis does: an optional
+/// minus sign followed by digits, without a plus sign, leading zeros or spaces.
+fn parse_integer(argument: &[u8]) -> StorageResult<i64> {
+    let digits = argument.strip_prefix(b"-").unwrap_or(argument);
+    let canonical = match digits {
+        [] => false,
+        [b'0'] => digits.len() == argument.len(),
+        [b'0', ..] => false,
+        _ => digits.iter().all(u8::is_ascii_digit),
+    };
+    if !canonical {
+        return Err(StorageError::NotAnInteger);
+    }
+    std::str::from_utf8(argument)
+        .ok()
+        .and_then(|argument| argument.parse().ok())
+        .ok_or(StorageError::NotAnInteger)
+}
+
+/// Parses a command argument as a float. Infinities are accepted, NaN is not.
+fn parse_float(argument: &[u8]) -> StorageResult<f64> {
+    std::str::from_utf8(argument)
+        .ok()
+        .and_then(|argument| argument.parse::<f64>().ok())
+        .filter(|value| !value.is_nan())
+        .ok_or(StorageError::NotAFloat)
+}
+
+/// Formats the result of INCRBYFLOAT and HINCRBYFLOAT, which Redis never writes in
+/// exponent notation: it uses `%.17Lf` and trims the trailing zeros. Rust prints the
+/// shortest decimal that reads back as the same value, so only the digits past the
+/// 17th decimal place need rounding away.
+fn format_float(value: f64) -> Vec<u8> {
+    let formatted = value.to_string();
+    let decimals = formatted
+        .split_once('.')
+        .map_or(0, |(_, decimals)| decimals.len());
+    if decimals <= 17 {
+        return formatted.into_bytes();
+    }
+    let rounded = format!("{:.17}", value);
+    let trimmed = rounded.trim_end_matches('0').trim_end_matches('.');
+    trimmed.as_bytes().to_vec()
+}
+
+/// Resolves a relative or absolute SET-style expiry to a deadline, or `None` if it is
+/// `KEEPTTL` or if the deadline in milliseconds since the epoch overflows an `i64`, which
+/// Redis rejects.
+fn expiry_deadline(expiry: &KeyExpiry, now: SystemTime) -> Option<SystemTime> {
+    let deadline = match *expiry {
+        KeyExpiry::EX(v) => now.checked_add(Duration::from_secs(v)),
+        KeyExpiry::PX(v) => now.checked_add(Duration::from_millis(v)),
+        KeyExpiry::EXAT(v) => UNIX_EPOCH.checked_add(Duration::from_secs(v)),
+        KeyExpiry::PXAT(v) => UNIX_EPOCH.checked_add(Duration::from_millis(v)),
+        KeyExpiry::KEEPTTL => None,
+    }?;
+    let millis = deadline.duration_since(UNIX_EPOCH).ok()?.as_millis();
+    i64::try_from(millis).ok().map(|_| deadline)
+}
+
+/// Whether a collection is small enough for Redis to store it as a listpack.
+fn is_listpack<'a, T: AsRef<[u8]> + 'a>(mut items: impl ExactSizeIterator<Item = &'a T>) -> bool {
+    items.len() <= LISTPACK_MAX_ENTRIES
+        && items.all(|item| item.as_ref().len() <= LISTPACK_MAX_VALUE)
+}
+
+/// Resolves an inclusive `start..=stop` range of possibly negative indexes, counted from
+/// the end, against a collection of `length` items. Returns `None` when it is empty.
+fn resolve_range(start: i64, stop: i64, length: usize) -> Option<(usize, usize)> {
+    let length = length as i64;
+    let start = if start < 0 { start + length } else { start }.max(0);
+    let stop = if stop < 0 { stop + length } else { stop }.min(length - 1);
+    if start > stop || start >= length {
+        return None;
+    }
+    Some((start as usize, stop as usize))
+}
+
+/// Milliseconds between the epoch and `time`, saturating at the bounds of an `i64`.
+fn millis_since_epoch(time: SystemTime) -> i64 {
+    match time.duration_since(UNIX_EPOCH) {
+        Ok(duration) => i64::try_from(duration.as_millis()).unwrap_or(i64::MAX),
+        Err(e) => i64::try_from(e.duration().as_millis()).map_or(i64::MIN, |millis| -millis),
+    }
+}
+
+fn now_millis() -> i64 {
+    millis_since_epoch(SystemTime::now())
+}
+
+/// Shorthands shared by the tests of the storage and of its command modules.
+#[cfg(test)]
+pub(crate) mod test_helpers {
+    use super::Storage;
+    use crate::resp::RESP;
+    use crate::storage_result::StorageResult;
+
+    pub(crate) fn command(args: &[&str]) -> Vec<Vec<u8>> {
+        args.iter().map(|arg| arg.as_bytes().to_vec()).collect()
+    }
+
+    pub(crate) fn run(storage: &mut Storage, args: &[&str]) -> StorageResult<RESP> {
+        storage.process_command(&command(args))
+    }
+
+    pub(crate) fn bulk(value: &str) -> RESP {
+        RESP::BulkString(value.as_bytes().to_vec())
+    }
+
+    pub(crate) fn bulks(values: &[&str]) -> RESP {
+        RESP::Array(values.iter().map(|value| bulk(value)).collect())
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use super::test_helpers::command;
     use super::*;
 
     #[test]
@@ -163,13 +1058,23 @@ mod tests {
         let storage: Storage = Storage::new();
         assert_eq!(storage.store.len(), 0);
         assert_eq!(storage.expiry.len(), 0);
-        assert_eq!(storage.expiry, HashMap::<String, SystemTime>::new());
-        assert!(storage.active_expiry);
+        assert_eq!(storage.expiry, HashMap::<Vec<u8>, SystemTime>::new());
+        assert!(storage.config.active_expire);
+    }
+
+    #[test]
+    fn test_create_from_config() {
+        let config = Config {
+            active_expire: false,
+            ..Config::default()
+        };
+        let storage: Storage = Storage::from_config(config);
+        assert!(!storage.config.active_expire);
     }
 
     #[test]
     fn test_command_ping() {
-        let command = vec![String::from("ping")];
+        let command = vec![b"ping".to_vec()];
         let storage: Storage = Storage::new();
         let output = storage.command_ping(&command).unwrap();
         assert_eq!(output, RESP::SimpleString(String::from("PONG")));
@@ -177,7 +1082,7 @@ mod tests {
 
     #[test]
     fn test_command_ping_uppercase() {
-        let command = vec![String::from("PING")];
+        let command = vec![b"PING".to_vec()];
         let storage: Storage = Storage::new();
         let output = storage.command_ping(&command).unwrap();
         assert_eq!(output, RESP::SimpleString(String::from("PONG")));
@@ -185,26 +1090,51 @@ mod tests {
 
     #[test]
     fn test_command_echo() {
-        let command = vec![String::from("echo"), String::from("Hello, World!")];
+        let command = vec![b"echo".to_vec(), b"Hello, World!".to_vec()];
         let storage: Storage = Storage::new();
         let output = storage.command_echo(&command).unwrap();
-        assert_eq!(output, RESP::BulkString(String::from("Hello, World!")));
+        assert_eq!(output, RESP::BulkString(b"Hello, World!".to_vec()));
+    }
+
+    #[test]
+    fn test_command_echo_missing_argument() {
+        let command = vec![b"echo".to_vec()];
+        let storage: Storage = Storage::new();
+        let error = storage.command_echo(&command).unwrap_err();
+        assert_eq!(
+            error,
+            StorageError::WrongArgumentCount(String::from("echo"))
+        );
+    }
+
+    #[test]
+    fn test_process_command_empty() {
+        let mut storage: Storage = Storage::new();
+        let error = storage.process_command(&[]).unwrap_err();
+        assert_eq!(error, StorageError::IncorrectRequest);
+    }
+
+    #[test]
+    fn test_process_command_unknown() {
+        let mut storage: Storage = Storage::new();
+        let command = vec![b"foo".to_vec()];
+        let error = storage.process_command(&command).unwrap_err();
+        assert_eq!(
+            error,
+            StorageError::CommandNotAvailable(String::from("foo"))
+        );
     }
 
     #[test]
     fn test_set_value() {
         let mut storage = Storage::new();
-        let some_value = StorageData::from(String::from("some_value"));
+        let some_value = StorageData::from(b"some_value".to_vec());
         let output = storage
-            .set(
-                String::from("some_key"),
-                String::from("some_value"),
-                SetArgs::new(),
-            )
+            .set(b"some_key".to_vec(), b"some_value".to_vec(), SetArgs::new())
             .unwrap();
         assert_eq!(output, String::from("OK"));
         assert_eq!(storage.store.len(), 1);
-        match storage.store.get(&String::from("some_key")) {
+        match storage.store.get(b"some_key".as_slice()) {
             Some(value) => assert_eq!(value, &some_value),
             None => panic!("Value not found in storage"),
         }
@@ -214,18 +1144,18 @@ mod tests {
     fn test_get_value() {
         let mut storage = Storage::new();
         storage.store.insert(
-            String::from("some_key"),
-            StorageData::from(String::from("some_value")),
+            b"some_key".to_vec(),
+            StorageData::from(b"some_value".to_vec()),
         );
-        let result = storage.get(String::from("some_key")).unwrap();
+        let result = storage.get(b"some_key".to_vec()).unwrap();
         assert_eq!(storage.store.len(), 1);
-        assert_eq!(result, Some(String::from("some_value")));
+        assert_eq!(result, Some(b"some_value".to_vec()));
     }
 
     #[test]
     fn test_get_value_key_does_not_exist() {
         let mut storage = Storage::new();
-        let result = storage.get(String::from("null_key")).unwrap();
+        let result = storage.get(b"null_key".to_vec()).unwrap();
         assert_eq!(storage.store.len(), 0);
         assert_eq!(result, None);
     }
@@ -233,11 +1163,7 @@ mod tests {
     #[test]
     fn test_process_command_set() {
         let mut storage: Storage = Storage::new();
-        let command = vec![
-            String::from("set"),
-            String::from("key"),
-            String::from("value"),
-        ];
+        let command = vec![b"set".to_vec(), b"key".to_vec(), b"value".to_vec()];
         let output = storage.process_command(&command).unwrap();
         assert_eq!(output, RESP::SimpleString(String::from("OK")));
         assert_eq!(storage.store.len(), 1);
@@ -245,28 +1171,34 @@ mod tests {
     #[test]
     fn test_process_command_get() {
         let mut storage: Storage = Storage::new();
-        storage.store.insert(
-            String::from("akey"),
-            StorageData::from(String::from("avalue")),
-        );
-        let command = vec![String::from("get"), String::from("akey")];
+        storage
+            .store
+            .insert(b"akey".to_vec(), StorageData::from(b"avalue".to_vec()));
+        let command = vec![b"get".to_vec(), b"akey".to_vec()];
         let output = storage.process_command(&command).unwrap();
-        assert_eq!(output, RESP::BulkString(String::from("avalue")));
+        assert_eq!(output, RESP::BulkString(b"avalue".to_vec()));
         assert_eq!(storage.store.len(), 1);
     }
 
+    #[test]
+    fn test_process_command_dbsize() {
+        let mut storage: Storage = Storage::new();
+        storage
+            .store
+            .insert(b"akey".to_vec(), StorageData::from(b"avalue".to_vec()));
+        let command = vec![b"dbsize".to_vec()];
+        let output = storage.process_command(&command).unwrap();
+        assert_eq!(output, RESP::Integer(1));
+    }
+
     #[test]
     fn test_expire_keys() {
         let mut storage: Storage = Storage::new();
         storage
-            .set(
-                String::from("some_key"),
-                String::from("some_value"),
-                SetArgs::new(),
-            )
+            .set(b"some_key".to_vec(), b"some_value".to_vec(), SetArgs::new())
             .unwrap();
         storage.expiry.insert(
-            String::from("some_key"),
+            b"some_key".to_vec(),
             SystemTime::now() - Duration::from_secs(5),
         );
         storage.expire_keys();
@@ -278,14 +1210,10 @@ mod tests {
         let mut storage = Storage::new();
         storage.set_active_expiry(false);
         storage
-            .set(
-                String::from("some_key"),
-                String::from("some_value"),
-                SetArgs::new(),
-            )
+            .set(b"some_key".to_vec(), b"some_value".to_vec(), SetArgs::new())
             .unwrap();
         storage.expiry.insert(
-            String::from("some_key"),
+            b"some_key".to_vec(),
             SystemTime::now() - Duration::from_secs(5),
         );
         storage.expire_keys();
@@ -295,13 +1223,13 @@ mod tests {
     #[test]
     fn test_set_value_with_px() {
         let mut storage = Storage::new();
-        let mut some_value = StorageData::from(String::from("some_value"));
+        let mut some_value = StorageData::from(b"some_value".to_vec());
         some_value.add_expiry(Duration::from_millis(100));
 
         let output = storage
             .set(
-                String::from("some_key"),
-                String::from("some_value"),
+                b"some_key".to_vec(),
+                b"some_value".to_vec(),
                 SetArgs {
                     expiry: Some(KeyExpiry::PX(100)),
                     existence: None,
@@ -312,12 +1240,746 @@ mod tests {
 
         assert_eq!(output, String::from("OK"));
         assert_eq!(storage.store.len(), 1);
-        match storage.store.get(&String::from("some_key")) {
+        match storage.store.get(b"some_key".as_slice()) {
             Some(value) => {
                 assert_eq!(value, &some_value);
             }
             None => panic!("Value not found in storage"),
         }
-        storage.expiry.get(&String::from("some_key")).unwrap();
+        storage.expiry.get(b"some_key".as_slice()).unwrap();
+    }
+
+    #[test]
+    fn test_command_config_get() {
+        let mut storage = Storage::new();
+        let output = storage
+            .process_command(&command(&["config", "get", "max*", "HZ"]))
+            .unwrap();
+        assert_eq!(
+            output,
+            RESP::Map(vec![
+                (
+                    RESP::BulkString(b"hz".to_vec()),
+                    RESP::BulkString(b"100".to_vec())
+                ),
+                (
+                    RESP::BulkString(b"maxclients".to_vec()),
+                    RESP::BulkString(b"10000".to_vec())
+                ),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_command_config_set() {
+        let mut storage = Storage::new();
+        let output = storage
+            .process_command(&command(&["config", "set", "hz", "50", "timeout", "30"]))
+            .unwrap();
+        assert_eq!(output, RESP::SimpleString(String::from("OK")));
+        assert_eq!(storage.config().hz, 50);
+        assert_eq!(storage.config().timeout, 30);
+    }
+
+    #[test]
+    fn test_command_config_set_invalid() {
+        let mut storage = Storage::new();
+        let error = storage
+            .process_command(&command(&["config", "set", "hz", "50", "port", "7000"]))
+            .unwrap_err();
+        assert!(RESP::from(error)
+            .to_string()
+            .contains("CONFIG SET failed (possibly related to argument 'port')"));
+        assert_eq!(storage.config().hz, 100);
+
+        assert!(storage
+            .process_command(&command(&["config", "set", "hz"]))
+            .is_err());
+    }
+
+    #[test]
+    fn test_command_config_resetstat() {
+        let mut storage = Storage::new();
+        let output = storage
+            .process_command(&command(&["config", "resetstat"]))
+            .unwrap();
+        assert_eq!(output, RESP::SimpleString(String::from("OK")));
+    }
+
+    #[test]
+    fn test_command_config_rewrite_without_file() {
+        let mut storage = Storage::new();
+        assert!(storage
+            .process_command(&command(&["config", "rewrite"]))
+            .is_err());
+    }
+
+    #[test]
+    fn test_command_set_nx() {
+        let mut storage = Storage::new();
+        let output = storage
+            .process_command(&command(&["set", "lock", "a", "NX", "PX", "30000"]))
+            .unwrap();
+        assert_eq!(output, RESP::SimpleString(String::from("OK")));
+        let output = storage
+            .process_command(&command(&["set", "lock", "b", "NX", "PX", "30000"]))
+            .unwrap();
+        assert_eq!(output, RESP::Null);
+        assert_eq!(storage.get(b"lock".to_vec()).unwrap(), Some(b"a".to_vec()));
+    }
+
+    #[test]
+    fn test_command_set_nx_after_expiry() {
+        let mut storage = Storage::new();
+        storage
+            .process_command(&command(&["set", "lock", "a", "PX", "100"]))
+            .unwrap();
+        storage
+            .expiry
+            .insert(b"lock".to_vec(), SystemTime::now() - Duration::from_secs(1));
+        let output = storage
+            .process_command(&command(&["set", "lock", "b", "NX"]))
+            .unwrap();
+        assert_eq!(output, RESP::SimpleString(String::from("OK")));
+        assert_eq!(storage.get(b"lock".to_vec()).unwrap(), Some(b"b".to_vec()));
+    }
+
+    #[test]
+    fn test_command_set_xx() {
+        let mut storage = Storage::new();
+        let output = storage
+            .process_command(&command(&["set", "key", "a", "XX"]))
+            .unwrap();
+        assert_eq!(output, RESP::Null);
+        assert_eq!(storage.store.len(), 0);
+
+        storage
+            .process_command(&command(&["set", "key", "a"]))
+            .unwrap();
+        let output = storage
+            .process_command(&command(&["set", "key", "b", "XX"]))
+            .unwrap();
+        assert_eq!(output, RESP::SimpleString(String::from("OK")));
+        assert_eq!(storage.get(b"key".to_vec()).unwrap(), Some(b"b".to_vec()));
+    }
+
+    #[test]
+    fn test_command_set_get() {
+        let mut storage = Storage::new();
+        let output = storage
+            .process_command(&command(&["set", "key", "a", "GET"]))
+            .unwrap();
+        assert_eq!(output, RESP::Null);
+        let output = storage
+            .process_command(&command(&["set", "key", "b", "GET"]))
+            .unwrap();
+        assert_eq!(output, RESP::BulkString(b"a".to_vec()));
+        assert_eq!(storage.get(b"key".to_vec()).unwrap(), Some(b"b".to_vec()));
+    }
+
+    #[test]
+    fn test_command_set_nx_get() {
+        let mut storage = Storage::new();
+        let output = storage
+            .process_command(&command(&["set", "key", "a", "NX", "GET"]))
+            .unwrap();
+        assert_eq!(output, RESP::Null);
+        let output = storage
+            .process_command(&command(&["set", "key", "b", "NX", "GET"]))
+            .unwrap();
+        assert_eq!(output, RESP::BulkString(b"a".to_vec()));
+        assert_eq!(storage.get(b"key".to_vec()).unwrap(), Some(b"a".to_vec()));
+    }
+
+    #[test]
+    fn test_command_set_xx_get() {
+        let mut storage = Storage::new();
+        let output = storage
+            .process_command(&command(&["set", "key", "a", "XX", "GET"]))
+            .unwrap();
+        assert_eq!(output, RESP::Null);
+        assert_eq!(storage.store.len(), 0);
+
+        storage
+            .process_command(&command(&["set", "key", "a"]))
+            .unwrap();
+        let output = storage
+            .process_command(&command(&["set", "key", "b", "XX", "GET"]))
+            .unwrap();
+        assert_eq!(output, RESP::BulkString(b"a".to_vec()));
+        assert_eq!(storage.get(b"key".to_vec()).unwrap(), Some(b"b".to_vec()));
+    }
+
+    #[test]
+    fn test_command_set_clears_ttl() {
+        let mut storage = Storage::new();
+        storage
+            .process_command(&command(&["set", "key", "a", "EX", "100"]))
+            .unwrap();
+        assert!(storage.expiry.contains_key(b"key".as_slice()));
+        storage
+            .process_command(&command(&["set", "key", "b"]))
+            .unwrap();
+        assert!(!storage.expiry.contains_key(b"key".as_slice()));
+        assert_eq!(storage.store[b"key".as_slice()].expiry, None);
+    }
+
+    #[test]
+    fn test_command_set_keepttl() {
+        let mut storage = Storage::new();
+        storage
+            .process_command(&command(&["set", "key", "a", "EX", "100"]))
+            .unwrap();
+        let deadline = storage.expiry[b"key".as_slice()];
+        storage
+            .process_command(&command(&["set", "key", "b", "KEEPTTL"]))
+            .unwrap();
+        assert_eq!(storage.expiry[b"key".as_slice()], deadline);
+        assert_eq!(storage.get(b"key".to_vec()).unwrap(), Some(b"b".to_vec()));
+
+        storage
+            .process_command(&command(&["set", "other", "a", "KEEPTTL"]))
+            .unwrap();
+        assert!(!storage.expiry.contains_key(b"other".as_slice()));
+    }
+
+    #[test]
+    fn test_command_set_exat_and_pxat() {
+        let mut storage = Storage::new();
+        storage
+            .process_command(&command(&["set", "key", "a", "EXAT", "4102444800"]))
+            .unwrap();
+        assert_eq!(
+            storage.expiry[b"key".as_slice()],
+            UNIX_EPOCH + Duration::from_secs(4102444800)
+        );
+        storage
+            .process_command(&command(&["set", "key", "a", "PXAT", "4102444800123"]))
+            .unwrap();
+        assert_eq!(
+            storage.expiry[b"key".as_slice()],
+            UNIX_EPOCH + Duration::from_millis(4102444800123)
+        );
+
+        // A deadline in the past leaves the key already expired.
+        storage
+            .process_command(&command(&["set", "key", "a", "PXAT", "1"]))
+            .unwrap();
+        assert_eq!(storage.get(b"key".to_vec()).unwrap(), None);
+        assert_eq!(storage.store.len(), 0);
+        assert_eq!(storage.expiry.len(), 0);
+    }
+
+    #[test]
+    fn test_command_set_expire_overflow() {
+        let mut storage = Storage::new();
+        assert!(storage
+            .process_command(&command(&["set", "key", "a", "EX", "18446744073709551615"]))
+            .is_err());
+        let invalid = Err(StorageError::InvalidArgument(String::from(
+            "invalid expire time in 'set' command",
+        )));
+        for (option, value) in [
+            ("EX", "9223372036854776"),
+            ("PX", "9223372036854775807"),
+            ("EXAT", "9223372036854776"),
+            ("EX", "-1"),
+        ] {
+            assert_eq!(
+                storage.process_command(&command(&["set", "key", "a", option, value])),
+                invalid
+            );
+        }
+        assert_eq!(storage.store.len(), 0);
+    }
+
+    #[test]
+    fn test_command_expire_and_ttl() {
+        let mut storage = Storage::new();
+        assert_eq!(
+            storage.process_command(&command(&["ttl", "key"])).unwrap(),
+            RESP::Integer(-2)
+        );
+        assert_eq!(
+            storage
+                .process_command(&command(&["expire", "key", "100"]))
+                .unwrap(),
+            RESP::Integer(0)
+        );
+        storage
+            .process_command(&command(&["set", "key", "value"]))
+            .unwrap();
+        assert_eq!(
+            storage.process_command(&command(&["ttl", "key"])).unwrap(),
+            RESP::Integer(-1)
+        );
+        assert_eq!(
+            storage
+                .process_command(&command(&["expire", "key", "100"]))
+                .unwrap(),
+            RESP::Integer(1)
+        );
+        assert_eq!(
+            storage.process_command(&command(&["ttl", "key"])).unwrap(),
+            RESP::Integer(100)
+        );
+        let pttl = match storage.process_command(&command(&["pttl", "key"])).unwrap() {
+            RESP::Integer(pttl) => pttl,
+            _ => panic!("Expected an integer"),
+        };
+        assert!(pttl > 99_000 && pttl <= 100_000);
+        assert!(storage.store[b"key".as_slice()].expiry.is_some());
+    }
+
+    #[test]
+    fn test_command_expire_in_the_past_deletes() {
+        let mut storage = Storage::new();
+        storage
+            .process_command(&command(&["set", "key", "value", "EX", "100"]))
+            .unwrap();
+        assert_eq!(
+            storage
+                .process_command(&command(&["pexpire", "key", "-1"]))
+                .unwrap(),
+            RESP::Integer(1)
+        );
+        assert_eq!(storage.store.len(), 0);
+        assert_eq!(storage.expiry.len(), 0);
+    }
+
+    #[test]
+    fn test_command_expire_flags() {
+        let mut storage = Storage::new();
+        storage
+            .process_command(&command(&["set", "key", "value"]))
+            .unwrap();
+        let expire = |storage: &mut Storage, args: &[&str]| {
+            let mut full = vec!["expire", "key"];
+            full.extend_from_slice(args);
+            storage.process_command(&command(&full)).unwrap()
+        };
+        assert_eq!(expire(&mut storage, &["100", "XX"]), RESP::Integer(0));
+        assert_eq!(expire(&mut storage, &["100", "GT"]), RESP::Integer(0));
+        assert_eq!(expire(&mut storage, &["100", "NX"]), RESP::Integer(1));
+        assert_eq!(expire(&mut storage, &["200", "NX"]), RESP::Integer(0));
+        assert_eq!(expire(&mut storage, &["50", "GT"]), RESP::Integer(0));
+        assert_eq!(expire(&mut storage, &["200", "GT"]), RESP::Integer(1));
+        assert_eq!(expire(&mut storage, &["300", "LT"]), RESP::Integer(0));
+        assert_eq!(expire(&mut storage, &["150", "LT", "XX"]), RESP::Integer(1));
+        assert_eq!(
+            storage.process_command(&command(&["ttl", "key"])).unwrap(),
+            RESP::Integer(150)
+        );
+
+        assert!(storage
+            .process_command(&command(&["expire", "key", "10", "NX", "GT"]))
+            .is_err());
+        assert!(storage
+            .process_command(&command(&["expire", "key", "10", "GT", "LT"]))
+            .is_err());
+        assert!(storage
+            .process_command(&command(&["expire", "key", "10", "FOO"]))
+            .is_err());
+        assert_eq!(
+            storage
+                .process_command(&command(&["expire", "key", "ten"]))
+                .unwrap_err(),
+            StorageError::NotAnInteger
+        );
+        assert!(storage
+            .process_command(&command(&["expire", "key", "9223372036854775807"]))
+            .is_err());
+    }
+
+    #[test]
+    fn test_command_expireat_and_expiretime() {
+        let mut storage = Storage::new();
+        storage
+            .process_command(&command(&["set", "key", "value"]))
+            .unwrap();
+        assert_eq!(
+            storage
+                .process_command(&command(&["expiretime", "key"]))
+                .unwrap(),
+            RESP::Integer(-1)
+        );
+        assert_eq!(
+            storage
+                .process_command(&command(&["pexpireat", "key", "4102444800123"]))
+                .unwrap(),
+            RESP::Integer(1)
+        );
+        assert_eq!(
+            storage
+                .process_command(&command(&["pexpiretime", "key"]))
+                .unwrap(),
+            RESP::Integer(4102444800123)
+        );
+        storage
+            .process_command(&command(&["pexpireat", "key", "4102444800999"]))
+            .unwrap();
+        assert_eq!(
+            storage
+                .process_command(&command(&["expiretime", "key"]))
+                .unwrap(),
+            RESP::Integer(4102444800)
+        );
+        storage
+            .process_command(&command(&["expireat", "key", "4102444800"]))
+            .unwrap();
+        assert_eq!(
+            storage
+                .process_command(&command(&["expiretime", "key"]))
+                .unwrap(),
+            RESP::Integer(4102444800)
+        );
+    }
+
+    #[test]
+    fn test_command_persist() {
+        let mut storage = Storage::new();
+        assert_eq!(
+            storage
+                .process_command(&command(&["persist", "key"]))
+                .unwrap(),
+            RESP::Integer(0)
+        );
+        storage
+            .process_command(&command(&["set", "key", "value", "EX", "100"]))
+            .unwrap();
+        assert_eq!(
+            storage
+                .process_command(&command(&["persist", "key"]))
+                .unwrap(),
+            RESP::Integer(1)
+        );
+        assert_eq!(
+            storage
+                .process_command(&command(&["persist", "key"]))
+                .unwrap(),
+            RESP::Integer(0)
+        );
+        assert_eq!(storage.expiry.len(), 0);
+        assert_eq!(storage.store[b"key".as_slice()].expiry, None);
+    }
+
+    #[test]
+    fn test_command_del_and_unlink() {
+        let mut storage = Storage::new();
+        for key in ["a", "b", "c"] {
+            storage
+                .process_command(&command(&["set", key, "value", "EX", "100"]))
+                .unwrap();
+        }
+        assert_eq!(
+            storage
+                .process_command(&command(&["del", "a", "b", "missing"]))
+                .unwrap(),
+            RESP::Integer(2)
+        );
+        assert_eq!(
+            storage
+                .process_command(&command(&["unlink", "c", "c"]))
+                .unwrap(),
+            RESP::Integer(1)
+        );
+        assert_eq!(storage.store.len(), 0);
+        assert_eq!(storage.expiry.len(), 0);
+    }
+
+    #[test]
+    fn test_command_exists_and_type() {
+        let mut storage = Storage::new();
+        storage
+            .process_command(&command(&["set", "key", "value"]))
+            .unwrap();
+        assert_eq!(
+            storage
+                .process_command(&command(&["exists", "key", "key", "missing"]))
+                .unwrap(),
+            RESP::Integer(2)
+        );
+        assert_eq!(
+            storage.process_command(&command(&["type", "key"])).unwrap(),
+            RESP::SimpleString(String::from("string"))
+        );
+        assert_eq!(
+            storage
+                .process_command(&command(&["type", "missing"]))
+                .unwrap(),
+            RESP::SimpleString(String::from("none"))
+        );
+    }
+
+    #[test]
+    fn test_command_rename_moves_ttl() {
+        let mut storage = Storage::new();
+        storage
+            .process_command(&command(&["set", "source", "a", "EX", "100"]))
+            .unwrap();
+        storage
+            .process_command(&command(&["set", "destination", "b", "EX", "5"]))
+            .unwrap();
+        let deadline = storage.expiry[b"source".as_slice()];
+        assert_eq!(
+            storage
+                .process_command(&command(&["rename", "source", "destination"]))
+                .unwrap(),
+            RESP::SimpleString(String::from("OK"))
+        );
+        assert_eq!(storage.expiry[b"destination".as_slice()], deadline);
+        assert!(!storage.store.contains_key(b"source".as_slice()));
+        assert_eq!(
+            storage.get(b"destination".to_vec()).unwrap(),
+            Some(b"a".to_vec())
+        );
+
+        storage
+            .process_command(&command(&["set", "plain", "c"]))
+            .unwrap();
+        storage
+            .process_command(&command(&["rename", "plain", "destination"]))
+            .unwrap();
+        assert!(!storage.expiry.contains_key(b"destination".as_slice()));
+        assert_eq!(storage.expiry.len(), 0);
+
+        assert_eq!(
+            RESP::from(
+                storage
+                    .process_command(&command(&["rename", "missing", "x"]))
+                    .unwrap_err()
+            ),
+            RESP::Error(String::from("ERR no such key"))
+        );
+    }
+
+    #[test]
+    fn test_command_renamenx() {
+        let mut storage = Storage::new();
+        storage
+            .process_command(&command(&["set", "a", "1"]))
+            .unwrap();
+        storage
+            .process_command(&command(&["set", "b", "2"]))
+            .unwrap();
+        assert_eq!(
+            storage
+                .process_command(&command(&["renamenx", "a", "b"]))
+                .unwrap(),
+            RESP::Integer(0)
+        );
+        assert_eq!(
+            storage
+                .process_command(&command(&["renamenx", "a", "c"]))
+                .unwrap(),
+            RESP::Integer(1)
+        );
+        assert_eq!(storage.get(b"c".to_vec()).unwrap(), Some(b"1".to_vec()));
+        assert_eq!(storage.get(b"a".to_vec()).unwrap(), None);
+    }
+
+    #[test]
+    fn test_command_copy() {
+        let mut storage = Storage::new();
+        storage
+            .process_command(&command(&["set", "a", "1", "EX", "100"]))
+            .unwrap();
+        storage
+            .process_command(&command(&["set", "b", "2"]))
+            .unwrap();
+        assert_eq!(
+            storage
+                .process_command(&command(&["copy", "a", "b"]))
+                .unwrap(),
+            RESP::Integer(0)
+        );
+        assert_eq!(
+            storage
+                .process_command(&command(&["copy", "a", "b", "DB", "0", "REPLACE"]))
+                .unwrap(),
+            RESP::Integer(1)
+        );
+        assert_eq!(storage.get(b"b".to_vec()).unwrap(), Some(b"1".to_vec()));
+        assert_eq!(storage.get(b"a".to_vec()).unwrap(), Some(b"1".to_vec()));
+        assert_eq!(
+            storage.expiry[b"a".as_slice()],
+            storage.expiry[b"b".as_slice()]
+        );
+        assert_eq!(
+            storage
+                .process_command(&command(&["copy", "missing", "c"]))
+                .unwrap(),
+            RESP::Integer(0)
+        );
+        assert!(storage
+            .process_command(&command(&["copy", "a", "a"]))
+            .is_err());
+        assert!(storage
+            .process_command(&command(&["copy", "a", "c", "DB", "1"]))
+            .is_err());
+    }
+
+    #[test]
+    fn test_command_keys() {
+        let mut storage = Storage::new();
+        for key in ["user:1", "user:2", "user:10", "session:1"] {
+            storage
+                .process_command(&command(&["set", key, "value"]))
+                .unwrap();
+        }
+        storage.expiry.insert(
+            b"user:10".to_vec(),
+            SystemTime::now() - Duration::from_secs(1),
+        );
+        let mut keys = match storage
+            .process_command(&command(&["keys", "user:[0-9]*"]))
+            .unwrap()
+        {
+            RESP::Array(keys) => keys,
+            _ => panic!("Expected an array"),
+        };
+        keys.sort_by_key(|key| key.to_string());
+        assert_eq!(
+            keys,
+            vec![
+                RESP::BulkString(b"user:1".to_vec()),
+                RESP::BulkString(b"user:2".to_vec()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_command_scan() {
+        let mut storage = Storage::new();
+        for i in 0..100 {
+            storage
+                .process_command(&command(&["set", &format!("key:{}", i), "value"]))
+                .unwrap();
+        }
+        let mut seen = Vec::new();
+        let mut cursor = String::from("0");
+        loop {
+            let output = storage
+                .process_command(&command(&[
+                    "scan", &cursor, "COUNT", "7", "MATCH", "key:1*",
+                ]))
+                .unwrap();
+            let (next, keys) = match output {
+                RESP::Array(mut reply) => match (reply.remove(0), reply.remove(0)) {
+                    (RESP::BulkString(next), RESP::Array(keys)) => {
+                        (String::from_utf8(next).unwrap(), keys)
+                    }
+                    _ => panic!("Unexpected SCAN reply"),
+                },
+                _ => panic!("Expected an array"),
+            };
+            seen.extend(keys);
+            // Keys added mid-scan may or may not be returned, the original ones must be.
+            storage
+                .process_command(&command(&["set", &format!("new:{}", next), "value"]))
+                .unwrap();
+            if next == "0" {
+                break;
+            }
+            cursor = next;
+        }
+        assert_eq!(seen.len(), 11);
+    }
+
+    #[test]
+    fn test_command_scan_type() {
+        let mut storage = Storage::new();
+        storage
+            .process_command(&command(&["set", "a", "1"]))
+            .unwrap();
+        let output = storage
+            .process_command(&command(&["scan", "0", "TYPE", "list"]))
+            .unwrap();
+        assert_eq!(
+            output,
+            RESP::Array(vec![RESP::BulkString(b"0".to_vec()), RESP::Array(vec![])])
+        );
+        assert!(storage.process_command(&command(&["scan", "abc"])).is_err());
+    }
+
+    #[test]
+    fn test_storage_value_from_bytes() {
+        assert_eq!(
+            StorageValue::from_bytes(b"-9223372036854775808".to_vec()),
+            StorageValue::Integer(i64::MIN)
+        );
+        for value in ["012", "+1", "-0", " 1", "1.0", "9223372036854775808", ""] {
+            assert_eq!(
+                StorageValue::from_bytes(value.as_bytes().to_vec()),
+                StorageValue::String(value.as_bytes().to_vec())
+            );
+        }
+    }
+
+    #[test]
+    fn test_command_object_encoding() {
+        let mut storage = Storage::new();
+        let encoding = |storage: &mut Storage, key: &str| {
+            storage
+                .process_command(&command(&["object", "encoding", key]))
+                .unwrap()
+        };
+        storage
+            .process_command(&command(&["set", "int", "12345"]))
+            .unwrap();
+        storage
+            .process_command(&command(&["set", "embstr", "hello"]))
+            .unwrap();
+        storage
+            .process_command(&command(&["set", "raw", &"x".repeat(45)]))
+            .unwrap();
+        assert_eq!(
+            encoding(&mut storage, "int"),
+            RESP::BulkString(b"int".to_vec())
+        );
+        assert_eq!(
+            encoding(&mut storage, "embstr"),
+            RESP::BulkString(b"embstr".to_vec())
+        );
+        assert_eq!(
+            encoding(&mut storage, "raw"),
+            RESP::BulkString(b"raw".to_vec())
+        );
+        assert_eq!(encoding(&mut storage, "missing"), RESP::Null);
+        assert!(storage
+            .process_command(&command(&["object", "freq", "int"]))
+            .is_err());
+    }
+
+    #[test]
+    fn test_integer_encoding_is_transparent() {
+        let mut storage = Storage::new();
+        storage
+            .process_command(&command(&["set", "key", "10"]))
+            .unwrap();
+        storage.process_command(&command(&["incr", "key"])).unwrap();
+        assert_eq!(
+            storage.store[b"key".as_slice()].value,
+            StorageValue::Integer(11)
+        );
+        storage
+            .process_command(&command(&["append", "key", "5"]))
+            .unwrap();
+        assert_eq!(
+            storage.store[b"key".as_slice()].value,
+            StorageValue::String(b"115".to_vec())
+        );
+        assert_eq!(
+            storage.process_command(&command(&["incr", "key"])).unwrap(),
+            RESP::Integer(116)
+        );
+        assert_eq!(
+            storage.store[b"key".as_slice()].value,
+            StorageValue::Integer(116)
+        );
+        assert_eq!(
+            storage.process_command(&command(&["get", "key"])).unwrap(),
+            RESP::BulkString(b"116".to_vec())
+        );
     }
 }
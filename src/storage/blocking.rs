@@ -0,0 +1,891 @@
+//! Blocking commands and the clients they park.
+//!
+//! A blocking command that finds nothing to serve it is not left holding the storage:
+//! the client is registered as waiting on its keys and handed the receiving end of a
+//! channel to await. Commands that may let a waiting client through, such as pushes,
+//! ZADD and XADD, mark their key as ready, and once the command has completed the
+//! clients waiting on each ready key are served in the order they blocked. Serving only
+//! ever happens between commands, so a client never observes a key halfway through a
+//! command that pushes and then pops or deletes it, the same guarantee Redis gives for
+//! a MULTI/EXEC block (which this server does not implement yet).
+
+use super::list::ListEnd;
+use super::stream::{parse_stream_read, StreamRead};
+use super::{parse_float, parse_integer, Storage};
+use crate::resp::RESP;
+use crate::storage_result::{command_name, command_to_string, StorageError, StorageResult};
+use std::collections::{HashMap, VecDeque};
+use std::time::Duration;
+use tokio::sync::oneshot;
+
+/// What a blocking command does to the first of its keys that can serve it.
+#[derive(Debug, Clone, PartialEq)]
+enum BlockedOperation {
+    /// BLPOP and BRPOP, replying with the key and the element.
+    Pop(ListEnd),
+    /// BLMPOP, replying with the key and up to `count` elements.
+    MultiPop(ListEnd, usize),
+    /// BLMOVE, replying with the element pushed onto `destination`.
+    Move {
+        destination: Vec<u8>,
+        from: ListEnd,
+        to: ListEnd,
+    },
+    /// BZPOPMIN and BZPOPMAX, replying with the key, the member and its score.
+    SortedSetPop { max: bool },
+    /// BZMPOP, replying with the key and up to `count` members with their scores.
+    SortedSetMultiPop { max: bool, count: usize },
+    /// XREAD and XREADGROUP with BLOCK, replying with the entries read from the key.
+    ReadStreams(StreamRead),
+}
+
+impl BlockedOperation {
+    /// The type of value that can serve the operation.
+    fn type_name(&self) -> &'static str {
+        match self {
+            BlockedOperation::Pop(_)
+            | BlockedOperation::MultiPop(..)
+            | BlockedOperation::Move { .. } => "list",
+            BlockedOperation::SortedSetPop { .. } | BlockedOperation::SortedSetMultiPop { .. } => {
+                "zset"
+            }
+            BlockedOperation::ReadStreams(_) => "stream",
+        }
+    }
+}
+
+/// A parsed blocking command.
+#[derive(Debug, PartialEq)]
+struct BlockingCommand {
+    keys: Vec<Vec<u8>>,
+    operation: BlockedOperation,
+    /// `None` waits forever.
+    timeout: Option<Duration>,
+}
+
+struct Waiter {
+    keys: Vec<Vec<u8>>,
+    operation: BlockedOperation,
+    reply: oneshot::Sender<RESP>,
+}
+
+/// The clients parked by blocking commands, queued per key in the order they blocked.
+#[derive(Default)]
+pub(super) struct BlockedClients {
+    next_id: u64,
+    waiters: HashMap<u64, Waiter>,
+    by_key: HashMap<Vec<u8>, VecDeque<u64>>,
+    ready_keys: Vec<Vec<u8>>,
+}
+
+impl BlockedClients {
+    fn remove(&mut self, id: u64) -> Option<Waiter> {
+        let waiter = self.waiters.remove(&id)?;
+        for key in waiter.keys.iter() {
+            if let Some(queue) = self.by_key.get_mut(key) {
+                queue.retain(|&waiting| waiting != id);
+                if queue.is_empty() {
+                    self.by_key.remove(key);
+                }
+            }
+        }
+        Some(waiter)
+    }
+}
+
+/// A client waiting for an element. It is sent its reply once served, and must be passed to
+/// `Storage::unblock` or `Storage::unblock_disconnected` if it stops waiting first.
+#[derive(Debug)]
+pub struct BlockedClient {
+    pub id: u64,
+    pub reply: oneshot::Receiver<RESP>,
+    pub timeout: Option<Duration>,
+    operation: BlockedOperation,
+}
+
+/// The outcome of a command sent by a client that can wait for its reply.
+#[derive(Debug)]
+pub enum Response {
+    Reply(RESP),
+    Blocked(BlockedClient),
+}
+
+impl Storage {
+    /// Runs a command on behalf of a connected client. Unlike `process_command`, a
+    /// blocking command that cannot be served right away parks the client instead of
+    /// replying with a null.
+    pub fn process_client_command(&mut self, command: &[Vec<u8>]) -> StorageResult<Response> {
+        let mut blocking = match parse_blocking_command(command)? {
+            Some(blocking) => blocking,
+            None => return self.process_command(command).map(Response::Reply),
+        };
+        if let BlockedOperation::ReadStreams(read) = &mut blocking.operation {
+            self.resolve_stream_read(read)?;
+        }
+        let reply = self.serve_blocking_command(&blocking);
+        // BLMOVE may have pushed onto a key another client is waiting on.
+        self.serve_blocked_clients();
+        match reply? {
+            Some(reply) => Ok(Response::Reply(reply)),
+            None => Ok(Response::Blocked(self.block(blocking))),
+        }
+    }
+
+    /// Handles the blocking commands for callers that cannot wait, replying with a null
+    /// when none of the keys can serve them.
+    pub(super) fn command_blocking(&mut self, command: &[Vec<u8>]) -> StorageResult<RESP> {
+        let blocking = match parse_blocking_command(command)? {
+            Some(blocking) => blocking,
+            None => return Err(StorageError::CommandSyntaxError(command_to_string(command))),
+        };
+        Ok(self
+            .serve_blocking_command(&blocking)?
+            .unwrap_or(RESP::Null))
+    }
+
+    /// Stops waiting on behalf of a blocked client whose timeout elapsed. Returns the
+    /// reply it was sent in the meantime, if a push served it before the lock was taken.
+    pub fn unblock(&mut self, client: &mut BlockedClient) -> Option<RESP> {
+        self.blocked.remove(client.id);
+        client.reply.try_recv().ok()
+    }
+
+    /// Stops waiting on behalf of a blocked client that disconnected. Whatever a push
+    /// served it in the meantime is put back, so that the client does not take it along.
+    pub fn unblock_disconnected(&mut self, mut client: BlockedClient) {
+        if let Some(reply) = self.unblock(&mut client) {
+            self.restore_served(&client.operation, reply);
+            self.serve_blocked_clients();
+        }
+    }
+
+    /// Puts back what serving `operation` popped, given the reply it produced. BLMOVE
+    /// leaves its element on the destination and reading a stream takes nothing away,
+    /// so only the pops have anything to undo. Should the key have been replaced by a
+    /// value of another type, there is nowhere left to put the elements.
+    fn restore_served(&mut self, operation: &BlockedOperation, reply: RESP) {
+        let mut items = match reply {
+            RESP::Array(items) => items.into_iter(),
+            _ => return,
+        };
+        let key = match items.next() {
+            Some(RESP::BulkString(key)) => key,
+            _ => return,
+        };
+        let mut popped = Vec::new();
+        flatten(items, &mut popped);
+        match operation {
+            BlockedOperation::Pop(end) | BlockedOperation::MultiPop(end, _) => {
+                // Pushed in reverse onto the end they came from, they keep their order.
+                let elements: Vec<Vec<u8>> = popped
+                    .into_iter()
+                    .rev()
+                    .filter_map(|element| match element {
+                        RESP::BulkString(element) => Some(element),
+                        _ => None,
+                    })
+                    .collect();
+                let _ = self.list_push(&key, &elements, *end, false);
+            }
+            BlockedOperation::SortedSetPop { .. } | BlockedOperation::SortedSetMultiPop { .. } => {
+                let entries = popped
+                    .chunks(2)
+                    .filter_map(|entry| match entry {
+                        [RESP::BulkString(member), RESP::Double(score)] => {
+                            Some((member.clone(), *score))
+                        }
+                        _ => None,
+                    })
+                    .collect();
+                let _ = self.sorted_set_restore(&key, entries);
+            }
+            BlockedOperation::Move { .. } | BlockedOperation::ReadStreams(_) => {}
+        }
+    }
+
+    /// Records that `key` may now serve the clients blocked on it.
+    pub(super) fn signal_key_ready(&mut self, key: &[u8]) {
+        if self.blocked.by_key.contains_key(key)
+            && !self.blocked.ready_keys.iter().any(|ready| ready == key)
+        {
+            self.blocked.ready_keys.push(key.to_vec());
+        }
+    }
+
+    /// Serves the clients blocked on every key signalled ready, oldest first, for as long
+    /// as the key can serve them. Serving a BLMOVE may signal its destination in turn.
+    pub(super) fn serve_blocked_clients(&mut self) {
+        while !self.blocked.ready_keys.is_empty() {
+            let key = self.blocked.ready_keys.remove(0);
+            let queued: Vec<u64> = match self.blocked.by_key.get(&key) {
+                Some(queue) => queue.iter().copied().collect(),
+                None => continue,
+            };
+            for id in queued {
+                let waiter = &self.blocked.waiters[&id];
+                // A client dropped without being unblocked cannot take an element.
+                if waiter.reply.is_closed() {
+                    self.blocked.remove(id);
+                    continue;
+                }
+                // Clients waiting for another type of value keep waiting, as in Redis.
+                if self
+                    .store
+                    .get(&key)
+                    .is_some_and(|data| data.value.type_name() != waiter.operation.type_name())
+                {
+                    continue;
+                }
+                let operation = waiter.operation.clone();
+                let reply = match self.serve_key(&key, &operation) {
+                    Ok(Some(reply)) => reply,
+                    // Stream readers after different IDs may still be served, but once
+                    // the key is empty nobody else popping from it can be.
+                    Ok(None) if matches!(operation, BlockedOperation::ReadStreams(_)) => continue,
+                    Ok(None) => break,
+                    Err(e) => RESP::from(e),
+                };
+                if let Some(waiter) = self.blocked.remove(id) {
+                    // The receiver was checked above while holding the storage, so this
+                    // cannot fail.
+                    let _ = waiter.reply.send(reply);
+                }
+            }
+        }
+    }
+
+    fn block(&mut self, blocking: BlockingCommand) -> BlockedClient {
+        let id = self.blocked.next_id;
+        self.blocked.next_id += 1;
+        let (sender, receiver) = oneshot::channel();
+        let mut keys: Vec<Vec<u8>> = Vec::with_capacity(blocking.keys.len());
+        for key in blocking.keys {
+            if !keys.contains(&key) {
+                self.blocked
+                    .by_key
+                    .entry(key.clone())
+                    .or_default()
+                    .push_back(id);
+                keys.push(key);
+            }
+        }
+        self.blocked.waiters.insert(
+            id,
+            Waiter {
+                keys,
+                operation: blocking.operation.clone(),
+                reply: sender,
+            },
+        );
+        BlockedClient {
+            id,
+            reply: receiver,
+            timeout: blocking.timeout,
+            operation: blocking.operation,
+        }
+    }
+
+    /// Runs the command against the first of its keys that can serve it, if any.
+    fn serve_blocking_command(
+        &mut self,
+        blocking: &BlockingCommand,
+    ) -> StorageResult<Option<RESP>> {
+        if let BlockedOperation::ReadStreams(read) = &blocking.operation {
+            return self.read_streams(read);
+        }
+        for key in blocking.keys.iter() {
+            if let Some(reply) = self.serve_key(key, &blocking.operation)? {
+                return Ok(Some(reply));
+            }
+        }
+        Ok(None)
+    }
+
+    fn serve_key(
+        &mut self,
+        key: &[u8],
+        operation: &BlockedOperation,
+    ) -> StorageResult<Option<RESP>> {
+        let reply = match operation {
+            BlockedOperation::Pop(end) => self
+                .list_pop(key, *end, 1)?
+                .and_then(|mut elements| elements.pop())
+                .map(|element| {
+                    RESP::Array(vec![
+                        RESP::BulkString(key.to_vec()),
+                        RESP::BulkString(element),
+                    ])
+                }),
+            BlockedOperation::MultiPop(end, count) => {
+                self.list_pop(key, *end, *count)?.map(|elements| {
+                    RESP::Array(vec![
+                        RESP::BulkString(key.to_vec()),
+                        RESP::Array(elements.into_iter().map(RESP::BulkString).collect()),
+                    ])
+                })
+            }
+            BlockedOperation::Move {
+                destination,
+                from,
+                to,
+            } => self
+                .list_move(key, destination, *from, *to)?
+                .map(RESP::BulkString),
+            BlockedOperation::SortedSetPop { max } => {
+                self.sorted_set_pop(key, *max, 1)?
+                    .pop()
+                    .map(|(member, score)| {
+                        RESP::Array(vec![
+                            RESP::BulkString(key.to_vec()),
+                            RESP::BulkString(member),
+                            RESP::Double(score),
+                        ])
+                    })
+            }
+            BlockedOperation::SortedSetMultiPop { max, count } => {
+                let popped = self.sorted_set_pop(key, *max, *count)?;
+                (!popped.is_empty()).then(|| {
+                    let entries = popped
+                        .into_iter()
+                        .map(|(member, score)| {
+                            RESP::Array(vec![RESP::BulkString(member), RESP::Double(score)])
+                        })
+                        .collect();
+                    RESP::Array(vec![RESP::BulkString(key.to_vec()), RESP::Array(entries)])
+                })
+            }
+            BlockedOperation::ReadStreams(read) => self.read_streams(&read.for_key(key))?,
+        };
+        Ok(reply)
+    }
+}
+
+/// Collects the elements of `items`, descending into nested arrays.
+fn flatten(items: impl IntoIterator<Item = RESP>, elements: &mut Vec<RESP>) {
+    for item in items {
+        match item {
+            RESP::Array(nested) => flatten(nested, elements),
+            element => elements.push(element),
+        }
+    }
+}
+
+/// Parses BLPOP, BRPOP, BLMOVE, BLMPOP, BZPOPMIN, BZPOPMAX, BZMPOP, and XREAD and
+/// XREADGROUP given BLOCK, or returns `None` for any other command.
+fn parse_blocking_command(command: &[Vec<u8>]) -> StorageResult<Option<BlockingCommand>> {
+    let name = match command.first() {
+        Some(name) => name.to_ascii_lowercase(),
+        None => return Ok(None),
+    };
+    let syntax_error = || StorageError::CommandSyntaxError(command_to_string(command));
+    let wrong_argument_count = || StorageError::WrongArgumentCount(command_name(command));
+    let blocking = match name.as_slice() {
+        b"blpop" | b"brpop" => {
+            if command.len() < 3 {
+                return Err(wrong_argument_count());
+            }
+            let end = match name.as_slice() {
+                b"blpop" => ListEnd::Left,
+                _ => ListEnd::Right,
+            };
+            BlockingCommand {
+                keys: command[1..command.len() - 1].to_vec(),
+                operation: BlockedOperation::Pop(end),
+                timeout: parse_timeout(&command[command.len() - 1])?,
+            }
+        }
+        b"blmove" => {
+            if command.len() != 6 {
+                return Err(wrong_argument_count());
+            }
+            let from = ListEnd::parse(&command[3]).ok_or_else(syntax_error)?;
+            let to = ListEnd::parse(&command[4]).ok_or_else(syntax_error)?;
+            BlockingCommand {
+                keys: vec![command[1].clone()],
+                operation: BlockedOperation::Move {
+                    destination: command[2].clone(),
+                    from,
+                    to,
+                },
+                timeout: parse_timeout(&command[5])?,
+            }
+        }
+        b"bzpopmin" | b"bzpopmax" => {
+            if command.len() < 3 {
+                return Err(wrong_argument_count());
+            }
+            BlockingCommand {
+                keys: command[1..command.len() - 1].to_vec(),
+                operation: BlockedOperation::SortedSetPop {
+                    max: name == b"bzpopmax",
+                },
+                timeout: parse_timeout(&command[command.len() - 1])?,
+            }
+        }
+        b"blmpop" | b"bzmpop" => {
+            if command.len() < 5 {
+                return Err(wrong_argument_count());
+            }
+            let timeout = parse_timeout(&command[1])?;
+            let numkeys = parse_integer(&command[2])?;
+            if numkeys <= 0 {
+                return Err(StorageError::InvalidArgument(String::from(
+                    "numkeys should be greater than 0",
+                )));
+            }
+            let numkeys = numkeys as usize;
+            if numkeys > command.len() - 4 {
+                return Err(syntax_error());
+            }
+            let keys = command[3..3 + numkeys].to_vec();
+            let side = &command[3 + numkeys];
+            let count = match &command[4 + numkeys..] {
+                [] => 1,
+                [option, count] if option.eq_ignore_ascii_case(b"count") => {
+                    let count = parse_integer(count)?;
+                    if count <= 0 {
+                        return Err(StorageError::InvalidArgument(String::from(
+                            "count should be greater than 0",
+                        )));
+                    }
+                    count as usize
+                }
+                _ => return Err(syntax_error()),
+            };
+            let operation = match name.as_slice() {
+                b"blmpop" => BlockedOperation::MultiPop(
+                    ListEnd::parse(side).ok_or_else(syntax_error)?,
+                    count,
+                ),
+                _ => BlockedOperation::SortedSetMultiPop {
+                    max: match side.to_ascii_lowercase().as_slice() {
+                        b"min" => false,
+                        b"max" => true,
+                        _ => return Err(syntax_error()),
+                    },
+                    count,
+                },
+            };
+            BlockingCommand {
+                keys,
+                operation,
+                timeout,
+            }
+        }
+        b"xread" | b"xreadgroup" => match parse_stream_read(command)? {
+            (read, Some(timeout)) => BlockingCommand {
+                keys: read.keys.clone(),
+                operation: BlockedOperation::ReadStreams(read),
+                timeout,
+            },
+            (_, None) => return Ok(None),
+        },
+        _ => return Ok(None),
+    };
+    Ok(Some(blocking))
+}
+
+/// Parses a timeout in seconds, where 0 means waiting forever.
+fn parse_timeout(argument: &[u8]) -> StorageResult<Option<Duration>> {
+    let seconds = parse_float(argument).map_err(|_| {
+        StorageError::InvalidArgument(String::from("timeout is not a float or out of range"))
+    })?;
+    if seconds < 0.0 {
+        return Err(StorageError::InvalidArgument(String::from(
+            "timeout is negative",
+        )));
+    }
+    if seconds == 0.0 {
+        return Ok(None);
+    }
+    Duration::try_from_secs_f64(seconds)
+        .map(Some)
+        .map_err(|_| StorageError::InvalidArgument(String::from("timeout is out of range")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::test_helpers::{bulk, bulks, command, run};
+
+    fn block(storage: &mut Storage, args: &[&str]) -> BlockedClient {
+        match storage.process_client_command(&command(args)) {
+            Ok(Response::Blocked(client)) => client,
+            other => panic!("{:?} did not block: {:?}", args, other),
+        }
+    }
+
+    #[test]
+    fn test_parse_blocking_command() {
+        assert_eq!(
+            parse_blocking_command(&command(&[
+                "BLMPOP", "1.5", "2", "a", "b", "right", "COUNT", "3"
+            ])),
+            Ok(Some(BlockingCommand {
+                keys: vec![b"a".to_vec(), b"b".to_vec()],
+                operation: BlockedOperation::MultiPop(ListEnd::Right, 3),
+                timeout: Some(Duration::from_millis(1500)),
+            }))
+        );
+        assert_eq!(parse_blocking_command(&command(&["lpop", "a"])), Ok(None));
+        assert_eq!(
+            parse_blocking_command(&command(&["blpop", "a", "-1"])),
+            Err(StorageError::InvalidArgument(String::from(
+                "timeout is negative"
+            )))
+        );
+        assert!(parse_blocking_command(&command(&["blpop", "a", "soon"])).is_err());
+        assert!(parse_blocking_command(&command(&["blmpop", "0", "0", "a", "left"])).is_err());
+        assert!(parse_blocking_command(&command(&["blmpop", "0", "2", "a", "left"])).is_err());
+        assert!(
+            parse_blocking_command(&command(&["blmove", "a", "b", "up", "left", "0"])).is_err()
+        );
+        assert_eq!(
+            parse_blocking_command(&command(&["bzmpop", "0", "1", "z", "MAX"])),
+            Ok(Some(BlockingCommand {
+                keys: vec![b"z".to_vec()],
+                operation: BlockedOperation::SortedSetMultiPop {
+                    max: true,
+                    count: 1
+                },
+                timeout: None,
+            }))
+        );
+        assert!(parse_blocking_command(&command(&["bzmpop", "0", "1", "z", "left"])).is_err());
+        assert!(parse_blocking_command(&command(&["bzpopmin", "z"])).is_err());
+    }
+
+    #[test]
+    fn test_blocking_commands_served_right_away() {
+        let mut storage = Storage::new();
+        run(&mut storage, &["rpush", "b", "1", "2", "3"]).unwrap();
+        assert_eq!(
+            run(&mut storage, &["blpop", "a", "b", "0"]),
+            Ok(bulks(&["b", "1"]))
+        );
+        assert_eq!(
+            run(&mut storage, &["brpop", "a", "b", "0"]),
+            Ok(bulks(&["b", "3"]))
+        );
+        assert_eq!(
+            run(&mut storage, &["blmove", "b", "c", "left", "right", "0"]),
+            Ok(bulk("2"))
+        );
+        assert_eq!(
+            run(
+                &mut storage,
+                &["blmpop", "0", "2", "b", "c", "left", "count", "5"]
+            ),
+            Ok(RESP::Array(vec![bulk("c"), bulks(&["2"])]))
+        );
+        // Without a client to park, an empty key gets the timeout reply immediately.
+        assert_eq!(run(&mut storage, &["blpop", "a", "0"]), Ok(RESP::Null));
+
+        run(&mut storage, &["set", "s", "x"]).unwrap();
+        assert_eq!(
+            run(&mut storage, &["blpop", "s", "0"]),
+            Err(StorageError::WrongType)
+        );
+    }
+
+    #[test]
+    fn test_push_serves_blocked_clients_in_order() {
+        let mut storage = Storage::new();
+        let mut first = block(&mut storage, &["blpop", "a", "b", "0"]);
+        let mut second = block(&mut storage, &["brpop", "b", "0"]);
+        assert_eq!(first.timeout, None);
+
+        run(&mut storage, &["rpush", "b", "x"]).unwrap();
+        assert_eq!(first.reply.try_recv(), Ok(bulks(&["b", "x"])));
+        assert!(second.reply.try_recv().is_err());
+        assert_eq!(run(&mut storage, &["exists", "b"]), Ok(RESP::Integer(0)));
+
+        // The first client is no longer waiting on `a` either.
+        run(&mut storage, &["rpush", "a", "y"]).unwrap();
+        assert_eq!(run(&mut storage, &["llen", "a"]), Ok(RESP::Integer(1)));
+
+        run(&mut storage, &["rpush", "b", "1", "2"]).unwrap();
+        assert_eq!(second.reply.try_recv(), Ok(bulks(&["b", "2"])));
+        assert_eq!(
+            run(&mut storage, &["lrange", "b", "0", "-1"]),
+            Ok(bulks(&["1"]))
+        );
+    }
+
+    #[test]
+    fn test_one_push_serves_several_clients() {
+        let mut storage = Storage::new();
+        let mut first = block(&mut storage, &["blpop", "a", "0"]);
+        let mut second = block(
+            &mut storage,
+            &["blmpop", "0", "1", "a", "left", "count", "5"],
+        );
+        let mut third = block(&mut storage, &["blpop", "a", "0"]);
+
+        run(&mut storage, &["rpush", "a", "1", "2", "3"]).unwrap();
+        assert_eq!(first.reply.try_recv(), Ok(bulks(&["a", "1"])));
+        assert_eq!(
+            second.reply.try_recv(),
+            Ok(RESP::Array(vec![bulk("a"), bulks(&["2", "3"])]))
+        );
+        assert!(third.reply.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_unblocked_and_dropped_clients_are_skipped() {
+        let mut storage = Storage::new();
+        let mut timed_out = block(&mut storage, &["blpop", "a", "1"]);
+        let disconnected = block(&mut storage, &["blpop", "a", "0"]);
+        let mut waiting = block(&mut storage, &["blpop", "a", "0"]);
+
+        assert_eq!(storage.unblock(&mut timed_out), None);
+        drop(disconnected);
+        run(&mut storage, &["lpush", "a", "x"]).unwrap();
+        assert_eq!(waiting.reply.try_recv(), Ok(bulks(&["a", "x"])));
+        assert!(storage.blocked.waiters.is_empty());
+        assert!(storage.blocked.by_key.is_empty());
+    }
+
+    #[test]
+    fn test_disconnected_clients_give_back_what_they_were_served() {
+        let mut storage = Storage::new();
+        let popper = block(
+            &mut storage,
+            &["blmpop", "0", "1", "a", "left", "count", "2"],
+        );
+        run(&mut storage, &["rpush", "a", "x", "y"]).unwrap();
+        let mut waiting = block(&mut storage, &["brpop", "a", "0"]);
+        storage.unblock_disconnected(popper);
+        assert_eq!(waiting.reply.try_recv(), Ok(bulks(&["a", "y"])));
+        assert_eq!(
+            run(&mut storage, &["lrange", "a", "0", "-1"]),
+            Ok(bulks(&["x"]))
+        );
+
+        let popper = block(&mut storage, &["bzpopmin", "z", "0"]);
+        run(&mut storage, &["zadd", "z", "1", "m"]).unwrap();
+        storage.unblock_disconnected(popper);
+        assert_eq!(
+            run(&mut storage, &["zscore", "z", "m"]),
+            Ok(RESP::Double(1.0))
+        );
+        assert!(storage.blocked.waiters.is_empty());
+    }
+
+    #[test]
+    fn test_blmove_serves_clients_blocked_on_destination() {
+        let mut storage = Storage::new();
+        let mut mover = block(
+            &mut storage,
+            &["blmove", "src", "dst", "right", "left", "0"],
+        );
+        let mut popper = block(&mut storage, &["blpop", "dst", "0"]);
+
+        run(&mut storage, &["rpush", "src", "x"]).unwrap();
+        assert_eq!(mover.reply.try_recv(), Ok(bulk("x")));
+        assert_eq!(popper.reply.try_recv(), Ok(bulks(&["dst", "x"])));
+        assert_eq!(run(&mut storage, &["dbsize"]), Ok(RESP::Integer(0)));
+    }
+
+    #[test]
+    fn test_rename_serves_blocked_clients() {
+        let mut storage = Storage::new();
+        let mut client = block(&mut storage, &["blpop", "a", "0"]);
+        run(&mut storage, &["rpush", "tmp", "1", "2"]).unwrap();
+        run(&mut storage, &["rename", "tmp", "a"]).unwrap();
+        assert_eq!(client.reply.try_recv(), Ok(bulks(&["a", "1"])));
+    }
+
+    #[test]
+    fn test_sorted_set_pops_served_right_away() {
+        let mut storage = Storage::new();
+        run(&mut storage, &["zadd", "z", "1", "a", "2", "b", "3", "c"]).unwrap();
+        assert_eq!(
+            run(&mut storage, &["bzpopmin", "none", "z", "0"]),
+            Ok(RESP::Array(vec![bulk("z"), bulk("a"), RESP::Double(1.0)]))
+        );
+        assert_eq!(
+            run(&mut storage, &["bzpopmax", "z", "0"]),
+            Ok(RESP::Array(vec![bulk("z"), bulk("c"), RESP::Double(3.0)]))
+        );
+        assert_eq!(
+            run(
+                &mut storage,
+                &["bzmpop", "0", "1", "z", "min", "count", "5"]
+            ),
+            Ok(RESP::Array(vec![
+                bulk("z"),
+                RESP::Array(vec![RESP::Array(vec![bulk("b"), RESP::Double(2.0)])]),
+            ]))
+        );
+        assert_eq!(run(&mut storage, &["exists", "z"]), Ok(RESP::Integer(0)));
+        assert_eq!(run(&mut storage, &["bzpopmin", "z", "0"]), Ok(RESP::Null));
+        run(&mut storage, &["rpush", "l", "x"]).unwrap();
+        assert_eq!(
+            run(&mut storage, &["bzpopmin", "l", "0"]),
+            Err(StorageError::WrongType)
+        );
+    }
+
+    #[test]
+    fn test_zadd_serves_blocked_clients_in_order() {
+        let mut storage = Storage::new();
+        let mut first = block(&mut storage, &["bzpopmin", "jobs", "0"]);
+        let mut second = block(
+            &mut storage,
+            &["bzmpop", "0", "1", "jobs", "max", "count", "2"],
+        );
+        let mut third = block(&mut storage, &["bzpopmax", "jobs", "0"]);
+
+        run(
+            &mut storage,
+            &["zadd", "jobs", "5", "x", "1", "y", "3", "z"],
+        )
+        .unwrap();
+        assert_eq!(
+            first.reply.try_recv(),
+            Ok(RESP::Array(vec![
+                bulk("jobs"),
+                bulk("y"),
+                RESP::Double(1.0)
+            ]))
+        );
+        assert_eq!(
+            second.reply.try_recv(),
+            Ok(RESP::Array(vec![
+                bulk("jobs"),
+                RESP::Array(vec![
+                    RESP::Array(vec![bulk("x"), RESP::Double(5.0)]),
+                    RESP::Array(vec![bulk("z"), RESP::Double(3.0)]),
+                ]),
+            ]))
+        );
+        assert!(third.reply.try_recv().is_err());
+
+        run(&mut storage, &["zincrby", "jobs", "2", "w"]).unwrap();
+        assert_eq!(
+            third.reply.try_recv(),
+            Ok(RESP::Array(vec![
+                bulk("jobs"),
+                bulk("w"),
+                RESP::Double(2.0)
+            ]))
+        );
+        assert_eq!(run(&mut storage, &["exists", "jobs"]), Ok(RESP::Integer(0)));
+    }
+
+    #[test]
+    fn test_clients_waiting_for_another_type_keep_waiting() {
+        let mut storage = Storage::new();
+        let mut list_client = block(&mut storage, &["blpop", "k", "0"]);
+        let mut zset_client = block(&mut storage, &["bzpopmin", "k", "0"]);
+
+        run(&mut storage, &["zadd", "tmp", "1", "a"]).unwrap();
+        run(&mut storage, &["rename", "tmp", "k"]).unwrap();
+        assert!(list_client.reply.try_recv().is_err());
+        assert_eq!(
+            zset_client.reply.try_recv(),
+            Ok(RESP::Array(vec![bulk("k"), bulk("a"), RESP::Double(1.0)]))
+        );
+
+        run(&mut storage, &["rpush", "k", "x"]).unwrap();
+        assert_eq!(list_client.reply.try_recv(), Ok(bulks(&["k", "x"])));
+    }
+
+    #[test]
+    fn test_xadd_serves_stream_readers() {
+        let mut storage = Storage::new();
+        run(&mut storage, &["xadd", "events", "1", "n", "old"]).unwrap();
+        let mut from_last = block(
+            &mut storage,
+            &[
+                "xread", "BLOCK", "0", "STREAMS", "other", "events", "0", "$",
+            ],
+        );
+        let mut from_later = block(
+            &mut storage,
+            &["xread", "BLOCK", "0", "STREAMS", "events", "5"],
+        );
+
+        run(&mut storage, &["xadd", "events", "2", "n", "new"]).unwrap();
+        assert_eq!(
+            from_last.reply.try_recv(),
+            Ok(RESP::Array(vec![RESP::Array(vec![
+                bulk("events"),
+                RESP::Array(vec![RESP::Array(vec![bulk("2-0"), bulks(&["n", "new"])])]),
+            ])]))
+        );
+        // Nothing past 5-0 yet, though an earlier reader was served.
+        assert!(from_later.reply.try_recv().is_err());
+        run(&mut storage, &["xadd", "events", "6", "n", "newer"]).unwrap();
+        assert!(from_later.reply.try_recv().is_ok());
+
+        assert_eq!(
+            storage
+                .process_client_command(&command(&[
+                    "xread", "BLOCK", "0", "STREAMS", "events", "0"
+                ]))
+                .map(|response| matches!(response, Response::Reply(_))),
+            Ok(true)
+        );
+    }
+
+    #[test]
+    fn test_xadd_serves_group_readers_once() {
+        let mut storage = Storage::new();
+        run(
+            &mut storage,
+            &["xgroup", "create", "jobs", "g", "$", "MKSTREAM"],
+        )
+        .unwrap();
+        let mut first = block(
+            &mut storage,
+            &[
+                "xreadgroup",
+                "GROUP",
+                "g",
+                "a",
+                "BLOCK",
+                "0",
+                "STREAMS",
+                "jobs",
+                ">",
+            ],
+        );
+        let mut second = block(
+            &mut storage,
+            &[
+                "xreadgroup",
+                "GROUP",
+                "g",
+                "b",
+                "BLOCK",
+                "0",
+                "STREAMS",
+                "jobs",
+                ">",
+            ],
+        );
+
+        run(&mut storage, &["xadd", "jobs", "1", "task", "x"]).unwrap();
+        assert_eq!(
+            first.reply.try_recv(),
+            Ok(RESP::Array(vec![RESP::Array(vec![
+                bulk("jobs"),
+                RESP::Array(vec![RESP::Array(vec![bulk("1-0"), bulks(&["task", "x"])])]),
+            ])]))
+        );
+        assert!(second.reply.try_recv().is_err());
+        assert_eq!(
+            run(
+                &mut storage,
+                &["xpending", "jobs", "g", "-", "+", "10", "a"]
+            )
+            .map(|reply| matches!(reply, RESP::Array(entries) if entries.len() == 1)),
+            Ok(true)
+        );
+
+        run(&mut storage, &["xadd", "jobs", "2", "task", "y"]).unwrap();
+        assert!(second.reply.try_recv().is_ok());
+    }
+}
@@ -0,0 +1,1046 @@
+use super::{
+    format_float, millis_since_epoch, now_millis, parse_float, parse_integer, ExpireConditions,
+    Storage, StorageData, StorageValue,
+};
+use crate::glob::glob_match;
+use crate::resp::RESP;
+use crate::scan::{parse_cursor, parse_scan_arguments, ScanMap};
+use crate::storage_result::{command_name, command_to_string, StorageError, StorageResult};
+use std::collections::{hash_map, HashMap};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// A hash value. Fields given a TTL by HEXPIRE and friends also have a deadline, which
+/// is dropped along with the field or when HSET overwrites it.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Hash {
+    fields: ScanMap<Vec<u8>>,
+    deadlines: HashMap<Vec<u8>, SystemTime>,
+}
+
+impl Hash {
+    pub fn len(&self) -> usize {
+        self.fields.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty()
+    }
+
+    pub fn keys(&self) -> hash_map::Keys<'_, Arc<[u8]>, Vec<u8>> {
+        self.fields.keys()
+    }
+
+    pub fn values(&self) -> hash_map::Values<'_, Arc<[u8]>, Vec<u8>> {
+        self.fields.values()
+    }
+
+    /// Whether any field has a TTL.
+    pub fn has_deadlines(&self) -> bool {
+        !self.deadlines.is_empty()
+    }
+
+    fn iter(&self) -> hash_map::Iter<'_, Arc<[u8]>, Vec<u8>> {
+        self.fields.iter()
+    }
+
+    fn get(&self, field: &[u8]) -> Option<&Vec<u8>> {
+        self.fields.get(field)
+    }
+
+    fn contains_key(&self, field: &[u8]) -> bool {
+        self.fields.contains_key(field)
+    }
+
+    fn random(&self) -> Option<(&Arc<[u8]>, &Vec<u8>)> {
+        self.fields.random()
+    }
+
+    fn random_picks(&self, count: i64) -> Vec<(&Arc<[u8]>, &Vec<u8>)> {
+        self.fields.random_picks(count)
+    }
+
+    /// Sets a field, clearing its TTL. Returns the previous value.
+    fn insert(&mut self, field: Vec<u8>, value: Vec<u8>) -> Option<Vec<u8>> {
+        self.deadlines.remove(&field);
+        self.fields.insert(field, value)
+    }
+
+    /// Sets a field, keeping its TTL as HINCRBY does.
+    fn update(&mut self, field: Vec<u8>, value: Vec<u8>) {
+        self.fields.insert(field, value);
+    }
+
+    fn remove(&mut self, field: &[u8]) -> Option<Vec<u8>> {
+        self.deadlines.remove(field);
+        self.fields.remove(field)
+    }
+
+    /// Removes the fields whose TTL has passed at `now`, returning how many there were.
+    pub fn remove_expired(&mut self, now: SystemTime) -> usize {
+        if self.deadlines.is_empty() {
+            return 0;
+        }
+        let fields = &mut self.fields;
+        let before = self.deadlines.len();
+        self.deadlines.retain(|field, &mut deadline| {
+            if deadline <= now {
+                fields.remove(field);
+                false
+            } else {
+                true
+            }
+        });
+        before - self.deadlines.len()
+    }
+}
+
+impl Storage {
+    /// Returns the hash stored at `key`, or `None` if there is no such key. Fields whose
+    /// TTL has passed are removed first, along with the key if none are left.
+    fn hash_at(&mut self, key: &[u8]) -> StorageResult<Option<&mut Hash>> {
+        let expired = match self.lookup(key) {
+            Some(StorageData {
+                value: StorageValue::Hash(hash),
+                ..
+            }) => hash.remove_expired(SystemTime::now()),
+            Some(_) => return Err(StorageError::WrongType),
+            None => return Ok(None),
+        };
+        if expired > 0 {
+            self.remove_if_empty(key);
+        }
+        match self.store.get_mut(key) {
+            Some(StorageData {
+                value: StorageValue::Hash(hash),
+                ..
+            }) => Ok(Some(hash)),
+            _ => Ok(None),
+        }
+    }
+
+    /// Returns the hash stored at `key`, creating an empty one if there is no such key.
+    /// Callers must not leave a created hash empty.
+    fn hash_at_or_insert(&mut self, key: &[u8]) -> StorageResult<&mut Hash> {
+        if self.hash_at(key)?.is_none() {
+            self.store.insert(
+                key.to_vec(),
+                StorageData::from(StorageValue::Hash(Hash::default())),
+            );
+        }
+        Ok(self.hash_at(key)?.expect("the hash was just created"))
+    }
+
+    pub(super) fn command_hset(&mut self, command: &[Vec<u8>]) -> StorageResult<RESP> {
+        if command.len() < 4 || !command.len().is_multiple_of(2) {
+            return Err(StorageError::WrongArgumentCount(command_name(command)));
+        }
+        let hash = self.hash_at_or_insert(&command[1])?;
+        let added = command[2..]
+            .chunks(2)
+            .filter(|pair| hash.insert(pair[0].clone(), pair[1].clone()).is_none())
+            .count();
+        Ok(RESP::Integer(added as i64))
+    }
+
+    pub(super) fn command_hsetnx(&mut self, command: &[Vec<u8>]) -> StorageResult<RESP> {
+        if command.len() != 4 {
+            return Err(StorageError::WrongArgumentCount(command_name(command)));
+        }
+        let hash = self.hash_at_or_insert(&command[1])?;
+        if hash.contains_key(&command[2]) {
+            return Ok(RESP::Integer(0));
+        }
+        hash.insert(command[2].clone(), command[3].clone());
+        Ok(RESP::Integer(1))
+    }
+
+    pub(super) fn command_hget(&mut self, command: &[Vec<u8>]) -> StorageResult<RESP> {
+        if command.len() != 3 {
+            return Err(StorageError::WrongArgumentCount(command_name(command)));
+        }
+        let value = self
+            .hash_at(&command[1])?
+            .and_then(|hash| hash.get(&command[2]));
+        Ok(value.map_or(RESP::Null, |value| RESP::BulkString(value.clone())))
+    }
+
+    pub(super) fn command_hmget(&mut self, command: &[Vec<u8>]) -> StorageResult<RESP> {
+        if command.len() < 3 {
+            return Err(StorageError::WrongArgumentCount(command_name(command)));
+        }
+        let hash = self.hash_at(&command[1])?;
+        let values = command[2..]
+            .iter()
+            .map(|field| {
+                hash.as_ref()
+                    .and_then(|hash| hash.get(field))
+                    .map_or(RESP::Null, |value| RESP::BulkString(value.clone()))
+            })
+            .collect();
+        Ok(RESP::Array(values))
+    }
+
+    pub(super) fn command_hgetall(&mut self, command: &[Vec<u8>]) -> StorageResult<RESP> {
+        if command.len() != 2 {
+            return Err(StorageError::WrongArgumentCount(command_name(command)));
+        }
+        let pairs = match self.hash_at(&command[1])? {
+            Some(hash) => hash
+                .iter()
+                .map(|(field, value)| {
+                    (
+                        RESP::BulkString(field.to_vec()),
+                        RESP::BulkString(value.clone()),
+                    )
+                })
+                .collect(),
+            None => vec![],
+        };
+        Ok(RESP::Map(pairs))
+    }
+
+    pub(super) fn command_hdel(&mut self, command: &[Vec<u8>]) -> StorageResult<RESP> {
+        if command.len() < 3 {
+            return Err(StorageError::WrongArgumentCount(command_name(command)));
+        }
+        let key = &command[1];
+        let removed = match self.hash_at(key)? {
+            Some(hash) => command[2..]
+                .iter()
+                .filter(|field| hash.remove(field).is_some())
+                .count(),
+            None => 0,
+        };
+        self.remove_if_empty(key);
+        Ok(RESP::Integer(removed as i64))
+    }
+
+    pub(super) fn command_hexists(&mut self, command: &[Vec<u8>]) -> StorageResult<RESP> {
+        if command.len() != 3 {
+            return Err(StorageError::WrongArgumentCount(command_name(command)));
+        }
+        let exists = self
+            .hash_at(&command[1])?
+            .is_some_and(|hash| hash.contains_key(&command[2]));
+        Ok(RESP::Integer(exists as i64))
+    }
+
+    pub(super) fn command_hlen(&mut self, command: &[Vec<u8>]) -> StorageResult<RESP> {
+        if command.len() != 2 {
+            return Err(StorageError::WrongArgumentCount(command_name(command)));
+        }
+        let length = self.hash_at(&command[1])?.map_or(0, |hash| hash.len());
+        Ok(RESP::Integer(length as i64))
+    }
+
+    /// Handles HKEYS and, with `values`, HVALS.
+    pub(super) fn command_hkeys(
+        &mut self,
+        command: &[Vec<u8>],
+        values: bool,
+    ) -> StorageResult<RESP> {
+        if command.len() != 2 {
+            return Err(StorageError::WrongArgumentCount(command_name(command)));
+        }
+        let items = match self.hash_at(&command[1])? {
+            Some(hash) => hash
+                .iter()
+                .map(|(field, value)| {
+                    RESP::BulkString(if values { value } else { &field[..] }.to_vec())
+                })
+                .collect(),
+            None => vec![],
+        };
+        Ok(RESP::Array(items))
+    }
+
+    pub(super) fn command_hstrlen(&mut self, command: &[Vec<u8>]) -> StorageResult<RESP> {
+        if command.len() != 3 {
+            return Err(StorageError::WrongArgumentCount(command_name(command)));
+        }
+        let length = self
+            .hash_at(&command[1])?
+            .and_then(|hash| hash.get(&command[2]))
+            .map_or(0, |value| value.len());
+        Ok(RESP::Integer(length as i64))
+    }
+
+    pub(super) fn command_hincrby(&mut self, command: &[Vec<u8>]) -> StorageResult<RESP> {
+        if command.len() != 4 {
+            return Err(StorageError::WrongArgumentCount(command_name(command)));
+        }
+        let delta = parse_integer(&command[3])?;
+        let hash = self.hash_at_or_insert(&command[1])?;
+        let current = match hash.get(&command[2]) {
+            Some(value) => parse_integer(value).map_err(|_| {
+                StorageError::InvalidArgument(String::from("hash value is not an integer"))
+            })?,
+            None => 0,
+        };
+        let value = current.checked_add(delta).ok_or_else(|| {
+            StorageError::InvalidArgument(String::from("increment or decrement would overflow"))
+        })?;
+        hash.update(command[2].clone(), value.to_string().into_bytes());
+        Ok(RESP::Integer(value))
+    }
+
+    pub(super) fn command_hincrbyfloat(&mut self, command: &[Vec<u8>]) -> StorageResult<RESP> {
+        if command.len() != 4 {
+            return Err(StorageError::WrongArgumentCount(command_name(command)));
+        }
+        let increment = parse_float(&command[3])?;
+        let hash = self.hash_at_or_insert(&command[1])?;
+        let current = match hash.get(&command[2]) {
+            Some(value) => parse_float(value).map_err(|_| {
+                StorageError::InvalidArgument(String::from("hash value is not a float"))
+            })?,
+            None => 0.0,
+        };
+        let value = current + increment;
+        if !value.is_finite() {
+            // Only reachable through a new field, as an infinite one cannot be stored.
+            self.remove_if_empty(&command[1]);
+            return Err(StorageError::InvalidArgument(String::from(
+                "increment would produce NaN or Infinity",
+            )));
+        }
+        // Formatted like INCRBYFLOAT.
+        let value = format_float(value);
+        hash.update(command[2].clone(), value.clone());
+        Ok(RESP::BulkString(value))
+    }
+
+    pub(super) fn command_hrandfield(&mut self, command: &[Vec<u8>]) -> StorageResult<RESP> {
+        if command.len() < 2 {
+            return Err(StorageError::WrongArgumentCount(command_name(command)));
+        }
+        if command.len() > 4 {
+            return Err(StorageError::CommandSyntaxError(command_to_string(command)));
+        }
+        let with_values = match command.get(3) {
+            Some(option) if option.eq_ignore_ascii_case(b"withvalues") => true,
+            Some(_) => return Err(StorageError::CommandSyntaxError(command_to_string(command))),
+            None => false,
+        };
+        let count = command
+            .get(2)
+            .map(|count| parse_integer(count))
+            .transpose()?;
+        // Negating the count must not overflow, nor doubling it for the values.
+        let limit = if with_values { i64::MAX / 2 } else { i64::MAX };
+        if count.is_some_and(|count| count.unsigned_abs() > limit as u64) {
+            return Err(StorageError::InvalidArgument(String::from(
+                "value is out of range",
+            )));
+        }
+
+        let hash = match self.hash_at(&command[1])? {
+            Some(hash) => hash,
+            None if count.is_some() => return Ok(RESP::Array(vec![])),
+            None => return Ok(RESP::Null),
+        };
+        let count = match count {
+            Some(count) => count,
+            None => {
+                return Ok(hash
+                    .random()
+                    .map_or(RESP::Null, |(field, _)| RESP::BulkString(field.to_vec())));
+            }
+        };
+        let mut items = Vec::new();
+        for (field, value) in hash.random_picks(count) {
+            items.push(RESP::BulkString(field.to_vec()));
+            if with_values {
+                items.push(RESP::BulkString(value.clone()));
+            }
+        }
+        Ok(RESP::Array(items))
+    }
+
+    pub(super) fn command_hscan(&mut self, command: &[Vec<u8>]) -> StorageResult<RESP> {
+        if command.len() < 3 {
+            return Err(StorageError::WrongArgumentCount(command_name(command)));
+        }
+        let cursor = parse_cursor(&command[2])?;
+        let args = parse_scan_arguments(&command[3..], false)?;
+        let hash = match self.hash_at(&command[1])? {
+            Some(hash) => hash,
+            None => {
+                return Ok(RESP::Array(vec![
+                    RESP::BulkString(b"0".to_vec()),
+                    RESP::Array(vec![]),
+                ]))
+            }
+        };
+        let (next, batch) = hash.fields.scan(cursor, args.count);
+        let mut items = Vec::new();
+        for (field, value) in batch {
+            if args
+                .pattern
+                .as_ref()
+                .is_none_or(|pattern| glob_match(pattern, field, false))
+            {
+                items.push(RESP::BulkString(field.to_vec()));
+                items.push(RESP::BulkString(value.clone()));
+            }
+        }
+        Ok(RESP::Array(vec![
+            RESP::BulkString(next.to_string().into_bytes()),
+            RESP::Array(items),
+        ]))
+    }
+
+    /// Registers `key` for active expiry if it holds a hash with field TTLs.
+    pub(super) fn track_field_expiry(&mut self, key: &[u8]) {
+        if let Some(StorageData {
+            value: StorageValue::Hash(hash),
+            ..
+        }) = self.store.get(key)
+        {
+            if hash.has_deadlines() {
+                self.field_expiry.insert(key.to_vec());
+            }
+        }
+    }
+
+    /// Handles HEXPIRE, HPEXPIRE and HEXPIREAT, with `unit` and `absolute` as for EXPIRE.
+    /// Replies for each field with -2 if it does not exist, 0 if the condition is not
+    /// met, 1 if the TTL was set and 2 if the field was deleted by a time in the past.
+    pub(super) fn command_hexpire(
+        &mut self,
+        command: &[Vec<u8>],
+        unit: i64,
+        absolute: bool,
+    ) -> StorageResult<RESP> {
+        if command.len() < 6 {
+            return Err(StorageError::WrongArgumentCount(command_name(command)));
+        }
+        let key = &command[1];
+        let value = parse_integer(&command[2])?;
+        let mut conditions = ExpireConditions::default();
+        let fields = match conditions.set(&command[3]) {
+            true => parse_fields(command, 4)?,
+            false => parse_fields(command, 3)?,
+        };
+
+        let invalid = || {
+            StorageError::InvalidArgument(format!(
+                "invalid expire time in '{}' command",
+                String::from_utf8_lossy(&command[0]).to_lowercase()
+            ))
+        };
+        if value < 0 {
+            return Err(StorageError::InvalidArgument(String::from(
+                "invalid expire time, must be >= 0",
+            )));
+        }
+        let now = now_millis();
+        let mut when = value.checked_mul(unit).ok_or_else(invalid)?;
+        if !absolute {
+            when = when.checked_add(now).ok_or_else(invalid)?;
+        }
+
+        let hash = match self.hash_at(key)? {
+            Some(hash) => hash,
+            None => return Ok(missing_fields(fields.len())),
+        };
+        let mut replies = Vec::with_capacity(fields.len());
+        for field in fields {
+            let reply = if !hash.contains_key(field) {
+                -2
+            } else if !conditions.allow(
+                hash.deadlines.get(field).map(|&d| millis_since_epoch(d)),
+                when,
+            ) {
+                0
+            } else if when <= now {
+                hash.remove(field);
+                2
+            } else {
+                let deadline = UNIX_EPOCH + Duration::from_millis(when as u64);
+                hash.deadlines.insert(field.clone(), deadline);
+                1
+            };
+            replies.push(RESP::Integer(reply));
+        }
+        self.remove_if_empty(key);
+        self.track_field_expiry(key);
+        Ok(RESP::Array(replies))
+    }
+
+    /// Handles HTTL and HPTTL, replying for each field with -2 if it does not exist, -1
+    /// if it has no TTL and the time left otherwise.
+    pub(super) fn command_httl(&mut self, command: &[Vec<u8>], unit: i64) -> StorageResult<RESP> {
+        let fields = parse_fields(command, 2)?;
+        let hash = match self.hash_at(&command[1])? {
+            Some(hash) => hash,
+            None => return Ok(missing_fields(fields.len())),
+        };
+        let now = now_millis();
+        let replies = fields
+            .iter()
+            .map(|field| {
+                if !hash.contains_key(field) {
+                    return RESP::Integer(-2);
+                }
+                match hash.deadlines.get(field) {
+                    Some(&deadline) => {
+                        let millis = (millis_since_epoch(deadline) - now).max(0);
+                        RESP::Integer((millis + unit / 2) / unit)
+                    }
+                    None => RESP::Integer(-1),
+                }
+            })
+            .collect();
+        Ok(RESP::Array(replies))
+    }
+
+    /// Replies for each field with -2 if it does not exist, -1 if it has no TTL and 1
+    /// once its TTL is removed.
+    pub(super) fn command_hpersist(&mut self, command: &[Vec<u8>]) -> StorageResult<RESP> {
+        let fields = parse_fields(command, 2)?;
+        let hash = match self.hash_at(&command[1])? {
+            Some(hash) => hash,
+            None => return Ok(missing_fields(fields.len())),
+        };
+        let replies = fields
+            .iter()
+            .map(|field| match hash.contains_key(field) {
+                false => RESP::Integer(-2),
+                true => match hash.deadlines.remove(field) {
+                    Some(_) => RESP::Integer(1),
+                    None => RESP::Integer(-1),
+                },
+            })
+            .collect();
+        Ok(RESP::Array(replies))
+    }
+}
+
+/// The reply of the per-field TTL commands for a hash that does not exist.
+fn missing_fields(count: usize) -> RESP {
+    RESP::Array((0..count).map(|_| RESP::Integer(-2)).collect())
+}
+
+/// Parses the `FIELDS numfields field [field ...]` arguments that end the per-field TTL
+/// commands, starting at `idx`.
+fn parse_fields(command: &[Vec<u8>], idx: usize) -> StorageResult<&[Vec<u8>]> {
+    match command.get(idx) {
+        Some(argument) if argument.eq_ignore_ascii_case(b"fields") => {}
+        _ => {
+            return Err(StorageError::InvalidArgument(String::from(
+                "Mandatory argument FIELDS is missing or not at the right position",
+            )))
+        }
+    }
+    let count = match command.get(idx + 1) {
+        Some(count) => parse_integer(count)?,
+        None => return Err(StorageError::CommandSyntaxError(command_to_string(command))),
+    };
+    if count <= 0 {
+        return Err(StorageError::InvalidArgument(String::from(
+            "Parameter `numFields` should be greater than 0",
+        )));
+    }
+    let fields = &command[idx + 2..];
+    if fields.len() as i64 != count {
+        return Err(StorageError::InvalidArgument(String::from(
+            "The `numfields` parameter must match the number of arguments",
+        )));
+    }
+    Ok(fields)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::test_helpers::{bulk, bulks, run};
+    use std::collections::HashSet;
+
+    /// The strings in an array reply, sorted since hashes have no order.
+    fn sorted(reply: StorageResult<RESP>) -> Vec<Vec<u8>> {
+        let mut items: Vec<Vec<u8>> = match reply {
+            Ok(RESP::Array(items)) => items
+                .into_iter()
+                .map(|item| match item {
+                    RESP::BulkString(v) => v,
+                    other => panic!("unexpected item {:?}", other),
+                })
+                .collect(),
+            other => panic!("unexpected reply {:?}", other),
+        };
+        items.sort();
+        items
+    }
+
+    fn strings(values: &[&str]) -> Vec<Vec<u8>> {
+        values
+            .iter()
+            .map(|value| value.as_bytes().to_vec())
+            .collect()
+    }
+
+    #[test]
+    fn test_command_hset_and_hget() {
+        let mut storage = Storage::new();
+        assert_eq!(
+            run(&mut storage, &["hset", "h", "a", "1", "b", "2"]),
+            Ok(RESP::Integer(2))
+        );
+        assert_eq!(
+            run(&mut storage, &["hset", "h", "a", "3", "c", "4"]),
+            Ok(RESP::Integer(1))
+        );
+        assert_eq!(run(&mut storage, &["hget", "h", "a"]), Ok(bulk("3")));
+        assert_eq!(run(&mut storage, &["hget", "h", "z"]), Ok(RESP::Null));
+        assert_eq!(run(&mut storage, &["hget", "none", "a"]), Ok(RESP::Null));
+        assert_eq!(run(&mut storage, &["hlen", "h"]), Ok(RESP::Integer(3)));
+        assert_eq!(
+            run(&mut storage, &["type", "h"]),
+            Ok(RESP::SimpleString(String::from("hash")))
+        );
+        assert!(run(&mut storage, &["hset", "h", "a"]).is_err());
+        assert!(run(&mut storage, &["hset", "h", "a", "1", "b"]).is_err());
+    }
+
+    #[test]
+    fn test_command_hsetnx() {
+        let mut storage = Storage::new();
+        assert_eq!(
+            run(&mut storage, &["hsetnx", "h", "a", "1"]),
+            Ok(RESP::Integer(1))
+        );
+        assert_eq!(
+            run(&mut storage, &["hsetnx", "h", "a", "2"]),
+            Ok(RESP::Integer(0))
+        );
+        assert_eq!(run(&mut storage, &["hget", "h", "a"]), Ok(bulk("1")));
+    }
+
+    #[test]
+    fn test_command_hmget_and_hgetall() {
+        let mut storage = Storage::new();
+        run(&mut storage, &["hset", "h", "a", "1", "b", "2"]).unwrap();
+        assert_eq!(
+            run(&mut storage, &["hmget", "h", "b", "z", "a"]),
+            Ok(RESP::Array(vec![bulk("2"), RESP::Null, bulk("1")]))
+        );
+        assert_eq!(
+            run(&mut storage, &["hmget", "none", "a"]),
+            Ok(RESP::Array(vec![RESP::Null]))
+        );
+        match run(&mut storage, &["hgetall", "h"]) {
+            Ok(RESP::Map(mut pairs)) => {
+                pairs.sort_by_key(|(field, _)| format!("{:?}", field));
+                assert_eq!(pairs, vec![(bulk("a"), bulk("1")), (bulk("b"), bulk("2"))]);
+            }
+            other => panic!("unexpected reply {:?}", other),
+        }
+        assert_eq!(
+            run(&mut storage, &["hgetall", "none"]),
+            Ok(RESP::Map(vec![]))
+        );
+        assert_eq!(
+            sorted(run(&mut storage, &["hkeys", "h"])),
+            strings(&["a", "b"])
+        );
+        assert_eq!(
+            sorted(run(&mut storage, &["hvals", "h"])),
+            strings(&["1", "2"])
+        );
+    }
+
+    #[test]
+    fn test_command_hdel_removes_empty_hash() {
+        let mut storage = Storage::new();
+        run(&mut storage, &["hset", "h", "a", "1", "b", "2"]).unwrap();
+        assert_eq!(
+            run(&mut storage, &["hdel", "h", "a", "z"]),
+            Ok(RESP::Integer(1))
+        );
+        assert_eq!(
+            run(&mut storage, &["hexists", "h", "a"]),
+            Ok(RESP::Integer(0))
+        );
+        assert_eq!(
+            run(&mut storage, &["hexists", "h", "b"]),
+            Ok(RESP::Integer(1))
+        );
+        assert_eq!(run(&mut storage, &["hdel", "h", "b"]), Ok(RESP::Integer(1)));
+        assert_eq!(run(&mut storage, &["exists", "h"]), Ok(RESP::Integer(0)));
+    }
+
+    #[test]
+    fn test_command_hstrlen() {
+        let mut storage = Storage::new();
+        run(&mut storage, &["hset", "h", "a", "hello"]).unwrap();
+        assert_eq!(
+            run(&mut storage, &["hstrlen", "h", "a"]),
+            Ok(RESP::Integer(5))
+        );
+        assert_eq!(
+            run(&mut storage, &["hstrlen", "h", "z"]),
+            Ok(RESP::Integer(0))
+        );
+    }
+
+    #[test]
+    fn test_command_hincrby() {
+        let mut storage = Storage::new();
+        assert_eq!(
+            run(&mut storage, &["hincrby", "h", "n", "5"]),
+            Ok(RESP::Integer(5))
+        );
+        assert_eq!(
+            run(&mut storage, &["hincrby", "h", "n", "-7"]),
+            Ok(RESP::Integer(-2))
+        );
+        run(
+            &mut storage,
+            &["hset", "h", "s", "abc", "max", "9223372036854775807"],
+        )
+        .unwrap();
+        assert_eq!(
+            run(&mut storage, &["hincrby", "h", "s", "1"]),
+            Err(StorageError::InvalidArgument(String::from(
+                "hash value is not an integer"
+            )))
+        );
+        assert!(run(&mut storage, &["hincrby", "h", "max", "1"]).is_err());
+        assert_eq!(
+            run(&mut storage, &["hincrby", "h", "n", "x"]),
+            Err(StorageError::NotAnInteger)
+        );
+    }
+
+    #[test]
+    fn test_command_hincrbyfloat() {
+        let mut storage = Storage::new();
+        assert_eq!(
+            run(&mut storage, &["hincrbyfloat", "h", "f", "10.5"]),
+            Ok(bulk("10.5"))
+        );
+        assert_eq!(
+            run(&mut storage, &["hincrbyfloat", "h", "f", "-0.5"]),
+            Ok(bulk("10"))
+        );
+        assert_eq!(
+            run(&mut storage, &["hincrbyfloat", "h", "large", "1e20"]),
+            Ok(bulk("100000000000000000000"))
+        );
+        assert_eq!(
+            run(&mut storage, &["hincrbyfloat", "h", "tiny", "0.00001"]),
+            Ok(bulk("0.00001"))
+        );
+        assert!(run(&mut storage, &["hincrbyfloat", "h", "f", "inf"]).is_err());
+        assert!(run(&mut storage, &["hincrbyfloat", "new", "f", "inf"]).is_err());
+        assert_eq!(run(&mut storage, &["exists", "new"]), Ok(RESP::Integer(0)));
+    }
+
+    #[test]
+    fn test_command_hrandfield() {
+        let mut storage = Storage::new();
+        assert_eq!(run(&mut storage, &["hrandfield", "h"]), Ok(RESP::Null));
+        assert_eq!(run(&mut storage, &["hrandfield", "h", "3"]), Ok(bulks(&[])));
+        run(&mut storage, &["hset", "h", "a", "1", "b", "2", "c", "3"]).unwrap();
+
+        let fields = strings(&["a", "b", "c"]);
+        match run(&mut storage, &["hrandfield", "h"]) {
+            Ok(RESP::BulkString(field)) => assert!(fields.contains(&field)),
+            other => panic!("unexpected reply {:?}", other),
+        }
+        assert_eq!(
+            sorted(run(&mut storage, &["hrandfield", "h", "10"])),
+            fields
+        );
+        assert_eq!(
+            sorted(run(&mut storage, &["hrandfield", "h", "2"])).len(),
+            2
+        );
+        let repeated = sorted(run(&mut storage, &["hrandfield", "h", "-10"]));
+        assert_eq!(repeated.len(), 10);
+        assert!(repeated.iter().all(|field| fields.contains(field)));
+
+        let pairs = [
+            (bulk("a"), bulk("1")),
+            (bulk("b"), bulk("2")),
+            (bulk("c"), bulk("3")),
+        ];
+        match run(&mut storage, &["hrandfield", "h", "-5", "withvalues"]) {
+            Ok(RESP::Array(items)) => {
+                assert_eq!(items.len(), 10);
+                assert!(items
+                    .chunks(2)
+                    .all(|pair| pairs.iter().any(|(f, v)| *f == pair[0] && *v == pair[1])));
+            }
+            other => panic!("unexpected reply {:?}", other),
+        }
+        assert!(run(&mut storage, &["hrandfield", "h", "1", "values"]).is_err());
+        assert!(run(&mut storage, &["hrandfield", "h", "-9223372036854775808"]).is_err());
+        assert!(run(
+            &mut storage,
+            &["hrandfield", "h", "-4611686018427387904", "withvalues"]
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_command_hscan() {
+        let mut storage = Storage::new();
+        for i in 0..30 {
+            let field = format!("field:{}", i);
+            run(&mut storage, &["hset", "h", &field, "v"]).unwrap();
+        }
+        run(&mut storage, &["hset", "h", "other", "v"]).unwrap();
+        let mut seen = HashSet::new();
+        let mut cursor = String::from("0");
+        loop {
+            let reply = run(
+                &mut storage,
+                &["hscan", "h", &cursor, "match", "field:*", "count", "7"],
+            );
+            let (next, items) = match reply {
+                Ok(RESP::Array(mut reply)) => match (reply.remove(0), reply.remove(0)) {
+                    (RESP::BulkString(next), RESP::Array(items)) => (next, items),
+                    other => panic!("unexpected reply {:?}", other),
+                },
+                other => panic!("unexpected reply {:?}", other),
+            };
+            for pair in items.chunks(2) {
+                assert_eq!(pair[1], bulk("v"));
+                match &pair[0] {
+                    RESP::BulkString(field) => seen.insert(field.clone()),
+                    other => panic!("unexpected field {:?}", other),
+                };
+            }
+            cursor = String::from_utf8(next).unwrap();
+            if cursor == "0" {
+                break;
+            }
+        }
+        assert_eq!(seen.len(), 30);
+        assert!(!seen.contains(b"other".as_slice()));
+        assert!(run(&mut storage, &["hscan", "h", "0", "type", "hash"]).is_err());
+    }
+
+    #[test]
+    fn test_hash_wrong_type_and_encoding() {
+        let mut storage = Storage::new();
+        run(&mut storage, &["set", "s", "x"]).unwrap();
+        assert_eq!(
+            run(&mut storage, &["hset", "s", "a", "1"]),
+            Err(StorageError::WrongType)
+        );
+        assert_eq!(
+            run(&mut storage, &["hmget", "s", "a"]),
+            Err(StorageError::WrongType)
+        );
+        run(&mut storage, &["hset", "h", "a", "1"]).unwrap();
+        assert_eq!(
+            run(&mut storage, &["object", "encoding", "h"]),
+            Ok(bulk("listpack"))
+        );
+        let long = "x".repeat(65);
+        run(&mut storage, &["hset", "h", "b", &long]).unwrap();
+        assert_eq!(
+            run(&mut storage, &["object", "encoding", "h"]),
+            Ok(bulk("hashtable"))
+        );
+    }
+
+    fn integers(values: &[i64]) -> RESP {
+        RESP::Array(values.iter().map(|&value| RESP::Integer(value)).collect())
+    }
+
+    /// Moves the deadline of a field with a TTL into the past.
+    fn backdate(storage: &mut Storage, key: &str, field: &str) {
+        match storage.store.get_mut(key.as_bytes()) {
+            Some(StorageData {
+                value: StorageValue::Hash(hash),
+                ..
+            }) => {
+                let deadline = hash.deadlines.get_mut(field.as_bytes()).unwrap();
+                *deadline = SystemTime::now() - Duration::from_secs(1);
+            }
+            other => panic!("unexpected value {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_command_hexpire_and_httl() {
+        let mut storage = Storage::new();
+        run(&mut storage, &["hset", "h", "a", "1", "b", "2"]).unwrap();
+        assert_eq!(
+            run(
+                &mut storage,
+                &["hexpire", "h", "100", "FIELDS", "3", "a", "b", "z"]
+            ),
+            Ok(integers(&[1, 1, -2]))
+        );
+        assert_eq!(
+            run(&mut storage, &["httl", "h", "FIELDS", "2", "a", "z"]),
+            Ok(integers(&[100, -2]))
+        );
+        match run(&mut storage, &["hpttl", "h", "FIELDS", "1", "b"]) {
+            Ok(RESP::Array(ttls)) => match ttls[0] {
+                RESP::Integer(ttl) => assert!((99_000..=100_000).contains(&ttl)),
+                ref other => panic!("unexpected ttl {:?}", other),
+            },
+            other => panic!("unexpected reply {:?}", other),
+        }
+        assert_eq!(
+            run(&mut storage, &["object", "encoding", "h"]),
+            Ok(bulk("listpackex"))
+        );
+
+        assert_eq!(
+            run(
+                &mut storage,
+                &["hexpire", "h", "200", "GT", "FIELDS", "1", "a"]
+            ),
+            Ok(integers(&[1]))
+        );
+        assert_eq!(
+            run(
+                &mut storage,
+                &["hexpire", "h", "300", "LT", "FIELDS", "1", "a"]
+            ),
+            Ok(integers(&[0]))
+        );
+        assert_eq!(
+            run(
+                &mut storage,
+                &["hexpire", "h", "300", "NX", "FIELDS", "1", "a"]
+            ),
+            Ok(integers(&[0]))
+        );
+        assert_eq!(
+            run(
+                &mut storage,
+                &["hpexpire", "h", "5000", "XX", "FIELDS", "1", "b"]
+            ),
+            Ok(integers(&[1]))
+        );
+        assert_eq!(
+            run(&mut storage, &["httl", "h", "FIELDS", "2", "a", "b"]),
+            Ok(integers(&[200, 5]))
+        );
+        assert_eq!(
+            run(&mut storage, &["hexpire", "none", "10", "FIELDS", "1", "a"]),
+            Ok(integers(&[-2]))
+        );
+    }
+
+    #[test]
+    fn test_command_hexpire_invalid() {
+        let mut storage = Storage::new();
+        run(&mut storage, &["hset", "h", "a", "1"]).unwrap();
+        assert_eq!(
+            run(&mut storage, &["hexpire", "h", "10", "FIELDS", "2", "a"]),
+            Err(StorageError::InvalidArgument(String::from(
+                "The `numfields` parameter must match the number of arguments"
+            )))
+        );
+        assert!(run(&mut storage, &["hexpire", "h", "10", "FIELDS", "0", "a"]).is_err());
+        assert!(run(&mut storage, &["hexpire", "h", "10", "NX", "XX", "1", "a"]).is_err());
+        assert!(run(&mut storage, &["hexpire", "h", "-1", "FIELDS", "1", "a"]).is_err());
+        assert!(run(&mut storage, &["httl", "h", "FIELDS", "1"]).is_err());
+        assert_eq!(
+            run(
+                &mut storage,
+                &["hexpire", "h", "9223372036854775807", "FIELDS", "1", "a"]
+            ),
+            Err(StorageError::InvalidArgument(String::from(
+                "invalid expire time in 'hexpire' command"
+            )))
+        );
+    }
+
+    #[test]
+    fn test_command_hexpire_in_the_past_deletes() {
+        let mut storage = Storage::new();
+        run(&mut storage, &["hset", "h", "a", "1", "b", "2"]).unwrap();
+        assert_eq!(
+            run(&mut storage, &["hexpireat", "h", "1", "FIELDS", "1", "a"]),
+            Ok(integers(&[2]))
+        );
+        assert_eq!(run(&mut storage, &["hkeys", "h"]), Ok(bulks(&["b"])));
+        run(&mut storage, &["hpexpire", "h", "0", "FIELDS", "1", "b"]).unwrap();
+        assert_eq!(run(&mut storage, &["exists", "h"]), Ok(RESP::Integer(0)));
+    }
+
+    #[test]
+    fn test_command_hpersist_and_overwrites() {
+        let mut storage = Storage::new();
+        run(&mut storage, &["hset", "h", "a", "1", "b", "2", "n", "1"]).unwrap();
+        run(
+            &mut storage,
+            &["hexpire", "h", "100", "FIELDS", "3", "a", "b", "n"],
+        )
+        .unwrap();
+        assert_eq!(
+            run(
+                &mut storage,
+                &["hpersist", "h", "FIELDS", "3", "a", "a", "z"]
+            ),
+            Ok(integers(&[1, -1, -2]))
+        );
+        // HSET replaces the field along with its TTL, HINCRBY only changes the value.
+        run(&mut storage, &["hset", "h", "b", "3"]).unwrap();
+        run(&mut storage, &["hincrby", "h", "n", "1"]).unwrap();
+        assert_eq!(
+            run(&mut storage, &["httl", "h", "FIELDS", "3", "a", "b", "n"]),
+            Ok(integers(&[-1, -1, 100]))
+        );
+        assert_eq!(
+            run(&mut storage, &["object", "encoding", "h"]),
+            Ok(bulk("listpackex"))
+        );
+        run(&mut storage, &["hdel", "h", "n"]).unwrap();
+        assert_eq!(
+            run(&mut storage, &["object", "encoding", "h"]),
+            Ok(bulk("listpack"))
+        );
+    }
+
+    #[test]
+    fn test_expired_fields_are_reclaimed_lazily() {
+        let mut storage = Storage::new();
+        run(&mut storage, &["hset", "h", "a", "1", "b", "2"]).unwrap();
+        run(
+            &mut storage,
+            &["hexpire", "h", "100", "FIELDS", "2", "a", "b"],
+        )
+        .unwrap();
+        backdate(&mut storage, "h", "a");
+        assert_eq!(run(&mut storage, &["hget", "h", "a"]), Ok(RESP::Null));
+        assert_eq!(run(&mut storage, &["hlen", "h"]), Ok(RESP::Integer(1)));
+
+        backdate(&mut storage, "h", "b");
+        assert_eq!(run(&mut storage, &["hgetall", "h"]), Ok(RESP::Map(vec![])));
+        assert_eq!(run(&mut storage, &["exists", "h"]), Ok(RESP::Integer(0)));
+    }
+
+    #[test]
+    fn test_expired_fields_are_reclaimed_actively() {
+        let mut storage = Storage::new();
+        run(&mut storage, &["hset", "h", "a", "1", "b", "2"]).unwrap();
+        run(&mut storage, &["hexpire", "h", "100", "FIELDS", "1", "a"]).unwrap();
+        // The renamed hash keeps its field TTLs and is still visited.
+        run(&mut storage, &["rename", "h", "renamed"]).unwrap();
+        backdate(&mut storage, "renamed", "a");
+        storage.expire_keys();
+        assert!(storage.field_expiry.is_empty());
+        assert_eq!(run(&mut storage, &["hkeys", "renamed"]), Ok(bulks(&["b"])));
+
+        run(
+            &mut storage,
+            &["hexpire", "renamed", "100", "FIELDS", "1", "b"],
+        )
+        .unwrap();
+        backdate(&mut storage, "renamed", "b");
+        storage.expire_keys();
+        assert!(storage.store.is_empty());
+    }
+}
@@ -0,0 +1,713 @@
+use super::{parse_integer, resolve_range, Storage, StorageData, StorageValue};
+use crate::resp::RESP;
+use crate::storage_result::{command_name, command_to_string, StorageError, StorageResult};
+use std::collections::VecDeque;
+
+/// The side of a list an element is pushed to or popped from.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ListEnd {
+    Left,
+    Right,
+}
+
+impl ListEnd {
+    /// Parses the `LEFT`/`RIGHT` arguments of LMOVE and friends.
+    pub fn parse(argument: &[u8]) -> Option<ListEnd> {
+        match argument.to_ascii_lowercase().as_slice() {
+            b"left" => Some(ListEnd::Left),
+            b"right" => Some(ListEnd::Right),
+            _ => None,
+        }
+    }
+}
+
+fn push(list: &mut VecDeque<Vec<u8>>, element: Vec<u8>, end: ListEnd) {
+    match end {
+        ListEnd::Left => list.push_front(element),
+        ListEnd::Right => list.push_back(element),
+    }
+}
+
+fn pop(list: &mut VecDeque<Vec<u8>>, end: ListEnd) -> Option<Vec<u8>> {
+    match end {
+        ListEnd::Left => list.pop_front(),
+        ListEnd::Right => list.pop_back(),
+    }
+}
+
+impl Storage {
+    /// Returns the list stored at `key`, or `None` if there is no such key.
+    fn list_at(&mut self, key: &[u8]) -> StorageResult<Option<&mut VecDeque<Vec<u8>>>> {
+        match self.lookup(key) {
+            Some(StorageData {
+                value: StorageValue::List(list),
+                ..
+            }) => Ok(Some(list)),
+            Some(_) => Err(StorageError::WrongType),
+            None => Ok(None),
+        }
+    }
+
+    /// Pushes `elements` one after the other onto `key`, creating the list unless
+    /// `only_existing` is set. Returns the length of the list, 0 if it does not exist.
+    pub(super) fn list_push(
+        &mut self,
+        key: &[u8],
+        elements: &[Vec<u8>],
+        end: ListEnd,
+        only_existing: bool,
+    ) -> StorageResult<usize> {
+        let length = match self.list_at(key)? {
+            Some(list) => {
+                for element in elements.iter() {
+                    push(list, element.clone(), end);
+                }
+                list.len()
+            }
+            None if only_existing => return Ok(0),
+            None => {
+                let mut list = VecDeque::with_capacity(elements.len());
+                for element in elements.iter() {
+                    push(&mut list, element.clone(), end);
+                }
+                let length = list.len();
+                self.store
+                    .insert(key.to_vec(), StorageData::from(StorageValue::List(list)));
+                length
+            }
+        };
+        self.signal_key_ready(key);
+        Ok(length)
+    }
+
+    /// Pops up to `count` elements from `key`, deleting the list once it is empty.
+    /// Returns `None` if there is no such key.
+    pub(super) fn list_pop(
+        &mut self,
+        key: &[u8],
+        end: ListEnd,
+        count: usize,
+    ) -> StorageResult<Option<Vec<Vec<u8>>>> {
+        let list = match self.list_at(key)? {
+            Some(list) => list,
+            None => return Ok(None),
+        };
+        let count = count.min(list.len());
+        let elements = (0..count).filter_map(|_| pop(list, end)).collect();
+        self.remove_if_empty(key);
+        Ok(Some(elements))
+    }
+
+    /// Moves one element from `source` to `destination`, which may be the same list.
+    /// Returns `None` if `source` does not exist.
+    pub(super) fn list_move(
+        &mut self,
+        source: &[u8],
+        destination: &[u8],
+        from: ListEnd,
+        to: ListEnd,
+    ) -> StorageResult<Option<Vec<u8>>> {
+        // Check the destination up front so that a wrong type never loses the element.
+        self.list_at(destination)?;
+        let element = match self.list_at(source)?.and_then(|list| pop(list, from)) {
+            Some(element) => element,
+            None => return Ok(None),
+        };
+        // Pushing before the emptiness check keeps a rotated single element list, and its
+        // TTL, in place.
+        self.list_push(destination, std::slice::from_ref(&element), to, false)?;
+        self.remove_if_empty(source);
+        Ok(Some(element))
+    }
+
+    /// Handles LPUSH, RPUSH and, with `only_existing`, LPUSHX and RPUSHX.
+    pub(super) fn command_push(
+        &mut self,
+        command: &[Vec<u8>],
+        end: ListEnd,
+        only_existing: bool,
+    ) -> StorageResult<RESP> {
+        if command.len() < 3 {
+            return Err(StorageError::WrongArgumentCount(command_name(command)));
+        }
+        let length = self.list_push(&command[1], &command[2..], end, only_existing)?;
+        Ok(RESP::Integer(length as i64))
+    }
+
+    /// Handles LPOP and RPOP, which reply with a single element unless given a count.
+    pub(super) fn command_pop(&mut self, command: &[Vec<u8>], end: ListEnd) -> StorageResult<RESP> {
+        if command.len() != 2 && command.len() != 3 {
+            return Err(StorageError::WrongArgumentCount(command_name(command)));
+        }
+        let count = match command.get(2) {
+            Some(count) => match parse_integer(count)? {
+                count if count < 0 => {
+                    return Err(StorageError::InvalidArgument(String::from(
+                        "value is out of range, must be positive",
+                    )))
+                }
+                count => Some(count as usize),
+            },
+            None => None,
+        };
+        let elements = self.list_pop(&command[1], end, count.unwrap_or(1))?;
+        match (elements, count) {
+            (Some(elements), Some(_)) => Ok(RESP::Array(
+                elements.into_iter().map(RESP::BulkString).collect(),
+            )),
+            (Some(mut elements), None) => Ok(elements.pop().map_or(RESP::Null, RESP::BulkString)),
+            (None, _) => Ok(RESP::Null),
+        }
+    }
+
+    pub(super) fn command_llen(&mut self, command: &[Vec<u8>]) -> StorageResult<RESP> {
+        if command.len() != 2 {
+            return Err(StorageError::WrongArgumentCount(command_name(command)));
+        }
+        let length = self.list_at(&command[1])?.map_or(0, |list| list.len());
+        Ok(RESP::Integer(length as i64))
+    }
+
+    pub(super) fn command_lrange(&mut self, command: &[Vec<u8>]) -> StorageResult<RESP> {
+        if command.len() != 4 {
+            return Err(StorageError::WrongArgumentCount(command_name(command)));
+        }
+        let start = parse_integer(&command[2])?;
+        let stop = parse_integer(&command[3])?;
+        let list = match self.list_at(&command[1])? {
+            Some(list) => list,
+            None => return Ok(RESP::Array(vec![])),
+        };
+        let elements = match resolve_range(start, stop, list.len()) {
+            Some((start, stop)) => list
+                .range(start..=stop)
+                .map(|element| RESP::BulkString(element.clone()))
+                .collect(),
+            None => vec![],
+        };
+        Ok(RESP::Array(elements))
+    }
+
+    pub(super) fn command_lindex(&mut self, command: &[Vec<u8>]) -> StorageResult<RESP> {
+        if command.len() != 3 {
+            return Err(StorageError::WrongArgumentCount(command_name(command)));
+        }
+        let index = parse_integer(&command[2])?;
+        let element = self
+            .list_at(&command[1])?
+            .and_then(|list| resolve_index(index, list.len()).map(|index| &list[index]));
+        Ok(element.map_or(RESP::Null, |element| RESP::BulkString(element.clone())))
+    }
+
+    pub(super) fn command_lset(&mut self, command: &[Vec<u8>]) -> StorageResult<RESP> {
+        if command.len() != 4 {
+            return Err(StorageError::WrongArgumentCount(command_name(command)));
+        }
+        let index = parse_integer(&command[2])?;
+        let list = match self.list_at(&command[1])? {
+            Some(list) => list,
+            None => return Err(StorageError::InvalidArgument(String::from("no such key"))),
+        };
+        match resolve_index(index, list.len()) {
+            Some(index) => {
+                list[index] = command[3].clone();
+                Ok(RESP::SimpleString(String::from("OK")))
+            }
+            None => Err(StorageError::InvalidArgument(String::from(
+                "index out of range",
+            ))),
+        }
+    }
+
+    pub(super) fn command_linsert(&mut self, command: &[Vec<u8>]) -> StorageResult<RESP> {
+        if command.len() != 5 {
+            return Err(StorageError::WrongArgumentCount(command_name(command)));
+        }
+        let after = match command[2].to_ascii_lowercase().as_slice() {
+            b"before" => false,
+            b"after" => true,
+            _ => return Err(StorageError::CommandSyntaxError(command_to_string(command))),
+        };
+        let (pivot, element) = (&command[3], &command[4]);
+        let list = match self.list_at(&command[1])? {
+            Some(list) => list,
+            None => return Ok(RESP::Integer(0)),
+        };
+        match list.iter().position(|item| item == pivot) {
+            Some(index) => {
+                list.insert(index + after as usize, element.clone());
+                Ok(RESP::Integer(list.len() as i64))
+            }
+            None => Ok(RESP::Integer(-1)),
+        }
+    }
+
+    pub(super) fn command_lrem(&mut self, command: &[Vec<u8>]) -> StorageResult<RESP> {
+        if command.len() != 4 {
+            return Err(StorageError::WrongArgumentCount(command_name(command)));
+        }
+        let key = &command[1];
+        let count = parse_integer(&command[2])?;
+        let element = &command[3];
+        let list = match self.list_at(key)? {
+            Some(list) => list,
+            None => return Ok(RESP::Integer(0)),
+        };
+
+        // A positive count removes from the head, a negative one from the tail and 0
+        // removes every occurrence.
+        let limit = match count {
+            0 => usize::MAX,
+            count => count.unsigned_abs() as usize,
+        };
+        let mut removed: usize = 0;
+        let mut matches = |item: &Vec<u8>| {
+            let matched = removed < limit && item == element;
+            removed += matched as usize;
+            matched
+        };
+        if count >= 0 {
+            list.retain(|item| !matches(item));
+        } else {
+            let mut kept = VecDeque::with_capacity(list.len());
+            while let Some(item) = list.pop_back() {
+                if !matches(&item) {
+                    kept.push_front(item);
+                }
+            }
+            *list = kept;
+        }
+        self.remove_if_empty(key);
+        Ok(RESP::Integer(removed as i64))
+    }
+
+    pub(super) fn command_ltrim(&mut self, command: &[Vec<u8>]) -> StorageResult<RESP> {
+        if command.len() != 4 {
+            return Err(StorageError::WrongArgumentCount(command_name(command)));
+        }
+        let key = &command[1];
+        let start = parse_integer(&command[2])?;
+        let stop = parse_integer(&command[3])?;
+        if let Some(list) = self.list_at(key)? {
+            match resolve_range(start, stop, list.len()) {
+                Some((start, stop)) => {
+                    list.truncate(stop + 1);
+                    list.drain(..start);
+                }
+                None => list.clear(),
+            }
+            self.remove_if_empty(key);
+        }
+        Ok(RESP::SimpleString(String::from("OK")))
+    }
+
+    pub(super) fn command_lpos(&mut self, command: &[Vec<u8>]) -> StorageResult<RESP> {
+        if command.len() < 3 {
+            return Err(StorageError::WrongArgumentCount(command_name(command)));
+        }
+        if command.len().is_multiple_of(2) {
+            return Err(StorageError::CommandSyntaxError(command_to_string(command)));
+        }
+        let element = &command[2];
+        let mut rank: i64 = 1;
+        let mut count: Option<usize> = None;
+        let mut maxlen: usize = 0;
+        for option in command[3..].chunks(2) {
+            let value = parse_integer(&option[1])?;
+            match option[0].to_ascii_lowercase().as_slice() {
+                b"rank" if value == 0 => {
+                    return Err(StorageError::InvalidArgument(String::from(
+                        "RANK can't be zero: use 1 to start from the first match, 2 from the \
+                         second ... or use negative to start from the end of the list",
+                    )))
+                }
+                b"rank" => rank = value,
+                b"count" if value < 0 => {
+                    return Err(StorageError::InvalidArgument(String::from(
+                        "COUNT can't be negative",
+                    )))
+                }
+                b"count" => count = Some(value as usize),
+                b"maxlen" if value < 0 => {
+                    return Err(StorageError::InvalidArgument(String::from(
+                        "MAXLEN can't be negative",
+                    )))
+                }
+                b"maxlen" => maxlen = value as usize,
+                _ => return Err(StorageError::CommandSyntaxError(command_to_string(command))),
+            }
+        }
+
+        let list = match self.list_at(&command[1])? {
+            Some(list) => list,
+            None if count.is_some() => return Ok(RESP::Array(vec![])),
+            None => return Ok(RESP::Null),
+        };
+        // A negative rank searches from the tail; either way positions count from the head.
+        let indexes: Box<dyn Iterator<Item = usize>> = match rank > 0 {
+            true => Box::new(0..list.len()),
+            false => Box::new((0..list.len()).rev()),
+        };
+        let wanted = match count {
+            Some(0) => usize::MAX,
+            Some(count) => count,
+            None => 1,
+        };
+        let positions: Vec<RESP> = indexes
+            .take(if maxlen == 0 { usize::MAX } else { maxlen })
+            .filter(|&index| list[index] == *element)
+            .skip(rank.unsigned_abs() as usize - 1)
+            .take(wanted)
+            .map(|index| RESP::Integer(index as i64))
+            .collect();
+        match count {
+            Some(_) => Ok(RESP::Array(positions)),
+            None => Ok(positions.into_iter().next().unwrap_or(RESP::Null)),
+        }
+    }
+
+    pub(super) fn command_lmove(&mut self, command: &[Vec<u8>]) -> StorageResult<RESP> {
+        if command.len() != 5 {
+            return Err(StorageError::WrongArgumentCount(command_name(command)));
+        }
+        let (from, to) = match (ListEnd::parse(&command[3]), ListEnd::parse(&command[4])) {
+            (Some(from), Some(to)) => (from, to),
+            _ => return Err(StorageError::CommandSyntaxError(command_to_string(command))),
+        };
+        let element = self.list_move(&command[1], &command[2], from, to)?;
+        Ok(element.map_or(RESP::Null, RESP::BulkString))
+    }
+
+    pub(super) fn command_rpoplpush(&mut self, command: &[Vec<u8>]) -> StorageResult<RESP> {
+        if command.len() != 3 {
+            return Err(StorageError::WrongArgumentCount(command_name(command)));
+        }
+        let element = self.list_move(&command[1], &command[2], ListEnd::Right, ListEnd::Left)?;
+        Ok(element.map_or(RESP::Null, RESP::BulkString))
+    }
+}
+
+/// Resolves a possibly negative index, counted from the end, against a list of `length`.
+fn resolve_index(index: i64, length: usize) -> Option<usize> {
+    let index = if index < 0 {
+        index + length as i64
+    } else {
+        index
+    };
+    (0..length as i64)
+        .contains(&index)
+        .then_some(index as usize)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::test_helpers::{bulk, bulks, run};
+
+    fn list(storage: &mut Storage, key: &str) -> RESP {
+        run(storage, &["lrange", key, "0", "-1"]).unwrap()
+    }
+
+    #[test]
+    fn test_command_push_and_range() {
+        let mut storage = Storage::new();
+        assert_eq!(
+            run(&mut storage, &["rpush", "list", "b", "c"]),
+            Ok(RESP::Integer(2))
+        );
+        assert_eq!(
+            run(&mut storage, &["lpush", "list", "a", "z"]),
+            Ok(RESP::Integer(4))
+        );
+        assert_eq!(list(&mut storage, "list"), bulks(&["z", "a", "b", "c"]));
+        assert_eq!(
+            run(&mut storage, &["lrange", "list", "-3", "1"]),
+            Ok(bulks(&["a"]))
+        );
+        assert_eq!(
+            run(&mut storage, &["lrange", "list", "5", "10"]),
+            Ok(bulks(&[]))
+        );
+        assert_eq!(run(&mut storage, &["llen", "list"]), Ok(RESP::Integer(4)));
+        assert_eq!(
+            run(&mut storage, &["llen", "missing"]),
+            Ok(RESP::Integer(0))
+        );
+        assert_eq!(
+            run(&mut storage, &["type", "list"]),
+            Ok(RESP::SimpleString(String::from("list")))
+        );
+    }
+
+    #[test]
+    fn test_command_pushx() {
+        let mut storage = Storage::new();
+        assert_eq!(
+            run(&mut storage, &["lpushx", "list", "a"]),
+            Ok(RESP::Integer(0))
+        );
+        assert_eq!(run(&mut storage, &["exists", "list"]), Ok(RESP::Integer(0)));
+        run(&mut storage, &["rpush", "list", "a"]).unwrap();
+        assert_eq!(
+            run(&mut storage, &["rpushx", "list", "b", "c"]),
+            Ok(RESP::Integer(3))
+        );
+    }
+
+    #[test]
+    fn test_command_pop() {
+        let mut storage = Storage::new();
+        run(&mut storage, &["rpush", "list", "a", "b", "c", "d"]).unwrap();
+        assert_eq!(run(&mut storage, &["lpop", "list"]), Ok(bulk("a")));
+        assert_eq!(run(&mut storage, &["rpop", "list"]), Ok(bulk("d")));
+        assert_eq!(
+            run(&mut storage, &["rpop", "list", "0"]),
+            Ok(RESP::Array(vec![]))
+        );
+        assert_eq!(
+            run(&mut storage, &["lpop", "list", "5"]),
+            Ok(bulks(&["b", "c"]))
+        );
+        assert_eq!(run(&mut storage, &["exists", "list"]), Ok(RESP::Integer(0)));
+        assert_eq!(run(&mut storage, &["lpop", "list"]), Ok(RESP::Null));
+        assert_eq!(run(&mut storage, &["lpop", "list", "2"]), Ok(RESP::Null));
+        assert!(run(&mut storage, &["lpop", "list", "-1"]).is_err());
+    }
+
+    #[test]
+    fn test_command_wrong_type() {
+        let mut storage = Storage::new();
+        run(&mut storage, &["set", "string", "value"]).unwrap();
+        run(&mut storage, &["rpush", "list", "a"]).unwrap();
+        assert_eq!(
+            run(&mut storage, &["lpush", "string", "a"]),
+            Err(StorageError::WrongType)
+        );
+        assert_eq!(
+            run(&mut storage, &["lrange", "string", "0", "-1"]),
+            Err(StorageError::WrongType)
+        );
+        assert_eq!(
+            run(&mut storage, &["get", "list"]),
+            Err(StorageError::WrongType)
+        );
+        assert_eq!(
+            run(&mut storage, &["incr", "list"]),
+            Err(StorageError::WrongType)
+        );
+        assert_eq!(
+            run(&mut storage, &["append", "list", "a"]),
+            Err(StorageError::WrongType)
+        );
+        assert_eq!(
+            run(&mut storage, &["lmove", "list", "string", "left", "left"]),
+            Err(StorageError::WrongType)
+        );
+        assert_eq!(list(&mut storage, "list"), bulks(&["a"]));
+        assert_eq!(
+            run(&mut storage, &["mget", "list", "string"]),
+            Ok(RESP::Array(vec![RESP::Null, bulk("value")]))
+        );
+        // SET replaces a value of any type.
+        assert_eq!(
+            run(&mut storage, &["set", "list", "value"]),
+            Ok(RESP::SimpleString(String::from("OK")))
+        );
+    }
+
+    #[test]
+    fn test_command_lindex_and_lset() {
+        let mut storage = Storage::new();
+        run(&mut storage, &["rpush", "list", "a", "b", "c"]).unwrap();
+        assert_eq!(run(&mut storage, &["lindex", "list", "0"]), Ok(bulk("a")));
+        assert_eq!(run(&mut storage, &["lindex", "list", "-1"]), Ok(bulk("c")));
+        assert_eq!(run(&mut storage, &["lindex", "list", "3"]), Ok(RESP::Null));
+        assert_eq!(run(&mut storage, &["lindex", "list", "-4"]), Ok(RESP::Null));
+        assert_eq!(
+            run(&mut storage, &["lset", "list", "-2", "x"]),
+            Ok(RESP::SimpleString(String::from("OK")))
+        );
+        assert_eq!(list(&mut storage, "list"), bulks(&["a", "x", "c"]));
+        assert_eq!(
+            RESP::from(run(&mut storage, &["lset", "list", "3", "x"]).unwrap_err()),
+            RESP::Error(String::from("ERR index out of range"))
+        );
+        assert_eq!(
+            RESP::from(run(&mut storage, &["lset", "missing", "0", "x"]).unwrap_err()),
+            RESP::Error(String::from("ERR no such key"))
+        );
+    }
+
+    #[test]
+    fn test_command_linsert() {
+        let mut storage = Storage::new();
+        assert_eq!(
+            run(&mut storage, &["linsert", "list", "before", "a", "x"]),
+            Ok(RESP::Integer(0))
+        );
+        run(&mut storage, &["rpush", "list", "a", "b"]).unwrap();
+        assert_eq!(
+            run(&mut storage, &["linsert", "list", "BEFORE", "b", "x"]),
+            Ok(RESP::Integer(3))
+        );
+        assert_eq!(
+            run(&mut storage, &["linsert", "list", "AFTER", "b", "y"]),
+            Ok(RESP::Integer(4))
+        );
+        assert_eq!(
+            run(&mut storage, &["linsert", "list", "after", "missing", "y"]),
+            Ok(RESP::Integer(-1))
+        );
+        assert_eq!(list(&mut storage, "list"), bulks(&["a", "x", "b", "y"]));
+        assert!(run(&mut storage, &["linsert", "list", "middle", "a", "y"]).is_err());
+    }
+
+    #[test]
+    fn test_command_lrem() {
+        let mut storage = Storage::new();
+        run(&mut storage, &["rpush", "list", "a", "b", "a", "c", "a"]).unwrap();
+        assert_eq!(
+            run(&mut storage, &["lrem", "list", "-2", "a"]),
+            Ok(RESP::Integer(2))
+        );
+        assert_eq!(list(&mut storage, "list"), bulks(&["a", "b", "c"]));
+        run(&mut storage, &["rpush", "list", "a", "a"]).unwrap();
+        assert_eq!(
+            run(&mut storage, &["lrem", "list", "1", "a"]),
+            Ok(RESP::Integer(1))
+        );
+        assert_eq!(list(&mut storage, "list"), bulks(&["b", "c", "a", "a"]));
+        assert_eq!(
+            run(&mut storage, &["lrem", "list", "0", "a"]),
+            Ok(RESP::Integer(2))
+        );
+        assert_eq!(list(&mut storage, "list"), bulks(&["b", "c"]));
+        run(&mut storage, &["lrem", "list", "0", "b"]).unwrap();
+        run(&mut storage, &["lrem", "list", "0", "c"]).unwrap();
+        assert_eq!(run(&mut storage, &["exists", "list"]), Ok(RESP::Integer(0)));
+    }
+
+    #[test]
+    fn test_command_ltrim() {
+        let mut storage = Storage::new();
+        run(&mut storage, &["rpush", "list", "a", "b", "c", "d", "e"]).unwrap();
+        assert_eq!(
+            run(&mut storage, &["ltrim", "list", "1", "-2"]),
+            Ok(RESP::SimpleString(String::from("OK")))
+        );
+        assert_eq!(list(&mut storage, "list"), bulks(&["b", "c", "d"]));
+        run(&mut storage, &["ltrim", "list", "5", "10"]).unwrap();
+        assert_eq!(run(&mut storage, &["exists", "list"]), Ok(RESP::Integer(0)));
+    }
+
+    #[test]
+    fn test_command_lpos() {
+        let mut storage = Storage::new();
+        run(
+            &mut storage,
+            &["rpush", "list", "a", "b", "c", "1", "2", "3", "c", "c"],
+        )
+        .unwrap();
+        assert_eq!(
+            run(&mut storage, &["lpos", "list", "c"]),
+            Ok(RESP::Integer(2))
+        );
+        assert_eq!(
+            run(&mut storage, &["lpos", "list", "c", "RANK", "2"]),
+            Ok(RESP::Integer(6))
+        );
+        assert_eq!(
+            run(&mut storage, &["lpos", "list", "c", "RANK", "-1"]),
+            Ok(RESP::Integer(7))
+        );
+        assert_eq!(
+            run(&mut storage, &["lpos", "list", "c", "COUNT", "2"]),
+            Ok(RESP::Array(vec![RESP::Integer(2), RESP::Integer(6)]))
+        );
+        assert_eq!(
+            run(
+                &mut storage,
+                &["lpos", "list", "c", "COUNT", "0", "RANK", "-2"]
+            ),
+            Ok(RESP::Array(vec![RESP::Integer(6), RESP::Integer(2)]))
+        );
+        assert_eq!(
+            run(
+                &mut storage,
+                &["lpos", "list", "c", "COUNT", "0", "MAXLEN", "7"]
+            ),
+            Ok(RESP::Array(vec![RESP::Integer(2), RESP::Integer(6)]))
+        );
+        assert_eq!(run(&mut storage, &["lpos", "list", "x"]), Ok(RESP::Null));
+        assert_eq!(
+            run(&mut storage, &["lpos", "missing", "x", "COUNT", "1"]),
+            Ok(RESP::Array(vec![]))
+        );
+        assert!(run(&mut storage, &["lpos", "list", "c", "RANK", "0"]).is_err());
+        assert!(run(&mut storage, &["lpos", "list", "c", "COUNT", "-1"]).is_err());
+        assert!(run(&mut storage, &["lpos", "list", "c", "MAXLEN"]).is_err());
+    }
+
+    #[test]
+    fn test_command_lmove() {
+        let mut storage = Storage::new();
+        run(&mut storage, &["rpush", "source", "a", "b", "c"]).unwrap();
+        assert_eq!(
+            run(
+                &mut storage,
+                &["lmove", "source", "destination", "RIGHT", "LEFT"]
+            ),
+            Ok(bulk("c"))
+        );
+        assert_eq!(
+            run(
+                &mut storage,
+                &["lmove", "source", "destination", "left", "right"]
+            ),
+            Ok(bulk("a"))
+        );
+        assert_eq!(list(&mut storage, "source"), bulks(&["b"]));
+        assert_eq!(list(&mut storage, "destination"), bulks(&["c", "a"]));
+        assert_eq!(
+            run(&mut storage, &["rpoplpush", "source", "destination"]),
+            Ok(bulk("b"))
+        );
+        assert_eq!(
+            run(&mut storage, &["exists", "source"]),
+            Ok(RESP::Integer(0))
+        );
+        assert_eq!(
+            run(&mut storage, &["rpoplpush", "source", "destination"]),
+            Ok(RESP::Null)
+        );
+        assert!(run(&mut storage, &["lmove", "destination", "x", "up", "left"]).is_err());
+    }
+
+    #[test]
+    fn test_command_lmove_rotates_in_place() {
+        let mut storage = Storage::new();
+        run(&mut storage, &["rpush", "list", "a"]).unwrap();
+        run(&mut storage, &["expire", "list", "100"]).unwrap();
+        assert_eq!(
+            run(&mut storage, &["rpoplpush", "list", "list"]),
+            Ok(bulk("a"))
+        );
+        assert_eq!(list(&mut storage, "list"), bulks(&["a"]));
+        assert_eq!(run(&mut storage, &["ttl", "list"]), Ok(RESP::Integer(100)));
+    }
+
+    #[test]
+    fn test_list_encoding() {
+        let mut storage = Storage::new();
+        run(&mut storage, &["rpush", "small", "a"]).unwrap();
+        run(&mut storage, &["rpush", "large", &"x".repeat(65)]).unwrap();
+        assert_eq!(
+            run(&mut storage, &["object", "encoding", "small"]),
+            Ok(bulk("listpack"))
+        );
+        assert_eq!(
+            run(&mut storage, &["object", "encoding", "large"]),
+            Ok(bulk("quicklist"))
+        );
+    }
+}
@@ -0,0 +1,700 @@
+use super::{parse_integer, Storage, StorageData, StorageValue};
+use crate::glob::glob_match;
+use crate::resp::RESP;
+use crate::scan::{parse_cursor, parse_scan_arguments, ScanSet};
+use crate::storage_result::{command_name, command_to_string, StorageError, StorageResult};
+use std::collections::HashSet;
+use std::sync::Arc;
+
+type Set = ScanSet;
+
+/// The ways SINTER, SUNION, SDIFF and their STORE variants combine sets.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SetOperation {
+    Intersection,
+    Union,
+    Difference,
+}
+
+impl Storage {
+    /// Returns the set stored at `key`, or `None` if there is no such key.
+    fn set_at(&mut self, key: &[u8]) -> StorageResult<Option<&mut Set>> {
+        match self.lookup(key) {
+            Some(StorageData {
+                value: StorageValue::Set(set),
+                ..
+            }) => Ok(Some(set)),
+            Some(_) => Err(StorageError::WrongType),
+            None => Ok(None),
+        }
+    }
+
+    /// Returns the set stored at `key`, creating an empty one if there is no such key.
+    /// Callers must not leave a created set empty.
+    fn set_at_or_insert(&mut self, key: &[u8]) -> StorageResult<&mut Set> {
+        if self.set_at(key)?.is_none() {
+            self.store.insert(
+                key.to_vec(),
+                StorageData::from(StorageValue::Set(Set::default())),
+            );
+        }
+        Ok(self.set_at(key)?.expect("the set was just created"))
+    }
+
+    /// Returns the sets stored at `keys`, `None` standing for a missing key, once all of
+    /// them are known to be sets.
+    fn sets_at(&mut self, keys: &[Vec<u8>]) -> StorageResult<Vec<Option<&Set>>> {
+        for key in keys.iter() {
+            self.set_at(key)?;
+        }
+        Ok(keys
+            .iter()
+            .map(|key| match self.store.get(key) {
+                Some(StorageData {
+                    value: StorageValue::Set(set),
+                    ..
+                }) => Some(set),
+                _ => None,
+            })
+            .collect())
+    }
+
+    pub(super) fn command_sadd(&mut self, command: &[Vec<u8>]) -> StorageResult<RESP> {
+        if command.len() < 3 {
+            return Err(StorageError::WrongArgumentCount(command_name(command)));
+        }
+        let set = self.set_at_or_insert(&command[1])?;
+        let added = command[2..]
+            .iter()
+            .filter(|member| set.insert(member.to_vec()))
+            .count();
+        Ok(RESP::Integer(added as i64))
+    }
+
+    pub(super) fn command_srem(&mut self, command: &[Vec<u8>]) -> StorageResult<RESP> {
+        if command.len() < 3 {
+            return Err(StorageError::WrongArgumentCount(command_name(command)));
+        }
+        let key = &command[1];
+        let removed = match self.set_at(key)? {
+            Some(set) => command[2..]
+                .iter()
+                .filter(|member| set.remove(member))
+                .count(),
+            None => 0,
+        };
+        self.remove_if_empty(key);
+        Ok(RESP::Integer(removed as i64))
+    }
+
+    pub(super) fn command_smembers(&mut self, command: &[Vec<u8>]) -> StorageResult<RESP> {
+        if command.len() != 2 {
+            return Err(StorageError::WrongArgumentCount(command_name(command)));
+        }
+        let members = match self.set_at(&command[1])? {
+            Some(set) => set
+                .iter()
+                .map(|member| RESP::BulkString(member.to_vec()))
+                .collect(),
+            None => vec![],
+        };
+        Ok(RESP::Set(members))
+    }
+
+    pub(super) fn command_sismember(&mut self, command: &[Vec<u8>]) -> StorageResult<RESP> {
+        if command.len() != 3 {
+            return Err(StorageError::WrongArgumentCount(command_name(command)));
+        }
+        let member = self
+            .set_at(&command[1])?
+            .is_some_and(|set| set.contains(&command[2]));
+        Ok(RESP::Integer(member as i64))
+    }
+
+    pub(super) fn command_smismember(&mut self, command: &[Vec<u8>]) -> StorageResult<RESP> {
+        if command.len() < 3 {
+            return Err(StorageError::WrongArgumentCount(command_name(command)));
+        }
+        let set = self.set_at(&command[1])?;
+        let replies = command[2..]
+            .iter()
+            .map(|member| {
+                let found = set.as_ref().is_some_and(|set| set.contains(member));
+                RESP::Integer(found as i64)
+            })
+            .collect();
+        Ok(RESP::Array(replies))
+    }
+
+    pub(super) fn command_scard(&mut self, command: &[Vec<u8>]) -> StorageResult<RESP> {
+        if command.len() != 2 {
+            return Err(StorageError::WrongArgumentCount(command_name(command)));
+        }
+        let length = self.set_at(&command[1])?.map_or(0, |set| set.len());
+        Ok(RESP::Integer(length as i64))
+    }
+
+    /// Replies with a single member unless given a count, like LPOP.
+    pub(super) fn command_spop(&mut self, command: &[Vec<u8>]) -> StorageResult<RESP> {
+        if command.len() != 2 && command.len() != 3 {
+            return Err(StorageError::WrongArgumentCount(command_name(command)));
+        }
+        let key = &command[1];
+        let count = match command.get(2) {
+            Some(count) => match parse_integer(count)? {
+                count if count < 0 => {
+                    return Err(StorageError::InvalidArgument(String::from(
+                        "value is out of range, must be positive",
+                    )))
+                }
+                count => Some(count),
+            },
+            None => None,
+        };
+        let set = match self.set_at(key)? {
+            Some(set) => set,
+            None if count.is_some() => return Ok(RESP::Set(vec![])),
+            None => return Ok(RESP::Null),
+        };
+        let popped: Vec<Vec<u8>> = match count {
+            Some(count) => set
+                .random_picks(count)
+                .into_iter()
+                .map(|member| member.to_vec())
+                .collect(),
+            None => set
+                .random()
+                .map(|member| member.to_vec())
+                .into_iter()
+                .collect(),
+        };
+        for member in popped.iter() {
+            set.remove(member);
+        }
+        self.remove_if_empty(key);
+        let mut popped = popped.into_iter().map(RESP::BulkString);
+        match count {
+            Some(_) => Ok(RESP::Set(popped.collect())),
+            None => Ok(popped.next().unwrap_or(RESP::Null)),
+        }
+    }
+
+    pub(super) fn command_srandmember(&mut self, command: &[Vec<u8>]) -> StorageResult<RESP> {
+        if command.len() != 2 && command.len() != 3 {
+            return Err(StorageError::WrongArgumentCount(command_name(command)));
+        }
+        let count = command
+            .get(2)
+            .map(|count| parse_integer(count))
+            .transpose()?;
+        // Negating the count must not overflow.
+        if count == Some(i64::MIN) {
+            return Err(StorageError::InvalidArgument(String::from(
+                "value is out of range",
+            )));
+        }
+        let set = match self.set_at(&command[1])? {
+            Some(set) => set,
+            None if count.is_some() => return Ok(RESP::Array(vec![])),
+            None => return Ok(RESP::Null),
+        };
+        match count {
+            Some(count) => Ok(RESP::Array(
+                set.random_picks(count)
+                    .into_iter()
+                    .map(|member| RESP::BulkString(member.to_vec()))
+                    .collect(),
+            )),
+            None => Ok(set
+                .random()
+                .map_or(RESP::Null, |member| RESP::BulkString(member.to_vec()))),
+        }
+    }
+
+    pub(super) fn command_smove(&mut self, command: &[Vec<u8>]) -> StorageResult<RESP> {
+        if command.len() != 4 {
+            return Err(StorageError::WrongArgumentCount(command_name(command)));
+        }
+        let (source, destination, member) = (&command[1], &command[2], &command[3]);
+        // Check the destination up front so that a wrong type never loses the member.
+        self.set_at(destination)?;
+        let moved = match self.set_at(source)? {
+            Some(set) if source == destination => set.contains(member),
+            Some(set) => set.remove(member),
+            None => false,
+        };
+        if moved && source != destination {
+            self.remove_if_empty(source);
+            self.set_at_or_insert(destination)?.insert(member.clone());
+        }
+        Ok(RESP::Integer(moved as i64))
+    }
+
+    /// Handles SINTER, SUNION and SDIFF.
+    pub(super) fn command_setop(
+        &mut self,
+        command: &[Vec<u8>],
+        operation: SetOperation,
+    ) -> StorageResult<RESP> {
+        if command.len() < 2 {
+            return Err(StorageError::WrongArgumentCount(command_name(command)));
+        }
+        let sets = self.sets_at(&command[1..])?;
+        let members = combine(&sets, operation)
+            .into_iter()
+            .map(|member| RESP::BulkString(member.to_vec()))
+            .collect();
+        Ok(RESP::Set(members))
+    }
+
+    /// Handles SINTERSTORE, SUNIONSTORE and SDIFFSTORE, which replace the destination
+    /// whatever it held, or delete it when the result is empty.
+    pub(super) fn command_setop_store(
+        &mut self,
+        command: &[Vec<u8>],
+        operation: SetOperation,
+    ) -> StorageResult<RESP> {
+        if command.len() < 3 {
+            return Err(StorageError::WrongArgumentCount(command_name(command)));
+        }
+        let destination = &command[1];
+        let sets = self.sets_at(&command[2..])?;
+        let result: Set = combine(&sets, operation)
+            .into_iter()
+            .map(|member| member.to_vec())
+            .collect();
+        let length = result.len();
+        self.remove(destination);
+        if !result.is_empty() {
+            self.store.insert(
+                destination.clone(),
+                StorageData::from(StorageValue::Set(result)),
+            );
+        }
+        Ok(RESP::Integer(length as i64))
+    }
+
+    pub(super) fn command_sintercard(&mut self, command: &[Vec<u8>]) -> StorageResult<RESP> {
+        if command.len() < 3 {
+            return Err(StorageError::WrongArgumentCount(command_name(command)));
+        }
+        let numkeys = parse_integer(&command[1])?;
+        if numkeys <= 0 {
+            return Err(StorageError::InvalidArgument(String::from(
+                "numkeys should be greater than 0",
+            )));
+        }
+        let numkeys = numkeys as usize;
+        if numkeys > command.len() - 2 {
+            return Err(StorageError::InvalidArgument(String::from(
+                "Number of keys can't be greater than number of args",
+            )));
+        }
+        let limit = match &command[2 + numkeys..] {
+            [] => 0,
+            [option, limit] if option.eq_ignore_ascii_case(b"limit") => {
+                match parse_integer(limit)? {
+                    limit if limit < 0 => {
+                        return Err(StorageError::InvalidArgument(String::from(
+                            "LIMIT can't be negative",
+                        )))
+                    }
+                    limit => limit as usize,
+                }
+            }
+            _ => return Err(StorageError::CommandSyntaxError(command_to_string(command))),
+        };
+
+        let sets = self.sets_at(&command[2..2 + numkeys])?;
+        let sets = match sets.into_iter().collect::<Option<Vec<&Set>>>() {
+            Some(sets) => sets,
+            None => return Ok(RESP::Integer(0)),
+        };
+        let (smallest, others) = smallest_first(sets);
+        // A limit of 0 counts the whole intersection.
+        let limit = if limit == 0 { usize::MAX } else { limit };
+        let count = smallest
+            .iter()
+            .filter(|member| others.iter().all(|set| set.contains(member)))
+            .take(limit)
+            .count();
+        Ok(RESP::Integer(count as i64))
+    }
+
+    pub(super) fn command_sscan(&mut self, command: &[Vec<u8>]) -> StorageResult<RESP> {
+        if command.len() < 3 {
+            return Err(StorageError::WrongArgumentCount(command_name(command)));
+        }
+        let cursor = parse_cursor(&command[2])?;
+        let args = parse_scan_arguments(&command[3..], false)?;
+        let set = match self.set_at(&command[1])? {
+            Some(set) => set,
+            None => {
+                return Ok(RESP::Array(vec![
+                    RESP::BulkString(b"0".to_vec()),
+                    RESP::Array(vec![]),
+                ]))
+            }
+        };
+        let (next, batch) = set.scan(cursor, args.count);
+        let members = batch
+            .into_iter()
+            .filter(|member| {
+                args.pattern
+                    .as_ref()
+                    .is_none_or(|pattern| glob_match(pattern, member, false))
+            })
+            .map(|member| RESP::BulkString(member.to_vec()))
+            .collect();
+        Ok(RESP::Array(vec![
+            RESP::BulkString(next.to_string().into_bytes()),
+            RESP::Array(members),
+        ]))
+    }
+}
+
+/// Splits off the smallest of `sets`, the cheapest one to walk when intersecting.
+fn smallest_first(mut sets: Vec<&Set>) -> (&Set, Vec<&Set>) {
+    let smallest = (0..sets.len())
+        .min_by_key(|&i| sets[i].len())
+        .expect("at least one set");
+    let first = sets.swap_remove(smallest);
+    (first, sets)
+}
+
+/// Combines `sets`, where `None` stands for a missing key and so an empty set.
+fn combine<'a>(sets: &[Option<&'a Set>], operation: SetOperation) -> Vec<&'a Arc<[u8]>> {
+    match operation {
+        SetOperation::Intersection => {
+            let sets = match sets.iter().copied().collect::<Option<Vec<&Set>>>() {
+                Some(sets) => sets,
+                None => return vec![],
+            };
+            let (smallest, others) = smallest_first(sets);
+            smallest
+                .iter()
+                .filter(|member| others.iter().all(|set| set.contains(member)))
+                .collect()
+        }
+        SetOperation::Union => {
+            let mut seen: HashSet<&Arc<[u8]>> = HashSet::new();
+            for set in sets.iter().flatten() {
+                seen.extend(set.iter());
+            }
+            seen.into_iter().collect()
+        }
+        SetOperation::Difference => {
+            let first = match sets[0] {
+                Some(first) => first,
+                None => return vec![],
+            };
+            first
+                .iter()
+                .filter(|member| sets[1..].iter().flatten().all(|set| !set.contains(member)))
+                .collect()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::test_helpers::{bulk, run};
+
+    /// The strings in an array or set reply, sorted since sets have no order.
+    fn sorted(reply: StorageResult<RESP>) -> Vec<String> {
+        let items = match reply {
+            Ok(RESP::Array(items)) | Ok(RESP::Set(items)) => items,
+            other => panic!("unexpected reply {:?}", other),
+        };
+        let mut members: Vec<String> = items
+            .into_iter()
+            .map(|item| match item {
+                RESP::BulkString(v) => String::from_utf8(v).unwrap(),
+                other => panic!("unexpected item {:?}", other),
+            })
+            .collect();
+        members.sort();
+        members
+    }
+
+    fn strings(values: &[&str]) -> Vec<String> {
+        values.iter().map(|value| value.to_string()).collect()
+    }
+
+    #[test]
+    fn test_command_sadd_and_srem() {
+        let mut storage = Storage::new();
+        assert_eq!(
+            run(&mut storage, &["sadd", "s", "a", "b", "a"]),
+            Ok(RESP::Integer(2))
+        );
+        assert_eq!(
+            run(&mut storage, &["sadd", "s", "b", "c"]),
+            Ok(RESP::Integer(1))
+        );
+        assert_eq!(
+            sorted(run(&mut storage, &["smembers", "s"])),
+            strings(&["a", "b", "c"])
+        );
+        assert_eq!(run(&mut storage, &["scard", "s"]), Ok(RESP::Integer(3)));
+        assert_eq!(
+            run(&mut storage, &["type", "s"]),
+            Ok(RESP::SimpleString(String::from("set")))
+        );
+        assert_eq!(
+            run(&mut storage, &["srem", "s", "a", "z"]),
+            Ok(RESP::Integer(1))
+        );
+        run(&mut storage, &["srem", "s", "b", "c"]).unwrap();
+        assert_eq!(run(&mut storage, &["exists", "s"]), Ok(RESP::Integer(0)));
+        assert_eq!(run(&mut storage, &["smembers", "s"]), Ok(RESP::Set(vec![])));
+    }
+
+    #[test]
+    fn test_command_sismember_and_smismember() {
+        let mut storage = Storage::new();
+        run(&mut storage, &["sadd", "s", "a", "b"]).unwrap();
+        assert_eq!(
+            run(&mut storage, &["sismember", "s", "a"]),
+            Ok(RESP::Integer(1))
+        );
+        assert_eq!(
+            run(&mut storage, &["sismember", "s", "z"]),
+            Ok(RESP::Integer(0))
+        );
+        assert_eq!(
+            run(&mut storage, &["smismember", "s", "z", "b"]),
+            Ok(RESP::Array(vec![RESP::Integer(0), RESP::Integer(1)]))
+        );
+        assert_eq!(
+            run(&mut storage, &["smismember", "none", "a"]),
+            Ok(RESP::Array(vec![RESP::Integer(0)]))
+        );
+    }
+
+    #[test]
+    fn test_command_spop() {
+        let mut storage = Storage::new();
+        assert_eq!(run(&mut storage, &["spop", "s"]), Ok(RESP::Null));
+        assert_eq!(
+            run(&mut storage, &["spop", "s", "2"]),
+            Ok(RESP::Set(vec![]))
+        );
+        run(&mut storage, &["sadd", "s", "a", "b", "c"]).unwrap();
+
+        let popped = match run(&mut storage, &["spop", "s"]) {
+            Ok(RESP::BulkString(member)) => String::from_utf8(member).unwrap(),
+            other => panic!("unexpected reply {:?}", other),
+        };
+        let mut rest = sorted(run(&mut storage, &["spop", "s", "5"]));
+        assert_eq!(rest.len(), 2);
+        rest.push(popped);
+        rest.sort();
+        assert_eq!(rest, strings(&["a", "b", "c"]));
+        assert_eq!(run(&mut storage, &["exists", "s"]), Ok(RESP::Integer(0)));
+        assert!(run(&mut storage, &["spop", "s", "-1"]).is_err());
+    }
+
+    #[test]
+    fn test_command_srandmember() {
+        let mut storage = Storage::new();
+        assert_eq!(run(&mut storage, &["srandmember", "s"]), Ok(RESP::Null));
+        assert_eq!(
+            run(&mut storage, &["srandmember", "s", "-3"]),
+            Ok(RESP::Array(vec![]))
+        );
+        run(&mut storage, &["sadd", "s", "a", "b", "c"]).unwrap();
+        assert_eq!(
+            sorted(run(&mut storage, &["srandmember", "s", "10"])),
+            strings(&["a", "b", "c"])
+        );
+        assert_eq!(
+            sorted(run(&mut storage, &["srandmember", "s", "2"])).len(),
+            2
+        );
+        let repeated = sorted(run(&mut storage, &["srandmember", "s", "-7"]));
+        assert_eq!(repeated.len(), 7);
+        assert!(repeated
+            .iter()
+            .all(|member| ["a", "b", "c"].contains(&member.as_str())));
+        assert_eq!(run(&mut storage, &["scard", "s"]), Ok(RESP::Integer(3)));
+        assert!(run(&mut storage, &["srandmember", "s", "-9223372036854775808"]).is_err());
+    }
+
+    #[test]
+    fn test_command_smove() {
+        let mut storage = Storage::new();
+        run(&mut storage, &["sadd", "src", "a", "b"]).unwrap();
+        assert_eq!(
+            run(&mut storage, &["smove", "src", "dst", "a"]),
+            Ok(RESP::Integer(1))
+        );
+        assert_eq!(
+            run(&mut storage, &["smove", "src", "dst", "z"]),
+            Ok(RESP::Integer(0))
+        );
+        assert_eq!(
+            run(&mut storage, &["smove", "src", "src", "b"]),
+            Ok(RESP::Integer(1))
+        );
+        run(&mut storage, &["set", "str", "x"]).unwrap();
+        assert_eq!(
+            run(&mut storage, &["smove", "src", "str", "b"]),
+            Err(StorageError::WrongType)
+        );
+        assert_eq!(
+            run(&mut storage, &["smove", "src", "dst", "b"]),
+            Ok(RESP::Integer(1))
+        );
+        assert_eq!(run(&mut storage, &["exists", "src"]), Ok(RESP::Integer(0)));
+        assert_eq!(
+            sorted(run(&mut storage, &["smembers", "dst"])),
+            strings(&["a", "b"])
+        );
+    }
+
+    #[test]
+    fn test_set_algebra() {
+        let mut storage = Storage::new();
+        run(&mut storage, &["sadd", "a", "1", "2", "3", "4"]).unwrap();
+        run(&mut storage, &["sadd", "b", "3", "4", "5"]).unwrap();
+        run(&mut storage, &["sadd", "c", "4", "6"]).unwrap();
+        assert_eq!(
+            sorted(run(&mut storage, &["sinter", "a", "b", "c"])),
+            strings(&["4"])
+        );
+        assert_eq!(
+            sorted(run(&mut storage, &["sinter", "a", "none"])),
+            strings(&[])
+        );
+        assert_eq!(
+            sorted(run(&mut storage, &["sunion", "a", "none", "c"])),
+            strings(&["1", "2", "3", "4", "6"])
+        );
+        assert_eq!(
+            sorted(run(&mut storage, &["sdiff", "a", "b", "none"])),
+            strings(&["1", "2"])
+        );
+        assert_eq!(
+            sorted(run(&mut storage, &["sdiff", "none", "a"])),
+            strings(&[])
+        );
+
+        run(&mut storage, &["set", "str", "x"]).unwrap();
+        assert_eq!(
+            run(&mut storage, &["sunion", "a", "str"]),
+            Err(StorageError::WrongType)
+        );
+    }
+
+    #[test]
+    fn test_set_algebra_store() {
+        let mut storage = Storage::new();
+        run(&mut storage, &["sadd", "a", "1", "2", "3"]).unwrap();
+        run(&mut storage, &["sadd", "b", "2", "3", "4"]).unwrap();
+        run(&mut storage, &["set", "dst", "x", "ex", "100"]).unwrap();
+        assert_eq!(
+            run(&mut storage, &["sinterstore", "dst", "a", "b"]),
+            Ok(RESP::Integer(2))
+        );
+        assert_eq!(
+            sorted(run(&mut storage, &["smembers", "dst"])),
+            strings(&["2", "3"])
+        );
+        assert_eq!(run(&mut storage, &["ttl", "dst"]), Ok(RESP::Integer(-1)));
+        assert_eq!(
+            run(&mut storage, &["sunionstore", "a", "a", "b"]),
+            Ok(RESP::Integer(4))
+        );
+        assert_eq!(
+            run(&mut storage, &["sdiffstore", "dst", "b", "a"]),
+            Ok(RESP::Integer(0))
+        );
+        assert_eq!(run(&mut storage, &["exists", "dst"]), Ok(RESP::Integer(0)));
+    }
+
+    #[test]
+    fn test_command_sintercard() {
+        let mut storage = Storage::new();
+        run(&mut storage, &["sadd", "a", "1", "2", "3", "4"]).unwrap();
+        run(&mut storage, &["sadd", "b", "2", "3", "4", "5"]).unwrap();
+        assert_eq!(
+            run(&mut storage, &["sintercard", "2", "a", "b"]),
+            Ok(RESP::Integer(3))
+        );
+        assert_eq!(
+            run(&mut storage, &["sintercard", "2", "a", "b", "LIMIT", "2"]),
+            Ok(RESP::Integer(2))
+        );
+        assert_eq!(
+            run(&mut storage, &["sintercard", "2", "a", "none"]),
+            Ok(RESP::Integer(0))
+        );
+        assert!(run(&mut storage, &["sintercard", "0", "a"]).is_err());
+        assert!(run(&mut storage, &["sintercard", "3", "a", "b"]).is_err());
+        assert!(run(&mut storage, &["sintercard", "1", "a", "limit", "-1"]).is_err());
+        assert!(run(&mut storage, &["sintercard", "1", "a", "b"]).is_err());
+    }
+
+    #[test]
+    fn test_command_sscan() {
+        let mut storage = Storage::new();
+        for i in 0..25 {
+            run(&mut storage, &["sadd", "s", &format!("member:{}", i)]).unwrap();
+        }
+        run(&mut storage, &["sadd", "s", "other"]).unwrap();
+        let mut seen = Vec::new();
+        let mut cursor = String::from("0");
+        loop {
+            let reply = run(
+                &mut storage,
+                &["sscan", "s", &cursor, "MATCH", "member:*", "COUNT", "4"],
+            );
+            let (next, members) = match reply {
+                Ok(RESP::Array(mut reply)) => {
+                    let members = reply.pop().unwrap();
+                    (reply.pop().unwrap(), Ok(members))
+                }
+                other => panic!("unexpected reply {:?}", other),
+            };
+            seen.extend(sorted(members));
+            cursor = match next {
+                RESP::BulkString(next) => String::from_utf8(next).unwrap(),
+                other => panic!("unexpected cursor {:?}", other),
+            };
+            if cursor == "0" {
+                break;
+            }
+        }
+        seen.sort();
+        seen.dedup();
+        assert_eq!(seen.len(), 25);
+        assert!(!seen.contains(&String::from("other")));
+    }
+
+    #[test]
+    fn test_set_encoding() {
+        let mut storage = Storage::new();
+        run(&mut storage, &["sadd", "s", "1", "2", "-3"]).unwrap();
+        assert_eq!(
+            run(&mut storage, &["object", "encoding", "s"]),
+            Ok(bulk("intset"))
+        );
+        run(&mut storage, &["sadd", "s", "a"]).unwrap();
+        assert_eq!(
+            run(&mut storage, &["object", "encoding", "s"]),
+            Ok(bulk("listpack"))
+        );
+        run(&mut storage, &["sadd", "s", &"x".repeat(65)]).unwrap();
+        assert_eq!(
+            run(&mut storage, &["object", "encoding", "s"]),
+            Ok(bulk("hashtable"))
+        );
+        run(&mut storage, &["set", "str", "x"]).unwrap();
+        assert_eq!(
+            run(&mut storage, &["sadd", "str", "a"]),
+            Err(StorageError::WrongType)
+        );
+    }
+}
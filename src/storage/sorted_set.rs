@@ -0,0 +1,1300 @@
+use super::set::SetOperation;
+use super::{parse_float, parse_integer, resolve_range, Storage, StorageData, StorageValue};
+use crate::glob::glob_match;
+use crate::resp::{FormattedDouble, RESP};
+use crate::scan::{parse_cursor, parse_scan_arguments, ScanMap};
+use crate::skiplist::SkipList;
+use crate::storage_result::{command_name, command_to_string, StorageError, StorageResult};
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+/// A sorted set: the score of every member, for lookups, plus the same entries ordered
+/// by score, for ranks and ranges.
+#[derive(Debug, Clone, Default)]
+pub struct SortedSet {
+    scores: ScanMap<f64>,
+    index: SkipList,
+}
+
+impl PartialEq for SortedSet {
+    fn eq(&self, other: &Self) -> bool {
+        // The index holds the same entries, whatever the shape of its skiplist.
+        self.scores == other.scores
+    }
+}
+
+impl SortedSet {
+    pub fn len(&self) -> usize {
+        self.scores.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.scores.is_empty()
+    }
+
+    pub fn members(&self) -> impl ExactSizeIterator<Item = &Arc<[u8]>> {
+        self.scores.keys()
+    }
+
+    fn score(&self, member: &[u8]) -> Option<f64> {
+        self.scores.get(member).copied()
+    }
+
+    /// Sets the score of `member`, returning whether it was added. The scores and the
+    /// index share one copy of the member.
+    fn insert(&mut self, member: Vec<u8>, score: f64) -> bool {
+        if let Some((shared, &previous)) = self.scores.get_key_value(member.as_slice()) {
+            if previous != score {
+                let shared = shared.clone();
+                self.index.remove(previous, &shared);
+                self.index.insert(score, shared.clone());
+                self.scores.insert(shared, score);
+            }
+            return false;
+        }
+        let member: Arc<[u8]> = member.into();
+        self.scores.insert(member.clone(), score);
+        self.index.insert(score, member);
+        true
+    }
+
+    fn remove(&mut self, member: &[u8]) -> bool {
+        match self.scores.remove(member) {
+            Some(score) => self.index.remove(score, member),
+            None => false,
+        }
+    }
+
+    /// The 0-based position of `member` in score order.
+    fn rank(&self, member: &[u8]) -> Option<usize> {
+        self.index.rank(self.score(member)?, member)
+    }
+
+    /// Removes the member with the lowest score, or the highest one if `max` is set.
+    fn pop(&mut self, max: bool) -> Option<(Vec<u8>, f64)> {
+        let rank = if max {
+            self.index.len().checked_sub(1)?
+        } else {
+            0
+        };
+        let (score, member) = self.index.get(rank)?;
+        let member = member.to_vec();
+        self.remove(&member);
+        Some((member, score))
+    }
+
+    /// The entries of `range`, in the order it asks for.
+    fn select(&self, range: &Range) -> Vec<(&[u8], f64)> {
+        let (start, end) = self.window(&range.by, range.reverse);
+        let (offset, count) = range.limit.unwrap_or((0, -1));
+        let length = end.saturating_sub(start);
+        if offset < 0 || offset as usize >= length {
+            return vec![];
+        }
+        let offset = offset as usize;
+        let count = match count {
+            count if count < 0 => length - offset,
+            count => (count as usize).min(length - offset),
+        };
+        let first = match range.reverse {
+            true => end - 1 - offset,
+            false => start + offset,
+        };
+        self.index
+            .iter_from(first, range.reverse)
+            .take(count)
+            .map(|(score, member)| (member, score))
+            .collect()
+    }
+
+    /// The ranks matched by `by`, as the ascending half-open window `start..end`.
+    fn window(&self, by: &RangeBy, reverse: bool) -> (usize, usize) {
+        let length = self.len();
+        match by {
+            RangeBy::Rank(start, stop) => match resolve_range(*start, *stop, length) {
+                // Indexes of a reversed range count from the highest score.
+                Some((start, stop)) if reverse => (length - 1 - stop, length - start),
+                Some((start, stop)) => (start, stop + 1),
+                None => (0, 0),
+            },
+            RangeBy::Score(min, max) => (
+                self.index.partition_point(|score, _| min.below_min(score)),
+                self.index.partition_point(|score, _| max.within_max(score)),
+            ),
+            RangeBy::Lex(min, max) => (
+                self.index
+                    .partition_point(|_, member| min.below_min(member)),
+                self.index
+                    .partition_point(|_, member| max.within_max(member)),
+            ),
+        }
+    }
+}
+
+/// An end of a BYSCORE range, such as `1.5`, `(1.5` or `-inf`.
+#[derive(Debug, Clone, Copy)]
+struct ScoreBound {
+    value: f64,
+    exclusive: bool,
+}
+
+impl ScoreBound {
+    fn parse(argument: &[u8]) -> StorageResult<ScoreBound> {
+        let (exclusive, value) = match argument.strip_prefix(b"(") {
+            Some(value) => (true, value),
+            None => (false, argument),
+        };
+        let value = parse_float(value).map_err(|_| {
+            StorageError::InvalidArgument(String::from("min or max is not a float"))
+        })?;
+        Ok(ScoreBound { value, exclusive })
+    }
+
+    fn below_min(&self, score: f64) -> bool {
+        score < self.value || (self.exclusive && score == self.value)
+    }
+
+    fn within_max(&self, score: f64) -> bool {
+        score < self.value || (!self.exclusive && score == self.value)
+    }
+}
+
+/// An end of a BYLEX range: `-`, `+`, `[member` or `(member`.
+#[derive(Debug, Clone)]
+enum LexBound {
+    Lowest,
+    Highest,
+    Inclusive(Vec<u8>),
+    Exclusive(Vec<u8>),
+}
+
+impl LexBound {
+    fn parse(argument: &[u8]) -> StorageResult<LexBound> {
+        match argument {
+            b"-" => Ok(LexBound::Lowest),
+            b"+" => Ok(LexBound::Highest),
+            [b'[', member @ ..] => Ok(LexBound::Inclusive(member.to_vec())),
+            [b'(', member @ ..] => Ok(LexBound::Exclusive(member.to_vec())),
+            _ => Err(StorageError::InvalidArgument(String::from(
+                "min or max not valid string range item",
+            ))),
+        }
+    }
+
+    fn below_min(&self, member: &[u8]) -> bool {
+        match self {
+            LexBound::Lowest => false,
+            LexBound::Highest => true,
+            LexBound::Inclusive(bound) => member < bound.as_slice(),
+            LexBound::Exclusive(bound) => member <= bound.as_slice(),
+        }
+    }
+
+    fn within_max(&self, member: &[u8]) -> bool {
+        match self {
+            LexBound::Lowest => false,
+            LexBound::Highest => true,
+            LexBound::Inclusive(bound) => member <= bound.as_slice(),
+            LexBound::Exclusive(bound) => member < bound.as_slice(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum RangeBy {
+    Rank(i64, i64),
+    Score(ScoreBound, ScoreBound),
+    Lex(LexBound, LexBound),
+}
+
+/// The arguments shared by ZRANGE and ZRANGESTORE.
+#[derive(Debug, Clone)]
+struct Range {
+    by: RangeBy,
+    reverse: bool,
+    /// Offset and count; a negative count takes everything past the offset.
+    limit: Option<(i64, i64)>,
+    with_scores: bool,
+}
+
+/// How ZUNIONSTORE and ZINTERSTORE combine the scores of a member.
+#[derive(Debug, Clone, Copy)]
+enum Aggregate {
+    Sum,
+    Min,
+    Max,
+}
+
+impl Aggregate {
+    fn apply(self, a: f64, b: f64) -> f64 {
+        match self {
+            // inf + -inf counts as 0, as in Redis.
+            Aggregate::Sum => zero_if_nan(a + b),
+            Aggregate::Min => a.min(b),
+            Aggregate::Max => a.max(b),
+        }
+    }
+}
+
+/// An input of ZUNIONSTORE or ZINTERSTORE, where plain sets count as scoring 1.
+enum Scored<'a> {
+    Set(&'a HashSet<Arc<[u8]>>),
+    Sorted(&'a SortedSet),
+}
+
+impl Scored<'_> {
+    fn len(&self) -> usize {
+        match self {
+            Scored::Set(set) => set.len(),
+            Scored::Sorted(zset) => zset.len(),
+        }
+    }
+
+    fn score(&self, member: &[u8]) -> Option<f64> {
+        match self {
+            Scored::Set(set) => set.contains(member).then_some(1.0),
+            Scored::Sorted(zset) => zset.score(member),
+        }
+    }
+
+    fn entries(&self) -> Vec<(&Arc<[u8]>, f64)> {
+        match self {
+            Scored::Set(set) => set.iter().map(|member| (member, 1.0)).collect(),
+            Scored::Sorted(zset) => zset.scores.iter().map(|(m, s)| (m, *s)).collect(),
+        }
+    }
+}
+
+impl Storage {
+    /// Returns the sorted set stored at `key`, or `None` if there is no such key.
+    fn sorted_set_at(&mut self, key: &[u8]) -> StorageResult<Option<&mut SortedSet>> {
+        match self.lookup(key) {
+            Some(StorageData {
+                value: StorageValue::SortedSet(zset),
+                ..
+            }) => Ok(Some(zset)),
+            Some(_) => Err(StorageError::WrongType),
+            None => Ok(None),
+        }
+    }
+
+    /// Returns the sorted set stored at `key`, creating an empty one if there is no such
+    /// key. Callers must not leave a created sorted set empty.
+    fn sorted_set_at_or_insert(&mut self, key: &[u8]) -> StorageResult<&mut SortedSet> {
+        if self.sorted_set_at(key)?.is_none() {
+            self.store.insert(
+                key.to_vec(),
+                StorageData::from(StorageValue::SortedSet(SortedSet::default())),
+            );
+        }
+        Ok(self
+            .sorted_set_at(key)?
+            .expect("the sorted set was just created"))
+    }
+
+    /// Returns the sets or sorted sets stored at `keys`, `None` standing for a missing
+    /// key, once all of them are known to be one or the other.
+    fn scored_at(&mut self, keys: &[Vec<u8>]) -> StorageResult<Vec<Option<Scored<'_>>>> {
+        for key in keys.iter() {
+            match self.lookup(key) {
+                Some(StorageData {
+                    value: StorageValue::Set(_) | StorageValue::SortedSet(_),
+                    ..
+                })
+                | None => {}
+                Some(_) => return Err(StorageError::WrongType),
+            }
+        }
+        Ok(keys
+            .iter()
+            .map(|key| match self.store.get(key) {
+                Some(StorageData {
+                    value: StorageValue::Set(set),
+                    ..
+                }) => Some(Scored::Set(set)),
+                Some(StorageData {
+                    value: StorageValue::SortedSet(zset),
+                    ..
+                }) => Some(Scored::Sorted(zset)),
+                _ => None,
+            })
+            .collect())
+    }
+
+    /// Replaces whatever `destination` held with `zset`, or deletes it if `zset` is
+    /// empty. Returns the number of members stored.
+    fn store_sorted_set(&mut self, destination: &[u8], zset: SortedSet) -> usize {
+        let length = zset.len();
+        self.remove(destination);
+        if !zset.is_empty() {
+            self.store.insert(
+                destination.to_vec(),
+                StorageData::from(StorageValue::SortedSet(zset)),
+            );
+            self.signal_key_ready(destination);
+        }
+        length
+    }
+
+    /// Pops up to `count` members from the low end of the sorted set at `key`, or from the
+    /// high end if `max` is set.
+    pub(super) fn sorted_set_pop(
+        &mut self,
+        key: &[u8],
+        max: bool,
+        count: usize,
+    ) -> StorageResult<Vec<(Vec<u8>, f64)>> {
+        let popped = match self.sorted_set_at(key)? {
+            Some(zset) => (0..count).map_while(|_| zset.pop(max)).collect(),
+            None => vec![],
+        };
+        self.remove_if_empty(key);
+        Ok(popped)
+    }
+
+    /// Puts back members popped by `sorted_set_pop`, except those added again since.
+    pub(super) fn sorted_set_restore(
+        &mut self,
+        key: &[u8],
+        entries: Vec<(Vec<u8>, f64)>,
+    ) -> StorageResult<()> {
+        if entries.is_empty() {
+            return Ok(());
+        }
+        let zset = self.sorted_set_at_or_insert(key)?;
+        for (member, score) in entries {
+            if zset.score(&member).is_none() {
+                zset.insert(member, score);
+            }
+        }
+        self.signal_key_ready(key);
+        Ok(())
+    }
+
+    pub(super) fn command_zadd(&mut self, command: &[Vec<u8>]) -> StorageResult<RESP> {
+        let (mut nx, mut xx, mut gt, mut lt, mut ch, mut incr) =
+            (false, false, false, false, false, false);
+        let mut idx = 2;
+        while let Some(option) = command.get(idx) {
+            match option.to_ascii_lowercase().as_slice() {
+                b"nx" => nx = true,
+                b"xx" => xx = true,
+                b"gt" => gt = true,
+                b"lt" => lt = true,
+                b"ch" => ch = true,
+                b"incr" => incr = true,
+                _ => break,
+            }
+            idx += 1;
+        }
+        let pairs = &command[idx.min(command.len())..];
+        if command.len() < 4 {
+            return Err(StorageError::WrongArgumentCount(command_name(command)));
+        }
+        if pairs.is_empty() || !pairs.len().is_multiple_of(2) {
+            return Err(StorageError::CommandSyntaxError(command_to_string(command)));
+        }
+        if nx && xx {
+            return Err(StorageError::InvalidArgument(String::from(
+                "XX and NX options at the same time are not compatible",
+            )));
+        }
+        if (gt && lt) || (nx && (gt || lt)) {
+            return Err(StorageError::InvalidArgument(String::from(
+                "GT, LT, and/or NX options at the same time are not compatible",
+            )));
+        }
+        if incr && pairs.len() != 2 {
+            return Err(StorageError::InvalidArgument(String::from(
+                "INCR option supports a single increment-element pair",
+            )));
+        }
+        // Check every score before touching the set.
+        let entries = pairs
+            .chunks(2)
+            .map(|pair| Ok((parse_float(&pair[0])?, &pair[1])))
+            .collect::<StorageResult<Vec<(f64, &Vec<u8>)>>>()?;
+
+        let key = &command[1];
+        if xx && self.sorted_set_at(key)?.is_none() {
+            return Ok(if incr { RESP::Null } else { RESP::Integer(0) });
+        }
+        let zset = self.sorted_set_at_or_insert(key)?;
+        let (mut added, mut updated) = (0, 0);
+        let mut result = None;
+        for (score, member) in entries {
+            let current = zset.score(member);
+            if (nx && current.is_some()) || (xx && current.is_none()) {
+                continue;
+            }
+            let score = match (incr, current) {
+                (true, Some(current)) => current + score,
+                _ => score,
+            };
+            if score.is_nan() {
+                self.remove_if_empty(key);
+                return Err(StorageError::InvalidArgument(String::from(
+                    "resulting score is not a number (NaN)",
+                )));
+            }
+            if let Some(current) = current {
+                if (gt && score <= current) || (lt && score >= current) {
+                    continue;
+                }
+                if score != current {
+                    updated += 1;
+                }
+            } else {
+                added += 1;
+            }
+            zset.insert(member.clone(), score);
+            result = Some(score);
+        }
+        self.remove_if_empty(key);
+        if added > 0 {
+            self.signal_key_ready(key);
+        }
+        if incr {
+            return Ok(result.map_or(RESP::Null, RESP::Double));
+        }
+        Ok(RESP::Integer(if ch { added + updated } else { added }))
+    }
+
+    pub(super) fn command_zincrby(&mut self, command: &[Vec<u8>]) -> StorageResult<RESP> {
+        if command.len() != 4 {
+            return Err(StorageError::WrongArgumentCount(command_name(command)));
+        }
+        let increment = parse_float(&command[2])?;
+        let zset = self.sorted_set_at_or_insert(&command[1])?;
+        let score = zset.score(&command[3]).unwrap_or(0.0) + increment;
+        if score.is_nan() {
+            self.remove_if_empty(&command[1]);
+            return Err(StorageError::InvalidArgument(String::from(
+                "resulting score is not a number (NaN)",
+            )));
+        }
+        zset.insert(command[3].clone(), score);
+        self.signal_key_ready(&command[1]);
+        Ok(RESP::Double(score))
+    }
+
+    pub(super) fn command_zrem(&mut self, command: &[Vec<u8>]) -> StorageResult<RESP> {
+        if command.len() < 3 {
+            return Err(StorageError::WrongArgumentCount(command_name(command)));
+        }
+        let key = &command[1];
+        let removed = match self.sorted_set_at(key)? {
+            Some(zset) => command[2..]
+                .iter()
+                .filter(|member| zset.remove(member))
+                .count(),
+            None => 0,
+        };
+        self.remove_if_empty(key);
+        Ok(RESP::Integer(removed as i64))
+    }
+
+    pub(super) fn command_zscore(&mut self, command: &[Vec<u8>]) -> StorageResult<RESP> {
+        if command.len() != 3 {
+            return Err(StorageError::WrongArgumentCount(command_name(command)));
+        }
+        let score = self
+            .sorted_set_at(&command[1])?
+            .and_then(|zset| zset.score(&command[2]));
+        Ok(score.map_or(RESP::Null, RESP::Double))
+    }
+
+    pub(super) fn command_zcard(&mut self, command: &[Vec<u8>]) -> StorageResult<RESP> {
+        if command.len() != 2 {
+            return Err(StorageError::WrongArgumentCount(command_name(command)));
+        }
+        let length = self
+            .sorted_set_at(&command[1])?
+            .map_or(0, |zset| zset.len());
+        Ok(RESP::Integer(length as i64))
+    }
+
+    pub(super) fn command_zcount(&mut self, command: &[Vec<u8>]) -> StorageResult<RESP> {
+        if command.len() != 4 {
+            return Err(StorageError::WrongArgumentCount(command_name(command)));
+        }
+        let by = RangeBy::Score(
+            ScoreBound::parse(&command[2])?,
+            ScoreBound::parse(&command[3])?,
+        );
+        let count = match self.sorted_set_at(&command[1])? {
+            Some(zset) => {
+                let (start, end) = zset.window(&by, false);
+                end.saturating_sub(start)
+            }
+            None => 0,
+        };
+        Ok(RESP::Integer(count as i64))
+    }
+
+    /// Handles ZRANK and, with `reverse`, ZREVRANK, which counts from the highest score.
+    pub(super) fn command_zrank(
+        &mut self,
+        command: &[Vec<u8>],
+        reverse: bool,
+    ) -> StorageResult<RESP> {
+        let with_score = match command.len() {
+            3 => false,
+            4 if command[3].eq_ignore_ascii_case(b"withscore") => true,
+            _ => return Err(StorageError::CommandSyntaxError(command_to_string(command))),
+        };
+        let zset = match self.sorted_set_at(&command[1])? {
+            Some(zset) => zset,
+            None => return Ok(RESP::Null),
+        };
+        let (rank, score) = match (zset.rank(&command[2]), zset.score(&command[2])) {
+            (Some(rank), Some(score)) => (rank, score),
+            _ => return Ok(RESP::Null),
+        };
+        let rank = if reverse { zset.len() - 1 - rank } else { rank };
+        Ok(match with_score {
+            true => RESP::Array(vec![RESP::Integer(rank as i64), RESP::Double(score)]),
+            false => RESP::Integer(rank as i64),
+        })
+    }
+
+    pub(super) fn command_zrange(&mut self, command: &[Vec<u8>]) -> StorageResult<RESP> {
+        if command.len() < 4 {
+            return Err(StorageError::WrongArgumentCount(command_name(command)));
+        }
+        let range = parse_range(command, 2)?;
+        let entries = match self.sorted_set_at(&command[1])? {
+            Some(zset) => zset.select(&range),
+            None => vec![],
+        };
+        Ok(scored_reply(entries, range.with_scores))
+    }
+
+    pub(super) fn command_zrangestore(&mut self, command: &[Vec<u8>]) -> StorageResult<RESP> {
+        if command.len() < 5 {
+            return Err(StorageError::WrongArgumentCount(command_name(command)));
+        }
+        let range = parse_range(command, 3)?;
+        if range.with_scores {
+            return Err(StorageError::CommandSyntaxError(command_to_string(command)));
+        }
+        let mut result = SortedSet::default();
+        if let Some(zset) = self.sorted_set_at(&command[2])? {
+            for (member, score) in zset.select(&range) {
+                result.insert(member.to_vec(), score);
+            }
+        }
+        let length = self.store_sorted_set(&command[1], result);
+        Ok(RESP::Integer(length as i64))
+    }
+
+    /// Handles ZPOPMIN and, with `max`, ZPOPMAX.
+    pub(super) fn command_zpop(&mut self, command: &[Vec<u8>], max: bool) -> StorageResult<RESP> {
+        if command.len() != 2 && command.len() != 3 {
+            return Err(StorageError::WrongArgumentCount(command_name(command)));
+        }
+        let count = match command.get(2) {
+            Some(count) => match parse_integer(count)? {
+                count if count < 0 => {
+                    return Err(StorageError::InvalidArgument(String::from(
+                        "value is out of range, must be positive",
+                    )))
+                }
+                count => count as usize,
+            },
+            None => 1,
+        };
+        let popped = self.sorted_set_pop(&command[1], max, count)?;
+        Ok(scored_reply(
+            popped
+                .iter()
+                .map(|(member, score)| (member.as_slice(), *score))
+                .collect(),
+            true,
+        ))
+    }
+
+    /// Handles ZUNIONSTORE and ZINTERSTORE.
+    pub(super) fn command_zsetop_store(
+        &mut self,
+        command: &[Vec<u8>],
+        operation: SetOperation,
+    ) -> StorageResult<RESP> {
+        if command.len() < 4 {
+            return Err(StorageError::WrongArgumentCount(command_name(command)));
+        }
+        let numkeys = parse_integer(&command[2])?;
+        if numkeys <= 0 {
+            return Err(StorageError::InvalidArgument(format!(
+                "at least 1 input key is needed for '{}' command",
+                String::from_utf8_lossy(&command[0].to_ascii_lowercase())
+            )));
+        }
+        let numkeys = numkeys as usize;
+        if numkeys > command.len() - 3 {
+            return Err(StorageError::CommandSyntaxError(command_to_string(command)));
+        }
+        let keys = &command[3..3 + numkeys];
+        let mut weights = vec![1.0; numkeys];
+        let mut aggregate = Aggregate::Sum;
+        let mut idx = 3 + numkeys;
+        while idx < command.len() {
+            match command[idx].to_ascii_lowercase().as_slice() {
+                b"weights" if idx + numkeys < command.len() => {
+                    for (weight, argument) in weights.iter_mut().zip(&command[idx + 1..]) {
+                        *weight = parse_float(argument).map_err(|_| {
+                            StorageError::InvalidArgument(String::from(
+                                "weight value is not a float",
+                            ))
+                        })?;
+                    }
+                    idx += 1 + numkeys;
+                }
+                b"aggregate" if idx + 1 < command.len() => {
+                    aggregate = match command[idx + 1].to_ascii_lowercase().as_slice() {
+                        b"sum" => Aggregate::Sum,
+                        b"min" => Aggregate::Min,
+                        b"max" => Aggregate::Max,
+                        _ => {
+                            return Err(StorageError::CommandSyntaxError(command_to_string(
+                                command,
+                            )))
+                        }
+                    };
+                    idx += 2;
+                }
+                _ => return Err(StorageError::CommandSyntaxError(command_to_string(command))),
+            }
+        }
+
+        let inputs = self.scored_at(keys)?;
+        let mut scores: HashMap<Vec<u8>, f64> = HashMap::new();
+        match operation {
+            SetOperation::Intersection => {
+                if let Some(inputs) = inputs
+                    .iter()
+                    .map(Option::as_ref)
+                    .collect::<Option<Vec<&Scored>>>()
+                {
+                    // Walk the smallest input, looking its members up in the others.
+                    let smallest = (0..numkeys)
+                        .min_by_key(|&i| inputs[i].len())
+                        .expect("at least one input");
+                    for (member, _) in inputs[smallest].entries() {
+                        let weighted = inputs
+                            .iter()
+                            .zip(&weights)
+                            .map(|(input, weight)| {
+                                input.score(member).map(|score| zero_if_nan(score * weight))
+                            })
+                            .collect::<Option<Vec<f64>>>();
+                        if let Some(weighted) = weighted {
+                            let combined = weighted
+                                .into_iter()
+                                .reduce(|a, b| aggregate.apply(a, b))
+                                .expect("at least one input");
+                            scores.insert(member.to_vec(), combined);
+                        }
+                    }
+                }
+            }
+            _ => {
+                for (input, weight) in inputs.iter().zip(&weights) {
+                    for (member, score) in input.iter().flat_map(|input| input.entries()) {
+                        let score = zero_if_nan(score * weight);
+                        scores
+                            .entry(member.to_vec())
+                            .and_modify(|combined| *combined = aggregate.apply(*combined, score))
+                            .or_insert(score);
+                    }
+                }
+            }
+        }
+
+        let mut result = SortedSet::default();
+        for (member, score) in scores {
+            result.insert(member, score);
+        }
+        let length = self.store_sorted_set(&command[1], result);
+        Ok(RESP::Integer(length as i64))
+    }
+
+    pub(super) fn command_zscan(&mut self, command: &[Vec<u8>]) -> StorageResult<RESP> {
+        if command.len() < 3 {
+            return Err(StorageError::WrongArgumentCount(command_name(command)));
+        }
+        let cursor = parse_cursor(&command[2])?;
+        let args = parse_scan_arguments(&command[3..], false)?;
+        let zset = match self.sorted_set_at(&command[1])? {
+            Some(zset) => zset,
+            None => {
+                return Ok(RESP::Array(vec![
+                    RESP::BulkString(b"0".to_vec()),
+                    RESP::Array(vec![]),
+                ]))
+            }
+        };
+        let (next, batch) = zset.scores.scan(cursor, args.count);
+        let mut items = Vec::new();
+        for (member, score) in batch {
+            if args
+                .pattern
+                .as_ref()
+                .is_none_or(|pattern| glob_match(pattern, member, false))
+            {
+                items.push(RESP::BulkString(member.to_vec()));
+                items.push(RESP::BulkString(
+                    FormattedDouble(*score).to_string().into_bytes(),
+                ));
+            }
+        }
+        Ok(RESP::Array(vec![
+            RESP::BulkString(next.to_string().into_bytes()),
+            RESP::Array(items),
+        ]))
+    }
+}
+
+/// Parses the `start stop [BYSCORE | BYLEX] [REV] [LIMIT offset count] [WITHSCORES]`
+/// arguments of ZRANGE and ZRANGESTORE, `start` being at `idx`.
+fn parse_range(command: &[Vec<u8>], idx: usize) -> StorageResult<Range> {
+    let (mut by_score, mut by_lex, mut reverse, mut with_scores) = (false, false, false, false);
+    let mut limit = None;
+    let mut option = idx + 2;
+    while option < command.len() {
+        match command[option].to_ascii_lowercase().as_slice() {
+            b"byscore" => by_score = true,
+            b"bylex" => by_lex = true,
+            b"rev" => reverse = true,
+            b"withscores" => with_scores = true,
+            b"limit" if option + 2 < command.len() => {
+                limit = Some((
+                    parse_integer(&command[option + 1])?,
+                    parse_integer(&command[option + 2])?,
+                ));
+                option += 2;
+            }
+            _ => return Err(StorageError::CommandSyntaxError(command_to_string(command))),
+        }
+        option += 1;
+    }
+    if by_score && by_lex {
+        return Err(StorageError::CommandSyntaxError(command_to_string(command)));
+    }
+    if limit.is_some() && !by_score && !by_lex {
+        return Err(StorageError::InvalidArgument(String::from(
+            "syntax error, LIMIT is only supported in combination with either BYSCORE or BYLEX",
+        )));
+    }
+    if with_scores && by_lex {
+        return Err(StorageError::InvalidArgument(String::from(
+            "syntax error, WITHSCORES not supported in combination with BYLEX",
+        )));
+    }
+    // Reversed score and lex ranges give the maximum first.
+    let (min, max) = match reverse && (by_score || by_lex) {
+        true => (&command[idx + 1], &command[idx]),
+        false => (&command[idx], &command[idx + 1]),
+    };
+    let by = if by_score {
+        RangeBy::Score(ScoreBound::parse(min)?, ScoreBound::parse(max)?)
+    } else if by_lex {
+        RangeBy::Lex(LexBound::parse(min)?, LexBound::parse(max)?)
+    } else {
+        RangeBy::Rank(parse_integer(min)?, parse_integer(max)?)
+    };
+    Ok(Range {
+        by,
+        reverse,
+        limit,
+        with_scores,
+    })
+}
+
+/// Replies with members, each followed by its score if `with_scores` is set.
+fn scored_reply(entries: Vec<(&[u8], f64)>, with_scores: bool) -> RESP {
+    let mut items = Vec::new();
+    for (member, score) in entries {
+        items.push(RESP::BulkString(member.to_vec()));
+        if with_scores {
+            items.push(RESP::Double(score));
+        }
+    }
+    RESP::Array(items)
+}
+
+/// Weighting an infinite score by 0 gives 0 rather than NaN, as in Redis.
+fn zero_if_nan(score: f64) -> f64 {
+    if score.is_nan() {
+        0.0
+    } else {
+        score
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::test_helpers::{bulk, bulks, run};
+
+    /// A flat array of members, each followed by its score.
+    fn scored(entries: &[(&str, f64)]) -> RESP {
+        let mut items = Vec::new();
+        for (member, score) in entries {
+            items.push(bulk(member));
+            items.push(RESP::Double(*score));
+        }
+        RESP::Array(items)
+    }
+
+    fn leaderboard() -> Storage {
+        let mut storage = Storage::new();
+        run(
+            &mut storage,
+            &[
+                "zadd", "z", "1", "a", "2", "b", "3", "c", "4", "d", "5", "e",
+            ],
+        )
+        .unwrap();
+        storage
+    }
+
+    #[test]
+    fn test_command_zadd() {
+        let mut storage = Storage::new();
+        assert_eq!(
+            run(&mut storage, &["zadd", "z", "1", "a", "2", "b", "1", "a"]),
+            Ok(RESP::Integer(2))
+        );
+        assert_eq!(
+            run(
+                &mut storage,
+                &["zadd", "z", "CH", "5", "a", "2", "b", "3", "c"]
+            ),
+            Ok(RESP::Integer(2))
+        );
+        assert_eq!(
+            run(&mut storage, &["zadd", "z", "NX", "9", "a", "4", "d"]),
+            Ok(RESP::Integer(1))
+        );
+        assert_eq!(
+            run(&mut storage, &["zadd", "z", "XX", "CH", "0", "a", "7", "x"]),
+            Ok(RESP::Integer(1))
+        );
+        assert_eq!(
+            run(
+                &mut storage,
+                &["zadd", "z", "GT", "CH", "-1", "a", "9", "b"]
+            ),
+            Ok(RESP::Integer(1))
+        );
+        assert_eq!(
+            run(
+                &mut storage,
+                &["zadd", "z", "LT", "CH", "-1", "a", "10", "b"]
+            ),
+            Ok(RESP::Integer(1))
+        );
+        assert_eq!(
+            run(&mut storage, &["zscore", "z", "a"]),
+            Ok(RESP::Double(-1.0))
+        );
+        assert_eq!(
+            run(&mut storage, &["zscore", "z", "b"]),
+            Ok(RESP::Double(9.0))
+        );
+        assert_eq!(run(&mut storage, &["zscore", "z", "x"]), Ok(RESP::Null));
+        assert_eq!(run(&mut storage, &["zcard", "z"]), Ok(RESP::Integer(4)));
+        assert_eq!(
+            run(&mut storage, &["type", "z"]),
+            Ok(RESP::SimpleString(String::from("zset")))
+        );
+        assert_eq!(
+            run(&mut storage, &["zadd", "none", "XX", "1", "a"]),
+            Ok(RESP::Integer(0))
+        );
+        assert_eq!(run(&mut storage, &["exists", "none"]), Ok(RESP::Integer(0)));
+    }
+
+    #[test]
+    fn test_command_zadd_errors() {
+        let mut storage = Storage::new();
+        assert_eq!(
+            run(&mut storage, &["zadd", "z", "1"]),
+            Err(StorageError::WrongArgumentCount(String::from("zadd")))
+        );
+        assert!(matches!(
+            run(&mut storage, &["zadd", "z", "1", "a", "2"]),
+            Err(StorageError::CommandSyntaxError(_))
+        ));
+        assert_eq!(
+            run(&mut storage, &["zadd", "z", "x", "a"]),
+            Err(StorageError::NotAFloat)
+        );
+        assert_eq!(
+            run(&mut storage, &["zadd", "z", "nan", "a"]),
+            Err(StorageError::NotAFloat)
+        );
+        assert!(run(&mut storage, &["zadd", "z", "NX", "XX", "1", "a"]).is_err());
+        assert!(run(&mut storage, &["zadd", "z", "GT", "LT", "1", "a"]).is_err());
+        assert!(run(&mut storage, &["zadd", "z", "NX", "GT", "1", "a"]).is_err());
+        assert!(run(&mut storage, &["zadd", "z", "INCR", "1", "a", "2", "b"]).is_err());
+        assert_eq!(run(&mut storage, &["exists", "z"]), Ok(RESP::Integer(0)));
+        run(&mut storage, &["set", "str", "x"]).unwrap();
+        assert_eq!(
+            run(&mut storage, &["zadd", "str", "1", "a"]),
+            Err(StorageError::WrongType)
+        );
+    }
+
+    #[test]
+    fn test_command_zadd_incr_and_zincrby() {
+        let mut storage = Storage::new();
+        assert_eq!(
+            run(&mut storage, &["zadd", "z", "INCR", "2.5", "a"]),
+            Ok(RESP::Double(2.5))
+        );
+        assert_eq!(
+            run(&mut storage, &["zadd", "z", "INCR", "1", "a"]),
+            Ok(RESP::Double(3.5))
+        );
+        assert_eq!(
+            run(&mut storage, &["zadd", "z", "NX", "INCR", "1", "a"]),
+            Ok(RESP::Null)
+        );
+        assert_eq!(
+            run(&mut storage, &["zadd", "z", "GT", "INCR", "-1", "a"]),
+            Ok(RESP::Null)
+        );
+        assert_eq!(
+            run(&mut storage, &["zincrby", "z", "-4", "a"]),
+            Ok(RESP::Double(-0.5))
+        );
+        assert_eq!(
+            run(&mut storage, &["zincrby", "z", "inf", "b"]),
+            Ok(RESP::Double(f64::INFINITY))
+        );
+        assert!(run(&mut storage, &["zincrby", "z", "-inf", "b"]).is_err());
+        assert!(run(&mut storage, &["zadd", "z", "INCR", "-inf", "b"]).is_err());
+        assert_eq!(
+            run(&mut storage, &["zscore", "z", "b"]),
+            Ok(RESP::Double(f64::INFINITY))
+        );
+        assert!(run(&mut storage, &["zincrby", "fresh", "x", "a"]).is_err());
+        assert_eq!(
+            run(&mut storage, &["exists", "fresh"]),
+            Ok(RESP::Integer(0))
+        );
+    }
+
+    #[test]
+    fn test_command_zrem_and_zrank() {
+        let mut storage = leaderboard();
+        assert_eq!(
+            run(&mut storage, &["zrank", "z", "a"]),
+            Ok(RESP::Integer(0))
+        );
+        assert_eq!(
+            run(&mut storage, &["zrank", "z", "d"]),
+            Ok(RESP::Integer(3))
+        );
+        assert_eq!(
+            run(&mut storage, &["zrevrank", "z", "d"]),
+            Ok(RESP::Integer(1))
+        );
+        assert_eq!(
+            run(&mut storage, &["zrevrank", "z", "d", "WITHSCORE"]),
+            Ok(RESP::Array(vec![RESP::Integer(1), RESP::Double(4.0)]))
+        );
+        assert_eq!(run(&mut storage, &["zrank", "z", "x"]), Ok(RESP::Null));
+        assert_eq!(run(&mut storage, &["zrank", "none", "a"]), Ok(RESP::Null));
+        assert_eq!(
+            run(&mut storage, &["zrem", "z", "a", "c", "x"]),
+            Ok(RESP::Integer(2))
+        );
+        assert_eq!(
+            run(&mut storage, &["zrank", "z", "d"]),
+            Ok(RESP::Integer(1))
+        );
+        run(&mut storage, &["zrem", "z", "b", "d", "e"]).unwrap();
+        assert_eq!(run(&mut storage, &["exists", "z"]), Ok(RESP::Integer(0)));
+    }
+
+    #[test]
+    fn test_command_zrange_by_rank() {
+        let mut storage = leaderboard();
+        assert_eq!(
+            run(&mut storage, &["zrange", "z", "0", "-1"]),
+            Ok(bulks(&["a", "b", "c", "d", "e"]))
+        );
+        assert_eq!(
+            run(&mut storage, &["zrange", "z", "1", "2", "WITHSCORES"]),
+            Ok(scored(&[("b", 2.0), ("c", 3.0)]))
+        );
+        assert_eq!(
+            run(&mut storage, &["zrange", "z", "0", "1", "REV"]),
+            Ok(bulks(&["e", "d"]))
+        );
+        assert_eq!(
+            run(&mut storage, &["zrange", "z", "-2", "-1", "REV"]),
+            Ok(bulks(&["b", "a"]))
+        );
+        assert_eq!(
+            run(&mut storage, &["zrange", "z", "3", "1"]),
+            Ok(bulks(&[]))
+        );
+        assert_eq!(
+            run(&mut storage, &["zrange", "none", "0", "-1"]),
+            Ok(bulks(&[]))
+        );
+        assert!(run(&mut storage, &["zrange", "z", "0", "1", "LIMIT", "0", "1"]).is_err());
+    }
+
+    #[test]
+    fn test_command_zrange_by_score() {
+        let mut storage = leaderboard();
+        assert_eq!(
+            run(&mut storage, &["zrange", "z", "2", "4", "BYSCORE"]),
+            Ok(bulks(&["b", "c", "d"]))
+        );
+        assert_eq!(
+            run(&mut storage, &["zrange", "z", "(2", "+inf", "BYSCORE"]),
+            Ok(bulks(&["c", "d", "e"]))
+        );
+        assert_eq!(
+            run(&mut storage, &["zrange", "z", "(4", "(2", "BYSCORE", "REV"]),
+            Ok(bulks(&["c"]))
+        );
+        assert_eq!(
+            run(
+                &mut storage,
+                &[
+                    "zrange",
+                    "z",
+                    "-inf",
+                    "inf",
+                    "BYSCORE",
+                    "LIMIT",
+                    "1",
+                    "2",
+                    "WITHSCORES"
+                ]
+            ),
+            Ok(scored(&[("b", 2.0), ("c", 3.0)]))
+        );
+        assert_eq!(
+            run(
+                &mut storage,
+                &["zrange", "z", "+inf", "-inf", "BYSCORE", "REV", "LIMIT", "3", "-1"]
+            ),
+            Ok(bulks(&["b", "a"]))
+        );
+        assert_eq!(
+            run(&mut storage, &["zrange", "z", "4", "2", "BYSCORE"]),
+            Ok(bulks(&[]))
+        );
+        assert_eq!(
+            run(&mut storage, &["zcount", "z", "(1", "3"]),
+            Ok(RESP::Integer(2))
+        );
+        assert_eq!(
+            run(&mut storage, &["zcount", "z", "-inf", "+inf"]),
+            Ok(RESP::Integer(5))
+        );
+        assert!(run(&mut storage, &["zrange", "z", "x", "2", "BYSCORE"]).is_err());
+    }
+
+    #[test]
+    fn test_command_zrange_by_lex() {
+        let mut storage = Storage::new();
+        run(
+            &mut storage,
+            &["zadd", "z", "0", "a", "0", "b", "0", "c", "0", "d"],
+        )
+        .unwrap();
+        assert_eq!(
+            run(&mut storage, &["zrange", "z", "[b", "(d", "BYLEX"]),
+            Ok(bulks(&["b", "c"]))
+        );
+        assert_eq!(
+            run(
+                &mut storage,
+                &["zrange", "z", "-", "+", "BYLEX", "LIMIT", "1", "2"]
+            ),
+            Ok(bulks(&["b", "c"]))
+        );
+        assert_eq!(
+            run(&mut storage, &["zrange", "z", "+", "(b", "BYLEX", "REV"]),
+            Ok(bulks(&["d", "c"]))
+        );
+        assert!(run(&mut storage, &["zrange", "z", "a", "+", "BYLEX"]).is_err());
+        assert!(run(
+            &mut storage,
+            &["zrange", "z", "-", "+", "BYLEX", "WITHSCORES"]
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_command_zrangestore() {
+        let mut storage = leaderboard();
+        run(&mut storage, &["set", "dst", "x"]).unwrap();
+        assert_eq!(
+            run(
+                &mut storage,
+                &["zrangestore", "dst", "z", "(1", "3", "BYSCORE"]
+            ),
+            Ok(RESP::Integer(2))
+        );
+        assert_eq!(
+            run(&mut storage, &["zrange", "dst", "0", "-1", "WITHSCORES"]),
+            Ok(scored(&[("b", 2.0), ("c", 3.0)]))
+        );
+        assert_eq!(
+            run(&mut storage, &["zrangestore", "dst", "none", "0", "-1"]),
+            Ok(RESP::Integer(0))
+        );
+        assert_eq!(run(&mut storage, &["exists", "dst"]), Ok(RESP::Integer(0)));
+    }
+
+    #[test]
+    fn test_command_zpopmin_and_zpopmax() {
+        let mut storage = leaderboard();
+        assert_eq!(
+            run(&mut storage, &["zpopmin", "z"]),
+            Ok(scored(&[("a", 1.0)]))
+        );
+        assert_eq!(
+            run(&mut storage, &["zpopmax", "z", "2"]),
+            Ok(scored(&[("e", 5.0), ("d", 4.0)]))
+        );
+        assert_eq!(
+            run(&mut storage, &["zpopmin", "z", "10"]),
+            Ok(scored(&[("b", 2.0), ("c", 3.0)]))
+        );
+        assert_eq!(run(&mut storage, &["exists", "z"]), Ok(RESP::Integer(0)));
+        assert_eq!(run(&mut storage, &["zpopmin", "z"]), Ok(bulks(&[])));
+        assert!(run(&mut storage, &["zpopmin", "z", "-1"]).is_err());
+    }
+
+    #[test]
+    fn test_command_zunionstore_and_zinterstore() {
+        let mut storage = Storage::new();
+        run(&mut storage, &["zadd", "a", "1", "x", "2", "y", "3", "z"]).unwrap();
+        run(
+            &mut storage,
+            &["zadd", "b", "10", "y", "20", "z", "30", "w"],
+        )
+        .unwrap();
+        run(&mut storage, &["sadd", "s", "z", "v"]).unwrap();
+        assert_eq!(
+            run(&mut storage, &["zunionstore", "out", "2", "a", "b"]),
+            Ok(RESP::Integer(4))
+        );
+        assert_eq!(
+            run(&mut storage, &["zrange", "out", "0", "-1", "WITHSCORES"]),
+            Ok(scored(&[("x", 1.0), ("y", 12.0), ("z", 23.0), ("w", 30.0)]))
+        );
+        assert_eq!(
+            run(
+                &mut storage,
+                &[
+                    "zinterstore",
+                    "out",
+                    "3",
+                    "a",
+                    "b",
+                    "s",
+                    "WEIGHTS",
+                    "2",
+                    "1",
+                    "5"
+                ]
+            ),
+            Ok(RESP::Integer(1))
+        );
+        assert_eq!(
+            run(&mut storage, &["zrange", "out", "0", "-1", "WITHSCORES"]),
+            Ok(scored(&[("z", 31.0)]))
+        );
+        assert_eq!(
+            run(
+                &mut storage,
+                &["zinterstore", "out", "2", "a", "b", "AGGREGATE", "MAX"]
+            ),
+            Ok(RESP::Integer(2))
+        );
+        assert_eq!(
+            run(&mut storage, &["zrange", "out", "0", "-1", "WITHSCORES"]),
+            Ok(scored(&[("y", 10.0), ("z", 20.0)]))
+        );
+        assert_eq!(
+            run(&mut storage, &["zinterstore", "out", "2", "a", "none"]),
+            Ok(RESP::Integer(0))
+        );
+        assert_eq!(run(&mut storage, &["exists", "out"]), Ok(RESP::Integer(0)));
+
+        assert!(run(&mut storage, &["zunionstore", "out", "0", "a"]).is_err());
+        assert!(run(&mut storage, &["zunionstore", "out", "3", "a", "b"]).is_err());
+        assert!(run(
+            &mut storage,
+            &["zunionstore", "out", "1", "a", "WEIGHTS", "x"]
+        )
+        .is_err());
+        assert!(run(
+            &mut storage,
+            &["zunionstore", "out", "1", "a", "AGGREGATE", "avg"]
+        )
+        .is_err());
+        run(&mut storage, &["set", "str", "x"]).unwrap();
+        assert_eq!(
+            run(&mut storage, &["zunionstore", "out", "2", "a", "str"]),
+            Err(StorageError::WrongType)
+        );
+    }
+
+    #[test]
+    fn test_command_zunionstore_infinities() {
+        let mut storage = Storage::new();
+        run(&mut storage, &["zadd", "a", "inf", "x"]).unwrap();
+        run(&mut storage, &["zadd", "b", "-inf", "x"]).unwrap();
+        run(&mut storage, &["zunionstore", "out", "2", "a", "b"]).unwrap();
+        assert_eq!(
+            run(&mut storage, &["zscore", "out", "x"]),
+            Ok(RESP::Double(0.0))
+        );
+        run(
+            &mut storage,
+            &["zunionstore", "out", "1", "a", "WEIGHTS", "0"],
+        )
+        .unwrap();
+        assert_eq!(
+            run(&mut storage, &["zscore", "out", "x"]),
+            Ok(RESP::Double(0.0))
+        );
+    }
+
+    #[test]
+    fn test_command_zscan() {
+        let mut storage = leaderboard();
+        let reply = run(
+            &mut storage,
+            &["zscan", "z", "0", "MATCH", "c", "COUNT", "100"],
+        );
+        assert_eq!(reply, Ok(RESP::Array(vec![bulk("0"), bulks(&["c", "3"])])));
+    }
+
+    #[test]
+    fn test_sorted_set_encoding() {
+        let mut storage = leaderboard();
+        assert_eq!(
+            run(&mut storage, &["object", "encoding", "z"]),
+            Ok(bulk("listpack"))
+        );
+        run(&mut storage, &["zadd", "z", "6", &"x".repeat(65)]).unwrap();
+        assert_eq!(
+            run(&mut storage, &["object", "encoding", "z"]),
+            Ok(bulk("skiplist"))
+        );
+    }
+}
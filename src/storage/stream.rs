@@ -0,0 +1,2031 @@
+use super::{now_millis, parse_integer, Storage, StorageData, StorageValue};
+use crate::resp::RESP;
+use crate::storage_result::{command_name, command_to_string, StorageError, StorageResult};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::ops::Bound;
+use std::time::Duration;
+
+/// The ID of a stream entry: the millisecond it was added at, and a sequence number
+/// telling apart the entries added within the same millisecond.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct StreamId {
+    ms: u64,
+    seq: u64,
+}
+
+impl StreamId {
+    const MIN: StreamId = StreamId { ms: 0, seq: 0 };
+    const MAX: StreamId = StreamId {
+        ms: u64::MAX,
+        seq: u64::MAX,
+    };
+
+    /// Parses `ms-seq`, or a bare `ms` taking `missing_seq` as its sequence number.
+    fn parse(argument: &[u8], missing_seq: u64) -> StorageResult<StreamId> {
+        let invalid = || {
+            StorageError::InvalidArgument(String::from(
+                "Invalid stream ID specified as stream command argument",
+            ))
+        };
+        let (ms, seq) = match argument.iter().position(|&c| c == b'-') {
+            Some(dash) => (&argument[..dash], Some(&argument[dash + 1..])),
+            None => (argument, None),
+        };
+        let ms = parse_u64(ms).ok_or_else(invalid)?;
+        let seq = match seq {
+            Some(seq) => parse_u64(seq).ok_or_else(invalid)?,
+            None => missing_seq,
+        };
+        Ok(StreamId { ms, seq })
+    }
+
+    fn next(self) -> Option<StreamId> {
+        match self.seq.checked_add(1) {
+            Some(seq) => Some(StreamId { ms: self.ms, seq }),
+            None => self.ms.checked_add(1).map(|ms| StreamId { ms, seq: 0 }),
+        }
+    }
+
+    fn previous(self) -> Option<StreamId> {
+        match self.seq.checked_sub(1) {
+            Some(seq) => Some(StreamId { ms: self.ms, seq }),
+            None => self
+                .ms
+                .checked_sub(1)
+                .map(|ms| StreamId { ms, seq: u64::MAX }),
+        }
+    }
+
+    fn reply(self) -> RESP {
+        RESP::BulkString(self.to_string().into_bytes())
+    }
+}
+
+impl fmt::Display for StreamId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}-{}", self.ms, self.seq)
+    }
+}
+
+/// An entry's fields and values, alternating as they were given to XADD.
+type Fields = Vec<Vec<u8>>;
+
+/// An entry delivered to a consumer of a group and not acknowledged yet.
+#[derive(Debug, Clone, PartialEq)]
+struct PendingEntry {
+    consumer: Vec<u8>,
+    /// When it was last delivered, in milliseconds since the epoch.
+    delivered_at: i64,
+    deliveries: u64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Consumer {
+    /// When the consumer last tried to read or claim entries.
+    seen_at: i64,
+    /// When it last got any.
+    active_at: Option<i64>,
+    /// The IDs of the group's pending entries owned by this consumer.
+    pending: BTreeSet<StreamId>,
+}
+
+impl Consumer {
+    fn new(now: i64) -> Consumer {
+        Consumer {
+            seen_at: now,
+            active_at: None,
+            pending: BTreeSet::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct ConsumerGroup {
+    last_delivered: StreamId,
+    /// How many entries the group has been delivered since the stream was created, or
+    /// `None` when deletions make that unknown.
+    entries_read: Option<u64>,
+    pending: BTreeMap<StreamId, PendingEntry>,
+    consumers: BTreeMap<Vec<u8>, Consumer>,
+}
+
+impl ConsumerGroup {
+    fn new(last_delivered: StreamId, entries_read: Option<u64>) -> ConsumerGroup {
+        ConsumerGroup {
+            last_delivered,
+            entries_read,
+            pending: BTreeMap::new(),
+            consumers: BTreeMap::new(),
+        }
+    }
+
+    /// Returns the consumer called `name`, creating it if needed, and marks it seen.
+    fn consumer(&mut self, name: &[u8], now: i64) -> &mut Consumer {
+        let consumer = self
+            .consumers
+            .entry(name.to_vec())
+            .or_insert_with(|| Consumer::new(now));
+        consumer.seen_at = now;
+        consumer
+    }
+
+    /// Makes `consumer` the owner of the pending entry `id`, adding the entry as never
+    /// delivered if it was not pending, and returns it.
+    fn assign(&mut self, id: StreamId, consumer: &[u8], now: i64) -> &mut PendingEntry {
+        let pending = self.pending.entry(id).or_insert_with(|| PendingEntry {
+            consumer: consumer.to_vec(),
+            delivered_at: now,
+            deliveries: 0,
+        });
+        if pending.consumer != consumer {
+            if let Some(owner) = self.consumers.get_mut(&pending.consumer) {
+                owner.pending.remove(&id);
+            }
+            pending.consumer = consumer.to_vec();
+        }
+        self.consumers
+            .entry(consumer.to_vec())
+            .or_insert_with(|| Consumer::new(now))
+            .pending
+            .insert(id);
+        pending
+    }
+
+    /// Removes the pending entry `id`, returning whether there was one.
+    fn acknowledge(&mut self, id: StreamId) -> bool {
+        match self.pending.remove(&id) {
+            Some(pending) => {
+                if let Some(owner) = self.consumers.get_mut(&pending.consumer) {
+                    owner.pending.remove(&id);
+                }
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// An append-only log of entries ordered by ID, read either directly or through
+/// consumer groups that track which entries each of their consumers has yet to
+/// acknowledge. Unlike other collections, a stream is kept when it has no entries left.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Stream {
+    entries: BTreeMap<StreamId, Fields>,
+    last_id: StreamId,
+    /// The largest ID removed by XDEL, as opposed to trimming.
+    max_deleted_id: StreamId,
+    entries_added: u64,
+    groups: BTreeMap<Vec<u8>, ConsumerGroup>,
+}
+
+impl Stream {
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    fn first_id(&self) -> StreamId {
+        self.entries.keys().next().copied().unwrap_or_default()
+    }
+
+    /// Picks the ID of a new entry, which must be above every ID used so far.
+    fn next_id(&self, requested: NewId, now: i64) -> StorageResult<StreamId> {
+        let too_small = || {
+            StorageError::InvalidArgument(String::from(
+                "The ID specified in XADD is equal or smaller than the target stream top item",
+            ))
+        };
+        let id = match requested {
+            NewId::Auto => {
+                let ms = now.max(0) as u64;
+                if ms > self.last_id.ms {
+                    Some(StreamId { ms, seq: 0 })
+                } else {
+                    self.last_id.next()
+                }
+            }
+            NewId::AutoSequence(ms) if ms > self.last_id.ms => Some(StreamId { ms, seq: 0 }),
+            NewId::AutoSequence(ms) if ms == self.last_id.ms => {
+                self.last_id.next().filter(|id| id.ms == ms)
+            }
+            NewId::AutoSequence(_) => return Err(too_small()),
+            NewId::Explicit(id) if id > self.last_id => Some(id),
+            NewId::Explicit(_) => return Err(too_small()),
+        };
+        id.ok_or_else(|| {
+            StorageError::InvalidArgument(String::from(
+                "The stream has exhausted the last possible ID, unable to add more items",
+            ))
+        })
+    }
+
+    fn add(&mut self, id: StreamId, fields: Fields) {
+        self.entries.insert(id, fields);
+        self.last_id = id;
+        self.entries_added += 1;
+    }
+
+    fn delete(&mut self, id: StreamId) -> bool {
+        let deleted = self.entries.remove(&id).is_some();
+        if deleted {
+            self.max_deleted_id = self.max_deleted_id.max(id);
+        }
+        deleted
+    }
+
+    /// Removes the oldest entries as `trim` asks, at most `limit` of them unless it is 0,
+    /// returning how many went.
+    fn trim(&mut self, trim: Trim, limit: usize) -> usize {
+        let limit = if limit == 0 { usize::MAX } else { limit };
+        let mut removed = 0;
+        while removed < limit {
+            let first = match self.entries.keys().next() {
+                Some(&first) => first,
+                None => break,
+            };
+            let keep = match trim {
+                Trim::MaxLen(length) => self.entries.len() <= length,
+                Trim::MinId(id) => first >= id,
+            };
+            if keep {
+                break;
+            }
+            self.entries.remove(&first);
+            removed += 1;
+        }
+        removed
+    }
+
+    /// Whether XDEL removed anything between `start` and the last entry, which breaks
+    /// the count of entries a group has read from there.
+    fn has_tombstones(&self, start: StreamId) -> bool {
+        if self.entries.is_empty() || self.max_deleted_id == StreamId::MIN {
+            return false;
+        }
+        self.max_deleted_id >= start && self.max_deleted_id <= self.last_id
+    }
+
+    /// How many entries had been added up to and including `id`, if deletions do not
+    /// make that impossible to tell.
+    fn entries_added_until(&self, id: StreamId) -> Option<u64> {
+        if self.entries_added == 0 {
+            return Some(0);
+        }
+        if self.entries.is_empty() && id <= self.last_id {
+            return Some(self.entries_added);
+        }
+        match id.cmp(&self.last_id) {
+            std::cmp::Ordering::Equal => return Some(self.entries_added),
+            std::cmp::Ordering::Greater => return None,
+            std::cmp::Ordering::Less => {}
+        }
+        let first = self.first_id();
+        let length = self.entries.len() as u64;
+        if self.max_deleted_id == StreamId::MIN || self.max_deleted_id < first {
+            if id < first {
+                return Some(self.entries_added - length);
+            }
+            if id == first {
+                return Some(self.entries_added - length + 1);
+            }
+        }
+        None
+    }
+
+    /// How many entries the group has yet to be delivered, if that can be told.
+    fn lag(&self, group: &ConsumerGroup) -> Option<u64> {
+        if self.entries_added == 0 {
+            return Some(0);
+        }
+        let read = match group.entries_read {
+            Some(read) if !self.has_tombstones(group.last_delivered) => Some(read),
+            _ => self.entries_added_until(group.last_delivered),
+        };
+        read.map(|read| self.entries_added.saturating_sub(read))
+    }
+
+    /// Delivers the entries past the group's last delivered one to a consumer, adding
+    /// them to its pending entries unless `noack` is set.
+    fn deliver(&mut self, reader: &GroupReader, count: usize, now: i64) -> Vec<RESP> {
+        let group = &self.groups[&reader.group];
+        let (mut last, mut read) = (group.last_delivered, group.entries_read);
+        let delivered: Vec<StreamId> = self
+            .entries
+            .range((Bound::Excluded(last), Bound::Unbounded))
+            .take(count)
+            .map(|(id, _)| *id)
+            .collect();
+        for &id in delivered.iter() {
+            read = match read {
+                Some(read) if !self.has_tombstones(last) => Some(read + 1),
+                _ => self.entries_added_until(id),
+            };
+            last = id;
+        }
+        let replies = delivered
+            .iter()
+            .map(|id| entry_reply(*id, self.entries.get(id)))
+            .collect();
+
+        let group = self
+            .groups
+            .get_mut(&reader.group)
+            .expect("the group was looked up above");
+        group.last_delivered = last;
+        group.entries_read = read;
+        if !reader.noack {
+            for &id in delivered.iter() {
+                let pending = group.assign(id, &reader.consumer, now);
+                pending.delivered_at = now;
+                pending.deliveries = 1;
+            }
+        }
+        let consumer = group.consumer(&reader.consumer, now);
+        if !delivered.is_empty() {
+            consumer.active_at = Some(now);
+        }
+        replies
+    }
+}
+
+/// The ID asked of XADD.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum NewId {
+    /// `*`
+    Auto,
+    /// `ms-*`
+    AutoSequence(u64),
+    Explicit(StreamId),
+}
+
+impl NewId {
+    fn parse(argument: &[u8]) -> StorageResult<NewId> {
+        if argument == b"*" {
+            return Ok(NewId::Auto);
+        }
+        if let Some(ms) = argument.strip_suffix(b"-*") {
+            return parse_u64(ms).map(NewId::AutoSequence).ok_or_else(|| {
+                StorageError::InvalidArgument(String::from(
+                    "Invalid stream ID specified as stream command argument",
+                ))
+            });
+        }
+        let id = StreamId::parse(argument, 0)?;
+        if id == StreamId::MIN {
+            return Err(StorageError::InvalidArgument(String::from(
+                "The ID specified in XADD must be greater than 0-0",
+            )));
+        }
+        Ok(NewId::Explicit(id))
+    }
+}
+
+/// How XADD and XTRIM trim a stream.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Trim {
+    MaxLen(usize),
+    MinId(StreamId),
+}
+
+/// Where XREAD and XREADGROUP read a stream from.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(super) enum ReadPosition {
+    After(StreamId),
+    /// `$`: only entries added from now on.
+    Last,
+    /// `>`: the entries never delivered to the group.
+    Undelivered,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(super) struct GroupReader {
+    group: Vec<u8>,
+    consumer: Vec<u8>,
+    noack: bool,
+}
+
+/// A parsed XREAD or XREADGROUP.
+#[derive(Debug, Clone, PartialEq)]
+pub(super) struct StreamRead {
+    group: Option<GroupReader>,
+    pub(super) keys: Vec<Vec<u8>>,
+    positions: Vec<ReadPosition>,
+    count: Option<usize>,
+}
+
+impl StreamRead {
+    /// The same read restricted to `key`, for serving a client blocked on it.
+    pub(super) fn for_key(&self, key: &[u8]) -> StreamRead {
+        let (keys, positions) = self
+            .keys
+            .iter()
+            .zip(self.positions.iter())
+            .filter(|(read, _)| read.as_slice() == key)
+            .map(|(key, position)| (key.clone(), *position))
+            .unzip();
+        StreamRead {
+            group: self.group.clone(),
+            keys,
+            positions,
+            count: self.count,
+        }
+    }
+}
+
+impl Storage {
+    /// Returns the stream stored at `key`, or `None` if there is no such key.
+    fn stream_at(&mut self, key: &[u8]) -> StorageResult<Option<&mut Stream>> {
+        match self.lookup(key) {
+            Some(StorageData {
+                value: StorageValue::Stream(stream),
+                ..
+            }) => Ok(Some(stream)),
+            Some(_) => Err(StorageError::WrongType),
+            None => Ok(None),
+        }
+    }
+
+    /// Returns the stream stored at `key`, creating an empty one if there is no such key.
+    fn stream_at_or_insert(&mut self, key: &[u8]) -> StorageResult<&mut Stream> {
+        if self.stream_at(key)?.is_none() {
+            self.store.insert(
+                key.to_vec(),
+                StorageData::from(StorageValue::Stream(Stream::default())),
+            );
+        }
+        Ok(self.stream_at(key)?.expect("the stream was just created"))
+    }
+
+    /// Returns the stream at `key` if it has the consumer group `group`.
+    fn stream_with_group(&mut self, key: &[u8], group: &[u8]) -> StorageResult<&mut Stream> {
+        match self.stream_at(key)? {
+            Some(stream) if stream.groups.contains_key(group) => Ok(stream),
+            _ => Err(StorageError::NoGroup(format!(
+                "No such key '{}' or consumer group '{}'",
+                String::from_utf8_lossy(key),
+                String::from_utf8_lossy(group)
+            ))),
+        }
+    }
+
+    pub(super) fn command_xadd(&mut self, command: &[Vec<u8>]) -> StorageResult<RESP> {
+        let mut nomkstream = false;
+        let mut trim = None;
+        let mut idx = 2;
+        while let Some(option) = command.get(idx) {
+            match option.to_ascii_lowercase().as_slice() {
+                b"nomkstream" => {
+                    nomkstream = true;
+                    idx += 1;
+                }
+                b"maxlen" | b"minid" => {
+                    let (parsed, next) = parse_trim(command, idx)?;
+                    trim = Some(parsed);
+                    idx = next;
+                }
+                _ => break,
+            }
+        }
+        let fields = command.get(idx + 1..).unwrap_or_default();
+        if fields.is_empty() || !fields.len().is_multiple_of(2) {
+            return Err(StorageError::CommandSyntaxError(command_to_string(command)));
+        }
+        let requested = NewId::parse(&command[idx])?;
+
+        let key = &command[1];
+        if nomkstream && self.stream_at(key)?.is_none() {
+            return Ok(RESP::Null);
+        }
+        let stream = self.stream_at_or_insert(key)?;
+        // A new stream cannot refuse an ID, as 0-0 was rejected above.
+        let id = stream.next_id(requested, now_millis())?;
+        stream.add(id, fields.to_vec());
+        if let Some((trim, limit)) = trim {
+            stream.trim(trim, limit);
+        }
+        self.signal_key_ready(key);
+        Ok(id.reply())
+    }
+
+    pub(super) fn command_xlen(&mut self, command: &[Vec<u8>]) -> StorageResult<RESP> {
+        if command.len() != 2 {
+            return Err(StorageError::WrongArgumentCount(command_name(command)));
+        }
+        let length = self
+            .stream_at(&command[1])?
+            .map_or(0, |stream| stream.len());
+        Ok(RESP::Integer(length as i64))
+    }
+
+    /// Handles XRANGE and, with `reverse`, XREVRANGE, which takes the end first.
+    pub(super) fn command_xrange(
+        &mut self,
+        command: &[Vec<u8>],
+        reverse: bool,
+    ) -> StorageResult<RESP> {
+        let count = match command.len() {
+            4 => usize::MAX,
+            6 if command[4].eq_ignore_ascii_case(b"count") => {
+                parse_integer(&command[5])?.max(0) as usize
+            }
+            _ => return Err(StorageError::CommandSyntaxError(command_to_string(command))),
+        };
+        let (start, end) = match reverse {
+            true => (&command[3], &command[2]),
+            false => (&command[2], &command[3]),
+        };
+        let (start, end) = (parse_bound(start, true)?, parse_bound(end, false)?);
+        let stream = match self.stream_at(&command[1])? {
+            Some(stream) => stream,
+            None => return Ok(RESP::Array(vec![])),
+        };
+        let entries = match (start, end) {
+            (Some(start), Some(end)) if start <= end => {
+                let range = stream.entries.range(start..=end);
+                let range: Box<dyn Iterator<Item = (&StreamId, &Fields)>> = match reverse {
+                    true => Box::new(range.rev()),
+                    false => Box::new(range),
+                };
+                range
+                    .take(count)
+                    .map(|(id, fields)| entry_reply(*id, Some(fields)))
+                    .collect()
+            }
+            _ => vec![],
+        };
+        Ok(RESP::Array(entries))
+    }
+
+    pub(super) fn command_xdel(&mut self, command: &[Vec<u8>]) -> StorageResult<RESP> {
+        if command.len() < 3 {
+            return Err(StorageError::WrongArgumentCount(command_name(command)));
+        }
+        let ids = command[2..]
+            .iter()
+            .map(|id| StreamId::parse(id, 0))
+            .collect::<StorageResult<Vec<StreamId>>>()?;
+        let deleted = match self.stream_at(&command[1])? {
+            Some(stream) => ids.into_iter().filter(|id| stream.delete(*id)).count(),
+            None => 0,
+        };
+        Ok(RESP::Integer(deleted as i64))
+    }
+
+    pub(super) fn command_xtrim(&mut self, command: &[Vec<u8>]) -> StorageResult<RESP> {
+        if command.len() < 4 {
+            return Err(StorageError::WrongArgumentCount(command_name(command)));
+        }
+        let ((trim, limit), next) = parse_trim(command, 2)?;
+        if next != command.len() {
+            return Err(StorageError::CommandSyntaxError(command_to_string(command)));
+        }
+        let removed = match self.stream_at(&command[1])? {
+            Some(stream) => stream.trim(trim, limit),
+            None => 0,
+        };
+        Ok(RESP::Integer(removed as i64))
+    }
+
+    /// Handles XREAD and XREADGROUP for callers that cannot wait, replying with a null
+    /// when there is nothing to read.
+    pub(super) fn command_xread(&mut self, command: &[Vec<u8>]) -> StorageResult<RESP> {
+        let (read, _) = parse_stream_read(command)?;
+        Ok(self.read_streams(&read)?.unwrap_or(RESP::Null))
+    }
+
+    /// Replaces the `$` positions of a read with the last ID of their stream, so that a
+    /// blocked client is served the entries added after it blocked.
+    pub(super) fn resolve_stream_read(&mut self, read: &mut StreamRead) -> StorageResult<()> {
+        for (key, position) in read.keys.iter().zip(read.positions.iter_mut()) {
+            if *position == ReadPosition::Last {
+                let last = self
+                    .stream_at(key)?
+                    .map_or(StreamId::MIN, |stream| stream.last_id);
+                *position = ReadPosition::After(last);
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads the streams of an XREAD or XREADGROUP, replying with each stream that has
+    /// entries to return, or `None` if none has.
+    pub(super) fn read_streams(&mut self, read: &StreamRead) -> StorageResult<Option<RESP>> {
+        let count = read.count.unwrap_or(usize::MAX);
+        let now = now_millis();
+        let mut replies = Vec::new();
+        for (key, position) in read.keys.iter().zip(read.positions.iter()) {
+            let (entries, always) = match &read.group {
+                None => {
+                    let stream = match self.stream_at(key)? {
+                        Some(stream) => stream,
+                        None => continue,
+                    };
+                    let after = match position {
+                        ReadPosition::After(after) => *after,
+                        _ => continue,
+                    };
+                    let entries = stream
+                        .entries
+                        .range((Bound::Excluded(after), Bound::Unbounded))
+                        .take(count)
+                        .map(|(id, fields)| entry_reply(*id, Some(fields)))
+                        .collect();
+                    (entries, false)
+                }
+                Some(reader) => {
+                    let stream = match self.stream_at(key)? {
+                        Some(stream) if stream.groups.contains_key(&reader.group) => stream,
+                        _ => {
+                            return Err(StorageError::NoGroup(format!(
+                                "No such key '{}' or consumer group '{}' in XREADGROUP with GROUP option",
+                                String::from_utf8_lossy(key),
+                                String::from_utf8_lossy(&reader.group)
+                            )))
+                        }
+                    };
+                    match position {
+                        ReadPosition::After(after) => {
+                            // The consumer's own history, even if it turns out empty.
+                            let group = stream
+                                .groups
+                                .get_mut(&reader.group)
+                                .expect("the group was checked above");
+                            let entries = group
+                                .consumer(&reader.consumer, now)
+                                .pending
+                                .range((Bound::Excluded(*after), Bound::Unbounded))
+                                .take(count)
+                                .map(|id| entry_reply(*id, stream.entries.get(id)))
+                                .collect();
+                            (entries, true)
+                        }
+                        _ => (stream.deliver(reader, count, now), false),
+                    }
+                }
+            };
+            if always || !entries.is_empty() {
+                replies.push(RESP::Array(vec![
+                    RESP::BulkString(key.clone()),
+                    RESP::Array(entries),
+                ]));
+            }
+        }
+        Ok((!replies.is_empty()).then_some(RESP::Array(replies)))
+    }
+
+    pub(super) fn command_xgroup(&mut self, command: &[Vec<u8>]) -> StorageResult<RESP> {
+        if command.len() < 4 {
+            return Err(StorageError::WrongArgumentCount(command_name(command)));
+        }
+        let subcommand = command[1].to_ascii_lowercase();
+        let (key, group) = (&command[2], &command[3]);
+        let mkstream = subcommand == b"create"
+            && command[5.min(command.len())..]
+                .iter()
+                .any(|option| option.eq_ignore_ascii_case(b"mkstream"));
+        if self.stream_at(key)?.is_none() && !mkstream {
+            return Err(StorageError::InvalidArgument(String::from(
+                "The XGROUP subcommand requires the key to exist. Note that for CREATE you may want to use the MKSTREAM option to create an empty stream automatically.",
+            )));
+        }
+        let no_group = || {
+            StorageError::NoGroup(format!(
+                "No such consumer group '{}' for key name '{}'",
+                String::from_utf8_lossy(group),
+                String::from_utf8_lossy(key)
+            ))
+        };
+        match (subcommand.as_slice(), command.len()) {
+            (b"create", 5..) | (b"setid", 5 | 7) => {
+                let mut entries_read = None;
+                let mut idx = 5;
+                while idx < command.len() {
+                    match command[idx].to_ascii_lowercase().as_slice() {
+                        b"mkstream" if subcommand == b"create" => idx += 1,
+                        b"entriesread" if idx + 1 < command.len() => {
+                            entries_read = match parse_integer(&command[idx + 1])? {
+                                -1 => None,
+                                read if read >= 0 => Some(read as u64),
+                                _ => {
+                                    return Err(StorageError::InvalidArgument(String::from(
+                                        "value for ENTRIESREAD must be positive or -1",
+                                    )))
+                                }
+                            };
+                            idx += 2;
+                        }
+                        _ => {
+                            return Err(StorageError::CommandSyntaxError(command_to_string(
+                                command,
+                            )))
+                        }
+                    }
+                }
+                let id = match command[4].as_slice() {
+                    b"$" => None,
+                    id => Some(StreamId::parse(id, 0)?),
+                };
+                let stream = self.stream_at_or_insert(key)?;
+                let id = id.unwrap_or(stream.last_id);
+                if subcommand == b"create" {
+                    if stream.groups.contains_key(group) {
+                        return Err(StorageError::BusyGroup);
+                    }
+                    stream
+                        .groups
+                        .insert(group.clone(), ConsumerGroup::new(id, entries_read));
+                } else {
+                    let group = stream.groups.get_mut(group).ok_or_else(no_group)?;
+                    group.last_delivered = id;
+                    group.entries_read = entries_read;
+                }
+                Ok(RESP::SimpleString(String::from("OK")))
+            }
+            (b"destroy", 4) => {
+                let stream = self.stream_at_or_insert(key)?;
+                let destroyed = stream.groups.remove(group).is_some();
+                Ok(RESP::Integer(destroyed as i64))
+            }
+            (b"createconsumer", 5) => {
+                let stream = self.stream_at_or_insert(key)?;
+                let group = stream.groups.get_mut(group).ok_or_else(no_group)?;
+                let created = !group.consumers.contains_key(&command[4]);
+                group.consumer(&command[4], now_millis());
+                Ok(RESP::Integer(created as i64))
+            }
+            (b"delconsumer", 5) => {
+                let stream = self.stream_at_or_insert(key)?;
+                let group = stream.groups.get_mut(group).ok_or_else(no_group)?;
+                let pending = match group.consumers.remove(&command[4]) {
+                    Some(consumer) => consumer.pending,
+                    None => BTreeSet::new(),
+                };
+                for id in pending.iter() {
+                    group.pending.remove(id);
+                }
+                Ok(RESP::Integer(pending.len() as i64))
+            }
+            _ => Err(StorageError::CommandSyntaxError(command_to_string(command))),
+        }
+    }
+
+    pub(super) fn command_xack(&mut self, command: &[Vec<u8>]) -> StorageResult<RESP> {
+        if command.len() < 4 {
+            return Err(StorageError::WrongArgumentCount(command_name(command)));
+        }
+        let ids = command[3..]
+            .iter()
+            .map(|id| StreamId::parse(id, 0))
+            .collect::<StorageResult<Vec<StreamId>>>()?;
+        let group = self
+            .stream_at(&command[1])?
+            .and_then(|stream| stream.groups.get_mut(&command[2]));
+        let acknowledged = match group {
+            Some(group) => ids.iter().filter(|id| group.acknowledge(**id)).count(),
+            None => 0,
+        };
+        Ok(RESP::Integer(acknowledged as i64))
+    }
+
+    /// Replies with a summary of the group's pending entries, or with the entries
+    /// themselves when given a range.
+    pub(super) fn command_xpending(&mut self, command: &[Vec<u8>]) -> StorageResult<RESP> {
+        if command.len() < 3 {
+            return Err(StorageError::WrongArgumentCount(command_name(command)));
+        }
+        let mut idx = 3;
+        let mut min_idle = 0;
+        if command
+            .get(idx)
+            .is_some_and(|option| option.eq_ignore_ascii_case(b"idle"))
+        {
+            min_idle =
+                parse_integer(command.get(idx + 1).ok_or_else(|| {
+                    StorageError::CommandSyntaxError(command_to_string(command))
+                })?)?;
+            idx += 2;
+        }
+        let range = match &command[idx.min(command.len())..] {
+            [] if idx == 3 => None,
+            [start, end, count] => Some((start, end, count, None)),
+            [start, end, count, consumer] => Some((start, end, count, Some(consumer))),
+            _ => return Err(StorageError::CommandSyntaxError(command_to_string(command))),
+        };
+        let range = match range {
+            Some((start, end, count, consumer)) => Some((
+                parse_bound(start, true)?,
+                parse_bound(end, false)?,
+                parse_integer(count)?.max(0) as usize,
+                consumer,
+            )),
+            None => None,
+        };
+        let stream = self.stream_with_group(&command[1], &command[2])?;
+        let group = &stream.groups[&command[2]];
+
+        let (start, end, count, consumer) = match range {
+            Some(range) => range,
+            None => {
+                let (first, last) = match (group.pending.keys().next(), group.pending.keys().last())
+                {
+                    (Some(first), Some(last)) => (*first, *last),
+                    _ => {
+                        return Ok(RESP::Array(vec![
+                            RESP::Integer(0),
+                            RESP::Null,
+                            RESP::Null,
+                            RESP::Null,
+                        ]))
+                    }
+                };
+                let consumers = group
+                    .consumers
+                    .iter()
+                    .filter(|(_, consumer)| !consumer.pending.is_empty())
+                    .map(|(name, consumer)| {
+                        RESP::Array(vec![
+                            RESP::BulkString(name.clone()),
+                            RESP::BulkString(consumer.pending.len().to_string().into_bytes()),
+                        ])
+                    })
+                    .collect();
+                return Ok(RESP::Array(vec![
+                    RESP::Integer(group.pending.len() as i64),
+                    first.reply(),
+                    last.reply(),
+                    RESP::Array(consumers),
+                ]));
+            }
+        };
+        let (start, end) = match (start, end) {
+            (Some(start), Some(end)) if start <= end => (start, end),
+            _ => return Ok(RESP::Array(vec![])),
+        };
+        let now = now_millis();
+        let ids: Box<dyn Iterator<Item = &StreamId>> = match consumer {
+            Some(consumer) => match group.consumers.get(consumer) {
+                Some(consumer) => Box::new(consumer.pending.range(start..=end)),
+                None => return Ok(RESP::Array(vec![])),
+            },
+            None => Box::new(group.pending.range(start..=end).map(|(id, _)| id)),
+        };
+        let entries = ids
+            .map(|id| (id, &group.pending[id]))
+            .filter(|(_, pending)| now - pending.delivered_at >= min_idle)
+            .take(count)
+            .map(|(id, pending)| {
+                RESP::Array(vec![
+                    id.reply(),
+                    RESP::BulkString(pending.consumer.clone()),
+                    RESP::Integer((now - pending.delivered_at).max(0)),
+                    RESP::Integer(pending.deliveries as i64),
+                ])
+            })
+            .collect();
+        Ok(RESP::Array(entries))
+    }
+
+    pub(super) fn command_xclaim(&mut self, command: &[Vec<u8>]) -> StorageResult<RESP> {
+        if command.len() < 6 {
+            return Err(StorageError::WrongArgumentCount(command_name(command)));
+        }
+        let consumer = &command[3];
+        let min_idle = parse_integer(&command[4])?.max(0);
+        let mut ids = Vec::new();
+        let mut idx = 5;
+        while let Some(id) = command.get(idx).and_then(|id| StreamId::parse(id, 0).ok()) {
+            ids.push(id);
+            idx += 1;
+        }
+        let now = now_millis();
+        let (mut delivered_at, mut retry_count, mut force, mut just_id, mut last_id) =
+            (now, None, false, false, None);
+        while idx < command.len() {
+            let value = command.get(idx + 1);
+            match (command[idx].to_ascii_lowercase().as_slice(), value) {
+                (b"idle", Some(value)) => {
+                    delivered_at = now - parse_integer(value)?.max(0);
+                    idx += 1;
+                }
+                (b"time", Some(value)) => {
+                    delivered_at = parse_integer(value)?;
+                    idx += 1;
+                }
+                (b"retrycount", Some(value)) => {
+                    retry_count = Some(parse_integer(value)?.max(0) as u64);
+                    idx += 1;
+                }
+                (b"lastid", Some(value)) => {
+                    last_id = Some(StreamId::parse(value, 0)?);
+                    idx += 1;
+                }
+                (b"force", _) => force = true,
+                (b"justid", _) => just_id = true,
+                _ => return Err(StorageError::CommandSyntaxError(command_to_string(command))),
+            }
+            idx += 1;
+        }
+        if ids.is_empty() {
+            return Err(StorageError::InvalidArgument(String::from(
+                "Invalid stream ID specified as stream command argument",
+            )));
+        }
+
+        let stream = self.stream_with_group(&command[1], &command[2])?;
+        let group = stream
+            .groups
+            .get_mut(&command[2])
+            .expect("the group was checked above");
+        if let Some(last_id) = last_id {
+            group.last_delivered = group.last_delivered.max(last_id);
+        }
+        let mut claimed = Vec::new();
+        for id in ids {
+            if !stream.entries.contains_key(&id) {
+                // Deleted entries cannot be claimed, and are no longer pending.
+                group.acknowledge(id);
+                continue;
+            }
+            let created = match group.pending.get(&id) {
+                Some(pending) if min_idle > 0 && now - pending.delivered_at < min_idle => continue,
+                Some(_) => false,
+                None if force => true,
+                None => continue,
+            };
+            let pending = group.assign(id, consumer, now);
+            pending.delivered_at = delivered_at;
+            match retry_count {
+                Some(count) => pending.deliveries = count,
+                None if created || !just_id => pending.deliveries += 1,
+                None => {}
+            }
+            claimed.push(match just_id {
+                true => id.reply(),
+                false => entry_reply(id, stream.entries.get(&id)),
+            });
+        }
+        let consumer = group.consumer(consumer, now);
+        if !claimed.is_empty() {
+            consumer.active_at = Some(now);
+        }
+        Ok(RESP::Array(claimed))
+    }
+
+    pub(super) fn command_xautoclaim(&mut self, command: &[Vec<u8>]) -> StorageResult<RESP> {
+        if command.len() < 6 {
+            return Err(StorageError::WrongArgumentCount(command_name(command)));
+        }
+        let consumer = &command[3];
+        let min_idle = parse_integer(&command[4])?.max(0);
+        let start = parse_bound(&command[5], true)?;
+        let (mut count, mut just_id) = (100, false);
+        let mut idx = 6;
+        while idx < command.len() {
+            match command[idx].to_ascii_lowercase().as_slice() {
+                b"count" if idx + 1 < command.len() => {
+                    count = match parse_integer(&command[idx + 1])? {
+                        count if count > 0 => count as usize,
+                        _ => {
+                            return Err(StorageError::InvalidArgument(String::from(
+                                "COUNT must be > 0",
+                            )))
+                        }
+                    };
+                    idx += 1;
+                }
+                b"justid" => just_id = true,
+                _ => return Err(StorageError::CommandSyntaxError(command_to_string(command))),
+            }
+            idx += 1;
+        }
+
+        let now = now_millis();
+        let stream = self.stream_with_group(&command[1], &command[2])?;
+        let group = stream
+            .groups
+            .get_mut(&command[2])
+            .expect("the group was checked above");
+        // Like Redis, look at no more than ten pending entries per entry to claim.
+        let mut attempts = count.saturating_mul(10);
+        let mut claimed = Vec::new();
+        let mut deleted = Vec::new();
+        let mut next = StreamId::MIN;
+        let mut cursor = start;
+        while let Some((&id, pending)) =
+            cursor.and_then(|start| group.pending.range(start..).next())
+        {
+            if attempts == 0 || claimed.len() == count {
+                next = id;
+                break;
+            }
+            attempts -= 1;
+            cursor = id.next();
+            if !stream.entries.contains_key(&id) {
+                group.acknowledge(id);
+                deleted.push(id.reply());
+                continue;
+            }
+            if now - pending.delivered_at < min_idle {
+                continue;
+            }
+            let pending = group.assign(id, consumer, now);
+            pending.delivered_at = now;
+            if !just_id {
+                pending.deliveries += 1;
+            }
+            claimed.push(match just_id {
+                true => id.reply(),
+                false => entry_reply(id, stream.entries.get(&id)),
+            });
+        }
+        let consumer = group.consumer(consumer, now);
+        if !claimed.is_empty() {
+            consumer.active_at = Some(now);
+        }
+        Ok(RESP::Array(vec![
+            next.reply(),
+            RESP::Array(claimed),
+            RESP::Array(deleted),
+        ]))
+    }
+
+    pub(super) fn command_xinfo(&mut self, command: &[Vec<u8>]) -> StorageResult<RESP> {
+        if command.len() < 3 {
+            return Err(StorageError::WrongArgumentCount(command_name(command)));
+        }
+        let subcommand = command[1].to_ascii_lowercase();
+        let full = match (subcommand.as_slice(), &command[3..]) {
+            (b"stream", []) => None,
+            (b"stream", [full]) if full.eq_ignore_ascii_case(b"full") => Some(10),
+            (b"stream", [full, option, count])
+                if full.eq_ignore_ascii_case(b"full") && option.eq_ignore_ascii_case(b"count") =>
+            {
+                Some(parse_integer(count)?.max(0) as usize)
+            }
+            (b"groups", []) | (b"consumers", [_]) => None,
+            _ => return Err(StorageError::CommandSyntaxError(command_to_string(command))),
+        };
+        if subcommand == b"consumers" {
+            let now = now_millis();
+            let stream = self.stream_with_group(&command[2], &command[3])?;
+            let group = &stream.groups[&command[3]];
+            let consumers = group
+                .consumers
+                .iter()
+                .map(|(name, consumer)| {
+                    RESP::Map(vec![
+                        field("name", RESP::BulkString(name.clone())),
+                        field("pending", RESP::Integer(consumer.pending.len() as i64)),
+                        field("idle", RESP::Integer(now - consumer.seen_at)),
+                        field(
+                            "inactive",
+                            RESP::Integer(consumer.active_at.map_or(-1, |active| now - active)),
+                        ),
+                    ])
+                })
+                .collect();
+            return Ok(RESP::Array(consumers));
+        }
+
+        let stream = match self.stream_at(&command[2])? {
+            Some(stream) => stream,
+            None => {
+                return Err(StorageError::InvalidArgument(String::from("no such key")));
+            }
+        };
+        if subcommand == b"groups" {
+            let groups = stream
+                .groups
+                .iter()
+                .map(|(name, group)| {
+                    RESP::Map(vec![
+                        field("name", RESP::BulkString(name.clone())),
+                        field("consumers", RESP::Integer(group.consumers.len() as i64)),
+                        field("pending", RESP::Integer(group.pending.len() as i64)),
+                        field("last-delivered-id", group.last_delivered.reply()),
+                        field("entries-read", optional_integer(group.entries_read)),
+                        field("lag", optional_integer(stream.lag(group))),
+                    ])
+                })
+                .collect();
+            return Ok(RESP::Array(groups));
+        }
+
+        let mut info = vec![
+            field("length", RESP::Integer(stream.len() as i64)),
+            field("last-generated-id", stream.last_id.reply()),
+            field("max-deleted-entry-id", stream.max_deleted_id.reply()),
+            field("entries-added", RESP::Integer(stream.entries_added as i64)),
+            field("recorded-first-entry-id", stream.first_id().reply()),
+        ];
+        let count = match full {
+            Some(count) => count,
+            None => {
+                let first = stream.entries.iter().next();
+                let last = stream.entries.iter().next_back();
+                info.push(field("groups", RESP::Integer(stream.groups.len() as i64)));
+                info.push(field(
+                    "first-entry",
+                    first.map_or(RESP::Null, |(id, fields)| entry_reply(*id, Some(fields))),
+                ));
+                info.push(field(
+                    "last-entry",
+                    last.map_or(RESP::Null, |(id, fields)| entry_reply(*id, Some(fields))),
+                ));
+                return Ok(RESP::Map(info));
+            }
+        };
+        // A count of 0 lists everything.
+        let count = if count == 0 { usize::MAX } else { count };
+        let entries = stream
+            .entries
+            .iter()
+            .take(count)
+            .map(|(id, fields)| entry_reply(*id, Some(fields)))
+            .collect();
+        info.push(field("entries", RESP::Array(entries)));
+        let groups = stream
+            .groups
+            .iter()
+            .map(|(name, group)| {
+                let pending = group
+                    .pending
+                    .iter()
+                    .take(count)
+                    .map(|(id, pending)| {
+                        RESP::Array(vec![
+                            id.reply(),
+                            RESP::BulkString(pending.consumer.clone()),
+                            RESP::Integer(pending.delivered_at),
+                            RESP::Integer(pending.deliveries as i64),
+                        ])
+                    })
+                    .collect();
+                let consumers = group
+                    .consumers
+                    .iter()
+                    .map(|(consumer_name, consumer)| {
+                        let pending: Vec<RESP> = consumer
+                            .pending
+                            .iter()
+                            .take(count)
+                            .map(|id| {
+                                let pending = &group.pending[id];
+                                RESP::Array(vec![
+                                    id.reply(),
+                                    RESP::Integer(pending.delivered_at),
+                                    RESP::Integer(pending.deliveries as i64),
+                                ])
+                            })
+                            .collect();
+                        RESP::Map(vec![
+                            field("name", RESP::BulkString(consumer_name.clone())),
+                            field("seen-time", RESP::Integer(consumer.seen_at)),
+                            field(
+                                "active-time",
+                                RESP::Integer(consumer.active_at.unwrap_or(-1)),
+                            ),
+                            field("pel-count", RESP::Integer(consumer.pending.len() as i64)),
+                            field("pending", RESP::Array(pending)),
+                        ])
+                    })
+                    .collect();
+                RESP::Map(vec![
+                    field("name", RESP::BulkString(name.clone())),
+                    field("last-delivered-id", group.last_delivered.reply()),
+                    field("entries-read", optional_integer(group.entries_read)),
+                    field("lag", optional_integer(stream.lag(group))),
+                    field("pel-count", RESP::Integer(group.pending.len() as i64)),
+                    field("pending", RESP::Array(pending)),
+                    field("consumers", RESP::Array(consumers)),
+                ])
+            })
+            .collect();
+        info.push(field("groups", RESP::Array(groups)));
+        Ok(RESP::Map(info))
+    }
+}
+
+/// Parses XREAD and XREADGROUP, returning along with the read the timeout given by
+/// BLOCK, if any, where `None` waits forever.
+pub(super) fn parse_stream_read(
+    command: &[Vec<u8>],
+) -> StorageResult<(StreamRead, Option<Option<Duration>>)> {
+    let syntax_error = || StorageError::CommandSyntaxError(command_to_string(command));
+    let name = command
+        .first()
+        .map(|name| name.to_ascii_lowercase())
+        .unwrap_or_default();
+    let mut group = None;
+    let mut idx = 1;
+    if name == b"xreadgroup" {
+        match command.get(1..4) {
+            Some([option, name, consumer]) if option.eq_ignore_ascii_case(b"group") => {
+                group = Some(GroupReader {
+                    group: name.clone(),
+                    consumer: consumer.clone(),
+                    noack: false,
+                });
+            }
+            _ => return Err(syntax_error()),
+        }
+        idx = 4;
+    }
+    let (mut count, mut block) = (None, None);
+    loop {
+        let option = command.get(idx).ok_or_else(syntax_error)?;
+        let value = command.get(idx + 1);
+        match (option.to_ascii_lowercase().as_slice(), value) {
+            (b"streams", _) => break,
+            (b"count", Some(value)) => {
+                // A count of 0 or less reads everything.
+                count = Some(parse_integer(value)?)
+                    .filter(|count| *count > 0)
+                    .map(|count| count as usize);
+                idx += 2;
+            }
+            (b"block", Some(value)) => {
+                block = match parse_integer(value)? {
+                    ms if ms < 0 => {
+                        return Err(StorageError::InvalidArgument(String::from(
+                            "timeout is negative",
+                        )))
+                    }
+                    0 => Some(None),
+                    ms => Some(Some(Duration::from_millis(ms as u64))),
+                };
+                idx += 2;
+            }
+            (b"noack", _) if group.is_some() => {
+                if let Some(group) = group.as_mut() {
+                    group.noack = true;
+                }
+                idx += 1;
+            }
+            _ => return Err(syntax_error()),
+        }
+    }
+    let streams = &command[idx + 1..];
+    if streams.is_empty() || !streams.len().is_multiple_of(2) {
+        return Err(StorageError::InvalidArgument(format!(
+            "Unbalanced '{}' list of streams: for each stream key an ID or '$' must be specified.",
+            String::from_utf8_lossy(&name)
+        )));
+    }
+    let (keys, ids) = streams.split_at(streams.len() / 2);
+    let positions = ids
+        .iter()
+        .map(|id| match (id.as_slice(), group.is_some()) {
+            (b"$", false) => Ok(ReadPosition::Last),
+            (b">", true) => Ok(ReadPosition::Undelivered),
+            (b"$", true) => Err(StorageError::InvalidArgument(String::from(
+                "The $ ID is meaningless in the context of XREADGROUP: you want to read the history of this consumer by specifying a proper ID, or use the > ID to get new messages. The $ ID would just return an empty result set.",
+            ))),
+            (b">", false) => Err(StorageError::InvalidArgument(String::from(
+                "The > ID can be specified only when calling XREADGROUP using the GROUP <group> <consumer> option.",
+            ))),
+            (id, _) => StreamId::parse(id, 0).map(ReadPosition::After),
+        })
+        .collect::<StorageResult<Vec<ReadPosition>>>()?;
+    // Only new entries are worth waiting for; a consumer's history is read right away.
+    let block = block.filter(|_| {
+        group.is_none()
+            || positions
+                .iter()
+                .all(|position| *position == ReadPosition::Undelivered)
+    });
+    Ok((
+        StreamRead {
+            group,
+            keys: keys.to_vec(),
+            positions,
+            count,
+        },
+        block,
+    ))
+}
+
+/// Parses `MAXLEN|MINID [=|~] threshold [LIMIT count]` starting at `idx`, returning the
+/// trim, its limit and the index of the next argument. Trimming is always exact, which
+/// `~` allows as it only asks to keep at least as much as the threshold.
+fn parse_trim(command: &[Vec<u8>], idx: usize) -> StorageResult<((Trim, usize), usize)> {
+    let syntax_error = || StorageError::CommandSyntaxError(command_to_string(command));
+    let strategy = command[idx].to_ascii_lowercase();
+    let mut idx = idx + 1;
+    let mut approximate = false;
+    match command.get(idx).map(Vec::as_slice) {
+        Some(b"=") => idx += 1,
+        Some(b"~") => {
+            approximate = true;
+            idx += 1;
+        }
+        _ => {}
+    }
+    let threshold = command.get(idx).ok_or_else(syntax_error)?;
+    let trim = match strategy.as_slice() {
+        b"maxlen" => match parse_integer(threshold)? {
+            length if length < 0 => {
+                return Err(StorageError::InvalidArgument(String::from(
+                    "The MAXLEN argument must be >= 0.",
+                )))
+            }
+            length => Trim::MaxLen(length as usize),
+        },
+        b"minid" => Trim::MinId(StreamId::parse(threshold, 0)?),
+        _ => return Err(syntax_error()),
+    };
+    idx += 1;
+    let mut limit = 0;
+    if command
+        .get(idx)
+        .is_some_and(|option| option.eq_ignore_ascii_case(b"limit"))
+    {
+        limit = match parse_integer(command.get(idx + 1).ok_or_else(syntax_error)?)? {
+            limit if limit < 0 => {
+                return Err(StorageError::InvalidArgument(String::from(
+                    "The LIMIT argument must be >= 0.",
+                )))
+            }
+            limit => limit as usize,
+        };
+        if !approximate {
+            return Err(StorageError::InvalidArgument(String::from(
+                "syntax error, LIMIT cannot be used without the special ~ option",
+            )));
+        }
+        idx += 2;
+    }
+    Ok(((trim, limit), idx))
+}
+
+/// Parses an end of an XRANGE-style interval: `-`, `+`, an ID, or an ID prefixed with
+/// `(` to leave it out. A bare `ms` covers the whole millisecond. Returns `None` for an
+/// exclusive end beyond the smallest or largest ID, which matches nothing.
+fn parse_bound(argument: &[u8], start: bool) -> StorageResult<Option<StreamId>> {
+    let missing_seq = if start { 0 } else { u64::MAX };
+    match argument {
+        b"-" => Ok(Some(StreamId::MIN)),
+        b"+" => Ok(Some(StreamId::MAX)),
+        [b'(', id @ ..] => {
+            let id = StreamId::parse(id, missing_seq)?;
+            Ok(if start { id.next() } else { id.previous() })
+        }
+        id => StreamId::parse(id, missing_seq).map(Some),
+    }
+}
+
+/// Parses the digits of an ID part, rejecting the signs and spaces `str::parse` allows.
+fn parse_u64(digits: &[u8]) -> Option<u64> {
+    if digits.is_empty() || !digits.iter().all(u8::is_ascii_digit) {
+        return None;
+    }
+    std::str::from_utf8(digits).ok()?.parse().ok()
+}
+
+/// Replies with an entry as its ID and fields, the fields being null for an entry that
+/// was deleted while pending.
+fn entry_reply(id: StreamId, fields: Option<&Fields>) -> RESP {
+    let fields = match fields {
+        Some(fields) => RESP::Array(fields.iter().cloned().map(RESP::BulkString).collect()),
+        None => RESP::Null,
+    };
+    RESP::Array(vec![id.reply(), fields])
+}
+
+fn field(name: &str, value: RESP) -> (RESP, RESP) {
+    (RESP::BulkString(name.as_bytes().to_vec()), value)
+}
+
+fn optional_integer(value: Option<u64>) -> RESP {
+    value.map_or(RESP::Null, |value| RESP::Integer(value as i64))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::test_helpers::{bulk, bulks, run};
+
+    fn entry(id: &str, fields: &[&str]) -> RESP {
+        RESP::Array(vec![bulk(id), bulks(fields)])
+    }
+
+    /// The value of `name` in a map reply.
+    fn field_of<'a>(reply: &'a RESP, name: &str) -> &'a RESP {
+        match reply {
+            RESP::Map(fields) => fields
+                .iter()
+                .find(|(key, _)| *key == bulk(name))
+                .map(|(_, value)| value)
+                .unwrap_or_else(|| panic!("no field {}", name)),
+            other => panic!("unexpected reply {:?}", other),
+        }
+    }
+
+    fn stream_with_entries(storage: &mut Storage, ids: &[&str]) {
+        for (i, id) in ids.iter().enumerate() {
+            run(storage, &["xadd", "s", id, "n", &i.to_string()]).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_parse_stream_id() {
+        assert_eq!(StreamId::parse(b"5-3", 0), Ok(StreamId { ms: 5, seq: 3 }));
+        assert_eq!(
+            StreamId::parse(b"5", u64::MAX),
+            Ok(StreamId {
+                ms: 5,
+                seq: u64::MAX
+            })
+        );
+        for invalid in ["", "-", "5-", "+5", "5-x", "1-2-3", " 5"] {
+            assert!(
+                StreamId::parse(invalid.as_bytes(), 0).is_err(),
+                "{}",
+                invalid
+            );
+        }
+        assert_eq!(StreamId::MAX.next(), None);
+        assert_eq!(
+            StreamId { ms: 2, seq: 0 }.previous(),
+            Some(StreamId {
+                ms: 1,
+                seq: u64::MAX
+            })
+        );
+    }
+
+    #[test]
+    fn test_command_xadd_ids() {
+        let mut storage = Storage::new();
+        assert_eq!(
+            run(&mut storage, &["xadd", "s", "5-1", "a", "1"]),
+            Ok(bulk("5-1"))
+        );
+        assert_eq!(
+            run(&mut storage, &["xadd", "s", "5-*", "a", "2"]),
+            Ok(bulk("5-2"))
+        );
+        assert_eq!(
+            run(&mut storage, &["xadd", "s", "7-*", "a", "3"]),
+            Ok(bulk("7-0"))
+        );
+        assert!(run(&mut storage, &["xadd", "s", "7-0", "a", "4"]).is_err());
+        assert!(run(&mut storage, &["xadd", "s", "6-*", "a", "4"]).is_err());
+        assert!(run(&mut storage, &["xadd", "t", "0-0", "a", "4"]).is_err());
+        assert_eq!(run(&mut storage, &["exists", "t"]), Ok(RESP::Integer(0)));
+
+        let before = now_millis() as u64;
+        let id = match run(&mut storage, &["xadd", "s", "*", "a", "5"]) {
+            Ok(RESP::BulkString(id)) => StreamId::parse(&id, 0).unwrap(),
+            other => panic!("unexpected reply {:?}", other),
+        };
+        assert!(id.ms >= before && id.seq == 0);
+        assert_eq!(run(&mut storage, &["xlen", "s"]), Ok(RESP::Integer(4)));
+        assert_eq!(
+            run(&mut storage, &["type", "s"]),
+            Ok(RESP::SimpleString(String::from("stream")))
+        );
+
+        assert_eq!(
+            run(&mut storage, &["xadd", "u", "NOMKSTREAM", "*", "a", "1"]),
+            Ok(RESP::Null)
+        );
+        assert_eq!(run(&mut storage, &["exists", "u"]), Ok(RESP::Integer(0)));
+        assert!(run(&mut storage, &["xadd", "s", "*", "a"]).is_err());
+        run(&mut storage, &["set", "str", "x"]).unwrap();
+        assert_eq!(
+            run(&mut storage, &["xadd", "str", "*", "a", "1"]),
+            Err(StorageError::WrongType)
+        );
+    }
+
+    #[test]
+    fn test_command_xadd_trims() {
+        let mut storage = Storage::new();
+        stream_with_entries(&mut storage, &["1", "2", "3", "4"]);
+        run(
+            &mut storage,
+            &["xadd", "s", "MAXLEN", "=", "2", "5", "n", "5"],
+        )
+        .unwrap();
+        assert_eq!(
+            run(&mut storage, &["xrange", "s", "-", "+"]),
+            Ok(RESP::Array(vec![
+                entry("4-0", &["n", "3"]),
+                entry("5-0", &["n", "5"]),
+            ]))
+        );
+        run(&mut storage, &["xadd", "s", "MINID", "5", "6", "n", "6"]).unwrap();
+        assert_eq!(run(&mut storage, &["xlen", "s"]), Ok(RESP::Integer(2)));
+        assert!(run(
+            &mut storage,
+            &["xadd", "s", "MAXLEN", "2", "LIMIT", "1", "*", "n", "7"]
+        )
+        .is_err());
+        assert!(run(&mut storage, &["xadd", "s", "MAXLEN", "-1", "*", "n", "7"]).is_err());
+    }
+
+    #[test]
+    fn test_command_xrange_and_xrevrange() {
+        let mut storage = Storage::new();
+        stream_with_entries(&mut storage, &["1-1", "1-2", "2-1", "3-1"]);
+        assert_eq!(
+            run(&mut storage, &["xrange", "s", "1", "2"]),
+            Ok(RESP::Array(vec![
+                entry("1-1", &["n", "0"]),
+                entry("1-2", &["n", "1"]),
+                entry("2-1", &["n", "2"]),
+            ]))
+        );
+        assert_eq!(
+            run(&mut storage, &["xrange", "s", "(1-1", "+", "COUNT", "2"]),
+            Ok(RESP::Array(vec![
+                entry("1-2", &["n", "1"]),
+                entry("2-1", &["n", "2"]),
+            ]))
+        );
+        assert_eq!(
+            run(&mut storage, &["xrevrange", "s", "+", "(1-2"]),
+            Ok(RESP::Array(vec![
+                entry("3-1", &["n", "3"]),
+                entry("2-1", &["n", "2"]),
+            ]))
+        );
+        assert_eq!(
+            run(&mut storage, &["xrange", "s", "3", "2"]),
+            Ok(RESP::Array(vec![]))
+        );
+        assert_eq!(
+            run(&mut storage, &["xrange", "none", "-", "+"]),
+            Ok(RESP::Array(vec![]))
+        );
+        assert!(run(&mut storage, &["xrange", "s", "x", "+"]).is_err());
+    }
+
+    #[test]
+    fn test_command_xdel_and_xtrim() {
+        let mut storage = Storage::new();
+        stream_with_entries(&mut storage, &["1", "2", "3", "4", "5"]);
+        assert_eq!(
+            run(&mut storage, &["xdel", "s", "2", "9"]),
+            Ok(RESP::Integer(1))
+        );
+        assert_eq!(
+            run(&mut storage, &["xtrim", "s", "MINID", "4"]),
+            Ok(RESP::Integer(2))
+        );
+        assert_eq!(
+            run(
+                &mut storage,
+                &["xtrim", "s", "MAXLEN", "~", "0", "LIMIT", "1"]
+            ),
+            Ok(RESP::Integer(1))
+        );
+        assert_eq!(
+            run(&mut storage, &["xtrim", "s", "MAXLEN", "0"]),
+            Ok(RESP::Integer(1))
+        );
+        // An empty stream is kept, along with its last ID.
+        assert_eq!(run(&mut storage, &["xlen", "s"]), Ok(RESP::Integer(0)));
+        assert!(run(&mut storage, &["xadd", "s", "5", "n", "x"]).is_err());
+        assert_eq!(
+            run(&mut storage, &["xtrim", "s", "MAXLEN"]),
+            Err(StorageError::WrongArgumentCount(String::from("xtrim")))
+        );
+        assert_eq!(
+            run(&mut storage, &["XLEN"]),
+            Err(StorageError::WrongArgumentCount(String::from("xlen")))
+        );
+        assert_eq!(
+            run(&mut storage, &["xdel", "s"]),
+            Err(StorageError::WrongArgumentCount(String::from("xdel")))
+        );
+    }
+
+    #[test]
+    fn test_command_xread() {
+        let mut storage = Storage::new();
+        stream_with_entries(&mut storage, &["1", "2"]);
+        run(&mut storage, &["xadd", "t", "1", "x", "y"]).unwrap();
+        assert_eq!(
+            run(
+                &mut storage,
+                &["xread", "COUNT", "1", "STREAMS", "s", "t", "none", "0", "0", "0"]
+            ),
+            Ok(RESP::Array(vec![
+                RESP::Array(vec![
+                    bulk("s"),
+                    RESP::Array(vec![entry("1-0", &["n", "0"])])
+                ]),
+                RESP::Array(vec![
+                    bulk("t"),
+                    RESP::Array(vec![entry("1-0", &["x", "y"])])
+                ]),
+            ]))
+        );
+        assert_eq!(
+            run(&mut storage, &["xread", "STREAMS", "s", "2"]),
+            Ok(RESP::Null)
+        );
+        assert_eq!(
+            run(&mut storage, &["xread", "BLOCK", "10", "STREAMS", "s", "$"]),
+            Ok(RESP::Null)
+        );
+        assert!(run(&mut storage, &["xread", "STREAMS", "s"]).is_err());
+        assert!(run(&mut storage, &["xread", "STREAMS", "s", ">"]).is_err());
+        assert!(run(&mut storage, &["xread", "s", "0"]).is_err());
+    }
+
+    #[test]
+    fn test_consumer_groups() {
+        let mut storage = Storage::new();
+        assert!(run(&mut storage, &["xgroup", "create", "s", "g", "$"]).is_err());
+        assert_eq!(
+            run(
+                &mut storage,
+                &["xgroup", "create", "s", "g", "$", "MKSTREAM"]
+            ),
+            Ok(RESP::SimpleString(String::from("OK")))
+        );
+        assert_eq!(
+            run(&mut storage, &["xgroup", "create", "s", "g", "0"]),
+            Err(StorageError::BusyGroup)
+        );
+        stream_with_entries(&mut storage, &["1", "2", "3"]);
+
+        assert_eq!(
+            run(
+                &mut storage,
+                &[
+                    "xreadgroup",
+                    "GROUP",
+                    "g",
+                    "alice",
+                    "COUNT",
+                    "2",
+                    "STREAMS",
+                    "s",
+                    ">"
+                ]
+            ),
+            Ok(RESP::Array(vec![RESP::Array(vec![
+                bulk("s"),
+                RESP::Array(vec![entry("1-0", &["n", "0"]), entry("2-0", &["n", "1"])]),
+            ])]))
+        );
+        run(
+            &mut storage,
+            &["xreadgroup", "GROUP", "g", "bob", "STREAMS", "s", ">"],
+        )
+        .unwrap();
+        assert_eq!(
+            run(
+                &mut storage,
+                &["xreadgroup", "GROUP", "g", "bob", "STREAMS", "s", ">"]
+            ),
+            Ok(RESP::Null)
+        );
+        // Alice's history holds what she has not acknowledged.
+        assert_eq!(
+            run(&mut storage, &["xack", "s", "g", "1", "9"]),
+            Ok(RESP::Integer(1))
+        );
+        assert_eq!(
+            run(
+                &mut storage,
+                &["xreadgroup", "GROUP", "g", "alice", "STREAMS", "s", "0"]
+            ),
+            Ok(RESP::Array(vec![RESP::Array(vec![
+                bulk("s"),
+                RESP::Array(vec![entry("2-0", &["n", "1"])]),
+            ])]))
+        );
+
+        assert_eq!(
+            run(&mut storage, &["xpending", "s", "g"]),
+            Ok(RESP::Array(vec![
+                RESP::Integer(2),
+                bulk("2-0"),
+                bulk("3-0"),
+                RESP::Array(vec![bulks(&["alice", "1"]), bulks(&["bob", "1"])]),
+            ]))
+        );
+        match run(&mut storage, &["xpending", "s", "g", "-", "+", "10", "bob"]) {
+            Ok(RESP::Array(entries)) => match entries.as_slice() {
+                [RESP::Array(fields)] => {
+                    assert_eq!(fields[0], bulk("3-0"));
+                    assert_eq!(fields[1], bulk("bob"));
+                    assert_eq!(fields[3], RESP::Integer(1));
+                }
+                other => panic!("unexpected entries {:?}", other),
+            },
+            other => panic!("unexpected reply {:?}", other),
+        }
+
+        assert!(matches!(
+            run(
+                &mut storage,
+                &["xreadgroup", "GROUP", "nope", "alice", "STREAMS", "s", ">"]
+            ),
+            Err(StorageError::NoGroup(_))
+        ));
+        assert!(run(
+            &mut storage,
+            &["xreadgroup", "GROUP", "g", "alice", "STREAMS", "s", "$"]
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_command_xreadgroup_noack() {
+        let mut storage = Storage::new();
+        stream_with_entries(&mut storage, &["1", "2"]);
+        run(&mut storage, &["xgroup", "create", "s", "g", "0"]).unwrap();
+        run(
+            &mut storage,
+            &[
+                "xreadgroup",
+                "GROUP",
+                "g",
+                "c",
+                "NOACK",
+                "STREAMS",
+                "s",
+                ">",
+            ],
+        )
+        .unwrap();
+        assert_eq!(
+            run(&mut storage, &["xpending", "s", "g"]),
+            Ok(RESP::Array(vec![
+                RESP::Integer(0),
+                RESP::Null,
+                RESP::Null,
+                RESP::Null,
+            ]))
+        );
+    }
+
+    #[test]
+    fn test_command_xgroup_consumers_and_setid() {
+        let mut storage = Storage::new();
+        stream_with_entries(&mut storage, &["1", "2", "3"]);
+        run(&mut storage, &["xgroup", "create", "s", "g", "0"]).unwrap();
+        assert_eq!(
+            run(&mut storage, &["xgroup", "createconsumer", "s", "g", "c"]),
+            Ok(RESP::Integer(1))
+        );
+        assert_eq!(
+            run(&mut storage, &["xgroup", "createconsumer", "s", "g", "c"]),
+            Ok(RESP::Integer(0))
+        );
+        run(
+            &mut storage,
+            &["xreadgroup", "GROUP", "g", "c", "STREAMS", "s", ">"],
+        )
+        .unwrap();
+        assert_eq!(
+            run(&mut storage, &["xgroup", "delconsumer", "s", "g", "c"]),
+            Ok(RESP::Integer(3))
+        );
+        assert_eq!(
+            run(&mut storage, &["xgroup", "setid", "s", "g", "1"]),
+            Ok(RESP::SimpleString(String::from("OK")))
+        );
+        assert_eq!(
+            run(
+                &mut storage,
+                &["xreadgroup", "GROUP", "g", "c", "STREAMS", "s", ">"]
+            ),
+            Ok(RESP::Array(vec![RESP::Array(vec![
+                bulk("s"),
+                RESP::Array(vec![entry("2-0", &["n", "1"]), entry("3-0", &["n", "2"])]),
+            ])]))
+        );
+        assert!(matches!(
+            run(&mut storage, &["xgroup", "setid", "s", "none", "0"]),
+            Err(StorageError::NoGroup(_))
+        ));
+        assert_eq!(
+            run(&mut storage, &["xgroup", "destroy", "s", "g"]),
+            Ok(RESP::Integer(1))
+        );
+        assert_eq!(
+            run(&mut storage, &["xgroup", "destroy", "s", "g"]),
+            Ok(RESP::Integer(0))
+        );
+    }
+
+    #[test]
+    fn test_command_xclaim() {
+        let mut storage = Storage::new();
+        stream_with_entries(&mut storage, &["1", "2", "3"]);
+        run(&mut storage, &["xgroup", "create", "s", "g", "0"]).unwrap();
+        run(
+            &mut storage,
+            &["xreadgroup", "GROUP", "g", "alice", "STREAMS", "s", ">"],
+        )
+        .unwrap();
+        // Not idle for long enough yet.
+        assert_eq!(
+            run(&mut storage, &["xclaim", "s", "g", "bob", "60000", "1"]),
+            Ok(RESP::Array(vec![]))
+        );
+        assert_eq!(
+            run(&mut storage, &["xclaim", "s", "g", "bob", "0", "1", "2"]),
+            Ok(RESP::Array(vec![
+                entry("1-0", &["n", "0"]),
+                entry("2-0", &["n", "1"]),
+            ]))
+        );
+        run(&mut storage, &["xdel", "s", "2"]).unwrap();
+        assert_eq!(
+            run(
+                &mut storage,
+                &[
+                    "xclaim",
+                    "s",
+                    "g",
+                    "bob",
+                    "0",
+                    "1",
+                    "2",
+                    "3",
+                    "JUSTID",
+                    "RETRYCOUNT",
+                    "7"
+                ]
+            ),
+            Ok(bulks(&["1-0", "3-0"]))
+        );
+        match run(&mut storage, &["xpending", "s", "g", "-", "+", "10"]) {
+            Ok(RESP::Array(entries)) => {
+                assert_eq!(entries.len(), 2);
+                for pending in entries {
+                    match pending {
+                        RESP::Array(fields) => {
+                            assert_eq!(fields[1], bulk("bob"));
+                            assert_eq!(fields[3], RESP::Integer(7));
+                        }
+                        other => panic!("unexpected entry {:?}", other),
+                    }
+                }
+            }
+            other => panic!("unexpected reply {:?}", other),
+        }
+        // Claimed entries leave the history of their previous owner.
+        assert_eq!(
+            run(&mut storage, &["xpending", "s", "g"]),
+            Ok(RESP::Array(vec![
+                RESP::Integer(2),
+                bulk("1-0"),
+                bulk("3-0"),
+                RESP::Array(vec![bulks(&["bob", "2"])]),
+            ]))
+        );
+        assert_eq!(
+            run(
+                &mut storage,
+                &["xreadgroup", "GROUP", "g", "alice", "STREAMS", "s", "0"]
+            ),
+            Ok(RESP::Array(vec![RESP::Array(vec![
+                bulk("s"),
+                RESP::Array(vec![])
+            ])]))
+        );
+    }
+
+    #[test]
+    fn test_command_xclaim_force() {
+        let mut storage = Storage::new();
+        stream_with_entries(&mut storage, &["1"]);
+        run(&mut storage, &["xgroup", "create", "s", "g", "$"]).unwrap();
+        assert_eq!(
+            run(&mut storage, &["xclaim", "s", "g", "alice", "10000", "1-0"]),
+            Ok(RESP::Array(vec![]))
+        );
+        assert_eq!(
+            run(
+                &mut storage,
+                &["xclaim", "s", "g", "alice", "10000", "1-0", "FORCE"]
+            ),
+            Ok(RESP::Array(vec![entry("1-0", &["n", "0"])]))
+        );
+        match run(&mut storage, &["xpending", "s", "g", "-", "+", "10"]) {
+            Ok(RESP::Array(entries)) => match entries.as_slice() {
+                [RESP::Array(fields)] => {
+                    assert_eq!(fields[1], bulk("alice"));
+                    assert_eq!(fields[3], RESP::Integer(1));
+                }
+                other => panic!("unexpected entries {:?}", other),
+            },
+            other => panic!("unexpected reply {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_command_xautoclaim() {
+        let mut storage = Storage::new();
+        stream_with_entries(&mut storage, &["1", "2", "3", "4"]);
+        run(&mut storage, &["xgroup", "create", "s", "g", "0"]).unwrap();
+        run(
+            &mut storage,
+            &["xreadgroup", "GROUP", "g", "alice", "STREAMS", "s", ">"],
+        )
+        .unwrap();
+        run(&mut storage, &["xdel", "s", "2"]).unwrap();
+        assert_eq!(
+            run(
+                &mut storage,
+                &[
+                    "xautoclaim",
+                    "s",
+                    "g",
+                    "bob",
+                    "0",
+                    "-",
+                    "COUNT",
+                    "2",
+                    "JUSTID"
+                ]
+            ),
+            Ok(RESP::Array(vec![
+                bulk("4-0"),
+                bulks(&["1-0", "3-0"]),
+                bulks(&["2-0"]),
+            ]))
+        );
+        assert_eq!(
+            run(&mut storage, &["xautoclaim", "s", "g", "bob", "0", "4"]),
+            Ok(RESP::Array(vec![
+                bulk("0-0"),
+                RESP::Array(vec![entry("4-0", &["n", "3"])]),
+                RESP::Array(vec![]),
+            ]))
+        );
+        assert!(run(
+            &mut storage,
+            &["xautoclaim", "s", "g", "bob", "0", "-", "COUNT", "0"]
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_command_xinfo() {
+        let mut storage = Storage::new();
+        stream_with_entries(&mut storage, &["1", "2", "3"]);
+        run(&mut storage, &["xgroup", "create", "s", "g", "0"]).unwrap();
+        run(
+            &mut storage,
+            &[
+                "xreadgroup",
+                "GROUP",
+                "g",
+                "c",
+                "COUNT",
+                "1",
+                "STREAMS",
+                "s",
+                ">",
+            ],
+        )
+        .unwrap();
+
+        let info = run(&mut storage, &["xinfo", "stream", "s"]).unwrap();
+        assert_eq!(field_of(&info, "length"), &RESP::Integer(3));
+        assert_eq!(field_of(&info, "last-generated-id"), &bulk("3-0"));
+        assert_eq!(field_of(&info, "groups"), &RESP::Integer(1));
+        assert_eq!(field_of(&info, "first-entry"), &entry("1-0", &["n", "0"]));
+
+        let groups = match run(&mut storage, &["xinfo", "groups", "s"]) {
+            Ok(RESP::Array(groups)) => groups,
+            other => panic!("unexpected reply {:?}", other),
+        };
+        assert_eq!(field_of(&groups[0], "pending"), &RESP::Integer(1));
+        assert_eq!(field_of(&groups[0], "entries-read"), &RESP::Integer(1));
+        assert_eq!(field_of(&groups[0], "lag"), &RESP::Integer(2));
+
+        // Deleting an entry the group has yet to read makes its lag unknown.
+        run(&mut storage, &["xdel", "s", "2"]).unwrap();
+        let groups = match run(&mut storage, &["xinfo", "groups", "s"]) {
+            Ok(RESP::Array(groups)) => groups,
+            other => panic!("unexpected reply {:?}", other),
+        };
+        assert_eq!(field_of(&groups[0], "lag"), &RESP::Null);
+
+        let consumers = match run(&mut storage, &["xinfo", "consumers", "s", "g"]) {
+            Ok(RESP::Array(consumers)) => consumers,
+            other => panic!("unexpected reply {:?}", other),
+        };
+        assert_eq!(field_of(&consumers[0], "name"), &bulk("c"));
+        assert_eq!(field_of(&consumers[0], "pending"), &RESP::Integer(1));
+
+        let full = run(&mut storage, &["xinfo", "stream", "s", "FULL"]).unwrap();
+        assert!(matches!(field_of(&full, "entries"), RESP::Array(entries) if entries.len() == 2));
+        assert!(run(&mut storage, &["xinfo", "stream", "none"]).is_err());
+        assert!(matches!(
+            run(&mut storage, &["xinfo", "consumers", "s", "none"]),
+            Err(StorageError::NoGroup(_))
+        ));
+    }
+}
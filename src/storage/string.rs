@@ -0,0 +1,658 @@
+use super::{
+    expiry_deadline, format_float, parse_float, parse_integer, resolve_range, Storage, StorageData,
+    StorageValue,
+};
+use crate::resp::{MAX_BULK_LENGTH, RESP};
+use crate::set::{parse_set_arguments, KeyExpiry, SetArgs};
+use crate::storage_result::{command_name, command_to_string, StorageError, StorageResult};
+use std::borrow::Cow;
+use std::time::SystemTime;
+
+impl Storage {
+    /// Returns the string stored at `key`, or `None` if there is no such key.
+    pub(super) fn string_at(&mut self, key: &[u8]) -> StorageResult<Option<Cow<'_, [u8]>>> {
+        match self.lookup(key) {
+            Some(data) => data
+                .value
+                .string_bytes()
+                .map(Some)
+                .ok_or(StorageError::WrongType),
+            None => Ok(None),
+        }
+    }
+
+    /// Returns the string stored at `key` for modifying in place.
+    fn string_at_mut(&mut self, key: &[u8]) -> StorageResult<Option<&mut Vec<u8>>> {
+        match self.lookup(key) {
+            Some(data) => data
+                .value
+                .raw_string_mut()
+                .map(Some)
+                .ok_or(StorageError::WrongType),
+            None => Ok(None),
+        }
+    }
+
+    /// Replaces the value stored at `key`, keeping its TTL, or creates a new key. Callers
+    /// look the key up first so that an expired one is never written to.
+    fn write_string(&mut self, key: &[u8], value: StorageValue) {
+        match self.store.get_mut(key) {
+            Some(data) => data.value = value,
+            None => {
+                self.store.insert(key.to_vec(), StorageData::from(value));
+            }
+        }
+    }
+
+    fn increment(&mut self, key: &[u8], delta: i64) -> StorageResult<i64> {
+        let current = match self.lookup(key) {
+            Some(StorageData {
+                value: StorageValue::Integer(n),
+                ..
+            }) => *n,
+            Some(StorageData {
+                value: StorageValue::String(v),
+                ..
+            }) => parse_integer(v)?,
+            Some(_) => return Err(StorageError::WrongType),
+            None => 0,
+        };
+        let value = current.checked_add(delta).ok_or_else(|| {
+            StorageError::InvalidArgument(String::from("increment or decrement would overflow"))
+        })?;
+        self.write_string(key, StorageValue::Integer(value));
+        Ok(value)
+    }
+
+    pub(super) fn command_incr(&mut self, command: &[Vec<u8>], delta: i64) -> StorageResult<RESP> {
+        if command.len() != 2 {
+            return Err(StorageError::WrongArgumentCount(command_name(command)));
+        }
+        Ok(RESP::Integer(self.increment(&command[1], delta)?))
+    }
+
+    pub(super) fn command_incrby(
+        &mut self,
+        command: &[Vec<u8>],
+        negate: bool,
+    ) -> StorageResult<RESP> {
+        if command.len() != 3 {
+            return Err(StorageError::WrongArgumentCount(command_name(command)));
+        }
+        let mut delta = parse_integer(&command[2])?;
+        if negate {
+            delta = delta.checked_neg().ok_or_else(|| {
+                StorageError::InvalidArgument(String::from("decrement would overflow"))
+            })?;
+        }
+        Ok(RESP::Integer(self.increment(&command[1], delta)?))
+    }
+
+    pub(super) fn command_incrbyfloat(&mut self, command: &[Vec<u8>]) -> StorageResult<RESP> {
+        if command.len() != 3 {
+            return Err(StorageError::WrongArgumentCount(command_name(command)));
+        }
+        let key = &command[1];
+        let increment = parse_float(&command[2])?;
+        let current = match self.string_at(key)? {
+            Some(v) => parse_float(&v)?,
+            None => 0.0,
+        };
+        let value = current + increment;
+        if !value.is_finite() {
+            return Err(StorageError::InvalidArgument(String::from(
+                "increment would produce NaN or Infinity",
+            )));
+        }
+        let value = format_float(value);
+        self.write_string(key, StorageValue::from_bytes(value.clone()));
+        Ok(RESP::BulkString(value))
+    }
+
+    pub(super) fn command_append(&mut self, command: &[Vec<u8>]) -> StorageResult<RESP> {
+        if command.len() != 3 {
+            return Err(StorageError::WrongArgumentCount(command_name(command)));
+        }
+        let (key, suffix) = (&command[1], &command[2]);
+        match self.string_at_mut(key)? {
+            Some(v) => {
+                check_string_length(v.len() + suffix.len())?;
+                v.extend_from_slice(suffix);
+                Ok(RESP::Integer(v.len() as i64))
+            }
+            None => {
+                self.write_string(key, StorageValue::from_bytes(suffix.clone()));
+                Ok(RESP::Integer(suffix.len() as i64))
+            }
+        }
+    }
+
+    pub(super) fn command_strlen(&mut self, command: &[Vec<u8>]) -> StorageResult<RESP> {
+        if command.len() != 2 {
+            return Err(StorageError::WrongArgumentCount(command_name(command)));
+        }
+        let length = self.string_at(&command[1])?.map_or(0, |v| v.len());
+        Ok(RESP::Integer(length as i64))
+    }
+
+    pub(super) fn command_getrange(&mut self, command: &[Vec<u8>]) -> StorageResult<RESP> {
+        if command.len() != 4 {
+            return Err(StorageError::WrongArgumentCount(command_name(command)));
+        }
+        let start = parse_integer(&command[2])?;
+        let end = parse_integer(&command[3])?;
+        let value = match self.string_at(&command[1])? {
+            Some(v) => v,
+            None => return Ok(RESP::BulkString(vec![])),
+        };
+        match resolve_range(start, end, value.len()) {
+            Some((start, end)) => Ok(RESP::BulkString(value[start..=end].to_vec())),
+            None => Ok(RESP::BulkString(vec![])),
+        }
+    }
+
+    pub(super) fn command_setrange(&mut self, command: &[Vec<u8>]) -> StorageResult<RESP> {
+        if command.len() != 4 {
+            return Err(StorageError::WrongArgumentCount(command_name(command)));
+        }
+        let (key, patch) = (&command[1], &command[3]);
+        let offset = parse_integer(&command[2])?;
+        if offset < 0 {
+            return Err(StorageError::InvalidArgument(String::from(
+                "offset is out of range",
+            )));
+        }
+        let offset = offset as usize;
+
+        let current = self.string_at_mut(key)?;
+        let current_length = current.as_ref().map_or(0, |v| v.len());
+        // An empty patch changes nothing and, in particular, does not create the key.
+        if patch.is_empty() {
+            return Ok(RESP::Integer(current_length as i64));
+        }
+        check_string_length(offset.saturating_add(patch.len()))?;
+        let length = match current {
+            Some(v) => {
+                write_range(v, offset, patch);
+                v.len()
+            }
+            None => {
+                let mut v = Vec::new();
+                write_range(&mut v, offset, patch);
+                let length = v.len();
+                self.write_string(key, StorageValue::String(v));
+                length
+            }
+        };
+        Ok(RESP::Integer(length as i64))
+    }
+
+    pub(super) fn command_getdel(&mut self, command: &[Vec<u8>]) -> StorageResult<RESP> {
+        if command.len() != 2 {
+            return Err(StorageError::WrongArgumentCount(command_name(command)));
+        }
+        let key = &command[1];
+        let value = match self.string_at(key)? {
+            Some(v) => v.into_owned(),
+            None => return Ok(RESP::Null),
+        };
+        self.remove(key);
+        Ok(RESP::BulkString(value))
+    }
+
+    pub(super) fn command_getex(&mut self, command: &[Vec<u8>]) -> StorageResult<RESP> {
+        if command.len() < 2 {
+            return Err(StorageError::WrongArgumentCount(command_name(command)));
+        }
+        let key = &command[1];
+        let now = SystemTime::now();
+        let (deadline, persist) = match &command[2..] {
+            [] => (None, false),
+            [option] if option.eq_ignore_ascii_case(b"persist") => (None, true),
+            options => {
+                let invalid_expire = || {
+                    StorageError::InvalidArgument(String::from(
+                        "invalid expire time in 'getex' command",
+                    ))
+                };
+                // GETEX takes the expiry options of SET and nothing else.
+                let args = parse_set_arguments(options).map_err(|e| match e {
+                    StorageError::InvalidArgument(_) => invalid_expire(),
+                    e => e,
+                })?;
+                let expiry = match args {
+                    SetArgs {
+                        expiry: Some(expiry),
+                        existence: None,
+                        get: false,
+                    } if expiry != KeyExpiry::KEEPTTL => expiry,
+                    _ => return Err(StorageError::CommandSyntaxError(command_to_string(command))),
+                };
+                let deadline = expiry_deadline(&expiry, now).ok_or_else(invalid_expire)?;
+                (Some(deadline), false)
+            }
+        };
+
+        let value = match self.string_at(key)? {
+            Some(v) => v.into_owned(),
+            None => return Ok(RESP::Null),
+        };
+        if persist {
+            self.persist(key);
+        }
+        match deadline {
+            Some(deadline) if deadline <= now => {
+                self.remove(key);
+            }
+            Some(deadline) => self.set_deadline(key, deadline),
+            None => {}
+        }
+        Ok(RESP::BulkString(value))
+    }
+
+    pub(super) fn command_setnx(&mut self, command: &[Vec<u8>]) -> StorageResult<RESP> {
+        if command.len() != 3 {
+            return Err(StorageError::WrongArgumentCount(command_name(command)));
+        }
+        if self.lookup(&command[1]).is_some() {
+            return Ok(RESP::Integer(0));
+        }
+        self.set(command[1].clone(), command[2].clone(), SetArgs::new())?;
+        Ok(RESP::Integer(1))
+    }
+
+    /// Handles SETEX and PSETEX; `unit` is the length of the TTL's unit in milliseconds.
+    pub(super) fn command_setex(&mut self, command: &[Vec<u8>], unit: u64) -> StorageResult<RESP> {
+        if command.len() != 4 {
+            return Err(StorageError::WrongArgumentCount(command_name(command)));
+        }
+        let invalid_expire = || {
+            StorageError::InvalidArgument(format!(
+                "invalid expire time in '{}' command",
+                String::from_utf8_lossy(&command[0]).to_lowercase()
+            ))
+        };
+        let ttl = parse_integer(&command[2])?;
+        if ttl <= 0 {
+            return Err(invalid_expire());
+        }
+        let expiry = match unit {
+            1 => KeyExpiry::PX(ttl as u64),
+            _ => KeyExpiry::EX(ttl as u64),
+        };
+        let args = SetArgs {
+            expiry: Some(expiry),
+            existence: None,
+            get: false,
+        };
+        self.set(command[1].clone(), command[3].clone(), args)
+            .map_err(|_| invalid_expire())?;
+        Ok(RESP::SimpleString(String::from("OK")))
+    }
+
+    pub(super) fn command_mget(&mut self, command: &[Vec<u8>]) -> StorageResult<RESP> {
+        if command.len() < 2 {
+            return Err(StorageError::WrongArgumentCount(command_name(command)));
+        }
+        let values = command[1..]
+            .iter()
+            .map(|key| match self.string_at(key) {
+                Ok(Some(v)) => RESP::BulkString(v.into_owned()),
+                _ => RESP::Null,
+            })
+            .collect();
+        Ok(RESP::Array(values))
+    }
+
+    /// Handles MSET and, with `nx`, MSETNX, which sets nothing if any of the keys exists.
+    pub(super) fn command_mset(&mut self, command: &[Vec<u8>], nx: bool) -> StorageResult<RESP> {
+        if command.len() < 3 || command.len().is_multiple_of(2) {
+            return Err(StorageError::WrongArgumentCount(command_name(command)));
+        }
+        if nx {
+            for pair in command[1..].chunks(2) {
+                if self.lookup(&pair[0]).is_some() {
+                    return Ok(RESP::Integer(0));
+                }
+            }
+        }
+        for pair in command[1..].chunks(2) {
+            self.set(pair[0].clone(), pair[1].clone(), SetArgs::new())?;
+        }
+        match nx {
+            true => Ok(RESP::Integer(1)),
+            false => Ok(RESP::SimpleString(String::from("OK"))),
+        }
+    }
+}
+
+/// Rejects strings longer than the largest bulk string a client could send.
+fn check_string_length(length: usize) -> StorageResult<()> {
+    if length > MAX_BULK_LENGTH as usize {
+        return Err(StorageError::InvalidArgument(String::from(
+            "string exceeds maximum allowed size (proto-max-bulk-len)",
+        )));
+    }
+    Ok(())
+}
+
+/// Overwrites `value` with `patch` starting at `offset`, zero-padding any gap.
+fn write_range(value: &mut Vec<u8>, offset: usize, patch: &[u8]) {
+    let end = offset + patch.len();
+    if value.len() < end {
+        value.resize(end, 0);
+    }
+    value[offset..end].copy_from_slice(patch);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::test_helpers::{bulk, run};
+
+    #[test]
+    fn test_command_incr_and_decr() {
+        let mut storage = Storage::new();
+        assert_eq!(
+            run(&mut storage, &["incr", "counter"]),
+            Ok(RESP::Integer(1))
+        );
+        assert_eq!(
+            run(&mut storage, &["incrby", "counter", "41"]),
+            Ok(RESP::Integer(42))
+        );
+        assert_eq!(
+            run(&mut storage, &["decr", "counter"]),
+            Ok(RESP::Integer(41))
+        );
+        assert_eq!(
+            run(&mut storage, &["decrby", "counter", "-9"]),
+            Ok(RESP::Integer(50))
+        );
+        assert_eq!(run(&mut storage, &["get", "counter"]), Ok(bulk("50")));
+    }
+
+    #[test]
+    fn test_command_incr_keeps_ttl() {
+        let mut storage = Storage::new();
+        run(&mut storage, &["set", "counter", "1", "EX", "100"]).unwrap();
+        run(&mut storage, &["incr", "counter"]).unwrap();
+        assert_eq!(
+            run(&mut storage, &["ttl", "counter"]),
+            Ok(RESP::Integer(100))
+        );
+    }
+
+    #[test]
+    fn test_command_incr_not_an_integer() {
+        let mut storage = Storage::new();
+        for value in [
+            "abc",
+            "1.5",
+            " 1",
+            "+1",
+            "01",
+            "-0",
+            "",
+            "99999999999999999999",
+        ] {
+            run(&mut storage, &["set", "key", value]).unwrap();
+            assert_eq!(
+                run(&mut storage, &["incr", "key"]),
+                Err(StorageError::NotAnInteger),
+                "value {:?}",
+                value
+            );
+        }
+        assert_eq!(
+            run(&mut storage, &["incrby", "other", "+1"]),
+            Err(StorageError::NotAnInteger)
+        );
+    }
+
+    #[test]
+    fn test_command_incr_overflow() {
+        let mut storage = Storage::new();
+        run(&mut storage, &["set", "key", "9223372036854775807"]).unwrap();
+        assert_eq!(
+            RESP::from(run(&mut storage, &["incr", "key"]).unwrap_err()),
+            RESP::Error(String::from("ERR increment or decrement would overflow"))
+        );
+        run(&mut storage, &["set", "key", "-9223372036854775808"]).unwrap();
+        assert!(run(&mut storage, &["decr", "key"]).is_err());
+        assert_eq!(
+            RESP::from(run(&mut storage, &["decrby", "key", "-9223372036854775808"]).unwrap_err()),
+            RESP::Error(String::from("ERR decrement would overflow"))
+        );
+        assert_eq!(
+            run(&mut storage, &["get", "key"]),
+            Ok(bulk("-9223372036854775808"))
+        );
+    }
+
+    #[test]
+    fn test_command_incrbyfloat() {
+        let mut storage = Storage::new();
+        assert_eq!(
+            run(&mut storage, &["incrbyfloat", "key", "10.5"]),
+            Ok(bulk("10.5"))
+        );
+        assert_eq!(
+            run(&mut storage, &["incrbyfloat", "key", "0.1"]),
+            Ok(bulk("10.6"))
+        );
+        assert_eq!(
+            run(&mut storage, &["incrbyfloat", "key", "-5.6"]),
+            Ok(bulk("5"))
+        );
+        assert_eq!(
+            run(&mut storage, &["incrbyfloat", "key", "2.0e2"]),
+            Ok(bulk("205"))
+        );
+        assert_eq!(
+            run(&mut storage, &["incrbyfloat", "key", "abc"]),
+            Err(StorageError::NotAFloat)
+        );
+        assert_eq!(
+            run(&mut storage, &["incrbyfloat", "key", "nan"]),
+            Err(StorageError::NotAFloat)
+        );
+        assert!(run(&mut storage, &["incrbyfloat", "key", "inf"]).is_err());
+        run(&mut storage, &["set", "sum", "0.1"]).unwrap();
+        assert_eq!(
+            run(&mut storage, &["incrbyfloat", "sum", "0.2"]),
+            Ok(bulk("0.30000000000000004"))
+        );
+        assert_eq!(
+            run(&mut storage, &["incrbyfloat", "large", "1e20"]),
+            Ok(bulk("100000000000000000000"))
+        );
+        assert_eq!(
+            run(&mut storage, &["incrbyfloat", "large", "1e20"]),
+            Ok(bulk("200000000000000000000"))
+        );
+        assert_eq!(
+            run(&mut storage, &["incrbyfloat", "tiny", "0.00001"]),
+            Ok(bulk("0.00001"))
+        );
+        assert_eq!(
+            run(&mut storage, &["incrbyfloat", "tinier", "1e-20"]),
+            Ok(bulk("0"))
+        );
+        run(&mut storage, &["set", "text", "hello"]).unwrap();
+        assert_eq!(
+            run(&mut storage, &["incrbyfloat", "text", "1"]),
+            Err(StorageError::NotAFloat)
+        );
+    }
+
+    #[test]
+    fn test_command_append_and_strlen() {
+        let mut storage = Storage::new();
+        assert_eq!(run(&mut storage, &["strlen", "key"]), Ok(RESP::Integer(0)));
+        assert_eq!(
+            run(&mut storage, &["append", "key", "Hello"]),
+            Ok(RESP::Integer(5))
+        );
+        assert_eq!(
+            run(&mut storage, &["append", "key", " World"]),
+            Ok(RESP::Integer(11))
+        );
+        assert_eq!(run(&mut storage, &["strlen", "key"]), Ok(RESP::Integer(11)));
+        assert_eq!(run(&mut storage, &["get", "key"]), Ok(bulk("Hello World")));
+    }
+
+    #[test]
+    fn test_command_getrange() {
+        let mut storage = Storage::new();
+        run(&mut storage, &["set", "key", "This is a string"]).unwrap();
+        let getrange = |storage: &mut Storage, start: &str, end: &str| {
+            run(storage, &["getrange", "key", start, end]).unwrap()
+        };
+        assert_eq!(getrange(&mut storage, "0", "3"), bulk("This"));
+        assert_eq!(getrange(&mut storage, "-3", "-1"), bulk("ing"));
+        assert_eq!(getrange(&mut storage, "0", "-1"), bulk("This is a string"));
+        assert_eq!(getrange(&mut storage, "10", "100"), bulk("string"));
+        assert_eq!(getrange(&mut storage, "5", "3"), bulk(""));
+        assert_eq!(getrange(&mut storage, "-1", "-5"), bulk(""));
+        assert_eq!(getrange(&mut storage, "-100", "1"), bulk("Th"));
+        assert_eq!(
+            run(&mut storage, &["getrange", "missing", "0", "-1"]),
+            Ok(bulk(""))
+        );
+    }
+
+    #[test]
+    fn test_command_setrange() {
+        let mut storage = Storage::new();
+        run(&mut storage, &["set", "key", "Hello World"]).unwrap();
+        assert_eq!(
+            run(&mut storage, &["setrange", "key", "6", "Redis"]),
+            Ok(RESP::Integer(11))
+        );
+        assert_eq!(run(&mut storage, &["get", "key"]), Ok(bulk("Hello Redis")));
+
+        assert_eq!(
+            run(&mut storage, &["setrange", "padded", "3", "ab"]),
+            Ok(RESP::Integer(5))
+        );
+        assert_eq!(
+            run(&mut storage, &["get", "padded"]),
+            Ok(RESP::BulkString(b"\0\0\0ab".to_vec()))
+        );
+
+        assert_eq!(
+            run(&mut storage, &["setrange", "empty", "10", ""]),
+            Ok(RESP::Integer(0))
+        );
+        assert_eq!(
+            run(&mut storage, &["exists", "empty"]),
+            Ok(RESP::Integer(0))
+        );
+        assert!(run(&mut storage, &["setrange", "key", "-1", "a"]).is_err());
+        assert!(run(&mut storage, &["setrange", "key", "536870912", "a"]).is_err());
+    }
+
+    #[test]
+    fn test_command_getdel() {
+        let mut storage = Storage::new();
+        run(&mut storage, &["set", "key", "value", "EX", "100"]).unwrap();
+        assert_eq!(run(&mut storage, &["getdel", "key"]), Ok(bulk("value")));
+        assert_eq!(run(&mut storage, &["getdel", "key"]), Ok(RESP::Null));
+        assert_eq!(storage.expiry.len(), 0);
+    }
+
+    #[test]
+    fn test_command_getex() {
+        let mut storage = Storage::new();
+        assert_eq!(
+            run(&mut storage, &["getex", "key", "EX", "10"]),
+            Ok(RESP::Null)
+        );
+        run(&mut storage, &["set", "key", "value"]).unwrap();
+        assert_eq!(
+            run(&mut storage, &["getex", "key", "EX", "100"]),
+            Ok(bulk("value"))
+        );
+        assert_eq!(run(&mut storage, &["ttl", "key"]), Ok(RESP::Integer(100)));
+        assert_eq!(run(&mut storage, &["getex", "key"]), Ok(bulk("value")));
+        assert_eq!(run(&mut storage, &["ttl", "key"]), Ok(RESP::Integer(100)));
+        assert_eq!(
+            run(&mut storage, &["getex", "key", "PERSIST"]),
+            Ok(bulk("value"))
+        );
+        assert_eq!(run(&mut storage, &["ttl", "key"]), Ok(RESP::Integer(-1)));
+        assert_eq!(
+            run(&mut storage, &["getex", "key", "PXAT", "1"]),
+            Ok(bulk("value"))
+        );
+        assert_eq!(run(&mut storage, &["exists", "key"]), Ok(RESP::Integer(0)));
+
+        assert!(run(&mut storage, &["getex", "key", "NX"]).is_err());
+        assert!(run(&mut storage, &["getex", "key", "KEEPTTL"]).is_err());
+        assert!(run(&mut storage, &["getex", "key", "EX", "10", "PERSIST"]).is_err());
+        assert_eq!(
+            RESP::from(run(&mut storage, &["getex", "key", "EX", "0"]).unwrap_err()),
+            RESP::Error(String::from("ERR invalid expire time in 'getex' command"))
+        );
+    }
+
+    #[test]
+    fn test_command_setnx_and_setex() {
+        let mut storage = Storage::new();
+        assert_eq!(
+            run(&mut storage, &["setnx", "key", "a"]),
+            Ok(RESP::Integer(1))
+        );
+        assert_eq!(
+            run(&mut storage, &["setnx", "key", "b"]),
+            Ok(RESP::Integer(0))
+        );
+        assert_eq!(
+            run(&mut storage, &["setex", "key", "100", "c"]),
+            Ok(RESP::SimpleString(String::from("OK")))
+        );
+        assert_eq!(run(&mut storage, &["ttl", "key"]), Ok(RESP::Integer(100)));
+        assert_eq!(
+            run(&mut storage, &["psetex", "key", "5000", "d"]),
+            Ok(RESP::SimpleString(String::from("OK")))
+        );
+        assert_eq!(run(&mut storage, &["ttl", "key"]), Ok(RESP::Integer(5)));
+        assert_eq!(run(&mut storage, &["get", "key"]), Ok(bulk("d")));
+        assert_eq!(
+            RESP::from(run(&mut storage, &["setex", "key", "0", "e"]).unwrap_err()),
+            RESP::Error(String::from("ERR invalid expire time in 'setex' command"))
+        );
+    }
+
+    #[test]
+    fn test_command_mget_and_mset() {
+        let mut storage = Storage::new();
+        run(&mut storage, &["set", "a", "old", "EX", "100"]).unwrap();
+        assert_eq!(
+            run(&mut storage, &["mset", "a", "1", "b", "2"]),
+            Ok(RESP::SimpleString(String::from("OK")))
+        );
+        assert_eq!(run(&mut storage, &["ttl", "a"]), Ok(RESP::Integer(-1)));
+        assert_eq!(
+            run(&mut storage, &["mget", "a", "missing", "b"]),
+            Ok(RESP::Array(vec![bulk("1"), RESP::Null, bulk("2")]))
+        );
+        assert!(run(&mut storage, &["mset", "a", "1", "b"]).is_err());
+    }
+
+    #[test]
+    fn test_command_msetnx() {
+        let mut storage = Storage::new();
+        assert_eq!(
+            run(&mut storage, &["msetnx", "a", "1", "b", "2"]),
+            Ok(RESP::Integer(1))
+        );
+        assert_eq!(
+            run(&mut storage, &["msetnx", "c", "3", "b", "4"]),
+            Ok(RESP::Integer(0))
+        );
+        assert_eq!(run(&mut storage, &["exists", "c"]), Ok(RESP::Integer(0)));
+        assert_eq!(run(&mut storage, &["get", "b"]), Ok(bulk("2")));
+    }
+}
@@ -1,3 +1,4 @@
+use crate::resp::RESP;
 use std::fmt;
 
 #[derive(Debug, PartialEq)]
@@ -6,7 +7,17 @@ pub enum StorageError {
     StorageUnavailable,
     CommandNotAvailable(String),
     CommandSyntaxError(String),
-    CommandInternalError(String),
+    /// A command given too few or too many arguments, with the name of the command.
+    WrongArgumentCount(String),
+    InvalidArgument(String),
+    NotAnInteger,
+    NotAFloat,
+    WrongType,
+    /// A stream consumer group that does not exist, with the reason to report.
+    NoGroup(String),
+    BusyGroup,
+    UnsupportedProtocolVersion,
+    InvalidCredentials,
 }
 
 impl fmt::Display for StorageError {
@@ -20,11 +31,110 @@ impl fmt::Display for StorageError {
             StorageError::CommandSyntaxError(string) => {
                 write!(f, "Syntax error while processing {}!", string)
             }
-            StorageError::CommandInternalError(string) => {
-                write!(f, "Internal error while processing {}!", string)
+            StorageError::WrongArgumentCount(cmd) => {
+                write!(f, "Wrong number of arguments for `{}`!", cmd)
             }
+            StorageError::InvalidArgument(string) => write!(f, "Invalid argument: {}!", string),
+            StorageError::NotAnInteger => write!(f, "The value is not an integer!"),
+            StorageError::NotAFloat => write!(f, "The value is not a float!"),
+            StorageError::WrongType => {
+                write!(f, "The key holds a value of the wrong type!")
+            }
+            StorageError::NoGroup(reason) => write!(f, "No such consumer group: {}!", reason),
+            StorageError::BusyGroup => write!(f, "The consumer group already exists!"),
+            StorageError::UnsupportedProtocolVersion => {
+                write!(f, "The requested protocol version is not supported!")
+            }
+            StorageError::InvalidCredentials => write!(f, "The credentials are invalid!"),
         }
     }
 }
 
+impl From<StorageError> for RESP {
+    fn from(err: StorageError) -> Self {
+        let message = match err {
+            StorageError::IncorrectRequest => {
+                String::from("ERR Protocol error: expected an array of bulk strings")
+            }
+            StorageError::StorageUnavailable => {
+                String::from("ERR storage is currently unavailable")
+            }
+            StorageError::CommandNotAvailable(cmd) => format!("ERR unknown command '{}'", cmd),
+            StorageError::CommandSyntaxError(_) => String::from("ERR syntax error"),
+            StorageError::WrongArgumentCount(cmd) => {
+                format!("ERR wrong number of arguments for '{}' command", cmd)
+            }
+            StorageError::InvalidArgument(reason) => format!("ERR {}", reason),
+            StorageError::NotAnInteger => {
+                String::from("ERR value is not an integer or out of range")
+            }
+            StorageError::NotAFloat => String::from("ERR value is not a valid float"),
+            StorageError::WrongType => {
+                String::from("WRONGTYPE Operation against a key holding the wrong kind of value")
+            }
+            StorageError::NoGroup(reason) => format!("NOGROUP {}", reason),
+            StorageError::BusyGroup => String::from("BUSYGROUP Consumer Group name already exists"),
+            StorageError::UnsupportedProtocolVersion => {
+                String::from("NOPROTO unsupported protocol version")
+            }
+            StorageError::InvalidCredentials => {
+                String::from("WRONGPASS invalid username-password pair or user is disabled.")
+            }
+        };
+        RESP::Error(message)
+    }
+}
+
 pub type StorageResult<T> = Result<T, StorageError>;
+
+/// Renders a binary command as text, for use in error messages.
+pub fn command_to_string(command: &[Vec<u8>]) -> String {
+    command
+        .iter()
+        .map(|argument| String::from_utf8_lossy(argument))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// The name of a command in lower case, the way Redis names it in error messages.
+pub fn command_name(command: &[Vec<u8>]) -> String {
+    String::from_utf8_lossy(&command[0]).to_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unknown_command_to_resp() {
+        let output = RESP::from(StorageError::CommandNotAvailable(String::from("foo")));
+        assert_eq!(
+            output,
+            RESP::Error(String::from("ERR unknown command 'foo'"))
+        );
+    }
+
+    #[test]
+    fn test_command_to_string() {
+        let command = vec![b"SET".to_vec(), b"key".to_vec(), vec![0xff, b'a']];
+        assert_eq!(command_to_string(&command), "SET key \u{fffd}a");
+    }
+
+    #[test]
+    fn test_syntax_error_to_resp() {
+        let output = RESP::from(StorageError::CommandSyntaxError(String::from("set a")));
+        assert_eq!(output, RESP::Error(String::from("ERR syntax error")));
+    }
+
+    #[test]
+    fn test_wrong_argument_count_to_resp() {
+        let command = vec![b"XLEN".to_vec()];
+        let output = RESP::from(StorageError::WrongArgumentCount(command_name(&command)));
+        assert_eq!(
+            output,
+            RESP::Error(String::from(
+                "ERR wrong number of arguments for 'xlen' command"
+            ))
+        );
+    }
+}